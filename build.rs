@@ -1,12 +1,35 @@
 extern crate winres;
 
+use std::path::PathBuf;
+
+/// Sizes in every battery icon, for display scaling from 100% to 400%
+const ICON_SIZES: [u32; 6] = [16, 20, 24, 32, 48, 64];
+
 fn main() {
+    // Icons, the manifest and the string table are Windows resources
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("windows") {
+        return;
+    }
+
     let mut res = winres::WindowsResource::new();
     res.set_icon("src/icons/main.ico");
 
+    // Identity of the sparse package, which must match packaging/AppxManifest.xml
+    let msix_identity = if std::env::var_os("CARGO_FEATURE_MSIX").is_some() {
+        println!("cargo:rerun-if-env-changed=MSIX_PUBLISHER");
+        let publisher = std::env::var("MSIX_PUBLISHER")
+            .unwrap_or_else(|_| "CN=Headset Battery Indicator".to_string());
+        format!(
+            r#"<msix xmlns="urn:schemas-microsoft-com:msix.v1" publisher="{publisher}" packageName="HeadsetBatteryIndicator" applicationId="App"/>"#
+        )
+    } else {
+        String::new()
+    };
+
     // Application manifest for dark mode support
-    res.set_manifest(r#"
+    res.set_manifest(&format!(r#"
 <assembly xmlns="urn:schemas-microsoft-com:asm.v1" manifestVersion="1.0" xmlns:asmv3="urn:schemas-microsoft-com:asm.v3">
+    {msix_identity}
     <asmv3:application>
         <asmv3:windowsSettings>
             <dpiAware xmlns="http://schemas.microsoft.com/SMI/2005/WindowsSettings">true</dpiAware>
@@ -16,7 +39,7 @@ fn main() {
     </asmv3:application>
     <compatibility xmlns="urn:schemas-microsoft-com:compatibility.v1">
         <application>
-            <supportedOS Id="{8e0f7a12-bfb3-4fe8-b9a5-48fd50a15a9a}"/>
+            <supportedOS Id="{{8e0f7a12-bfb3-4fe8-b9a5-48fd50a15a9a}}"/>
         </application>
     </compatibility>
     <dependency>
@@ -25,26 +48,131 @@ fn main() {
         </dependentAssembly>
     </dependency>
 </assembly>
-"#);
-
-    // register light mode icons (10,20,...,50)
-    for i in (10..=50).step_by(10) {
-        res.set_icon_with_id(&format!("src/icons/battery{i}.ico"), &format!("{i}"));
-        let charging_i = i + 1;
-        res.set_icon_with_id(
-            &format!("src/icons/battery{charging_i}.ico"),
-            &format!("{charging_i}"),
-        );
+"#));
+
+    // Tray icons, with the ids that `battery_res_id_for` in src/platform/tray.rs looks up
+    for (id, path) in battery_icons() {
+        res.set_icon_with_id(path.to_str().unwrap(), &id.to_string());
     }
 
-    for i in (15..=55).step_by(10) {
-        res.set_icon_with_id(&format!("src/icons/battery{i}.ico"), &format!("{i}"));
-        let charging_i = i + 1;
-        res.set_icon_with_id(
-            &format!("src/icons/battery{charging_i}.ico"),
-            &format!("{charging_i}"),
-        );
+    // Names of the performance counters, looked up by `lodctr` in the executable.
+    // The ids must match the nameID/helpID values in packaging/perfcounters.man
+    let perf_strings = [
+        (1000, "Headset Battery"),
+        (
+            1001,
+            "Battery state of the headsets found by Headset Battery Indicator. One instance per headset.",
+        ),
+        (1002, "Battery Percent"),
+        (
+            1003,
+            "Battery level of the headset in percent. 0 while the headset is turned off.",
+        ),
+        (1004, "Charging"),
+        (1005, "1 while the headset is charging, otherwise 0."),
+        (1006, "Connected"),
+        (
+            1007,
+            "1 while the headset reports its battery, 0 when it is turned off or out of range.",
+        ),
+        (1010, "Headset Battery Indicator"),
+        (1011, "Polling statistics of Headset Battery Indicator."),
+        (1012, "Polls/sec"),
+        (1013, "Rate at which the headsets are queried."),
+        (1014, "Failed Polls"),
+        (
+            1015,
+            "Number of queries that failed since the program was started.",
+        ),
+        (1016, "Devices"),
+        (1017, "Number of headsets currently found."),
+    ];
+    let mut string_table = String::from("STRINGTABLE\nBEGIN\n");
+    for (id, text) in perf_strings {
+        string_table.push_str(&format!("    {id}, \"{text}\"\n"));
     }
+    string_table.push_str("END\n");
+    res.append_rc_content(&string_table);
 
     res.compile().unwrap();
 }
+
+/// Rasterizes src/icons/battery.svg into an .ico for every level, theme and charging state.
+///
+/// The ids are 10 to 50 for the levels from empty to full, plus 5 for the black icons shown on
+/// light taskbars, plus 1 while charging. The charging icons are repeated 100 apart with the dot
+/// in the color of each other palette, in the order of `Palette::ALL` in src/palette.rs.
+fn battery_icons() -> Vec<(u16, PathBuf)> {
+    println!("cargo:rerun-if-changed=src/icons/battery.svg");
+    let template = std::fs::read_to_string("src/icons/battery.svg").unwrap();
+    let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
+
+    // Default, deuteranopia, protanopia and tritanopia
+    let charging_colors = ["#4cff4c", "#56b4e9", "#56b4e9", "#009988"];
+
+    let mut icons = Vec::new();
+    for (theme_offset, color) in [(0, "#ffffff"), (5, "#000000")] {
+        for level in 0..=4u16 {
+            let base = (level + 1) * 10 + theme_offset;
+            // Not charging, then charging with the dot in the color of each palette
+            let mut variants = vec![(base, None)];
+            for (palette, dot) in (0..).zip(charging_colors) {
+                variants.push((base + 1 + palette * 100, Some(dot)));
+            }
+            for (id, dot) in variants {
+                // An empty battery has no fill at all
+                let fill_height = if level == 0 { 0 } else { 4 + 8 * level };
+                let svg = template
+                    .replace("{color}", color)
+                    .replace("{fill_y}", &(56 - fill_height).to_string())
+                    .replace("{fill_height}", &fill_height.to_string())
+                    .replace("{charging}", if dot.is_some() { "1" } else { "0" })
+                    .replace("{charging_color}", dot.unwrap_or("none"));
+
+                let path = out_dir.join(format!("battery{id}.ico"));
+                std::fs::write(&path, ico(&svg)).unwrap();
+                icons.push((id, path));
+            }
+        }
+    }
+    icons
+}
+
+/// An .ico with a PNG of `svg` in each of `ICON_SIZES`
+fn ico(svg: &str) -> Vec<u8> {
+    use resvg::{tiny_skia, usvg};
+
+    let tree = usvg::Tree::from_str(svg, &usvg::Options::default()).unwrap();
+    let images: Vec<(u32, Vec<u8>)> = ICON_SIZES
+        .iter()
+        .map(|&size| {
+            let mut pixmap = tiny_skia::Pixmap::new(size, size).unwrap();
+            let scale = size as f32 / tree.size().width();
+            resvg::render(
+                &tree,
+                tiny_skia::Transform::from_scale(scale, scale),
+                &mut pixmap.as_mut(),
+            );
+            (size, pixmap.encode_png().unwrap())
+        })
+        .collect();
+
+    // The header, an entry for each image, then the images
+    let mut ico = vec![0, 0, 1, 0];
+    ico.extend_from_slice(&(images.len() as u16).to_le_bytes());
+    let mut offset = 6 + 16 * images.len() as u32;
+    for (size, png) in &images {
+        // Width and height, no palette, and a reserved byte
+        ico.extend_from_slice(&[*size as u8, *size as u8, 0, 0]);
+        // One plane of 32 bits per pixel
+        ico.extend_from_slice(&1u16.to_le_bytes());
+        ico.extend_from_slice(&32u16.to_le_bytes());
+        ico.extend_from_slice(&(png.len() as u32).to_le_bytes());
+        ico.extend_from_slice(&offset.to_le_bytes());
+        offset += png.len() as u32;
+    }
+    for (_, png) in &images {
+        ico.extend_from_slice(png);
+    }
+    ico
+}
@@ -0,0 +1,41 @@
+# Builds and signs the sparse package (HeadsetBatteryIndicator.msix) next to this script.
+#
+# Usage: ./packaging/build-msix.ps1 -Certificate cert.pfx -Password <password>
+#
+# The certificate subject must match the Publisher in AppxManifest.xml and the
+# MSIX_PUBLISHER environment variable used when building the executable.
+param(
+    [Parameter(Mandatory = $true)][string]$Certificate,
+    [Parameter(Mandatory = $true)][string]$Password
+)
+
+$ErrorActionPreference = "Stop"
+Add-Type -AssemblyName System.Drawing
+
+$root = $PSScriptRoot
+$staging = Join-Path $root "staging"
+$assets = Join-Path $staging "Assets"
+Remove-Item -Recurse -Force $staging -ErrorAction SilentlyContinue
+New-Item -ItemType Directory -Force $assets | Out-Null
+
+Copy-Item (Join-Path $root "AppxManifest.xml") $staging
+
+# Render the package logos from the application icon
+$icon = New-Object System.Drawing.Icon((Join-Path $root "..\src\icons\main.ico"), 256, 256)
+foreach ($logo in @(@("StoreLogo.png", 50), @("Square44x44Logo.png", 44), @("Square150x150Logo.png", 150))) {
+    $bitmap = New-Object System.Drawing.Bitmap($logo[1], $logo[1])
+    $graphics = [System.Drawing.Graphics]::FromImage($bitmap)
+    $graphics.InterpolationMode = [System.Drawing.Drawing2D.InterpolationMode]::HighQualityBicubic
+    $graphics.DrawImage($icon.ToBitmap(), 0, 0, $logo[1], $logo[1])
+    $bitmap.Save((Join-Path $assets $logo[0]), [System.Drawing.Imaging.ImageFormat]::Png)
+    $graphics.Dispose()
+    $bitmap.Dispose()
+}
+
+$sdk = Get-ChildItem -Path "C:\Program Files*\Windows Kits\*\bin\*\x64" -Directory | Sort-Object FullName | Select-Object -Last 1
+$msix = Join-Path $root "HeadsetBatteryIndicator.msix"
+
+& (Join-Path $sdk.FullName "makeappx.exe") pack /o /d $staging /nv /p $msix
+& (Join-Path $sdk.FullName "signtool.exe") sign /fd sha256 /f $Certificate /p $Password $msix
+
+Remove-Item -Recurse -Force $staging
@@ -0,0 +1,92 @@
+//! Tells screen readers like NVDA and Narrator about the battery without a toast. Low, critical
+//! and full battery are raised as UI Automation notifications from the message window, which
+//! screen readers read out like a live region changing. The tray icon's accessible name is its
+//! tooltip, which already has the level of the selected headset.
+
+use anyhow::Context;
+use log::{debug, error};
+use windows::{
+    Win32::{
+        Foundation::{BOOL, HWND},
+        UI::{
+            Accessibility::{
+                NotificationKind_Other, NotificationProcessing_ImportantMostRecent,
+                UiaHostProviderFromHwnd, UiaRaiseNotificationEvent,
+            },
+            WindowsAndMessaging::{
+                SPI_GETSCREENREADER, SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS, SystemParametersInfoW,
+            },
+        },
+    },
+    core::BSTR,
+};
+
+use crate::actions::BatteryEvent;
+use crate::headset_control::Device;
+
+/// Lets screen readers tell the announcements of this app apart from others
+const ACTIVITY_ID: &str = "HeadsetBatteryIndicator.Battery";
+
+pub struct Announcer {
+    /// The element the notifications are raised on
+    hwnd: HWND,
+}
+
+impl Announcer {
+    pub fn new(hwnd: HWND) -> Self {
+        Self { hwnd }
+    }
+
+    /// Announces low, critical and full battery while a screen reader is running, other
+    /// events are ignored
+    pub fn alert(&self, event: BatteryEvent, device: &Device) {
+        let level = device.battery.level;
+        let text = match event {
+            BatteryEvent::Low => format!("{}: Battery low ({level}%)", device.product),
+            BatteryEvent::Critical => {
+                format!("{}: Battery critical ({level}%)", device.product)
+            }
+            BatteryEvent::Full => format!("{}: Battery full", device.product),
+            _ => return,
+        };
+        if !screen_reader_running() {
+            return;
+        }
+        if let Err(e) = self.announce(&text) {
+            error!(
+                "Failed to announce {} to the screen reader: {e:?}",
+                event.name()
+            );
+        }
+    }
+
+    /// Has the screen reader read `text`, interrupting older announcements of ours
+    pub fn announce(&self, text: &str) -> anyhow::Result<()> {
+        debug!("Announcing to the screen reader: {text}");
+        unsafe {
+            let provider = UiaHostProviderFromHwnd(self.hwnd).context("UiaHostProviderFromHwnd")?;
+            UiaRaiseNotificationEvent(
+                &provider,
+                NotificationKind_Other,
+                NotificationProcessing_ImportantMostRecent,
+                &BSTR::from(text),
+                &BSTR::from(ACTIVITY_ID),
+            )
+            .context("UiaRaiseNotificationEvent")
+        }
+    }
+}
+
+/// Whether NVDA, Narrator or another screen reader says it's running
+pub fn screen_reader_running() -> bool {
+    let mut running = BOOL(0);
+    let res = unsafe {
+        SystemParametersInfoW(
+            SPI_GETSCREENREADER,
+            0,
+            Some(&mut running as *mut BOOL as *mut _),
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+        )
+    };
+    res.is_ok() && running.as_bool()
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+use std::process;
+
+use log::{error, info};
+
+use crate::events::AppEvent;
+use crate::headset_control::{Battery, BatteryState, Device};
+
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BatteryEvent {
+    Low,
+    Critical,
+    Connected,
+    Disconnected,
+    Full,
+}
+
+impl BatteryEvent {
+    pub const ALL: [BatteryEvent; 5] = [
+        BatteryEvent::Low,
+        BatteryEvent::Critical,
+        BatteryEvent::Connected,
+        BatteryEvent::Disconnected,
+        BatteryEvent::Full,
+    ];
+
+    /// Name of the registry value holding the command, also passed to the command as `HEADSET_EVENT`
+    pub fn name(self) -> &'static str {
+        match self {
+            BatteryEvent::Low => "Low",
+            BatteryEvent::Critical => "Critical",
+            BatteryEvent::Connected => "Connected",
+            BatteryEvent::Disconnected => "Disconnected",
+            BatteryEvent::Full => "Full",
+        }
+    }
+}
+
+/// Runs the user-defined commands when a device crosses one of the battery events.
+/// The events are also returned, for the other integrations to act on.
+pub fn update(
+    events: &[AppEvent],
+    commands: &HashMap<BatteryEvent, String>,
+) -> Vec<(BatteryEvent, Device)> {
+    let mut fired = vec![];
+
+    for event in events {
+        match event {
+            AppEvent::BatteryChanged { previous, device } => {
+                for battery_event in transitions(previous, &device.battery) {
+                    fired.push((battery_event, device.clone()));
+                }
+            }
+            // Plugged in, or found by the first poll
+            AppEvent::DeviceAdded(device) if is_connected(device.battery.status) => {
+                fired.push((BatteryEvent::Connected, device.clone()));
+            }
+            // The device vanished from the list entirely (e.g. dongle unplugged)
+            AppEvent::DeviceRemoved(last) if is_connected(last.battery.status) => {
+                fired.push((BatteryEvent::Disconnected, last.clone()));
+            }
+            _ => {}
+        }
+    }
+
+    for (event, device) in &fired {
+        if let Some(command) = commands.get(event) {
+            run(command, *event, device);
+        }
+    }
+
+    fired
+}
+
+fn is_connected(status: BatteryState) -> bool {
+    matches!(
+        status,
+        BatteryState::BatteryAvailable | BatteryState::BatteryCharging
+    )
+}
+
+fn transitions(last: &Battery, current: &Battery) -> Vec<BatteryEvent> {
+    let mut events = vec![];
+
+    let (last_level, current_level) = (last.level, current.level);
+    let was_connected = is_connected(last.status);
+    let now_connected = is_connected(current.status);
+
+    if !was_connected && now_connected {
+        events.push(BatteryEvent::Connected);
+    } else if was_connected && !now_connected {
+        events.push(BatteryEvent::Disconnected);
+    }
+
+    match current.status {
+        BatteryState::BatteryAvailable => {
+            if current_level <= 3 && last_level > 3 {
+                events.push(BatteryEvent::Critical);
+            } else if current_level <= 10 && last_level > 10 {
+                events.push(BatteryEvent::Low);
+            }
+        }
+        BatteryState::BatteryCharging if current_level == 100 && last_level < 100 => {
+            events.push(BatteryEvent::Full);
+        }
+        _ => {}
+    }
+
+    events
+}
+
+fn run(command: &str, event: BatteryEvent, device: &Device) {
+    info!("Running action for {} event: {command}", event.name());
+
+    let res = shell(command)
+        .env("HEADSET_EVENT", event.name())
+        .env("HEADSET_NAME", &device.product)
+        .env("HEADSET_LEVEL", device.battery.level.to_string())
+        .env("HEADSET_STATUS", format!("{:?}", device.battery.status))
+        .spawn();
+
+    if let Err(err) = res {
+        error!("Failed to run action `{command}`: {err:?}");
+    }
+}
+
+/// The command is passed to cmd as-is, so the user can use %HEADSET_LEVEL% etc. in it
+#[cfg(windows)]
+fn shell(command: &str) -> process::Command {
+    let mut shell = process::Command::new("cmd");
+    shell
+        .arg("/C")
+        .raw_arg(command)
+        .creation_flags(CREATE_NO_WINDOW);
+    shell
+}
+
+/// The command is run by `sh`, so the user can use $HEADSET_LEVEL etc. in it
+#[cfg(not(windows))]
+fn shell(command: &str) -> process::Command {
+    let mut shell = process::Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
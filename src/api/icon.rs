@@ -0,0 +1,81 @@
+//! Battery icons rendered as SVG or PNG, sized for Stream Deck keys by default
+
+use std::sync::{Arc, OnceLock};
+
+use anyhow::Context;
+use resvg::{tiny_skia, usvg};
+
+use crate::headset_control::{BatteryState, Device};
+
+pub const DEFAULT_SIZE: u32 = 72;
+pub const MIN_SIZE: u32 = 16;
+pub const MAX_SIZE: u32 = 512;
+
+const GREEN: &str = "#4caf50";
+const ORANGE: &str = "#ff9800";
+const RED: &str = "#f44336";
+const GREY: &str = "#808080";
+
+/// Fonts for the level in the PNG icons, when Segoe UI and Arial are missing
+const SANS_SERIF: [&str; 4] = ["Arial", "DejaVu Sans", "Noto Sans", "Liberation Sans"];
+
+/// Renders a battery gauge with the level below it.
+///
+/// Drawn in a 72x72 view box and scaled to `size`, with a black background
+/// so it can be used as a key image as-is.
+pub fn render_svg(device: &Device, size: u32) -> String {
+    let available = matches!(
+        device.battery.status,
+        BatteryState::BatteryAvailable | BatteryState::BatteryCharging
+    );
+    let level = device.battery.level.clamp(0, 100);
+
+    let (color, label) = match (available, level) {
+        (false, _) => (GREY, "--".to_string()),
+        (true, 0..=10) => (RED, format!("{level}%")),
+        (true, 11..=25) => (ORANGE, format!("{level}%")),
+        (true, _) => (GREEN, format!("{level}%")),
+    };
+    let outline = if available { "#ffffff" } else { GREY };
+    // The inside of the battery is 36 units wide
+    let fill_width = if available { level as f32 * 0.36 } else { 0.0 };
+
+    let bolt = if device.battery.status == BatteryState::BatteryCharging {
+        r##"<polygon points="38,14 26,29 34,29 31,40 45,24 37,24" fill="#ffeb3b" stroke="#000000" stroke-width="1.5"/>"##
+    } else {
+        ""
+    };
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 72 72"><rect width="72" height="72" fill="#000000"/><rect x="12" y="14" width="44" height="26" rx="4" fill="none" stroke="{outline}" stroke-width="3"/><rect x="57" y="22" width="4" height="10" rx="1" fill="{outline}"/><rect x="16" y="18" width="{fill_width:.1}" height="18" fill="{color}"/>{bolt}<text x="36" y="62" font-family="Segoe UI, Arial, sans-serif" font-size="18" font-weight="bold" fill="#ffffff" text-anchor="middle">{label}</text></svg>"##
+    )
+}
+
+/// The SVG rasterized to `size` pixels, for clients that can't show SVG
+pub fn render_png(device: &Device, size: u32) -> anyhow::Result<Vec<u8>> {
+    // Loading the system fonts for the level takes a while, so it's only done once
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    let fonts = FONTS.get_or_init(|| {
+        let mut fonts = usvg::fontdb::Database::new();
+        fonts.load_system_fonts();
+        // `sans-serif` means Arial, which most Linux systems don't have
+        let has_family = |family: &str| {
+            fonts
+                .faces()
+                .any(|face| face.families.iter().any(|(name, _)| name == family))
+        };
+        if let Some(family) = SANS_SERIF.into_iter().find(|&family| has_family(family)) {
+            fonts.set_sans_serif_family(family);
+        }
+        Arc::new(fonts)
+    });
+
+    let options = usvg::Options {
+        fontdb: fonts.clone(),
+        ..Default::default()
+    };
+    let tree = usvg::Tree::from_str(&render_svg(device, size), &options).context("parsing icon")?;
+    let mut pixmap = tiny_skia::Pixmap::new(size, size).context("creating icon")?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    pixmap.encode_png().context("encoding icon")
+}
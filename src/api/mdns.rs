@@ -0,0 +1,100 @@
+//! DNS-SD advertisement of the local API over multicast DNS, using the responder built
+//! into Windows 10 1809 and newer, so companion apps can find it without configuration
+
+use std::ffi::c_void;
+
+use log::{debug, error, info};
+use windows::{
+    Win32::NetworkManagement::Dns::{
+        DNS_QUERY_REQUEST_VERSION1, DNS_SERVICE_INSTANCE, DNS_SERVICE_REGISTER_REQUEST,
+        DnsServiceConstructInstance, DnsServiceDeRegister, DnsServiceFreeInstance,
+        DnsServiceRegister,
+    },
+    core::{HSTRING, PCWSTR, w},
+};
+
+pub const SERVICE_TYPE: &str = "_headset-battery._tcp.local";
+
+/// Returned when the registration continues in the background
+const DNS_REQUEST_PENDING: u32 = 9506;
+
+/// Advertises the API until dropped
+pub struct Advertisement {
+    /// Used by the registration in the background, and again for deregistering
+    request: *mut DNS_SERVICE_REGISTER_REQUEST,
+}
+
+impl Advertisement {
+    pub fn register(port: u16) -> anyhow::Result<Self> {
+        let computer = std::env::var("COMPUTERNAME").unwrap_or_else(|_| "headset".to_string());
+        let service_name = HSTRING::from(format!("{computer}.{SERVICE_TYPE}"));
+        let host_name = HSTRING::from(format!("{computer}.local"));
+
+        // TXT record, so clients know what they found before connecting
+        let version = HSTRING::from(crate::VERSION);
+        let keys = [w!("version"), w!("path")];
+        let values = [PCWSTR(version.as_ptr()), w!("/devices")];
+
+        let instance = unsafe {
+            DnsServiceConstructInstance(
+                &service_name,
+                &host_name,
+                None,
+                None,
+                port,
+                0,
+                0,
+                keys.len() as u32,
+                keys.as_ptr(),
+                values.as_ptr(),
+            )
+        };
+        if instance.is_null() {
+            anyhow::bail!("DnsServiceConstructInstance failed");
+        }
+
+        let request = Box::into_raw(Box::new(DNS_SERVICE_REGISTER_REQUEST {
+            Version: DNS_QUERY_REQUEST_VERSION1.0,
+            // All interfaces
+            InterfaceIndex: 0,
+            pServiceInstance: instance,
+            pRegisterCompletionCallback: Some(registered),
+            ..Default::default()
+        }));
+
+        let status = unsafe { DnsServiceRegister(request, None) };
+        if status != DNS_REQUEST_PENDING {
+            unsafe {
+                DnsServiceFreeInstance(instance);
+                drop(Box::from_raw(request));
+            }
+            anyhow::bail!("DnsServiceRegister failed with {status}");
+        }
+
+        info!("Advertising local API as {service_name}");
+        Ok(Self { request })
+    }
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        // The deregistration finishes in the background and still uses the request
+        // and the instance, so neither is freed
+        unsafe { DnsServiceDeRegister(self.request, None) };
+    }
+}
+
+unsafe extern "system" fn registered(
+    status: u32,
+    _context: *const c_void,
+    instance: *const DNS_SERVICE_INSTANCE,
+) {
+    if status == 0 {
+        debug!("mDNS registration updated");
+    } else {
+        error!("mDNS registration failed with {status}");
+    }
+    if !instance.is_null() {
+        unsafe { DnsServiceFreeInstance(instance) };
+    }
+}
@@ -0,0 +1,956 @@
+use std::io::{BufRead, BufReader, Read, Take, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock, mpsc};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use log::{debug, error, info};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::events::AppEvent;
+use crate::headset_control::{BatteryState, Device};
+
+mod icon;
+#[cfg(windows)]
+mod mdns;
+mod websocket;
+
+pub const DEFAULT_PORT: u16 = 5838;
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_HEADER_SIZE: usize = 8 * 1024;
+/// Each connection has its own thread, clients past this many get a 503
+const MAX_CONNECTIONS: usize = 32;
+
+const JSON: &str = "application/json";
+const SVG: &str = "image/svg+xml";
+const PNG: &str = "image/png";
+const HTML: &str = "text/html; charset=utf-8";
+
+/// Status page for OBS browser sources, updated over the WebSocket
+const OBS_PAGE: &str = include_str!("obs.html");
+
+/// Opt-in HTTP server serving the device state as JSON, on localhost unless
+/// another bind address is configured.
+///
+/// Runs on its own thread and only reads the snapshot published after each poll,
+/// so requests never wait for headsetcontrol.
+/// WebSocket clients on `/ws` are pushed an event whenever the devices change.
+pub struct ApiServer {
+    snapshot: Arc<RwLock<Snapshot>>,
+    subscribers: Subscribers,
+    stop: Arc<AtomicBool>,
+    addr: SocketAddr,
+    /// Set when the API is advertised on the network
+    #[cfg(windows)]
+    _mdns: Option<mdns::Advertisement>,
+}
+
+/// Channels to the threads serving WebSocket connections
+type Subscribers = Arc<Mutex<Vec<mpsc::Sender<String>>>>;
+
+/// Who may use the API
+struct Access {
+    token: Option<String>,
+    /// Origins of the web pages that may read the API from a browser
+    allowed_origins: Vec<String>,
+    addr: SocketAddr,
+}
+
+#[derive(Default)]
+struct Snapshot {
+    devices: Vec<Device>,
+    selected: usize,
+    updated: Option<SystemTime>,
+}
+
+/// A device as served by the API and the named pipe
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DeviceResponse {
+    pub id: String,
+    pub name: String,
+    pub selected: bool,
+    pub battery: BatteryResponse,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BatteryResponse {
+    pub level: isize,
+    pub status: BatteryState,
+    pub charging: bool,
+}
+
+/// Messages pushed to WebSocket clients
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum PushEvent {
+    /// Sent on connect and whenever a device is added or removed
+    Devices { devices: Vec<DeviceResponse> },
+    /// The battery level or status of a device changed
+    BatteryChanged { device: DeviceResponse },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatusResponse {
+    version: &'static str,
+    device_count: usize,
+    selected_device: Option<String>,
+    /// Unix timestamp of the last poll, in seconds
+    last_update: Option<u64>,
+}
+
+impl ApiServer {
+    /// Clients have to send `token` when it's set, which is required
+    /// when listening on other addresses than loopback.
+    /// Browsers only let the pages in `allowed_origins` read the responses.
+    pub fn start(
+        addr: SocketAddr,
+        token: Option<String>,
+        allowed_origins: Vec<String>,
+        advertise: bool,
+    ) -> anyhow::Result<Self> {
+        if !addr.ip().is_loopback() && token.is_none() {
+            anyhow::bail!("an API token is required to listen on {}", addr.ip());
+        }
+
+        let listener = TcpListener::bind(addr).with_context(|| format!("binding to {addr}"))?;
+        let addr = listener.local_addr().context("getting local address")?;
+        let access = Arc::new(Access {
+            token,
+            allowed_origins,
+            addr,
+        });
+
+        let snapshot = Arc::new(RwLock::new(Snapshot::default()));
+        let subscribers = Subscribers::default();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        {
+            let snapshot = snapshot.clone();
+            let subscribers = subscribers.clone();
+            let stop = stop.clone();
+            let access = access.clone();
+            thread::Builder::new()
+                .name("api-server".to_string())
+                .spawn(move || accept_loop(listener, snapshot, subscribers, access, stop))
+                .context("spawning API server thread")?;
+        }
+
+        let with_token = access.token.as_ref().map_or("", |_| " with token");
+        info!("Local API listening on http://{addr}{with_token}");
+
+        // Other machines couldn't reach what's advertised
+        if advertise && addr.ip().is_loopback() {
+            log::warn!("Not advertising the local API with mDNS, it only listens on loopback");
+        }
+        let advertise = advertise && !addr.ip().is_loopback();
+        #[cfg(not(windows))]
+        if advertise {
+            log::warn!("Advertising the local API with mDNS is only supported on Windows");
+        }
+        #[cfg(windows)]
+        let mdns = advertise
+            .then(|| {
+                mdns::Advertisement::register(addr.port())
+                    .inspect_err(|e| error!("Failed to advertise local API: {e:?}"))
+                    .ok()
+            })
+            .flatten();
+
+        Ok(Self {
+            snapshot,
+            subscribers,
+            stop,
+            addr,
+            #[cfg(windows)]
+            _mdns: mdns,
+        })
+    }
+
+    /// Makes the latest poll result available to clients,
+    /// and pushes what changed since the last poll to WebSocket clients
+    pub fn publish(&self, devices: &[Device], selected: usize, events: &[AppEvent]) {
+        let events = {
+            let mut snapshot = self.snapshot.write().unwrap_or_else(|e| e.into_inner());
+            snapshot.devices = devices.to_vec();
+            let selection_changed = snapshot.selected != selected;
+            snapshot.selected = selected;
+            snapshot.updated = Some(SystemTime::now());
+
+            push_events(events, selection_changed, &snapshot)
+        };
+
+        if events.is_empty() {
+            return;
+        }
+
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        for event in events {
+            let message = match serde_json::to_string(&event) {
+                Ok(message) => message,
+                Err(e) => {
+                    error!("Failed to serialize push event: {e:?}");
+                    continue;
+                }
+            };
+            // Sending fails once the connection thread has exited
+            subscribers.retain(|subscriber| subscriber.send(message.clone()).is_ok());
+        }
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Closing the channels ends the WebSocket connections
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        // Wake up the blocking accept() so the thread sees the stop flag
+        let _ = TcpStream::connect(wake_addr(self.addr));
+        info!("Local API stopped");
+    }
+}
+
+/// What to push to WebSocket clients for the changes since the previous poll
+fn push_events(
+    events: &[AppEvent],
+    selection_changed: bool,
+    snapshot: &Snapshot,
+) -> Vec<PushEvent> {
+    let devices_changed = events
+        .iter()
+        .any(|event| matches!(event, AppEvent::DeviceAdded(_) | AppEvent::DeviceRemoved(_)));
+    if devices_changed || selection_changed {
+        return vec![devices_event(snapshot)];
+    }
+
+    let selected_id = snapshot.devices.get(snapshot.selected).map(Device::id);
+    events
+        .iter()
+        .filter_map(|event| match event {
+            AppEvent::BatteryChanged { device, .. } => Some(PushEvent::BatteryChanged {
+                device: device_response(device, selected_id == Some(device.id())),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn devices_event(snapshot: &Snapshot) -> PushEvent {
+    PushEvent::Devices {
+        devices: device_list(&snapshot.devices, snapshot.selected),
+    }
+}
+
+/// Where to connect to reach the listener, which can't be the unspecified address
+fn wake_addr(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => (Ipv4Addr::LOCALHOST, addr.port()).into(),
+        IpAddr::V6(ip) if ip.is_unspecified() => (Ipv6Addr::LOCALHOST, addr.port()).into(),
+        _ => addr,
+    }
+}
+
+fn accept_loop(
+    listener: TcpListener,
+    snapshot: Arc<RwLock<Snapshot>>,
+    subscribers: Subscribers,
+    access: Arc<Access>,
+    stop: Arc<AtomicBool>,
+) {
+    let open = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+
+        match stream {
+            Ok(mut stream) => {
+                let Some(connection) = Connection::open(&open) else {
+                    debug!("Rejecting API connection, {MAX_CONNECTIONS} are open already");
+                    let body = error_body("too many connections");
+                    let _ = write_response(&mut stream, 503, JSON, body.as_bytes(), None);
+                    continue;
+                };
+                let snapshot = snapshot.clone();
+                let subscribers = subscribers.clone();
+                let access = access.clone();
+                // Requests are tiny and rare, a thread per connection is plenty
+                let res = thread::Builder::new()
+                    .name("api-connection".to_string())
+                    .spawn(move || {
+                        let _connection = connection;
+                        if let Err(e) = handle_connection(stream, &snapshot, &subscribers, &access)
+                        {
+                            debug!("API connection failed: {e:?}");
+                        }
+                    });
+                if let Err(e) = res {
+                    error!("Failed to spawn API connection thread: {e:?}");
+                }
+            }
+            Err(e) => debug!("Failed to accept API connection: {e:?}"),
+        }
+    }
+}
+
+/// Counted as open until it's dropped, when its thread ends
+struct Connection(Arc<AtomicUsize>);
+
+impl Connection {
+    /// `None` when `MAX_CONNECTIONS` are open already
+    fn open(open: &Arc<AtomicUsize>) -> Option<Self> {
+        open.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+            (count < MAX_CONNECTIONS).then_some(count + 1)
+        })
+        .ok()?;
+        Some(Self(open.clone()))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    /// Header names are lowercase
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The percent-decoded value, `None` when it's missing or can't be decoded
+    fn query_param(&self, name: &str) -> Option<String> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .and_then(|(_, value)| percent_decode(value))
+    }
+
+    /// Whether the request has the token, either as a bearer token or as the `token`
+    /// query parameter for clients that can't set headers, like browser sources
+    fn is_authorized(&self, token: Option<&str>) -> bool {
+        let Some(token) = token else {
+            return true;
+        };
+        self.header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::to_string)
+            .or_else(|| self.query_param("token"))
+            .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()))
+    }
+}
+
+/// Decodes the `%XX` escapes in a URL, like the ones `encodeURIComponent` makes.
+/// `None` when an escape is invalid or the result isn't UTF-8.
+fn percent_decode(value: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail
+                .get(..2)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))?;
+            decoded.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            decoded.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+impl Access {
+    /// Whether the request was sent to this PC by name. Another name on a loopback address means
+    /// a web page made its own domain resolve to 127.0.0.1 to get around the browser's
+    /// same-origin policy. Other addresses require the token, which such a page doesn't have.
+    fn allows_host(&self, request: &Request) -> bool {
+        !self.addr.ip().is_loopback()
+            || request
+                .header("host")
+                .is_none_or(|host| self.is_local(host_name(host)))
+    }
+
+    /// Whether a web page at `origin` may open WebSockets and send other requests than GET,
+    /// which browsers send without asking the API first. Besides the allowed origins, that's
+    /// only the API's own pages like /obs, not other pages on this PC like a dev server.
+    fn allows_origin(&self, request: &Request, origin: &str) -> bool {
+        self.is_allowed_origin(origin)
+            || origin.strip_prefix("http://").is_some_and(|host| {
+                request
+                    .header("host")
+                    .is_some_and(|requested| requested.eq_ignore_ascii_case(host))
+                    || (self.is_local(host_name(host)) && host_port(host) == Some(self.addr.port()))
+            })
+    }
+
+    fn is_allowed_origin(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(origin))
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        name.eq_ignore_ascii_case("localhost")
+            || name
+                .parse::<IpAddr>()
+                .is_ok_and(|ip| ip.is_loopback() || ip == self.addr.ip())
+    }
+}
+
+/// The host name or address of a `Host` header or an origin, without the port
+fn host_name(host: &str) -> &str {
+    if let Some(ipv6) = host.strip_prefix('[') {
+        return ipv6.split_once(']').map_or(ipv6, |(ip, _)| ip);
+    }
+    host.split_once(':').map_or(host, |(name, _)| name)
+}
+
+/// The port of a `Host` header or an origin, which is 80 when it's left out
+fn host_port(host: &str) -> Option<u16> {
+    let port = match host.rsplit_once(']') {
+        Some((_, rest)) => rest.strip_prefix(':'),
+        None => host.split_once(':').map(|(_, port)| port),
+    };
+    port.map_or(Some(80), |port| port.parse().ok())
+}
+
+/// Compares without returning early, so the time taken doesn't reveal how much of the token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The request line and headers together were longer than `MAX_HEADER_SIZE`
+#[derive(Debug)]
+struct HeadersTooLarge;
+
+impl std::fmt::Display for HeadersTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "request headers are larger than {MAX_HEADER_SIZE} bytes")
+    }
+}
+
+impl std::error::Error for HeadersTooLarge {}
+
+fn read_request(stream: &TcpStream) -> anyhow::Result<Request> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    // Nothing is read past the limit, even when the client never ends the line
+    let mut reader = BufReader::new(stream.take(MAX_HEADER_SIZE as u64));
+
+    let request_line = read_line(&mut reader)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().context("missing method")?.to_string();
+    let target = parts.next().context("missing path")?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+
+    let mut headers = vec![];
+    loop {
+        let line = read_line(&mut reader)?;
+        if line.is_empty() || line == "\r\n" || line == "\n" {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    Ok(Request {
+        method,
+        path,
+        query,
+        headers,
+    })
+}
+
+/// Empty at the end of the stream, fails with `HeadersTooLarge` when the limit cuts a line off
+fn read_line(reader: &mut BufReader<Take<&TcpStream>>) -> anyhow::Result<String> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.ends_with('\n') && reader.get_ref().limit() == 0 {
+        return Err(HeadersTooLarge.into());
+    }
+    Ok(line)
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    snapshot: &RwLock<Snapshot>,
+    subscribers: &Mutex<Vec<mpsc::Sender<String>>>,
+    access: &Access,
+) -> anyhow::Result<()> {
+    let request = match read_request(&stream) {
+        Ok(request) => request,
+        Err(e) if e.is::<HeadersTooLarge>() => {
+            debug!("Rejecting API request: {e}");
+            let body = error_body("request headers too large");
+            return write_response(&mut stream, 431, JSON, body.as_bytes(), None);
+        }
+        Err(e) => return Err(e),
+    };
+    debug!("API request: {} {}", request.method, request.path);
+
+    let origin = request.header("origin");
+    // Only the allowed pages get to read the responses in a browser
+    let cors = origin.filter(|origin| access.is_allowed_origin(origin));
+
+    if !access.allows_host(&request) {
+        debug!(
+            "Rejecting API request for host {:?}",
+            request.header("host")
+        );
+        let body = error_body("forbidden host");
+        return write_response(&mut stream, 403, JSON, body.as_bytes(), None);
+    }
+    if (request.method != "GET" || request.path == "/ws")
+        && let Some(origin) = origin
+        && !access.allows_origin(&request, origin)
+    {
+        debug!("Rejecting API request from {origin}");
+        let body = error_body("forbidden origin");
+        return write_response(&mut stream, 403, JSON, body.as_bytes(), None);
+    }
+
+    if !request.is_authorized(access.token.as_deref()) {
+        debug!("Rejecting API request without a valid token");
+        let body = error_body("unauthorized");
+        return write_response(&mut stream, 401, JSON, body.as_bytes(), cors);
+    }
+
+    if request.method == "GET" && request.path == "/ws" && websocket::is_upgrade(&request) {
+        // The connection stays open, so the read timeout no longer applies
+        stream.set_read_timeout(None)?;
+
+        let (sender, receiver) = mpsc::channel();
+        // Subscribe before taking the initial state, so no change can fall in between
+        subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(sender);
+        let initial = {
+            let snapshot = snapshot.read().unwrap_or_else(|e| e.into_inner());
+            serde_json::to_string(&devices_event(&snapshot))?
+        };
+
+        return websocket::serve(stream, &request, initial, receiver);
+    }
+
+    let (status, content_type, body) = if request.method != "GET" {
+        (405, JSON, error_body("method not allowed").into_bytes())
+    } else {
+        let snapshot = snapshot.read().unwrap_or_else(|e| e.into_inner());
+        route(&request, &snapshot)
+    };
+
+    write_response(&mut stream, status, content_type, &body, cors)
+}
+
+fn route(request: &Request, snapshot: &Snapshot) -> (u16, &'static str, Vec<u8>) {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+
+    let body = match segments.as_slice() {
+        ["status"] => serde_json::to_string(&StatusResponse {
+            version: crate::VERSION,
+            device_count: snapshot.devices.len(),
+            selected_device: snapshot.devices.get(snapshot.selected).map(Device::id),
+            last_update: snapshot.updated.and_then(|t| {
+                t.duration_since(SystemTime::UNIX_EPOCH)
+                    .ok()
+                    .map(|d| d.as_secs())
+            }),
+        }),
+        ["devices"] => serde_json::to_string(&device_list(&snapshot.devices, snapshot.selected)),
+        ["devices", id, "battery"] => match find_device(snapshot, id) {
+            Some(device) => serde_json::to_string(&battery_response(device)),
+            None => return (404, JSON, error_body("device not found").into_bytes()),
+        },
+        ["devices", id, "icon.svg"] => match find_device(snapshot, id) {
+            Some(device) => {
+                let svg = icon::render_svg(device, icon_size(request));
+                return (200, SVG, svg.into_bytes());
+            }
+            None => return (404, JSON, error_body("device not found").into_bytes()),
+        },
+        ["devices", id, "icon.png"] => match find_device(snapshot, id) {
+            Some(device) => match icon::render_png(device, icon_size(request)) {
+                Ok(png) => return (200, PNG, png),
+                Err(e) => return (500, JSON, error_body(&format!("{e:#}")).into_bytes()),
+            },
+            None => return (404, JSON, error_body("device not found").into_bytes()),
+        },
+        ["devices", id] => match find_device(snapshot, id) {
+            Some(device) => {
+                let selected = snapshot.devices.get(snapshot.selected) == Some(device);
+                serde_json::to_string(&device_response(device, selected))
+            }
+            None => return (404, JSON, error_body("device not found").into_bytes()),
+        },
+        ["obs"] => return (200, HTML, OBS_PAGE.as_bytes().to_vec()),
+        _ => return (404, JSON, error_body("not found").into_bytes()),
+    };
+
+    match body {
+        Ok(body) => (200, JSON, body.into_bytes()),
+        Err(e) => (500, JSON, error_body(&e.to_string()).into_bytes()),
+    }
+}
+
+/// `?size=` clamped to what's sensible for a key image
+fn icon_size(request: &Request) -> u32 {
+    request
+        .query_param("size")
+        .and_then(|size| size.parse::<u32>().ok())
+        .unwrap_or(icon::DEFAULT_SIZE)
+        .clamp(icon::MIN_SIZE, icon::MAX_SIZE)
+}
+
+/// Finds a device by its id, its index in the device list,
+/// or `selected` for the device shown in the tray
+fn find_device<'a>(snapshot: &'a Snapshot, id: &str) -> Option<&'a Device> {
+    if id == "selected" {
+        return snapshot.devices.get(snapshot.selected);
+    }
+
+    let id = percent_decode(id)?;
+    snapshot
+        .devices
+        .iter()
+        .find(|device| device.id().eq_ignore_ascii_case(&id))
+        .or_else(|| snapshot.devices.get(id.parse::<usize>().ok()?))
+}
+
+pub(crate) fn device_list(devices: &[Device], selected: usize) -> Vec<DeviceResponse> {
+    devices
+        .iter()
+        .enumerate()
+        .map(|(i, device)| device_response(device, i == selected))
+        .collect()
+}
+
+fn device_response(device: &Device, selected: bool) -> DeviceResponse {
+    DeviceResponse {
+        id: device.id(),
+        name: device.product.clone(),
+        selected,
+        battery: battery_response(device),
+    }
+}
+
+fn battery_response(device: &Device) -> BatteryResponse {
+    BatteryResponse {
+        level: device.battery.level,
+        status: device.battery.status,
+        charging: device.battery.status == BatteryState::BatteryCharging,
+    }
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+fn write_response(
+    stream: &mut TcpStream,
+    status: u16,
+    content_type: &str,
+    body: &[u8],
+    allow_origin: Option<&str>,
+) -> anyhow::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {status} {reason}\r\n\
+         Content-Type: {content_type}\r\n\
+         Content-Length: {}\r\n",
+        body.len()
+    )?;
+    if let Some(origin) = allow_origin {
+        write!(
+            stream,
+            "Access-Control-Allow-Origin: {origin}\r\n\
+             Vary: Origin\r\n"
+        )?;
+    }
+    write!(
+        stream,
+        "Cache-Control: no-store\r\n\
+         Connection: close\r\n\r\n"
+    )?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headset_control::Battery;
+
+    const TOKEN: &str = "s3cret";
+
+    fn access(addr: &str, token: Option<&str>) -> Access {
+        Access {
+            token: token.map(str::to_string),
+            allowed_origins: vec!["https://deck.example".to_string()],
+            addr: addr.parse().unwrap(),
+        }
+    }
+
+    fn request(method: &str, target: &str, headers: &[(&str, &str)]) -> Request {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query.to_string(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    fn snapshot() -> Snapshot {
+        let device = |id_product: &str, product: &str, level| Device {
+            product: product.to_string(),
+            id_vendor: "0x1038".to_string(),
+            id_product: id_product.to_string(),
+            battery: Battery {
+                status: BatteryState::BatteryAvailable,
+                level,
+            },
+            ..Default::default()
+        };
+        Snapshot {
+            devices: vec![
+                device("0x12ad", "Arctis 7", 80),
+                device("0x2202", "Arctis Nova 7", 35),
+            ],
+            selected: 1,
+            updated: None,
+        }
+    }
+
+    fn json(body: &[u8]) -> serde_json::Value {
+        serde_json::from_slice(body).unwrap()
+    }
+
+    #[test]
+    fn connections_are_limited() {
+        let open = Arc::new(AtomicUsize::new(0));
+        let mut connections: Vec<_> = (0..MAX_CONNECTIONS)
+            .map(|_| Connection::open(&open).unwrap())
+            .collect();
+        assert!(Connection::open(&open).is_none());
+        connections.pop();
+        assert_eq!(open.load(Ordering::Relaxed), MAX_CONNECTIONS - 1);
+        assert!(Connection::open(&open).is_some());
+        drop(connections);
+        assert_eq!(open.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn host_must_be_local_on_loopback() {
+        let access = access("127.0.0.1:5838", None);
+        for host in [
+            "localhost:5838",
+            "127.0.0.1:5838",
+            "[::1]:5838",
+            "LOCALHOST",
+        ] {
+            assert!(
+                access.allows_host(&request("GET", "/", &[("host", host)])),
+                "{host}"
+            );
+        }
+        assert!(access.allows_host(&request("GET", "/", &[])));
+        assert!(!access.allows_host(&request("GET", "/", &[("host", "evil.example:5838")])));
+    }
+
+    #[test]
+    fn any_host_on_other_addresses() {
+        let access = access("192.168.1.5:5838", Some(TOKEN));
+        let request = request("GET", "/", &[("host", "headset-pc.local:5838")]);
+        assert!(access.allows_host(&request));
+    }
+
+    #[test]
+    fn own_and_allowed_origins() {
+        let access = access("127.0.0.1:5838", None);
+        let request = request("GET", "/ws", &[("host", "localhost:5838")]);
+        for origin in [
+            "http://localhost:5838",
+            "http://127.0.0.1:5838",
+            "http://[::1]:5838",
+            "https://deck.example",
+        ] {
+            assert!(access.allows_origin(&request, origin), "{origin}");
+        }
+    }
+
+    #[test]
+    fn other_local_pages_are_not_allowed() {
+        let access = access("127.0.0.1:5838", None);
+        let request = request("GET", "/ws", &[("host", "localhost:5838")]);
+        for origin in [
+            "http://localhost:3000",
+            "http://127.0.0.1",
+            "https://localhost:5838",
+            "http://evil.example:5838",
+            "https://deck.example.evil",
+            "null",
+        ] {
+            assert!(!access.allows_origin(&request, origin), "{origin}");
+        }
+    }
+
+    #[test]
+    fn page_served_to_another_pc() {
+        let access = access("0.0.0.0:5838", Some(TOKEN));
+        let request = request("GET", "/ws", &[("host", "192.168.1.5:5838")]);
+        assert!(access.allows_origin(&request, "http://192.168.1.5:5838"));
+        assert!(!access.allows_origin(&request, "http://192.168.1.6:5838"));
+    }
+
+    #[test]
+    fn host_names_and_ports() {
+        assert_eq!(host_name("localhost:5838"), "localhost");
+        assert_eq!(host_name("[::1]:5838"), "::1");
+        assert_eq!(host_name("127.0.0.1"), "127.0.0.1");
+        assert_eq!(host_port("localhost:5838"), Some(5838));
+        assert_eq!(host_port("[::1]:5838"), Some(5838));
+        assert_eq!(host_port("[::1]"), Some(80));
+        assert_eq!(host_port("localhost"), Some(80));
+        assert_eq!(host_port("localhost:http"), None);
+    }
+
+    #[test]
+    fn everyone_is_authorized_without_a_token() {
+        assert!(request("GET", "/devices", &[]).is_authorized(None));
+    }
+
+    #[test]
+    fn token_as_header_or_query_parameter() {
+        let bearer = format!("Bearer {TOKEN}");
+        let header = request("GET", "/devices", &[("authorization", &bearer)]);
+        assert!(header.is_authorized(Some(TOKEN)));
+        let query = request("GET", "/obs?size=64&token=s3cret", &[]);
+        assert!(query.is_authorized(Some(TOKEN)));
+        let encoded = request("GET", "/obs?token=s3%63ret", &[]);
+        assert!(encoded.is_authorized(Some(TOKEN)));
+    }
+
+    #[test]
+    fn wrong_or_missing_token() {
+        for request in [
+            request("GET", "/devices", &[]),
+            request("GET", "/devices", &[("authorization", "Bearer s3cre")]),
+            request("GET", "/devices", &[("authorization", "Basic s3cret")]),
+            request("GET", "/devices?token=s3cret2", &[]),
+            request("GET", "/devices?token=%zz", &[]),
+        ] {
+            assert!(!request.is_authorized(Some(TOKEN)), "{:?}", request.query);
+        }
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("plain").as_deref(), Some("plain"));
+        assert_eq!(percent_decode("1038%3A12ad").as_deref(), Some("1038:12ad"));
+        assert_eq!(percent_decode("%C3%A4%c3%a4").as_deref(), Some("ää"));
+        assert_eq!(percent_decode("100%"), None);
+        assert_eq!(percent_decode("%4"), None);
+        assert_eq!(percent_decode("%zz"), None);
+        // Not UTF-8
+        assert_eq!(percent_decode("%ff"), None);
+    }
+
+    #[test]
+    fn status_route() {
+        let (status, content_type, body) = route(&request("GET", "/status", &[]), &snapshot());
+        assert_eq!((status, content_type), (200, JSON));
+        let body = json(&body);
+        assert_eq!(body["deviceCount"], 2);
+        assert_eq!(body["selectedDevice"], "1038:2202");
+    }
+
+    #[test]
+    fn devices_route() {
+        let (status, _, body) = route(&request("GET", "/devices/", &[]), &snapshot());
+        assert_eq!(status, 200);
+        let body = json(&body);
+        assert_eq!(body[0]["name"], "Arctis 7");
+        assert_eq!(body[0]["selected"], false);
+        assert_eq!(body[1]["selected"], true);
+    }
+
+    #[test]
+    fn device_by_id_index_or_selected() {
+        let snapshot = snapshot();
+        for path in ["/devices/1038:12ad", "/devices/1038%3A12AD", "/devices/0"] {
+            let (status, _, body) = route(&request("GET", path, &[]), &snapshot);
+            assert_eq!(status, 200, "{path}");
+            assert_eq!(json(&body)["name"], "Arctis 7", "{path}");
+        }
+        let (_, _, body) = route(&request("GET", "/devices/selected", &[]), &snapshot);
+        assert_eq!(json(&body)["name"], "Arctis Nova 7");
+        let (_, _, body) = route(&request("GET", "/devices/0/battery", &[]), &snapshot);
+        assert_eq!(json(&body)["level"], 80);
+    }
+
+    #[test]
+    fn icons_and_obs_page() {
+        let snapshot = snapshot();
+        let (status, content_type, _) =
+            route(&request("GET", "/devices/0/icon.svg", &[]), &snapshot);
+        assert_eq!((status, content_type), (200, SVG));
+        let (status, content_type, _) = route(&request("GET", "/obs", &[]), &snapshot);
+        assert_eq!((status, content_type), (200, HTML));
+    }
+
+    #[test]
+    fn unknown_routes_and_devices() {
+        let snapshot = snapshot();
+        for path in [
+            "/",
+            "/device",
+            "/devices/2",
+            "/devices/1038:0000/battery",
+            "/devices/0/icon.gif",
+            "/status/extra",
+        ] {
+            let (status, content_type, _) = route(&request("GET", path, &[]), &snapshot);
+            assert_eq!((status, content_type), (404, JSON), "{path}");
+        }
+    }
+}
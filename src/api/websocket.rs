@@ -0,0 +1,138 @@
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+use anyhow::Context;
+use log::debug;
+
+use super::Request;
+
+/// Appended to the client's key to compute Sec-WebSocket-Accept (RFC 6455)
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Pings keep proxies from dropping idle connections and detect closed clients
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+
+pub fn is_upgrade(request: &Request) -> bool {
+    request
+        .header("upgrade")
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+/// Completes the handshake and forwards the messages from `events` to the client
+/// until either side goes away.
+pub fn serve(
+    mut stream: TcpStream,
+    request: &Request,
+    initial: String,
+    events: mpsc::Receiver<String>,
+) -> anyhow::Result<()> {
+    let key = request
+        .header("sec-websocket-key")
+        .context("missing Sec-WebSocket-Key")?;
+    let accept = accept_key(key);
+
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {accept}\r\n\r\n"
+    )?;
+
+    write_frame(&mut stream, OPCODE_TEXT, initial.as_bytes())?;
+
+    loop {
+        match events.recv_timeout(PING_INTERVAL) {
+            Ok(message) => write_frame(&mut stream, OPCODE_TEXT, message.as_bytes())?,
+            Err(RecvTimeoutError::Timeout) => write_frame(&mut stream, OPCODE_PING, &[])?,
+            Err(RecvTimeoutError::Disconnected) => {
+                // The server is shutting down
+                let _ = write_frame(&mut stream, OPCODE_CLOSE, &1001u16.to_be_bytes());
+                debug!("Closing WebSocket connection");
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// The Sec-WebSocket-Accept for the client's Sec-WebSocket-Key
+fn accept_key(key: &str) -> String {
+    base64(
+        &sha1_smol::Sha1::from(format!("{key}{HANDSHAKE_GUID}"))
+            .digest()
+            .bytes(),
+    )
+}
+
+/// Writes a single unmasked frame, as sent by servers
+fn write_frame(stream: &mut TcpStream, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_key_from_rfc_6455() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(&[0xfb, 0xff]), "+/8=");
+    }
+}
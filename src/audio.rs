@@ -0,0 +1,103 @@
+//! Which audio endpoints something is playing or recording on, through the Core Audio API
+
+use anyhow::Context;
+use windows::Win32::{
+    Devices::FunctionDiscovery::PKEY_Device_FriendlyName,
+    Media::Audio::{
+        AudioSessionStateActive, DEVICE_STATE_ACTIVE, IAudioSessionManager2, IMMDevice,
+        IMMDeviceEnumerator, MMDeviceEnumerator, eAll, eCapture, eCommunications, eConsole,
+        eRender,
+    },
+    System::Com::{
+        CLSCTX_ALL, COINIT_APARTMENTTHREADED, CoCreateInstance, CoInitializeEx, CoTaskMemFree,
+        STGM_READ,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    /// Like "Headphones (Arctis Nova 7)"
+    pub name: String,
+    /// Whether an app has an active audio session on it
+    pub active: bool,
+    /// Whether it's the default playback device
+    pub default: bool,
+}
+
+/// The playback and recording endpoints that are plugged in
+pub fn endpoints() -> anyhow::Result<Vec<Endpoint>> {
+    unsafe {
+        // Fails when COM is already set up differently on this thread, which works just as well
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)
+                .context("creating audio device enumerator")?;
+        let default_id = enumerator
+            .GetDefaultAudioEndpoint(eRender, eConsole)
+            .ok()
+            .and_then(|device| id(&device));
+
+        let collection = enumerator
+            .EnumAudioEndpoints(eAll, DEVICE_STATE_ACTIVE)
+            .context("enumerating audio endpoints")?;
+        let mut endpoints = vec![];
+        for i in 0..collection.GetCount()? {
+            let device = collection.Item(i)?;
+            let name = device
+                .OpenPropertyStore(STGM_READ)
+                .and_then(|store| store.GetValue(&PKEY_Device_FriendlyName))
+                .map(|name| name.to_string())
+                .unwrap_or_default();
+            endpoints.push(Endpoint {
+                name,
+                active: has_active_session(&device).unwrap_or_default(),
+                default: default_id.is_some() && id(&device) == default_id,
+            });
+        }
+        Ok(endpoints)
+    }
+}
+
+/// Whether an app is recording from the default microphone, like OBS, or from the default
+/// communications one, like Discord and Teams in a call
+pub fn microphone_in_use() -> anyhow::Result<bool> {
+    unsafe {
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)
+                .context("creating audio device enumerator")?;
+        for role in [eConsole, eCommunications] {
+            // Fails when there's no microphone at all
+            if let Ok(device) = enumerator.GetDefaultAudioEndpoint(eCapture, role)
+                && has_active_session(&device).context("checking audio sessions")?
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+unsafe fn has_active_session(device: &IMMDevice) -> windows::core::Result<bool> {
+    unsafe {
+        let manager: IAudioSessionManager2 = device.Activate(CLSCTX_ALL, None)?;
+        let sessions = manager.GetSessionEnumerator()?;
+        for i in 0..sessions.GetCount()? {
+            if sessions.GetSession(i)?.GetState()? == AudioSessionStateActive {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+unsafe fn id(device: &IMMDevice) -> Option<String> {
+    unsafe {
+        let id = device.GetId().ok()?;
+        let string = id.to_string().ok();
+        CoTaskMemFree(Some(id.0 as _));
+        string
+    }
+}
@@ -0,0 +1,100 @@
+//! `--bench-polls N` polls every backend N times, prints how long the polls took and how much
+//! they allocated, and exits, so slower queries show up before a release.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use crate::cli::attach_console;
+use crate::headset_control;
+use crate::settings::Settings;
+
+pub const BENCH_POLLS_ARG: &str = "--bench-polls";
+
+const DEFAULT_POLLS: usize = 10;
+
+/// Counts the allocations of every thread, since the queries read their output on another one
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// `polls` is the argument after `--bench-polls`, 10 when it's missing
+pub fn bench_polls(polls: Option<&str>) -> anyhow::Result<()> {
+    attach_console();
+
+    let polls = match polls {
+        Some(polls) if !polls.starts_with("--") => polls
+            .parse()
+            .ok()
+            .filter(|&polls: &usize| polls > 0)
+            .with_context(|| format!("{BENCH_POLLS_ARG} takes a number of polls: {polls}"))?,
+        _ => DEFAULT_POLLS,
+    };
+
+    let headsetcontrol = Settings::load()
+        .map(|settings| settings.headsetcontrol)
+        .unwrap_or_default();
+    // Each on its own, without the watchdog's skipping
+    for mut backend in headset_control::backends(&headsetcontrol, false) {
+        let name = backend.name();
+        let mut times = Vec::with_capacity(polls);
+        let mut allocations = 0;
+        let mut failed = 0;
+        let mut last_error = None;
+        let mut devices = 0;
+
+        for _ in 0..polls {
+            let allocated = ALLOCATIONS.load(Ordering::Relaxed);
+            let started = Instant::now();
+            let mut found = Vec::new();
+            let result = backend.poll(&mut found);
+            times.push(started.elapsed());
+            allocations += ALLOCATIONS.load(Ordering::Relaxed) - allocated;
+
+            match result {
+                Ok(()) => devices = found.len(),
+                Err(e) => {
+                    failed += 1;
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        times.sort();
+        let average = times.iter().sum::<Duration>() / polls as u32;
+        // The poll that 99% of the polls were at least as fast as
+        let p99 = times[(polls * 99).div_ceil(100) - 1];
+        println!(
+            "{name}: {polls} polls, {failed} failed, {devices} devices\n  \
+             min {:.1?}  avg {average:.1?}  p99 {p99:.1?}  {} allocations per poll",
+            times[0],
+            allocations / polls as u64,
+        );
+        if let Some(e) = last_error {
+            println!("  last error: {e:#}");
+        }
+    }
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use headset_battery_indicator::run;
+use headset_battery_indicator::{DEMO_ARG, run};
 use log::error;
 use simplelog::{Config, TermLogger};
 
@@ -11,7 +11,8 @@ fn main() {
     )
     .unwrap();
 
-    if let Err(e) = run() {
+    let demo = std::env::args().any(|arg| arg == DEMO_ARG);
+    if let Err(e) = run(demo) {
         error!("Application stopped unexpectedly: {e:?}");
     }
 }
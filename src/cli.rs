@@ -0,0 +1,134 @@
+#[cfg(windows)]
+use std::fs::File;
+#[cfg(windows)]
+use std::io::{ErrorKind, Read};
+#[cfg(windows)]
+use std::thread;
+#[cfg(windows)]
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crate::api::{self, DeviceResponse};
+use crate::headset_control;
+#[cfg(windows)]
+use crate::pipe::PIPE_NAME;
+use crate::settings::Settings;
+
+pub const QUERY_ARG: &str = "--query";
+pub const ONCE_ARG: &str = "--once";
+pub const JSON_ARG: &str = "--json";
+
+/// Another client may be reading the pipe at the same moment
+#[cfg(windows)]
+const PIPE_BUSY_RETRIES: usize = 10;
+
+/// Prints the devices of the running instance, read from its named pipe
+pub fn query(json: bool) -> anyhow::Result<()> {
+    attach_console();
+
+    let response = read_pipe()?;
+    if json {
+        println!("{response}");
+    } else {
+        let devices: Vec<DeviceResponse> =
+            serde_json::from_str(&response).context("parsing device list")?;
+        print_table(&devices);
+    }
+
+    Ok(())
+}
+
+/// Queries the devices directly and prints them, without starting the tray app
+pub fn once(json: bool) -> anyhow::Result<()> {
+    attach_console();
+
+    let (disabled, headsetcontrol) = Settings::load()
+        .map(|settings| (settings.disabled_backends, settings.headsetcontrol))
+        .unwrap_or_default();
+    let mut devices = vec![];
+    headset_control::Registry::new(&disabled, &headsetcontrol).poll(&mut devices, |_| {})?;
+
+    let devices = api::device_list(&devices, 0);
+    if json {
+        println!("{}", serde_json::to_string(&devices)?);
+    } else {
+        print_table(&devices);
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+fn read_pipe() -> anyhow::Result<String> {
+    let mut retries = PIPE_BUSY_RETRIES;
+    let mut pipe = loop {
+        match File::open(PIPE_NAME) {
+            Ok(pipe) => break pipe,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                anyhow::bail!(
+                    "Headset Battery Indicator is not running, or the named pipe is disabled"
+                )
+            }
+            // ERROR_PIPE_BUSY
+            Err(e) if e.raw_os_error() == Some(231) && retries > 0 => {
+                retries -= 1;
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(e) => return Err(e).with_context(|| format!("opening {PIPE_NAME}")),
+        }
+    };
+
+    let mut response = String::new();
+    pipe.read_to_string(&mut response)
+        .context("reading device list")?;
+
+    Ok(response)
+}
+
+#[cfg(not(windows))]
+fn read_pipe() -> anyhow::Result<String> {
+    anyhow::bail!("--query needs the named pipe, which is only available on Windows, use --once")
+}
+
+fn print_table(devices: &[DeviceResponse]) {
+    if devices.is_empty() {
+        println!("No devices found");
+        return;
+    }
+
+    let name_width = devices
+        .iter()
+        .map(|d| d.name.len())
+        .max()
+        .unwrap_or_default()
+        .max("NAME".len());
+
+    println!(
+        "  {:<9}  {:<name_width$}  {:>7}  STATUS",
+        "ID", "NAME", "BATTERY"
+    );
+    for device in devices {
+        println!(
+            "{} {:<9}  {:<name_width$}  {:>6}%  {}",
+            if device.selected { '*' } else { ' ' },
+            device.id,
+            device.name,
+            device.battery.level,
+            device.battery.status.label(),
+        );
+    }
+}
+
+/// The release build has no console of its own, so output would be lost
+/// when run from a terminal
+pub(crate) fn attach_console() {
+    #[cfg(windows)]
+    unsafe {
+        use windows::Win32::System::Console::{ATTACH_PARENT_PROCESS, AttachConsole};
+
+        // Fails when there's already a console (debug build) or no parent console,
+        // in which case stdout is used as-is
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
@@ -0,0 +1,98 @@
+//! `--demo` starts the tray app with a made-up headset instead of polling the real ones. Its
+//! battery runs down from 100% to 0%, is off for a moment, charges back up and stays full, a
+//! few minutes per round, so every icon, tooltip, notification and overlay state shows up
+//! without waiting for a real battery.
+
+use std::time::{Duration, Instant};
+
+use crate::headset_control::{Battery, BatteryState, Capabilities, Device};
+use crate::settings::Settings;
+
+pub const DEMO_ARG: &str = "--demo";
+
+/// The level changes by one or two percent between polls
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Notifications closer together than this are left out, so they don't pile up
+pub const NOTIFICATION_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How many seconds each part of a round lasts
+const DISCHARGING: u64 = 100;
+const OFF: u64 = 5;
+const CHARGING: u64 = 50;
+const FULL: u64 = 15;
+
+pub struct Demo {
+    started: Instant,
+}
+
+impl Demo {
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+        }
+    }
+
+    /// The made-up headset as it is now
+    pub fn devices(&self) -> Vec<Device> {
+        vec![Device {
+            status: "success".to_string(),
+            vendor: "Headset Battery Indicator".to_string(),
+            product: "Demo Headset".to_string(),
+            id_vendor: "0x0000".to_string(),
+            id_product: "0x0000".to_string(),
+            capabilities_str: vec!["battery".to_string()],
+            battery: battery(self.started.elapsed()),
+            docked: false,
+            sub_batteries: Vec::new(),
+            capabilities: Capabilities::default(),
+            chatmix: None,
+        }]
+    }
+}
+
+/// The battery `elapsed` into the demo
+fn battery(elapsed: Duration) -> Battery {
+    let second = elapsed.as_secs() % (DISCHARGING + OFF + CHARGING + FULL);
+    let (status, level) = if second < DISCHARGING {
+        (
+            BatteryState::BatteryAvailable,
+            100 - second * 100 / (DISCHARGING - 1),
+        )
+    } else if second < DISCHARGING + OFF {
+        (BatteryState::BatteryUnavailable, 0)
+    } else if second < DISCHARGING + OFF + CHARGING {
+        (
+            BatteryState::BatteryCharging,
+            (second - DISCHARGING - OFF) * 100 / CHARGING,
+        )
+    } else {
+        (BatteryState::BatteryCharging, 100)
+    };
+    Battery {
+        status,
+        level: level as isize,
+    }
+}
+
+/// Turns off what would save the made-up headset or send it anywhere, like the history,
+/// actions and the MQTT, Discord, Telegram and InfluxDB integrations. None of these are
+/// saved, so the tray menu can still change and save its own settings.
+pub fn restrict(settings: &mut Settings) {
+    settings.actions.clear();
+    settings.scripts.clear();
+    settings.webhooks.clear();
+    settings.push.clear();
+    settings.last_notification_state = None;
+    settings.history_enabled = false;
+    settings.health_warning = 0;
+    settings.weekly_summary = false;
+    settings.update_check = false;
+    settings.mqtt = None;
+    settings.discord = None;
+    settings.telegram = None;
+    settings.influx = None;
+    #[cfg(windows)]
+    {
+        settings.airpods = false;
+    }
+}
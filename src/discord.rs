@@ -0,0 +1,229 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use log::{debug, error, info};
+use serde_json::json;
+
+use crate::headset_control::{BatteryState, Device};
+use crate::lang;
+use crate::lang::Key::*;
+
+/// Discord allows 5 activity updates per 20 seconds, and friends don't need
+/// to see every percent anyway
+pub const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(60);
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+/// Discord listens on the first free one of discord-ipc-0..9
+const MAX_PIPE_INDEX: u32 = 9;
+
+const OP_HANDSHAKE: u32 = 0;
+const OP_FRAME: u32 = 1;
+const OP_CLOSE: u32 = 2;
+
+/// Details and state lines of the activity
+type Activity = (String, Option<&'static str>);
+
+/// Read from the `Discord` registry subkey
+#[derive(Debug, Clone)]
+pub struct DiscordConfig {
+    /// Id of the Discord application, whose name is shown as "Playing ..."
+    pub client_id: String,
+    /// Minimum time between activity updates
+    pub min_interval: Duration,
+}
+
+/// Shows the battery level of the selected headset in the Discord status.
+///
+/// Talks to the Discord client over its local IPC pipe on a separate thread,
+/// and reconnects whenever Discord is restarted.
+pub struct DiscordPresence {
+    sender: mpsc::Sender<Option<Device>>,
+}
+
+impl DiscordPresence {
+    pub fn start(config: DiscordConfig) -> anyhow::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("discord".to_string())
+            .spawn(move || Worker::new(config).run(receiver))
+            .context("spawning Discord thread")?;
+
+        Ok(Self { sender })
+    }
+
+    /// Sets the device shown in the status, or clears the status
+    pub fn publish(&self, device: Option<&Device>) {
+        if self.sender.send(device.cloned()).is_err() {
+            error!("Discord thread has stopped");
+        }
+    }
+}
+
+struct Worker {
+    config: DiscordConfig,
+    pipe: Option<File>,
+    /// The activity text Discord currently shows
+    shown: Option<Activity>,
+    last_update: Option<Instant>,
+    last_attempt: Option<Instant>,
+    nonce: u64,
+}
+
+impl Worker {
+    fn new(config: DiscordConfig) -> Self {
+        Self {
+            config,
+            pipe: None,
+            shown: None,
+            last_update: None,
+            last_attempt: None,
+            nonce: 0,
+        }
+    }
+
+    fn run(mut self, receiver: mpsc::Receiver<Option<Device>>) {
+        let mut device = None;
+
+        loop {
+            match receiver.recv_timeout(RECONNECT_DELAY) {
+                Ok(latest) => device = latest,
+                Err(RecvTimeoutError::Timeout) => {}
+                // Closing the pipe clears the activity
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+
+            if self.pipe.is_none()
+                && self
+                    .last_attempt
+                    .is_none_or(|attempt| attempt.elapsed() >= RECONNECT_DELAY)
+            {
+                self.last_attempt = Some(Instant::now());
+                // Failing just means Discord isn't running
+                if let Err(e) = self.connect() {
+                    debug!("Couldn't connect to Discord: {e:?}");
+                }
+            }
+
+            let activity = device.as_ref().and_then(activity_text);
+            if self.pipe.is_none()
+                || activity == self.shown
+                || self
+                    .last_update
+                    .is_some_and(|update| update.elapsed() < self.config.min_interval)
+            {
+                continue;
+            }
+
+            if let Err(e) = self.set_activity(&activity) {
+                info!("Lost connection to Discord: {e:?}");
+                self.pipe = None;
+                self.shown = None;
+            }
+        }
+    }
+
+    fn connect(&mut self) -> anyhow::Result<()> {
+        let mut pipe = (0..=MAX_PIPE_INDEX)
+            .find_map(|i| {
+                OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(format!(r"\\.\pipe\discord-ipc-{i}"))
+                    .ok()
+            })
+            .context("Discord IPC pipe not found")?;
+
+        write_frame(
+            &mut pipe,
+            OP_HANDSHAKE,
+            &json!({ "v": 1, "client_id": self.config.client_id }),
+        )?;
+        let (op, ready) = read_frame(&mut pipe)?;
+        anyhow::ensure!(op != OP_CLOSE, "Discord closed the connection: {ready}");
+
+        info!("Connected to Discord");
+        self.pipe = Some(pipe);
+        self.shown = None;
+
+        Ok(())
+    }
+
+    fn set_activity(&mut self, activity: &Option<Activity>) -> anyhow::Result<()> {
+        let pipe = self.pipe.as_mut().context("not connected")?;
+        self.nonce += 1;
+
+        let activity_json = activity.as_ref().map(|(details, state)| {
+            let mut activity = json!({ "details": details });
+            if let Some(state) = state {
+                activity["state"] = json!(state);
+            }
+            activity
+        });
+
+        write_frame(
+            pipe,
+            OP_FRAME,
+            &json!({
+                "cmd": "SET_ACTIVITY",
+                "args": {
+                    "pid": std::process::id(),
+                    "activity": activity_json,
+                },
+                "nonce": self.nonce.to_string(),
+            }),
+        )?;
+
+        let (op, response) = read_frame(pipe)?;
+        anyhow::ensure!(op != OP_CLOSE, "Discord closed the connection: {response}");
+        if response["evt"] == "ERROR" {
+            error!("Discord rejected the activity: {}", response["data"]);
+        }
+
+        self.shown = activity.clone();
+        self.last_update = Some(Instant::now());
+
+        Ok(())
+    }
+}
+
+/// `None` clears the activity while the headset is off
+fn activity_text(device: &Device) -> Option<Activity> {
+    let state = match device.battery.status {
+        BatteryState::BatteryAvailable => None,
+        BatteryState::BatteryCharging => Some(lang::t(discord_charging)),
+        _ => return None,
+    };
+    let details = lang::t(discord_battery).replace("{level}", &device.battery.level.to_string());
+
+    Some((details, state))
+}
+
+fn write_frame(pipe: &mut File, op: u32, payload: &serde_json::Value) -> anyhow::Result<()> {
+    let payload = payload.to_string();
+
+    let mut frame = Vec::with_capacity(payload.len() + 8);
+    frame.extend_from_slice(&op.to_le_bytes());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(payload.as_bytes());
+
+    pipe.write_all(&frame)?;
+
+    Ok(())
+}
+
+fn read_frame(pipe: &mut File) -> anyhow::Result<(u32, serde_json::Value)> {
+    let mut header = [0u8; 8];
+    pipe.read_exact(&mut header)?;
+    let op = u32::from_le_bytes(header[..4].try_into()?);
+    let len = u32::from_le_bytes(header[4..].try_into()?);
+
+    let mut payload = vec![0u8; len as usize];
+    pipe.read_exact(&mut payload)?;
+
+    Ok((op, serde_json::from_slice(&payload)?))
+}
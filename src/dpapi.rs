@@ -0,0 +1,65 @@
+//! Encryption of secrets stored in the registry, with a key tied to the Windows user account
+
+use anyhow::Context;
+use windows::{
+    Win32::{
+        Foundation::{HLOCAL, LocalFree},
+        Security::Cryptography::{
+            CRYPT_INTEGER_BLOB, CRYPTPROTECT_UI_FORBIDDEN, CryptProtectData, CryptUnprotectData,
+        },
+    },
+    core::PCWSTR,
+};
+
+pub fn protect(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let input = blob(data);
+    let mut output = CRYPT_INTEGER_BLOB::default();
+    unsafe {
+        CryptProtectData(
+            &input,
+            PCWSTR::null(),
+            None,
+            None,
+            None,
+            CRYPTPROTECT_UI_FORBIDDEN,
+            &mut output,
+        )
+    }
+    .context("CryptProtectData")?;
+    Ok(take(output))
+}
+
+pub fn unprotect(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let input = blob(data);
+    let mut output = CRYPT_INTEGER_BLOB::default();
+    unsafe {
+        CryptUnprotectData(
+            &input,
+            None,
+            None,
+            None,
+            None,
+            CRYPTPROTECT_UI_FORBIDDEN,
+            &mut output,
+        )
+    }
+    .context("CryptUnprotectData")?;
+    Ok(take(output))
+}
+
+fn blob(data: &[u8]) -> CRYPT_INTEGER_BLOB {
+    CRYPT_INTEGER_BLOB {
+        cbData: data.len() as u32,
+        // Only read by the API
+        pbData: data.as_ptr() as *mut u8,
+    }
+}
+
+/// Copies the data out of a blob allocated by the API, and frees it
+fn take(blob: CRYPT_INTEGER_BLOB) -> Vec<u8> {
+    unsafe {
+        let data = std::slice::from_raw_parts(blob.pbData, blob.cbData as usize).to_vec();
+        LocalFree(HLOCAL(blob.pbData as *mut _));
+        data
+    }
+}
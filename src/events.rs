@@ -0,0 +1,89 @@
+//! What changed between two polls, worked out once and handed to the integrations,
+//! so each of them doesn't have to keep the previous device list around
+
+use log::debug;
+
+use crate::headset_control::{Battery, BatteryState, Device};
+
+#[derive(Debug, Clone)]
+pub enum AppEvent {
+    /// A device showed up in the device list
+    DeviceAdded(Device),
+    /// A device is no longer in the device list, with its last known state
+    DeviceRemoved(Device),
+    /// The battery level or status of a device changed
+    BatteryChanged { previous: Battery, device: Device },
+    /// A device started or stopped charging, sent after its `BatteryChanged`
+    ChargingChanged(Device),
+}
+
+impl AppEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AppEvent::DeviceAdded(_) => "DeviceAdded",
+            AppEvent::DeviceRemoved(_) => "DeviceRemoved",
+            AppEvent::BatteryChanged { .. } => "BatteryChanged",
+            AppEvent::ChargingChanged(_) => "ChargingChanged",
+        }
+    }
+
+    pub fn device(&self) -> &Device {
+        match self {
+            AppEvent::DeviceAdded(device)
+            | AppEvent::DeviceRemoved(device)
+            | AppEvent::ChargingChanged(device)
+            | AppEvent::BatteryChanged { device, .. } => device,
+        }
+    }
+}
+
+/// Turns every poll into the events describing what changed since the previous one
+#[derive(Default)]
+pub struct EventBus {
+    last_devices: Vec<Device>,
+}
+
+impl EventBus {
+    pub fn update(&mut self, devices: &[Device]) -> Vec<AppEvent> {
+        let mut events = vec![];
+
+        for device in devices {
+            let Some(last) = self.last_devices.iter().find(|d| d.id() == device.id()) else {
+                events.push(AppEvent::DeviceAdded(device.clone()));
+                continue;
+            };
+            if last.battery == device.battery {
+                continue;
+            }
+
+            events.push(AppEvent::BatteryChanged {
+                previous: last.battery,
+                device: device.clone(),
+            });
+            let charging = device.battery.status == BatteryState::BatteryCharging;
+            if charging != (last.battery.status == BatteryState::BatteryCharging) {
+                events.push(AppEvent::ChargingChanged(device.clone()));
+            }
+        }
+
+        for last in &self.last_devices {
+            if !devices.iter().any(|d| d.id() == last.id()) {
+                events.push(AppEvent::DeviceRemoved(last.clone()));
+            }
+        }
+
+        for event in &events {
+            let device = event.device();
+            debug!(
+                "{} {} ({}%, {:?})",
+                event.name(),
+                device.id(),
+                device.battery.level,
+                device.battery.status
+            );
+        }
+
+        self.last_devices = devices.to_vec();
+        events
+    }
+}
@@ -0,0 +1,233 @@
+//! Battery history export as CSV or JSON, for analyzing it in a spreadsheet
+//! or attaching it to a warranty claim
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use serde_derive::Serialize;
+
+use crate::headset_control::{BatteryState, Device};
+use crate::history::{self, Sample};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Range {
+    Day,
+    Week,
+    Month,
+    All,
+}
+
+impl Range {
+    pub const ALL: [Range; 4] = [Range::Day, Range::Week, Range::Month, Range::All];
+
+    fn max_age(self) -> Option<Duration> {
+        const DAY: u64 = 24 * 60 * 60;
+        match self {
+            Range::Day => Some(Duration::from_secs(DAY)),
+            Range::Week => Some(Duration::from_secs(7 * DAY)),
+            Range::Month => Some(Duration::from_secs(30 * DAY)),
+            Range::All => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportedSample<'a> {
+    timestamp: u64,
+    /// UTC, in a format spreadsheets recognize as a date
+    time: String,
+    device: &'a str,
+    level: isize,
+    status: BatteryState,
+    anomaly: bool,
+}
+
+/// The samples in `range`, of one device or of all of them
+pub fn select<'a>(samples: &'a [Sample], range: Range, device: Option<&str>) -> Vec<&'a Sample> {
+    let samples = match range.max_age() {
+        Some(max_age) => history::recent(samples, max_age),
+        None => samples,
+    };
+    samples
+        .iter()
+        .filter(|sample| device.is_none_or(|device| sample.device == device))
+        .collect()
+}
+
+pub fn write(path: &Path, format: Format, samples: &[&Sample]) -> anyhow::Result<()> {
+    let samples: Vec<ExportedSample> = samples
+        .iter()
+        .map(|sample| ExportedSample {
+            timestamp: sample.timestamp,
+            time: format_utc(sample.timestamp),
+            device: &sample.device,
+            level: sample.level,
+            status: sample.status,
+            anomaly: sample.anomaly,
+        })
+        .collect();
+
+    let content = match format {
+        Format::Csv => {
+            let mut csv = String::from("timestamp,time (UTC),device,level,status,anomaly\r\n");
+            for sample in &samples {
+                let status = serde_json::to_value(sample.status)?;
+                csv += &format!(
+                    "{},{},{},{},{},{}\r\n",
+                    sample.timestamp,
+                    sample.time,
+                    sample.device,
+                    sample.level,
+                    status.as_str().unwrap_or_default(),
+                    u8::from(sample.anomaly)
+                );
+            }
+            csv
+        }
+        Format::Json => serde_json::to_string_pretty(&samples)?,
+    };
+
+    fs::write(path, content).with_context(|| format!("writing {}", path.display()))
+}
+
+/// Suggested in the save dialog, without the characters Windows doesn't allow in file names
+pub fn file_name(device: Option<&Device>) -> String {
+    let name = match device {
+        Some(device) => format!("{} battery history", device.product),
+        None => "Battery history".to_string(),
+    };
+    name.replace(['<', '>', ':', '"', '/', '\\', '|', '?', '*'], "_")
+}
+
+/// Asks where to save the export, `None` when the dialog was cancelled.
+/// The format is picked in the dialog's file type list.
+#[cfg(windows)]
+pub fn ask_path(file_name: &str) -> anyhow::Result<Option<(PathBuf, Format)>> {
+    use windows::{
+        Win32::UI::Controls::Dialogs::{
+            CommDlgExtendedError, GetSaveFileNameW, OFN_NOCHANGEDIR, OFN_OVERWRITEPROMPT,
+            OFN_PATHMUSTEXIST, OPENFILENAMEW,
+        },
+        core::{PWSTR, w},
+    };
+
+    // Pairs of description and pattern, ending with an empty string
+    let filter = w!("CSV (*.csv)\0*.csv\0JSON (*.json)\0*.json\0");
+
+    let mut file = vec![0u16; 1024];
+    let name: Vec<u16> = file_name.encode_utf16().collect();
+    file[..name.len()].copy_from_slice(&name);
+
+    let mut dialog = OPENFILENAMEW {
+        lStructSize: size_of::<OPENFILENAMEW>() as u32,
+        lpstrFilter: filter,
+        nFilterIndex: 1,
+        lpstrFile: PWSTR(file.as_mut_ptr()),
+        nMaxFile: file.len() as u32,
+        // Appended when the user doesn't type an extension, Windows swaps in the selected type's
+        lpstrDefExt: w!("csv"),
+        Flags: OFN_OVERWRITEPROMPT | OFN_PATHMUSTEXIST | OFN_NOCHANGEDIR,
+        ..Default::default()
+    };
+
+    if !unsafe { GetSaveFileNameW(&mut dialog) }.as_bool() {
+        let error = unsafe { CommDlgExtendedError() };
+        if error.0 != 0 {
+            anyhow::bail!("GetSaveFileNameW failed with {:#x}", error.0);
+        }
+        return Ok(None);
+    }
+
+    let len = file.iter().position(|&c| c == 0).unwrap_or(file.len());
+    let path = PathBuf::from(String::from_utf16_lossy(&file[..len]));
+    let format = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("json") => Format::Json,
+        Some(ext) if ext.eq_ignore_ascii_case("csv") => Format::Csv,
+        _ if dialog.nFilterIndex == 2 => Format::Json,
+        _ => Format::Csv,
+    };
+    Ok(Some((path, format)))
+}
+
+/// Asks with zenity, which blocks the tray until the dialog is closed.
+/// The format follows the extension, and is CSV without one.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn ask_path(file_name: &str) -> anyhow::Result<Option<(PathBuf, Format)>> {
+    let output = std::process::Command::new("zenity")
+        .args(["--file-selection", "--save", "--confirm-overwrite"])
+        .arg(format!("--filename={file_name}.csv"))
+        .args(["--file-filter=CSV | *.csv", "--file-filter=JSON | *.json"])
+        .output()
+        .context("running zenity")?;
+    // Exits with 1 when cancelled
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(path_and_format(&output.stdout)))
+}
+
+/// Asks with AppleScript's save dialog, which blocks the menu bar until it's closed.
+/// The format follows the extension, and is CSV without one.
+#[cfg(target_os = "macos")]
+pub fn ask_path(file_name: &str) -> anyhow::Result<Option<(PathBuf, Format)>> {
+    let script = format!(
+        "POSIX path of (choose file name default name \"{}.csv\")",
+        file_name.replace('\\', "\\\\").replace('"', "\\\"")
+    );
+    let output = std::process::Command::new("osascript")
+        .args(["-e", &script])
+        .output()
+        .context("running osascript")?;
+    // Exits with 1 when cancelled
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(path_and_format(&output.stdout)))
+}
+
+/// The path printed by the dialog, with `.csv` added when it has no extension
+#[cfg(not(windows))]
+fn path_and_format(output: &[u8]) -> (PathBuf, Format) {
+    let mut path = PathBuf::from(String::from_utf8_lossy(output).trim_end_matches('\n'));
+    let format = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("json") => Format::Json,
+        Some(_) => Format::Csv,
+        None => {
+            path.as_mut_os_string().push(".csv");
+            Format::Csv
+        }
+    };
+    (path, format)
+}
+
+/// `YYYY-MM-DD hh:mm:ss` from a Unix timestamp, without pulling in a date library
+fn format_utc(timestamp: u64) -> String {
+    let (days, seconds) = (timestamp / 86400, timestamp % 86400);
+
+    // Days since 1970-01-01 to a civil date, from Howard Hinnant's date algorithms
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
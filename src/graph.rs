@@ -0,0 +1,829 @@
+//! A window with a chart of the headsets' battery level over the last day, week or month,
+//! drawn with plain GDI into a winit window. Everything in it works from the keyboard too:
+//! Tab moves between the range buttons and the legend, Enter or Space presses them, and the
+//! focus goes back to the window that had it when this one is closed.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use log::error;
+use windows::{
+    Win32::{
+        Foundation::{COLORREF, HWND, POINT, RECT, SIZE},
+        Graphics::Gdi::{
+            BitBlt, CLEARTYPE_QUALITY, CLIP_DEFAULT_PRECIS, CreateCompatibleBitmap,
+            CreateCompatibleDC, CreateFontW, CreatePen, CreateSolidBrush, DEFAULT_CHARSET,
+            DEFAULT_PITCH, DeleteDC, DeleteObject, FF_SWISS, FW_NORMAL, FillRect, GetDC,
+            GetTextExtentPoint32W, HDC, HGDIOBJ, LineTo, MoveToEx, OUT_DEFAULT_PRECIS, PS_SOLID,
+            Polyline, ReleaseDC, SRCCOPY, SelectObject, SetBkMode, SetTextAlign, SetTextColor,
+            TA_CENTER, TA_LEFT, TA_RIGHT, TEXT_ALIGN_OPTIONS, TRANSPARENT, TextOutW,
+        },
+        UI::WindowsAndMessaging::{
+            GetClientRect, GetForegroundWindow, IsWindow, SetForegroundWindow,
+        },
+    },
+    core::w,
+};
+use winit::{
+    dpi::{LogicalSize, PhysicalPosition},
+    event::{ElementState, MouseButton, WindowEvent},
+    event_loop::ActiveEventLoop,
+    keyboard::{Key, ModifiersState, NamedKey},
+    raw_window_handle::{HasWindowHandle, RawWindowHandle},
+    window::{Theme, Window, WindowId},
+};
+
+use crate::headset_control::BatteryState;
+use crate::history::{self, Comparison, Sample};
+use crate::lang::{self, Key::*};
+use crate::palette::{self, Rgb};
+
+/// Redrawn at least this often, so the chart keeps moving while nothing changes
+const REDRAW_INTERVAL: Duration = Duration::from_secs(60);
+/// Height of a row in the comparison table, in logical pixels
+const ROW_HEIGHT: f64 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Range {
+    Day,
+    Week,
+    Month,
+}
+
+impl Range {
+    const ALL: [Range; 3] = [Range::Day, Range::Week, Range::Month];
+
+    fn duration(self) -> Duration {
+        const DAY: u64 = 24 * 60 * 60;
+        match self {
+            Range::Day => Duration::from_secs(DAY),
+            Range::Week => Duration::from_secs(7 * DAY),
+            Range::Month => Duration::from_secs(30 * DAY),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Range::Day => "24 h",
+            Range::Week => "7 d",
+            Range::Month => "30 d",
+        }
+    }
+
+    /// The time axis labels: how many steps there are, how long each is, and how it's written
+    fn steps(self) -> (u64, u64, &'static str) {
+        const HOUR: u64 = 60 * 60;
+        match self {
+            Range::Day => (4, 6 * HOUR, "h"),
+            Range::Week => (7, 24 * HOUR, "d"),
+            Range::Month => (6, 5 * 24 * HOUR, "d"),
+        }
+    }
+}
+
+/// What the keyboard focus can be on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Control {
+    Range(Range),
+    /// The legend entry of the series with this index
+    Legend(usize),
+}
+
+struct Colors {
+    background: COLORREF,
+    text: COLORREF,
+    grid: COLORREF,
+    /// One for each headset, repeating after the last
+    lines: [COLORREF; 5],
+    charging: COLORREF,
+    button: COLORREF,
+}
+
+impl Colors {
+    fn for_theme(theme: Theme) -> Self {
+        let palette = palette::current();
+        match theme {
+            Theme::Dark => Self {
+                background: rgb(32, 32, 32),
+                text: rgb(230, 230, 230),
+                grid: rgb(70, 70, 70),
+                lines: palette.lines(true).map(color),
+                charging: color(palette.charging_shade(true)),
+                button: rgb(60, 60, 60),
+            },
+            Theme::Light => Self {
+                background: rgb(255, 255, 255),
+                text: rgb(30, 30, 30),
+                grid: rgb(220, 220, 220),
+                lines: palette.lines(false).map(color),
+                charging: color(palette.charging_shade(false)),
+                button: rgb(225, 225, 225),
+            },
+        }
+    }
+}
+
+/// One headset in the chart
+struct Series {
+    /// History id of the headset
+    device: String,
+    name: String,
+    shown: bool,
+    /// Samples from the longest range, oldest first, without the glitches
+    samples: Vec<Sample>,
+    /// From the whole history, `None` for a headset first seen after the window was opened
+    comparison: Option<Comparison>,
+}
+
+/// The battery history window, closed by dropping it.
+///
+/// Every headset from the last month can be toggled on in the legend to compare them,
+/// which also shows a table of their runtime, charge cycles and health.
+pub struct GraphWindow {
+    window: Window,
+    /// The headset that was selected when the window was opened comes first
+    series: Vec<Series>,
+    /// Names of the connected headsets, by history id
+    names: HashMap<String, String>,
+    range: Range,
+    cursor: PhysicalPosition<f64>,
+    /// Where the legend entries were drawn, for clicking them
+    legend: Vec<RECT>,
+    last_redraw: Instant,
+    modifiers: ModifiersState,
+    /// Only drawn once the keyboard is used, like in other Windows dialogs
+    focus: Option<Control>,
+    /// The window that was in front when this one was opened, focused again when it's closed
+    previous_window: HWND,
+}
+
+impl GraphWindow {
+    /// `selected` is the history id of the headset shown at first, `names` has the names
+    /// of the connected headsets, the others are shown by their id
+    pub fn open(
+        event_loop: &ActiveEventLoop,
+        selected: Option<String>,
+        names: HashMap<String, String>,
+        samples: &[Sample],
+    ) -> anyhow::Result<Self> {
+        let attributes = Window::default_attributes()
+            .with_inner_size(LogicalSize::new(720.0, 360.0))
+            .with_min_inner_size(LogicalSize::new(360.0, 200.0));
+        let previous_window = unsafe { GetForegroundWindow() };
+        let window = event_loop
+            .create_window(attributes)
+            .context("creating battery history window")?;
+        // Opened from the tray, so it doesn't always get the keyboard focus by itself
+        window.focus_window();
+
+        let month = history::recent(samples, Range::Month.duration());
+        let mut devices: Vec<String> = selected.into_iter().collect();
+        for sample in month {
+            if !devices.contains(&sample.device) {
+                devices.push(sample.device.clone());
+            }
+        }
+
+        let comparisons = history::compare(samples);
+        let series = devices
+            .into_iter()
+            .enumerate()
+            .map(|(i, device)| Series {
+                name: names.get(&device).unwrap_or(&device).clone(),
+                shown: i == 0,
+                samples: month
+                    .iter()
+                    .filter(|sample| !sample.anomaly && sample.device == device)
+                    .cloned()
+                    .collect(),
+                comparison: comparisons.get(&device).copied(),
+                device,
+            })
+            .collect();
+
+        let graph = Self {
+            window,
+            series,
+            names,
+            range: Range::Day,
+            cursor: PhysicalPosition::default(),
+            legend: vec![],
+            last_redraw: Instant::now(),
+            modifiers: ModifiersState::empty(),
+            focus: None,
+            previous_window,
+        };
+        graph.update_title();
+        Ok(graph)
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    pub fn focus(&self) {
+        self.window.set_minimized(false);
+        self.window.focus_window();
+    }
+
+    /// Adds the new samples, called on every poll
+    pub fn add(&mut self, samples: &[Sample]) {
+        let mut changed = false;
+        for sample in samples.iter().filter(|sample| !sample.anomaly) {
+            let i = match self
+                .series
+                .iter()
+                .position(|series| series.device == sample.device)
+            {
+                Some(i) => i,
+                None => {
+                    self.series.push(Series {
+                        device: sample.device.clone(),
+                        name: self
+                            .names
+                            .get(&sample.device)
+                            .unwrap_or(&sample.device)
+                            .clone(),
+                        shown: false,
+                        samples: vec![],
+                        comparison: None,
+                    });
+                    self.series.len() - 1
+                }
+            };
+            self.series[i].samples.push(sample.clone());
+            changed = true;
+        }
+
+        if changed || self.last_redraw.elapsed() >= REDRAW_INTERVAL {
+            for series in &mut self.series {
+                let month = history::recent(&series.samples, Range::Month.duration()).len();
+                series.samples.drain(..series.samples.len() - month);
+            }
+            self.window.request_redraw();
+        }
+    }
+
+    /// Returns false once the window was closed
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::CloseRequested => return false,
+            WindowEvent::RedrawRequested => {
+                self.last_redraw = Instant::now();
+                if let Err(e) = self.draw() {
+                    error!("Failed to draw battery history: {e:?}");
+                }
+            }
+            WindowEvent::Resized(_)
+            | WindowEvent::ScaleFactorChanged { .. }
+            | WindowEvent::ThemeChanged(_) => self.window.request_redraw(),
+            WindowEvent::CursorMoved { position, .. } => self.cursor = *position,
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let (x, y) = (self.cursor.x as i32, self.cursor.y as i32);
+                let clicked = Range::ALL.into_iter().find(|&range| {
+                    let rect = self.button_rect(range);
+                    (rect.left..rect.right).contains(&x) && (rect.top..rect.bottom).contains(&y)
+                });
+                if let Some(range) = clicked {
+                    self.set_range(range);
+                }
+                let toggled = self.legend.iter().position(|rect| {
+                    (rect.left..rect.right).contains(&x) && (rect.top..rect.bottom).contains(&y)
+                });
+                if let Some(i) = toggled {
+                    self.activate(Control::Legend(i));
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers.state(),
+            // 1, 2 and 3 switch between the ranges like the buttons
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => {
+                match event.logical_key.as_ref() {
+                    Key::Character("1") => self.set_range(Range::Day),
+                    Key::Character("2") => self.set_range(Range::Week),
+                    Key::Character("3") => self.set_range(Range::Month),
+                    Key::Character("w" | "W") if self.modifiers.control_key() => return false,
+                    Key::Named(NamedKey::Escape) => return false,
+                    Key::Named(NamedKey::Tab) => {
+                        self.tab(if self.modifiers.shift_key() { -1 } else { 1 });
+                    }
+                    Key::Named(NamedKey::ArrowRight | NamedKey::ArrowDown) => self.arrow(1),
+                    Key::Named(NamedKey::ArrowLeft | NamedKey::ArrowUp) => self.arrow(-1),
+                    Key::Named(NamedKey::Enter | NamedKey::Space) => {
+                        if let Some(control) = self.focus {
+                            self.activate(control);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Named after the headsets shown
+    fn update_title(&self) {
+        let names: Vec<&str> = self
+            .series
+            .iter()
+            .filter(|series| series.shown)
+            .map(|series| series.name.as_str())
+            .collect();
+        let title = if names.is_empty() {
+            lang::t(history_window).to_string()
+        } else {
+            format!("{} – {}", lang::t(history_window), names.join(", "))
+        };
+        self.window.set_title(&title);
+    }
+
+    /// The selected range button, then the legend entries when the legend is shown. Like in a
+    /// group of radio buttons, the other ranges are reached with the arrow keys.
+    fn tab_order(&self) -> Vec<Control> {
+        let mut controls = vec![Control::Range(self.range)];
+        if self.series.len() > 1 {
+            controls.extend((0..self.series.len()).map(Control::Legend));
+        }
+        controls
+    }
+
+    /// Tab and Shift+Tab, wrapping around. The first press shows the focus on the selected range.
+    fn tab(&mut self, step: isize) {
+        let controls = self.tab_order();
+        let next = match self
+            .focus
+            .and_then(|focus| controls.iter().position(|&c| c == focus))
+        {
+            Some(i) => wrap(i, step, controls.len()),
+            None => 0,
+        };
+        self.focus = Some(controls[next]);
+        self.window.request_redraw();
+    }
+
+    /// Selects the next or previous range, or moves along the legend when it has the focus
+    fn arrow(&mut self, step: isize) {
+        match self.focus {
+            Some(Control::Legend(i)) => {
+                self.focus = Some(Control::Legend(wrap(i, step, self.series.len())));
+            }
+            _ => {
+                let i = Range::ALL
+                    .iter()
+                    .position(|&range| range == self.range)
+                    .unwrap_or_default();
+                self.focus = Some(Control::Range(self.range));
+                self.set_range(Range::ALL[wrap(i, step, Range::ALL.len())]);
+            }
+        }
+        self.window.request_redraw();
+    }
+
+    fn activate(&mut self, control: Control) {
+        match control {
+            Control::Range(range) => self.set_range(range),
+            Control::Legend(i) => {
+                self.series[i].shown = !self.series[i].shown;
+                self.update_title();
+                self.window.request_redraw();
+            }
+        }
+    }
+
+    fn set_range(&mut self, range: Range) {
+        // The focus stays on the selected range button
+        if let Some(Control::Range(_)) = self.focus {
+            self.focus = Some(Control::Range(range));
+        }
+        if range != self.range {
+            self.range = range;
+            self.window.request_redraw();
+        }
+    }
+
+    /// Scales a length in logical pixels to the monitor's DPI
+    fn px(&self, length: f64) -> i32 {
+        (length * self.window.scale_factor()).round() as i32
+    }
+
+    fn button_rect(&self, range: Range) -> RECT {
+        let i = Range::ALL
+            .iter()
+            .position(|&r| r == range)
+            .unwrap_or_default() as i32;
+        let left = self.px(12.0) + i * self.px(60.0);
+        RECT {
+            left,
+            top: self.px(10.0),
+            right: left + self.px(56.0),
+            bottom: self.px(34.0),
+        }
+    }
+
+    fn hwnd(&self) -> anyhow::Result<HWND> {
+        let handle = self
+            .window
+            .window_handle()
+            .context("getting window handle")?;
+        match handle.as_raw() {
+            RawWindowHandle::Win32(handle) => Ok(HWND(handle.hwnd.get() as _)),
+            _ => anyhow::bail!("not a Win32 window"),
+        }
+    }
+
+    /// Paints into a bitmap first and copies it to the window, so resizing doesn't flicker
+    fn draw(&mut self) -> anyhow::Result<()> {
+        let hwnd = self.hwnd()?;
+        let mut client = RECT::default();
+        unsafe { GetClientRect(hwnd, &mut client) }.context("GetClientRect")?;
+        let (width, height) = (client.right, client.bottom);
+        if width <= 0 || height <= 0 {
+            return Ok(());
+        }
+
+        unsafe {
+            let window_dc = GetDC(hwnd);
+            let dc = CreateCompatibleDC(window_dc);
+            let bitmap = Object(CreateCompatibleBitmap(window_dc, width, height).into());
+            let previous = SelectObject(dc, bitmap.0);
+
+            self.paint(dc, width, height);
+            let res = BitBlt(window_dc, 0, 0, width, height, dc, 0, 0, SRCCOPY);
+
+            SelectObject(dc, previous);
+            let _ = DeleteDC(dc);
+            ReleaseDC(hwnd, window_dc);
+            res.context("BitBlt")
+        }
+    }
+
+    unsafe fn paint(&mut self, dc: HDC, width: i32, height: i32) {
+        let colors = Colors::for_theme(self.window.theme().unwrap_or(Theme::Light));
+        let px = |length| self.px(length);
+
+        unsafe {
+            fill(
+                dc,
+                RECT {
+                    left: 0,
+                    top: 0,
+                    right: width,
+                    bottom: height,
+                },
+                colors.background,
+            );
+
+            let font = Object(
+                CreateFontW(
+                    -px(13.0),
+                    0,
+                    0,
+                    0,
+                    FW_NORMAL.0 as i32,
+                    0,
+                    0,
+                    0,
+                    DEFAULT_CHARSET.0 as u32,
+                    OUT_DEFAULT_PRECIS.0 as u32,
+                    CLIP_DEFAULT_PRECIS.0 as u32,
+                    CLEARTYPE_QUALITY.0 as u32,
+                    (DEFAULT_PITCH.0 | FF_SWISS.0) as u32,
+                    w!("Segoe UI"),
+                )
+                .into(),
+            );
+            let previous_font = SelectObject(dc, font.0);
+            SetBkMode(dc, TRANSPARENT);
+            SetTextColor(dc, colors.text);
+
+            for range in Range::ALL {
+                let rect = self.button_rect(range);
+                if range == self.range {
+                    fill(dc, rect, colors.button);
+                }
+                if self.focus == Some(Control::Range(range)) {
+                    outline(dc, rect, colors.text, px(1.0));
+                }
+                let x = (rect.left + rect.right) / 2;
+                draw_text(dc, x, rect.top + px(4.0), TA_CENTER, range.label());
+            }
+
+            // Toggles for the headsets, once there's more than one
+            // Hidden headsets are grayed out in the legend
+            let line_color = |i: usize, series: &Series| {
+                if series.shown {
+                    colors.lines[i % colors.lines.len()]
+                } else {
+                    colors.grid
+                }
+            };
+            let mut legend = vec![];
+            if self.series.len() > 1 {
+                let mut left = self.button_rect(Range::Month).right + px(24.0);
+                for (i, series) in self.series.iter().enumerate() {
+                    let rect = RECT {
+                        left,
+                        top: px(10.0),
+                        right: left + text_width(dc, &series.name) + px(32.0),
+                        bottom: px(34.0),
+                    };
+                    if series.shown {
+                        fill(dc, rect, colors.button);
+                    }
+                    if self.focus == Some(Control::Legend(i)) {
+                        outline(dc, rect, colors.text, px(1.0));
+                    }
+                    let swatch = RECT {
+                        left: left + px(8.0),
+                        top: px(17.0),
+                        right: left + px(18.0),
+                        bottom: px(27.0),
+                    };
+                    fill(dc, swatch, line_color(i, series));
+                    draw_text(dc, left + px(24.0), px(14.0), TA_LEFT, &series.name);
+                    legend.push(rect);
+                    left = rect.right + px(4.0);
+                }
+            }
+
+            let shown: Vec<(usize, &Series)> = self
+                .series
+                .iter()
+                .enumerate()
+                .filter(|(_, series)| series.shown)
+                .collect();
+            // Room for the comparison table below the chart
+            let table_height = match shown.len() {
+                0 | 1 => 0,
+                rows => px(ROW_HEIGHT) * (rows as i32 + 1) + px(8.0),
+            };
+
+            let plot = RECT {
+                left: px(48.0),
+                top: px(48.0),
+                right: width - px(24.0),
+                bottom: height - px(28.0) - table_height,
+            };
+            if plot.right <= plot.left || plot.bottom <= plot.top {
+                SelectObject(dc, previous_font);
+                self.legend = legend;
+                return;
+            }
+
+            let end = history::now();
+            let span = self.range.duration().as_secs();
+            let start = end.saturating_sub(span);
+            let x_of = |timestamp: u64| {
+                let elapsed = timestamp.clamp(start, end) - start;
+                plot.left + (elapsed as f64 / span as f64 * (plot.right - plot.left) as f64) as i32
+            };
+            let y_of = |level: isize| {
+                let level = level.clamp(0, 100) as f64 / 100.0;
+                plot.bottom - (level * (plot.bottom - plot.top) as f64) as i32
+            };
+
+            // Charging periods shaded behind everything else, only for a single headset
+            // since they'd overlap otherwise
+            if let [(_, series)] = shown[..] {
+                let samples = visible(&series.samples, start);
+                for (i, sample) in samples.iter().enumerate() {
+                    if sample.status == BatteryState::BatteryCharging {
+                        let left = x_of(sample.timestamp);
+                        let rect = RECT {
+                            left,
+                            top: plot.top,
+                            right: x_of(until(samples, i, end)).max(left + 1),
+                            bottom: plot.bottom,
+                        };
+                        fill(dc, rect, colors.charging);
+                    }
+                }
+            }
+
+            let grid_pen = Object(CreatePen(PS_SOLID, 1, colors.grid).into());
+            let previous_pen = SelectObject(dc, grid_pen.0);
+            for level in [0, 25, 50, 75, 100] {
+                let y = y_of(level);
+                let _ = MoveToEx(dc, plot.left, y, None);
+                let _ = LineTo(dc, plot.right, y);
+                draw_text(
+                    dc,
+                    plot.left - px(6.0),
+                    y - px(8.0),
+                    TA_RIGHT,
+                    &format!("{level}%"),
+                );
+            }
+
+            let (steps, step, unit) = self.range.steps();
+            let unit_length = match unit {
+                "h" => 60 * 60,
+                _ => 24 * 60 * 60,
+            };
+            for i in 0..=steps {
+                let timestamp = start + i * step;
+                let label = if i == steps {
+                    lang::t(history_now).to_string()
+                } else {
+                    format!("-{} {unit}", (steps - i) * step / unit_length)
+                };
+                let x = x_of(timestamp);
+                let _ = MoveToEx(dc, x, plot.top, None);
+                let _ = LineTo(dc, x, plot.bottom);
+                draw_text(dc, x, plot.bottom + px(6.0), TA_CENTER, &label);
+            }
+
+            if shown
+                .iter()
+                .all(|(_, series)| visible(&series.samples, start).is_empty())
+            {
+                let x = (plot.left + plot.right) / 2;
+                let y = (plot.top + plot.bottom) / 2 - px(8.0);
+                draw_text(dc, x, y, TA_CENTER, lang::t(history_empty));
+            }
+
+            // Steps from one sample to the next, with gaps while the headset was off
+            for &(index, series) in &shown {
+                let line_pen =
+                    Object(CreatePen(PS_SOLID, px(2.0), line_color(index, series)).into());
+                SelectObject(dc, line_pen.0);
+                let samples = visible(&series.samples, start);
+                let mut points = vec![];
+                for (i, sample) in samples.iter().enumerate() {
+                    let connected = matches!(
+                        sample.status,
+                        BatteryState::BatteryAvailable | BatteryState::BatteryCharging
+                    );
+                    if !connected {
+                        draw_line(dc, &mut points);
+                        continue;
+                    }
+                    let y = y_of(sample.level);
+                    points.push(POINT {
+                        x: x_of(sample.timestamp),
+                        y,
+                    });
+                    points.push(POINT {
+                        x: x_of(until(samples, i, end)),
+                        y,
+                    });
+                }
+                draw_line(dc, &mut points);
+                SelectObject(dc, previous_pen);
+            }
+            SelectObject(dc, previous_pen);
+
+            // The headsets compared over their whole history
+            if shown.len() > 1 {
+                let top = plot.bottom + px(32.0);
+                let columns = [plot.right - px(280.0), plot.right - px(140.0), plot.right];
+                draw_text(dc, plot.left, top, TA_LEFT, lang::t(compare_headset));
+                for (x, header) in
+                    columns
+                        .into_iter()
+                        .zip([compare_runtime, compare_cycles, compare_health])
+                {
+                    draw_text(dc, x, top, TA_RIGHT, lang::t(header));
+                }
+
+                for (row, &(i, series)) in shown.iter().enumerate() {
+                    let y = top + px(ROW_HEIGHT) * (row as i32 + 1);
+                    let swatch = RECT {
+                        left: plot.left,
+                        top: y + px(4.0),
+                        right: plot.left + px(10.0),
+                        bottom: y + px(14.0),
+                    };
+                    fill(dc, swatch, line_color(i, series));
+                    draw_text(dc, plot.left + px(16.0), y, TA_LEFT, &series.name);
+
+                    let comparison = series.comparison.unwrap_or_default();
+                    let values = [
+                        comparison.runtime.map(|hours| format!("{hours:.1} h")),
+                        Some(format!("{:.1}", comparison.cycles)),
+                        comparison.health.map(|health| format!("{health}%")),
+                    ];
+                    for (x, value) in columns.into_iter().zip(values) {
+                        draw_text(dc, x, y, TA_RIGHT, value.as_deref().unwrap_or("–"));
+                    }
+                }
+            }
+
+            SelectObject(dc, previous_font);
+            self.legend = legend;
+        }
+    }
+}
+
+impl Drop for GraphWindow {
+    /// Gives the focus back to where it was, unless another window took it in the meantime
+    fn drop(&mut self) {
+        let ours = self
+            .hwnd()
+            .is_ok_and(|hwnd| unsafe { GetForegroundWindow() } == hwnd);
+        if ours && unsafe { IsWindow(self.previous_window) }.as_bool() {
+            let _ = unsafe { SetForegroundWindow(self.previous_window) };
+        }
+    }
+}
+
+/// The index `step` away from `i`, wrapping around at both ends
+fn wrap(i: usize, step: isize, len: usize) -> usize {
+    (i as isize + step).rem_euclid(len as isize) as usize
+}
+
+/// The samples in the range, starting with the last one before it, which is where
+/// the chart starts from
+fn visible(samples: &[Sample], start: u64) -> &[Sample] {
+    let first = samples.partition_point(|sample| sample.timestamp < start);
+    &samples[first.saturating_sub(1)..]
+}
+
+/// Each sample lasts until the next one, the last one until now
+fn until(samples: &[Sample], i: usize, now: u64) -> u64 {
+    samples.get(i + 1).map_or(now, |next| next.timestamp)
+}
+
+/// Deletes the GDI object when dropped, after it was selected out of the DC
+struct Object(HGDIOBJ);
+
+impl Drop for Object {
+    fn drop(&mut self) {
+        let _ = unsafe { DeleteObject(self.0) };
+    }
+}
+
+fn rgb(r: u8, g: u8, b: u8) -> COLORREF {
+    COLORREF(r as u32 | (g as u32) << 8 | (b as u32) << 16)
+}
+
+fn color([r, g, b]: Rgb) -> COLORREF {
+    rgb(r, g, b)
+}
+
+unsafe fn fill(dc: HDC, rect: RECT, color: COLORREF) {
+    unsafe {
+        let brush = CreateSolidBrush(color);
+        FillRect(dc, &rect, brush);
+        let _ = DeleteObject(brush);
+    }
+}
+
+/// A frame `width` pixels wide just inside the rectangle
+unsafe fn outline(dc: HDC, rect: RECT, color: COLORREF, width: i32) {
+    let RECT {
+        left,
+        top,
+        right,
+        bottom,
+    } = rect;
+    for edge in [
+        RECT {
+            bottom: top + width,
+            ..rect
+        },
+        RECT {
+            top: bottom - width,
+            ..rect
+        },
+        RECT {
+            right: left + width,
+            ..rect
+        },
+        RECT {
+            left: right - width,
+            ..rect
+        },
+    ] {
+        unsafe { fill(dc, edge, color) };
+    }
+}
+
+unsafe fn text_width(dc: HDC, text: &str) -> i32 {
+    let text: Vec<u16> = text.encode_utf16().collect();
+    let mut size = SIZE::default();
+    let _ = unsafe { GetTextExtentPoint32W(dc, &text, &mut size) };
+    size.cx
+}
+
+unsafe fn draw_text(dc: HDC, x: i32, y: i32, align: TEXT_ALIGN_OPTIONS, text: &str) {
+    let text: Vec<u16> = text.encode_utf16().collect();
+    unsafe {
+        SetTextAlign(dc, align);
+        let _ = TextOutW(dc, x, y, &text);
+        SetTextAlign(dc, TA_LEFT);
+    }
+}
+
+/// Draws the points collected so far as one line, and starts a new one
+unsafe fn draw_line(dc: HDC, points: &mut Vec<POINT>) {
+    if points.len() >= 2 {
+        let _ = unsafe { Polyline(dc, points) };
+    }
+    points.clear();
+}
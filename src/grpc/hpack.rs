@@ -0,0 +1,593 @@
+//! HPACK header compression (RFC 7541).
+//!
+//! Requests are fully decoded, including Huffman coded strings. Responses are
+//! encoded as plain literals, which every decoder understands.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::OnceLock;
+
+use anyhow::Context;
+
+/// Also the limit announced in our settings, since we don't send a different one
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+pub struct Decoder {
+    /// Newest entry first, as the indices count from the newest entry
+    dynamic: VecDeque<(String, String)>,
+    size: usize,
+    max_size: usize,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self {
+            dynamic: VecDeque::new(),
+            size: 0,
+            max_size: DEFAULT_TABLE_SIZE,
+        }
+    }
+
+    pub fn decode(&mut self, mut block: &[u8]) -> anyhow::Result<Vec<(String, String)>> {
+        let mut headers = vec![];
+
+        while let Some(&first) = block.first() {
+            if first & 0x80 != 0 {
+                let index = read_int(&mut block, 7)?;
+                headers.push(self.entry(index)?);
+            } else if first & 0x40 != 0 {
+                let header = self.read_literal(&mut block, 6)?;
+                self.insert(header.clone());
+                headers.push(header);
+            } else if first & 0x20 != 0 {
+                let size = read_int(&mut block, 5)?;
+                anyhow::ensure!(size <= DEFAULT_TABLE_SIZE, "table size {size} too large");
+                self.max_size = size;
+                self.evict();
+            } else {
+                // Without indexing and never indexed only differ for proxies
+                headers.push(self.read_literal(&mut block, 4)?);
+            }
+        }
+
+        Ok(headers)
+    }
+
+    fn read_literal(&self, block: &mut &[u8], prefix: u8) -> anyhow::Result<(String, String)> {
+        let index = read_int(block, prefix)?;
+        let name = if index == 0 {
+            read_string(block)?
+        } else {
+            self.entry(index)?.0
+        };
+        let value = read_string(block)?;
+
+        Ok((name, value))
+    }
+
+    fn entry(&self, index: usize) -> anyhow::Result<(String, String)> {
+        let entry = match index {
+            0 => None,
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Some((name.to_string(), value.to_string()))
+            }
+            _ => self.dynamic.get(index - 62).cloned(),
+        };
+        entry.with_context(|| format!("invalid header index {index}"))
+    }
+
+    fn insert(&mut self, header: (String, String)) {
+        self.size += entry_size(&header);
+        self.dynamic.push_front(header);
+        // An entry larger than the table just empties it
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            let Some(oldest) = self.dynamic.pop_back() else {
+                break;
+            };
+            self.size -= entry_size(&oldest);
+        }
+    }
+}
+
+fn entry_size((name, value): &(String, String)) -> usize {
+    name.len() + value.len() + 32
+}
+
+/// Encodes headers as literals without indexing, so the peer's table stays empty
+pub fn encode(headers: &[(&str, &str)]) -> Vec<u8> {
+    let mut block = vec![];
+    for (name, value) in headers {
+        block.push(0);
+        write_string(&mut block, name);
+        write_string(&mut block, value);
+    }
+    block
+}
+
+fn read_int(block: &mut &[u8], prefix: u8) -> anyhow::Result<usize> {
+    let mask = (1u8 << prefix) - 1;
+    let (&first, rest) = block.split_first().context("truncated header block")?;
+    *block = rest;
+
+    let mut value = (first & mask) as usize;
+    if value < mask as usize {
+        return Ok(value);
+    }
+
+    let mut shift = 0;
+    loop {
+        let (&byte, rest) = block.split_first().context("truncated integer")?;
+        *block = rest;
+        anyhow::ensure!(shift <= 28, "integer too large");
+        value += ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn write_int(block: &mut Vec<u8>, prefix: u8, flags: u8, mut value: usize) {
+    let mask = (1usize << prefix) - 1;
+    if value < mask {
+        block.push(flags | value as u8);
+        return;
+    }
+
+    block.push(flags | mask as u8);
+    value -= mask;
+    while value >= 0x80 {
+        block.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    block.push(value as u8);
+}
+
+fn read_string(block: &mut &[u8]) -> anyhow::Result<String> {
+    let huffman = block.first().is_some_and(|b| b & 0x80 != 0);
+    let len = read_int(block, 7)?;
+    anyhow::ensure!(block.len() >= len, "truncated string");
+    let (bytes, rest) = block.split_at(len);
+    *block = rest;
+
+    let bytes = if huffman {
+        huffman_decode(bytes)?
+    } else {
+        bytes.to_vec()
+    };
+    String::from_utf8(bytes).context("header is not UTF-8")
+}
+
+fn write_string(block: &mut Vec<u8>, value: &str) {
+    write_int(block, 7, 0, value.len());
+    block.extend_from_slice(value.as_bytes());
+}
+
+fn huffman_decode(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+    static CODES: OnceLock<HashMap<(u32, u8), u8>> = OnceLock::new();
+    let codes = CODES.get_or_init(|| {
+        // The EOS symbol (256) must not appear in the data, so it's left out
+        HUFFMAN_CODES[..256]
+            .iter()
+            .enumerate()
+            .map(|(symbol, &code)| (code, symbol as u8))
+            .collect()
+    });
+
+    let mut decoded = vec![];
+    let (mut code, mut len) = (0u32, 0u8);
+    for byte in bytes {
+        for bit in (0..8).rev() {
+            code = (code << 1) | ((byte >> bit) & 1) as u32;
+            len += 1;
+            // The shortest codes are 5 bits long
+            if len >= 5
+                && let Some(&symbol) = codes.get(&(code, len))
+            {
+                decoded.push(symbol);
+                (code, len) = (0, 0);
+            }
+            anyhow::ensure!(len < 30, "invalid Huffman code");
+        }
+    }
+
+    // Padding is up to 7 bits of the EOS code, which is all ones
+    anyhow::ensure!(len < 8 && code == (1 << len) - 1, "invalid Huffman padding");
+
+    Ok(decoded)
+}
+
+/// Code and length in bits of every symbol, from RFC 7541 appendix B
+#[rustfmt::skip]
+const HUFFMAN_CODES: [(u32, u8); 257] = [
+    (0x1ff8, 13), (0x7fffd8, 23), (0xfffffe2, 28), (0xfffffe3, 28), (0xfffffe4, 28), (0xfffffe5, 28),
+    (0xfffffe6, 28), (0xfffffe7, 28), (0xfffffe8, 28), (0xffffea, 24), (0x3ffffffc, 30), (0xfffffe9, 28),
+    (0xfffffea, 28), (0x3ffffffd, 30), (0xfffffeb, 28), (0xfffffec, 28), (0xfffffed, 28), (0xfffffee, 28),
+    (0xfffffef, 28), (0xffffff0, 28), (0xffffff1, 28), (0xffffff2, 28), (0x3ffffffe, 30), (0xffffff3, 28),
+    (0xffffff4, 28), (0xffffff5, 28), (0xffffff6, 28), (0xffffff7, 28), (0xffffff8, 28), (0xffffff9, 28),
+    (0xffffffa, 28), (0xffffffb, 28), (0x14, 6), (0x3f8, 10), (0x3f9, 10), (0xffa, 12),
+    (0x1ff9, 13), (0x15, 6), (0xf8, 8), (0x7fa, 11), (0x3fa, 10), (0x3fb, 10),
+    (0xf9, 8), (0x7fb, 11), (0xfa, 8), (0x16, 6), (0x17, 6), (0x18, 6),
+    (0x0, 5), (0x1, 5), (0x2, 5), (0x19, 6), (0x1a, 6), (0x1b, 6),
+    (0x1c, 6), (0x1d, 6), (0x1e, 6), (0x1f, 6), (0x5c, 7), (0xfb, 8),
+    (0x7ffc, 15), (0x20, 6), (0xffb, 12), (0x3fc, 10), (0x1ffa, 13), (0x21, 6),
+    (0x5d, 7), (0x5e, 7), (0x5f, 7), (0x60, 7), (0x61, 7), (0x62, 7),
+    (0x63, 7), (0x64, 7), (0x65, 7), (0x66, 7), (0x67, 7), (0x68, 7),
+    (0x69, 7), (0x6a, 7), (0x6b, 7), (0x6c, 7), (0x6d, 7), (0x6e, 7),
+    (0x6f, 7), (0x70, 7), (0x71, 7), (0x72, 7), (0xfc, 8), (0x73, 7),
+    (0xfd, 8), (0x1ffb, 13), (0x7fff0, 19), (0x1ffc, 13), (0x3ffc, 14), (0x22, 6),
+    (0x7ffd, 15), (0x3, 5), (0x23, 6), (0x4, 5), (0x24, 6), (0x5, 5),
+    (0x25, 6), (0x26, 6), (0x27, 6), (0x6, 5), (0x74, 7), (0x75, 7),
+    (0x28, 6), (0x29, 6), (0x2a, 6), (0x7, 5), (0x2b, 6), (0x76, 7),
+    (0x2c, 6), (0x8, 5), (0x9, 5), (0x2d, 6), (0x77, 7), (0x78, 7),
+    (0x79, 7), (0x7a, 7), (0x7b, 7), (0x7ffe, 15), (0x7fc, 11), (0x3ffd, 14),
+    (0x1ffd, 13), (0xffffffc, 28), (0xfffe6, 20), (0x3fffd2, 22), (0xfffe7, 20), (0xfffe8, 20),
+    (0x3fffd3, 22), (0x3fffd4, 22), (0x3fffd5, 22), (0x7fffd9, 23), (0x3fffd6, 22), (0x7fffda, 23),
+    (0x7fffdb, 23), (0x7fffdc, 23), (0x7fffdd, 23), (0x7fffde, 23), (0xffffeb, 24), (0x7fffdf, 23),
+    (0xffffec, 24), (0xffffed, 24), (0x3fffd7, 22), (0x7fffe0, 23), (0xffffee, 24), (0x7fffe1, 23),
+    (0x7fffe2, 23), (0x7fffe3, 23), (0x7fffe4, 23), (0x1fffdc, 21), (0x3fffd8, 22), (0x7fffe5, 23),
+    (0x3fffd9, 22), (0x7fffe6, 23), (0x7fffe7, 23), (0xffffef, 24), (0x3fffda, 22), (0x1fffdd, 21),
+    (0xfffe9, 20), (0x3fffdb, 22), (0x3fffdc, 22), (0x7fffe8, 23), (0x7fffe9, 23), (0x1fffde, 21),
+    (0x7fffea, 23), (0x3fffdd, 22), (0x3fffde, 22), (0xfffff0, 24), (0x1fffdf, 21), (0x3fffdf, 22),
+    (0x7fffeb, 23), (0x7fffec, 23), (0x1fffe0, 21), (0x1fffe1, 21), (0x3fffe0, 22), (0x1fffe2, 21),
+    (0x7fffed, 23), (0x3fffe1, 22), (0x7fffee, 23), (0x7fffef, 23), (0xfffea, 20), (0x3fffe2, 22),
+    (0x3fffe3, 22), (0x3fffe4, 22), (0x7ffff0, 23), (0x3fffe5, 22), (0x3fffe6, 22), (0x7ffff1, 23),
+    (0x3ffffe0, 26), (0x3ffffe1, 26), (0xfffeb, 20), (0x7fff1, 19), (0x3fffe7, 22), (0x7ffff2, 23),
+    (0x3fffe8, 22), (0x1ffffec, 25), (0x3ffffe2, 26), (0x3ffffe3, 26), (0x3ffffe4, 26), (0x7ffffde, 27),
+    (0x7ffffdf, 27), (0x3ffffe5, 26), (0xfffff1, 24), (0x1ffffed, 25), (0x7fff2, 19), (0x1fffe3, 21),
+    (0x3ffffe6, 26), (0x7ffffe0, 27), (0x7ffffe1, 27), (0x3ffffe7, 26), (0x7ffffe2, 27), (0xfffff2, 24),
+    (0x1fffe4, 21), (0x1fffe5, 21), (0x3ffffe8, 26), (0x3ffffe9, 26), (0xffffffd, 28), (0x7ffffe3, 27),
+    (0x7ffffe4, 27), (0x7ffffe5, 27), (0xfffec, 20), (0xfffff3, 24), (0xfffed, 20), (0x1fffe6, 21),
+    (0x3fffe9, 22), (0x1fffe7, 21), (0x1fffe8, 21), (0x7ffff3, 23), (0x3fffea, 22), (0x3fffeb, 22),
+    (0x1ffffee, 25), (0x1ffffef, 25), (0xfffff4, 24), (0xfffff5, 24), (0x3ffffea, 26), (0x7ffff4, 23),
+    (0x3ffffeb, 26), (0x7ffffe6, 27), (0x3ffffec, 26), (0x3ffffed, 26), (0x7ffffe7, 27), (0x7ffffe8, 27),
+    (0x7ffffe9, 27), (0x7ffffea, 27), (0x7ffffeb, 27), (0xffffffe, 28), (0x7ffffec, 27), (0x7ffffed, 27),
+    (0x7ffffee, 27), (0x7ffffef, 27), (0x7fffff0, 27), (0x3ffffee, 26), (0x3fffffff, 30),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes written like in the examples of RFC 7541 appendix C
+    fn hex(bytes: &str) -> Vec<u8> {
+        let digits: Vec<u8> = bytes.bytes().filter(u8::is_ascii_hexdigit).collect();
+        digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect()
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn table(decoder: &Decoder) -> Vec<(&str, &str)> {
+        decoder
+            .dynamic
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn integers() {
+        // C.1.1 and C.1.2
+        for (value, prefix, encoded) in [(10, 5, "0a"), (1337, 5, "1f9a0a"), (31, 5, "1f00")] {
+            let mut block = vec![];
+            write_int(&mut block, prefix, 0, value);
+            assert_eq!(block, hex(encoded));
+            assert_eq!(read_int(&mut &block[..], prefix).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn integer_errors() {
+        assert!(read_int(&mut &hex("1f9a")[..], 5).is_err());
+        assert!(read_int(&mut &hex("1fffffffffff01")[..], 5).is_err());
+    }
+
+    #[test]
+    fn literal_with_indexing() {
+        // C.2.1
+        let mut decoder = Decoder::new();
+        let block = hex("400a 6375 7374 6f6d 2d6b 6579 0d63 7573 746f 6d2d 6865 6164 6572");
+        let decoded = decoder.decode(&block).unwrap();
+        assert_eq!(decoded, headers(&[("custom-key", "custom-header")]));
+        assert_eq!(table(&decoder), [("custom-key", "custom-header")]);
+        assert_eq!(decoder.size, 55);
+    }
+
+    #[test]
+    fn literals_without_indexing() {
+        // C.2.2 and C.2.3
+        let mut decoder = Decoder::new();
+        let block = hex("040c 2f73 616d 706c 652f 7061 7468");
+        let decoded = decoder.decode(&block).unwrap();
+        assert_eq!(decoded, headers(&[(":path", "/sample/path")]));
+        let block = hex("1008 7061 7373 776f 7264 0673 6563 7265 74");
+        let decoded = decoder.decode(&block).unwrap();
+        assert_eq!(decoded, headers(&[("password", "secret")]));
+        assert!(decoder.dynamic.is_empty());
+    }
+
+    #[test]
+    fn indexed() {
+        // C.2.4
+        let mut decoder = Decoder::new();
+        let decoded = decoder.decode(&hex("82")).unwrap();
+        assert_eq!(decoded, headers(&[(":method", "GET")]));
+        assert!(decoder.dynamic.is_empty());
+    }
+
+    /// C.3 and C.4 have the same requests, without and with Huffman coding
+    fn requests(blocks: [&str; 3]) {
+        let mut decoder = Decoder::new();
+
+        let decoded = decoder.decode(&hex(blocks[0])).unwrap();
+        assert_eq!(
+            decoded,
+            headers(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ])
+        );
+        assert_eq!(table(&decoder), [(":authority", "www.example.com")]);
+        assert_eq!(decoder.size, 57);
+
+        let decoded = decoder.decode(&hex(blocks[1])).unwrap();
+        assert_eq!(
+            decoded,
+            headers(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+                ("cache-control", "no-cache"),
+            ])
+        );
+        assert_eq!(
+            table(&decoder),
+            [
+                ("cache-control", "no-cache"),
+                (":authority", "www.example.com")
+            ]
+        );
+        assert_eq!(decoder.size, 110);
+
+        let decoded = decoder.decode(&hex(blocks[2])).unwrap();
+        assert_eq!(
+            decoded,
+            headers(&[
+                (":method", "GET"),
+                (":scheme", "https"),
+                (":path", "/index.html"),
+                (":authority", "www.example.com"),
+                ("custom-key", "custom-value"),
+            ])
+        );
+        assert_eq!(
+            table(&decoder),
+            [
+                ("custom-key", "custom-value"),
+                ("cache-control", "no-cache"),
+                (":authority", "www.example.com"),
+            ]
+        );
+        assert_eq!(decoder.size, 164);
+    }
+
+    #[test]
+    fn requests_without_huffman() {
+        requests([
+            "8286 8441 0f77 7777 2e65 7861 6d70 6c65 2e63 6f6d",
+            "8286 84be 5808 6e6f 2d63 6163 6865",
+            "8287 85bf 400a 6375 7374 6f6d 2d6b 6579 0c63 7573 746f 6d2d 7661 6c75 65",
+        ]);
+    }
+
+    #[test]
+    fn requests_with_huffman() {
+        requests([
+            "8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff",
+            "8286 84be 5886 a8eb 1064 9cbf",
+            "8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf",
+        ]);
+    }
+
+    #[test]
+    fn responses_with_eviction() {
+        // C.6, with the table size of 256 bytes the examples use
+        let mut decoder = Decoder::new();
+        decoder.decode(&hex("3fe101")).unwrap();
+        assert_eq!(decoder.max_size, 256);
+
+        let decoded = decoder
+            .decode(&hex(
+                "4882 6402 5885 aec3 771a 4b61 96d0 7abe 9410 54d4 44a8 2005 9504 0b81 66e0 82a6
+                 2d1b ff6e 919d 29ad 1718 63c7 8f0b 97c8 e9ae 82ae 43d3",
+            ))
+            .unwrap();
+        assert_eq!(
+            decoded,
+            headers(&[
+                (":status", "302"),
+                ("cache-control", "private"),
+                ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                ("location", "https://www.example.com"),
+            ])
+        );
+        assert_eq!(decoder.size, 222);
+
+        let decoded = decoder.decode(&hex("4883 640e ffc1 c0bf")).unwrap();
+        assert_eq!(
+            decoded,
+            headers(&[
+                (":status", "307"),
+                ("cache-control", "private"),
+                ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                ("location", "https://www.example.com"),
+            ])
+        );
+        // ":status: 302" made room for ":status: 307"
+        assert_eq!(
+            table(&decoder),
+            [
+                (":status", "307"),
+                ("location", "https://www.example.com"),
+                ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                ("cache-control", "private"),
+            ]
+        );
+        assert_eq!(decoder.size, 222);
+
+        let decoded = decoder
+            .decode(&hex(
+                "88c1 6196 d07a be94 1054 d444 a820 0595 040b 8166 e084 a62d 1bff c05a 839b d9ab
+                 77ad 94e7 821d d7f2 e6c7 b335 dfdf cd5b 3960 d5af 2708 7f36 72c1 ab27 0fb5 291f
+                 9587 3160 65c0 03ed 4ee5 b106 3d50 07",
+            ))
+            .unwrap();
+        assert_eq!(
+            decoded,
+            headers(&[
+                (":status", "200"),
+                ("cache-control", "private"),
+                ("date", "Mon, 21 Oct 2013 20:13:22 GMT"),
+                ("location", "https://www.example.com"),
+                ("content-encoding", "gzip"),
+                (
+                    "set-cookie",
+                    "foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1"
+                ),
+            ])
+        );
+        assert_eq!(
+            table(&decoder),
+            [
+                (
+                    "set-cookie",
+                    "foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1"
+                ),
+                ("content-encoding", "gzip"),
+                ("date", "Mon, 21 Oct 2013 20:13:22 GMT"),
+            ]
+        );
+        assert_eq!(decoder.size, 215);
+    }
+
+    #[test]
+    fn table_size_above_our_limit() {
+        // 4097, one more than we announce
+        assert!(Decoder::new().decode(&hex("3fe21f")).is_err());
+    }
+
+    #[test]
+    fn invalid_index() {
+        // Index 0, and the first dynamic index while the table is empty
+        assert!(Decoder::new().decode(&hex("80")).is_err());
+        assert!(Decoder::new().decode(&hex("be")).is_err());
+    }
+
+    #[test]
+    fn invalid_huffman_padding() {
+        // "www.example.com" with a padding byte too many, and with padding that isn't all ones
+        assert!(huffman_decode(&hex("f1e3 c2e5 f23a 6ba0 ab90 f4ff ff")).is_err());
+        assert!(huffman_decode(&hex("f1e3 c2e5 f23a 6ba0 ab90 f4fe")).is_err());
+        assert_eq!(
+            huffman_decode(&hex("f1e3 c2e5 f23a 6ba0 ab90 f4ff")).unwrap(),
+            b"www.example.com"
+        );
+    }
+
+    #[test]
+    fn truncated_string() {
+        assert!(Decoder::new().decode(&hex("400a 6375 7374")).is_err());
+    }
+
+    #[test]
+    fn encoded_headers_decode() {
+        let block = encode(&[
+            (":status", "200"),
+            ("grpc-message", "a".repeat(200).as_str()),
+        ]);
+        let decoded = Decoder::new().decode(&block).unwrap();
+        assert_eq!(
+            decoded,
+            headers(&[(":status", "200"), ("grpc-message", &"a".repeat(200))])
+        );
+    }
+}
@@ -0,0 +1,318 @@
+//! HTTP/2 framing (RFC 9113), enough for a server speaking cleartext HTTP/2 with
+//! prior knowledge, which is how gRPC clients connect without TLS.
+
+use std::io::{Read, Write};
+
+use anyhow::Context;
+
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Default and maximum size of frames we accept and send
+pub const MAX_FRAME_SIZE: usize = 16 * 1024;
+pub const DEFAULT_WINDOW_SIZE: i64 = 65_535;
+const MAX_WINDOW_SIZE: i64 = i32::MAX as i64;
+
+pub const DATA: u8 = 0x0;
+pub const HEADERS: u8 = 0x1;
+pub const RST_STREAM: u8 = 0x3;
+pub const SETTINGS: u8 = 0x4;
+pub const PING: u8 = 0x6;
+pub const GOAWAY: u8 = 0x7;
+pub const WINDOW_UPDATE: u8 = 0x8;
+pub const CONTINUATION: u8 = 0x9;
+
+pub const FLAG_END_STREAM: u8 = 0x1;
+pub const FLAG_ACK: u8 = 0x1;
+pub const FLAG_END_HEADERS: u8 = 0x4;
+pub const FLAG_PADDED: u8 = 0x8;
+pub const FLAG_PRIORITY: u8 = 0x20;
+
+pub const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+pub const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+pub const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+
+pub const NO_ERROR: u32 = 0x0;
+pub const PROTOCOL_ERROR: u32 = 0x1;
+pub const REFUSED_STREAM: u32 = 0x7;
+
+#[derive(Debug)]
+pub struct Frame {
+    pub kind: u8,
+    pub flags: u8,
+    pub stream: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// The payload of DATA and HEADERS frames without padding and priority fields
+    pub fn content(&self) -> anyhow::Result<&[u8]> {
+        let mut content = &self.payload[..];
+
+        if self.has(FLAG_PADDED) {
+            let (&padding, rest) = content.split_first().context("missing pad length")?;
+            anyhow::ensure!(padding as usize <= rest.len(), "too much padding");
+            content = &rest[..rest.len() - padding as usize];
+        }
+        if self.kind == HEADERS && self.has(FLAG_PRIORITY) {
+            anyhow::ensure!(content.len() >= 5, "truncated priority");
+            content = &content[5..];
+        }
+
+        Ok(content)
+    }
+}
+
+pub fn read_frame(reader: &mut impl Read) -> anyhow::Result<Frame> {
+    let mut header = [0u8; 9];
+    reader.read_exact(&mut header)?;
+
+    let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+    anyhow::ensure!(len <= MAX_FRAME_SIZE, "frame of {len} bytes is too large");
+    let stream = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7fff_ffff;
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+
+    Ok(Frame {
+        kind: header[3],
+        flags: header[4],
+        stream,
+        payload,
+    })
+}
+
+pub fn write_frame(
+    writer: &mut impl Write,
+    kind: u8,
+    flags: u8,
+    stream: u32,
+    payload: &[u8],
+) -> std::io::Result<()> {
+    let len = (payload.len() as u32).to_be_bytes();
+
+    let mut frame = Vec::with_capacity(payload.len() + 9);
+    frame.extend_from_slice(&len[1..]);
+    frame.push(kind);
+    frame.push(flags);
+    frame.extend_from_slice(&stream.to_be_bytes());
+    frame.extend_from_slice(payload);
+
+    writer.write_all(&frame)
+}
+
+/// Parses the (identifier, value) pairs of a SETTINGS frame
+pub fn settings(payload: &[u8]) -> anyhow::Result<Vec<(u16, u32)>> {
+    anyhow::ensure!(payload.len().is_multiple_of(6), "invalid settings length");
+
+    Ok(payload
+        .chunks(6)
+        .map(|setting| {
+            (
+                u16::from_be_bytes([setting[0], setting[1]]),
+                u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]),
+            )
+        })
+        .collect())
+}
+
+pub fn settings_payload(settings: &[(u16, u32)]) -> Vec<u8> {
+    settings
+        .iter()
+        .flat_map(|(id, value)| id.to_be_bytes().into_iter().chain(value.to_be_bytes()))
+        .collect()
+}
+
+pub fn read_u32(payload: &[u8]) -> anyhow::Result<u32> {
+    let bytes = payload.get(..4).context("truncated frame")?;
+    Ok(u32::from_be_bytes(bytes.try_into()?) & 0x7fff_ffff)
+}
+
+/// How much DATA the peer accepts on the connection or a stream. It goes negative when the
+/// peer lowers its initial window size after we've sent data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window(pub i64);
+
+impl Window {
+    /// Applies a WINDOW_UPDATE or a change of the initial window size,
+    /// which must not grow the window past 2^31-1 bytes
+    pub fn grow(&mut self, increment: i64) -> anyhow::Result<()> {
+        let size = self.0 + increment;
+        anyhow::ensure!(
+            size <= MAX_WINDOW_SIZE,
+            "window of {size} bytes is too large"
+        );
+        self.0 = size;
+        Ok(())
+    }
+}
+
+/// Takes `len` bytes from both windows if they both have room for them
+pub fn reserve(len: usize, stream: &mut Window, connection: &mut Window) -> bool {
+    let len = len as i64;
+    if len > stream.0 || len > connection.0 {
+        return false;
+    }
+    stream.0 -= len;
+    connection.0 -= len;
+    true
+}
+
+/// The increment of a WINDOW_UPDATE frame, which can't be 0
+pub fn window_increment(payload: &[u8]) -> anyhow::Result<i64> {
+    anyhow::ensure!(payload.len() == 4, "invalid WINDOW_UPDATE");
+    let increment = read_u32(payload)?;
+    anyhow::ensure!(increment > 0, "WINDOW_UPDATE of 0");
+    Ok(increment as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(kind: u8, flags: u8, stream: u32, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![];
+        write_frame(&mut bytes, kind, flags, stream, payload).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let bytes = frame(HEADERS, FLAG_END_HEADERS, 3, b"abc");
+        assert_eq!(bytes[..9], [0, 0, 3, HEADERS, FLAG_END_HEADERS, 0, 0, 0, 3]);
+
+        let read = read_frame(&mut &bytes[..]).unwrap();
+        assert_eq!(
+            (read.kind, read.flags, read.stream),
+            (HEADERS, FLAG_END_HEADERS, 3)
+        );
+        assert_eq!(read.payload, b"abc");
+    }
+
+    #[test]
+    fn frame_length_limit() {
+        let largest = frame(DATA, 0, 1, &[0; MAX_FRAME_SIZE]);
+        assert_eq!(
+            read_frame(&mut &largest[..]).unwrap().payload.len(),
+            MAX_FRAME_SIZE
+        );
+
+        let too_large = frame(DATA, 0, 1, &[0; MAX_FRAME_SIZE + 1]);
+        assert!(read_frame(&mut &too_large[..]).is_err());
+    }
+
+    #[test]
+    fn truncated_frame() {
+        let bytes = frame(DATA, 0, 1, b"abc");
+        assert!(read_frame(&mut &bytes[..bytes.len() - 1]).is_err());
+        assert!(read_frame(&mut &bytes[..5]).is_err());
+    }
+
+    #[test]
+    fn reserved_stream_bit_is_ignored() {
+        let mut bytes = frame(DATA, 0, 1, b"");
+        bytes[5] |= 0x80;
+        assert_eq!(read_frame(&mut &bytes[..]).unwrap().stream, 1);
+    }
+
+    #[test]
+    fn padding_and_priority() {
+        let frame = Frame {
+            kind: HEADERS,
+            flags: FLAG_PADDED | FLAG_PRIORITY,
+            stream: 1,
+            payload: [&[2][..], &[0, 0, 0, 0, 16], b"block", &[0, 0]].concat(),
+        };
+        assert_eq!(frame.content().unwrap(), b"block");
+    }
+
+    #[test]
+    fn padding_longer_than_frame() {
+        let frame = Frame {
+            kind: DATA,
+            flags: FLAG_PADDED,
+            stream: 1,
+            payload: vec![4, 0, 0, 0],
+        };
+        assert!(frame.content().is_err());
+
+        // Nothing but padding is fine
+        let frame = Frame {
+            payload: vec![3, 0, 0, 0],
+            ..frame
+        };
+        assert_eq!(frame.content().unwrap(), b"");
+    }
+
+    #[test]
+    fn truncated_priority() {
+        let frame = Frame {
+            kind: HEADERS,
+            flags: FLAG_PRIORITY,
+            stream: 1,
+            payload: vec![0, 0, 0, 0],
+        };
+        assert!(frame.content().is_err());
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let values = [(SETTINGS_INITIAL_WINDOW_SIZE, 1 << 20), (0x99, 7)];
+        let payload = settings_payload(&values);
+        assert_eq!(payload.len(), 12);
+        assert_eq!(settings(&payload).unwrap(), values);
+        assert!(settings(&payload[..7]).is_err());
+        assert!(settings(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn window_updates() {
+        assert_eq!(window_increment(&[0, 0, 1, 0]).unwrap(), 256);
+        // The reserved bit isn't part of the increment
+        assert_eq!(window_increment(&[0x80, 0, 0, 1]).unwrap(), 1);
+        assert!(window_increment(&[0, 0, 0, 0]).is_err());
+        assert!(window_increment(&[0, 0, 1]).is_err());
+        assert!(window_increment(&[0, 0, 0, 1, 0]).is_err());
+    }
+
+    #[test]
+    fn window_limit() {
+        let mut window = Window(DEFAULT_WINDOW_SIZE);
+        window.grow(MAX_WINDOW_SIZE - DEFAULT_WINDOW_SIZE).unwrap();
+        assert_eq!(window, Window(MAX_WINDOW_SIZE));
+        assert!(window.grow(1).is_err());
+        assert_eq!(window, Window(MAX_WINDOW_SIZE));
+    }
+
+    #[test]
+    fn window_goes_negative_when_lowered() {
+        let mut window = Window(DEFAULT_WINDOW_SIZE);
+        let mut connection = Window(DEFAULT_WINDOW_SIZE * 2);
+        assert!(reserve(60_000, &mut window, &mut connection));
+        // The initial window size set to 1000
+        window.grow(1000 - DEFAULT_WINDOW_SIZE).unwrap();
+        assert_eq!(window, Window(1000 - 60_000));
+        assert!(!reserve(1, &mut window, &mut connection));
+    }
+
+    #[test]
+    fn reserve_needs_both_windows() {
+        let mut stream = Window(100);
+        let mut connection = Window(50);
+        assert!(!reserve(80, &mut stream, &mut connection));
+        assert_eq!((stream, connection), (Window(100), Window(50)));
+
+        assert!(reserve(50, &mut stream, &mut connection));
+        assert_eq!((stream, connection), (Window(50), Window(0)));
+        assert!(!reserve(1, &mut stream, &mut connection));
+        // Empty messages can always be sent
+        assert!(reserve(0, &mut stream, &mut connection));
+
+        connection.grow(30).unwrap();
+        assert!(reserve(30, &mut stream, &mut connection));
+        assert_eq!((stream, connection), (Window(20), Window(0)));
+    }
+}
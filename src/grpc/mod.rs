@@ -0,0 +1,702 @@
+//! Opt-in gRPC service on localhost, a typed alternative to the JSON API for companion apps.
+//!
+//! Clients connect with cleartext HTTP/2 (`h2c` with prior knowledge), which is what gRPC
+//! libraries use for `http://` addresses. The service is described in headset_battery.proto.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, mpsc};
+use std::thread;
+
+use anyhow::Context;
+use log::{debug, error, info};
+use winit::event_loop::EventLoopProxy;
+
+use crate::UserEvent;
+use crate::api::{self, DeviceResponse};
+use crate::headset_control::Device;
+
+use self::http2::{Frame, Window};
+
+mod hpack;
+mod http2;
+mod proto;
+
+pub const DEFAULT_PORT: u16 = 5839;
+
+const SERVICE_PREFIX: &str = "/headset_battery.v1.HeadsetBattery/";
+const MAX_STREAMS: u32 = 100;
+/// Requests are empty or a few bytes, anything bigger is a broken client
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+// gRPC status codes
+const OK: u32 = 0;
+const INVALID_ARGUMENT: u32 = 3;
+const NOT_FOUND: u32 = 5;
+const RESOURCE_EXHAUSTED: u32 = 8;
+const UNIMPLEMENTED: u32 = 12;
+const UNAVAILABLE: u32 = 14;
+
+/// A failed call, with the gRPC status code and message
+type Status = (u32, String);
+
+pub struct GrpcServer {
+    shared: Arc<Shared>,
+    stop: Arc<AtomicBool>,
+    addr: SocketAddr,
+}
+
+struct Shared {
+    state: RwLock<State>,
+    /// Channels to the connection threads
+    connections: Mutex<Vec<mpsc::Sender<Event>>>,
+    /// Settings changes and refreshes are carried out by the event loop
+    proxy: Mutex<EventLoopProxy<UserEvent>>,
+}
+
+#[derive(Default)]
+struct State {
+    devices: Vec<Device>,
+    selected: usize,
+    notifications_enabled: bool,
+}
+
+enum Event {
+    Frame(Frame),
+    /// The client closed the connection, or sent something that isn't HTTP/2
+    Closed(Option<anyhow::Error>),
+    DevicesChanged,
+    Shutdown,
+}
+
+impl GrpcServer {
+    pub fn start(port: u16, proxy: EventLoopProxy<UserEvent>) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .with_context(|| format!("binding to port {port}"))?;
+        let addr = listener.local_addr().context("getting local address")?;
+
+        let shared = Arc::new(Shared {
+            state: RwLock::new(State::default()),
+            connections: Mutex::new(vec![]),
+            proxy: Mutex::new(proxy),
+        });
+        let stop = Arc::new(AtomicBool::new(false));
+
+        {
+            let shared = shared.clone();
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("grpc-server".to_string())
+                .spawn(move || accept_loop(listener, shared, stop))
+                .context("spawning gRPC server thread")?;
+        }
+
+        info!("gRPC service listening on {addr}");
+
+        Ok(Self { shared, stop, addr })
+    }
+
+    /// Makes the latest poll result available to clients, and updates `WatchDevices` streams
+    pub fn publish(&self, devices: &[Device], selected: usize, notifications_enabled: bool) {
+        {
+            let mut state = self.shared.state.write().unwrap_or_else(|e| e.into_inner());
+            state.devices = devices.to_vec();
+            state.selected = selected;
+            state.notifications_enabled = notifications_enabled;
+        }
+        // Streams only send something when their device list differs
+        self.shared.notify(|| Event::DevicesChanged);
+    }
+}
+
+impl Drop for GrpcServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.shared.notify(|| Event::Shutdown);
+        // Wake up the blocking accept() so the thread sees the stop flag
+        let _ = TcpStream::connect(self.addr);
+        info!("gRPC service stopped");
+    }
+}
+
+impl Shared {
+    fn notify(&self, event: impl Fn() -> Event) {
+        let mut connections = self.connections.lock().unwrap_or_else(|e| e.into_inner());
+        // Sending fails once the connection thread has exited
+        connections.retain(|connection| connection.send(event()).is_ok());
+    }
+
+    fn send_event(&self, event: UserEvent) -> Result<(), Status> {
+        let proxy = self.proxy.lock().unwrap_or_else(|e| e.into_inner());
+        proxy
+            .send_event(event)
+            .map_err(|_| (UNAVAILABLE, "the program is exiting".to_string()))
+    }
+
+    fn device_list(&self) -> Vec<DeviceResponse> {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        api::device_list(&state.devices, state.selected)
+    }
+
+    fn settings(&self) -> proto::Settings {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        settings(&state)
+    }
+
+    /// Applies the change to the snapshot right away, so that the response and
+    /// following calls see it before the event loop has caught up
+    fn update_settings(
+        &self,
+        request: proto::UpdateSettingsRequest,
+    ) -> Result<proto::Settings, Status> {
+        let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(id) = request.selected_device_id {
+            let idx = state
+                .devices
+                .iter()
+                .position(|device| device.id() == id)
+                .ok_or_else(|| (NOT_FOUND, format!("no device with id {id}")))?;
+            state.selected = idx;
+            self.send_event(UserEvent::SelectDevice(id))?;
+        }
+        if let Some(enabled) = request.notifications_enabled {
+            state.notifications_enabled = enabled;
+            self.send_event(UserEvent::SetNotifications(enabled))?;
+        }
+
+        Ok(settings(&state))
+    }
+}
+
+fn settings(state: &State) -> proto::Settings {
+    proto::Settings {
+        notifications_enabled: state.notifications_enabled,
+        selected_device_id: state
+            .devices
+            .get(state.selected)
+            .map(Device::id)
+            .unwrap_or_default(),
+    }
+}
+
+fn accept_loop(listener: TcpListener, shared: Arc<Shared>, stop: Arc<AtomicBool>) {
+    for stream in listener.incoming() {
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+
+        match stream {
+            Ok(stream) => {
+                let shared = shared.clone();
+                let res = thread::Builder::new()
+                    .name("grpc-connection".to_string())
+                    .spawn(move || {
+                        if let Err(e) = serve(stream, shared) {
+                            debug!("gRPC connection failed: {e:?}");
+                        }
+                    });
+                if let Err(e) = res {
+                    error!("Failed to spawn gRPC connection thread: {e:?}");
+                }
+            }
+            Err(e) => debug!("Failed to accept gRPC connection: {e:?}"),
+        }
+    }
+}
+
+/// Frames are read on a separate thread, so that the connection thread
+/// can wait for both client frames and device changes
+fn serve(socket: TcpStream, shared: Arc<Shared>) -> anyhow::Result<()> {
+    let reader = socket.try_clone().context("cloning socket")?;
+    let (sender, events) = mpsc::channel();
+
+    {
+        let sender = sender.clone();
+        thread::Builder::new()
+            .name("grpc-reader".to_string())
+            .spawn(move || {
+                let error = read_frames(reader, &sender).err();
+                let _ = sender.send(Event::Closed(error));
+            })
+            .context("spawning gRPC reader thread")?;
+    }
+    shared
+        .connections
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(sender);
+
+    let result = Connection::new(socket.try_clone()?, shared).run(events);
+    // Unblocks the reader thread
+    let _ = socket.shutdown(Shutdown::Both);
+    result
+}
+
+fn read_frames(mut reader: TcpStream, sender: &mpsc::Sender<Event>) -> anyhow::Result<()> {
+    let mut preface = [0u8; http2::PREFACE.len()];
+    reader.read_exact(&mut preface)?;
+    anyhow::ensure!(preface == http2::PREFACE, "not an HTTP/2 connection");
+
+    loop {
+        let frame = http2::read_frame(&mut reader)?;
+        if sender.send(Event::Frame(frame)).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+struct Connection {
+    socket: TcpStream,
+    shared: Arc<Shared>,
+    decoder: hpack::Decoder,
+    streams: HashMap<u32, Stream>,
+    /// A header block that continues in CONTINUATION frames,
+    /// with its stream and whether it ends the stream
+    continuation: Option<(u32, Vec<u8>, bool)>,
+    /// How much DATA the client currently accepts on the connection
+    send_window: Window,
+    /// Window of new streams, from the client's settings
+    initial_window: i64,
+    last_stream: u32,
+}
+
+struct Stream {
+    path: String,
+    /// Request messages received so far
+    body: Vec<u8>,
+    send_window: Window,
+    /// A response message waiting for the flow control window
+    pending: Option<Vec<u8>>,
+    /// Status sent in the trailers after the response, ending unary calls
+    status: Option<Status>,
+    /// `WatchDevices` calls stay open and are sent every change
+    watching: bool,
+    last_sent: Vec<u8>,
+}
+
+impl Connection {
+    fn new(socket: TcpStream, shared: Arc<Shared>) -> Self {
+        Self {
+            socket,
+            shared,
+            decoder: hpack::Decoder::new(),
+            streams: HashMap::new(),
+            continuation: None,
+            send_window: Window(http2::DEFAULT_WINDOW_SIZE),
+            initial_window: http2::DEFAULT_WINDOW_SIZE,
+            last_stream: 0,
+        }
+    }
+
+    fn run(mut self, events: mpsc::Receiver<Event>) -> anyhow::Result<()> {
+        let settings = http2::settings_payload(&[
+            (http2::SETTINGS_MAX_CONCURRENT_STREAMS, MAX_STREAMS),
+            (
+                http2::SETTINGS_HEADER_TABLE_SIZE,
+                hpack::DEFAULT_TABLE_SIZE as u32,
+            ),
+        ]);
+        self.write(http2::SETTINGS, 0, 0, &settings)?;
+
+        for event in events {
+            match event {
+                Event::Frame(frame) => {
+                    if let Err(e) = self.handle_frame(frame) {
+                        self.go_away(http2::PROTOCOL_ERROR);
+                        return Err(e);
+                    }
+                }
+                Event::DevicesChanged => self.update_watchers()?,
+                Event::Shutdown => {
+                    self.go_away(http2::NO_ERROR);
+                    return Ok(());
+                }
+                Event::Closed(error) => return error.map_or(Ok(()), Err),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_frame(&mut self, frame: Frame) -> anyhow::Result<()> {
+        if self.continuation.is_some() && frame.kind != http2::CONTINUATION {
+            anyhow::bail!("expected CONTINUATION, got frame type {}", frame.kind);
+        }
+
+        match frame.kind {
+            http2::SETTINGS if !frame.has(http2::FLAG_ACK) => {
+                for (id, value) in http2::settings(&frame.payload)? {
+                    if id == http2::SETTINGS_INITIAL_WINDOW_SIZE {
+                        anyhow::ensure!(value <= i32::MAX as u32, "window size too large");
+                        let delta = value as i64 - self.initial_window;
+                        self.initial_window = value as i64;
+                        for stream in self.streams.values_mut() {
+                            stream.send_window.grow(delta)?;
+                        }
+                    }
+                }
+                self.write(http2::SETTINGS, http2::FLAG_ACK, 0, &[])?;
+                self.flush_all()?;
+            }
+            http2::PING if !frame.has(http2::FLAG_ACK) => {
+                anyhow::ensure!(frame.payload.len() == 8, "invalid PING");
+                self.write(http2::PING, http2::FLAG_ACK, 0, &frame.payload)?;
+            }
+            http2::WINDOW_UPDATE => {
+                let increment = http2::window_increment(&frame.payload)?;
+                if frame.stream == 0 {
+                    self.send_window.grow(increment)?;
+                } else if let Some(stream) = self.streams.get_mut(&frame.stream) {
+                    stream.send_window.grow(increment)?;
+                }
+                self.flush_all()?;
+            }
+            http2::HEADERS => {
+                let block = frame.content()?.to_vec();
+                let end_stream = frame.has(http2::FLAG_END_STREAM);
+                if frame.has(http2::FLAG_END_HEADERS) {
+                    self.handle_headers(frame.stream, &block, end_stream)?;
+                } else {
+                    self.continuation = Some((frame.stream, block, end_stream));
+                }
+            }
+            http2::CONTINUATION => {
+                let (stream, mut block, end_stream) = self
+                    .continuation
+                    .take()
+                    .context("unexpected CONTINUATION")?;
+                anyhow::ensure!(stream == frame.stream, "CONTINUATION on another stream");
+                block.extend_from_slice(&frame.payload);
+                anyhow::ensure!(block.len() <= MAX_REQUEST_SIZE, "headers too large");
+
+                if frame.has(http2::FLAG_END_HEADERS) {
+                    self.handle_headers(stream, &block, end_stream)?;
+                } else {
+                    self.continuation = Some((stream, block, end_stream));
+                }
+            }
+            http2::DATA => {
+                // Requests are tiny, so the window is handed back right away
+                let len = frame.payload.len() as u32;
+                if len > 0 {
+                    self.write(http2::WINDOW_UPDATE, 0, 0, &len.to_be_bytes())?;
+                }
+
+                let Some(stream) = self.streams.get_mut(&frame.stream) else {
+                    // The call may have been answered or cancelled already
+                    return Ok(());
+                };
+                stream.body.extend_from_slice(frame.content()?);
+                if stream.body.len() > MAX_REQUEST_SIZE {
+                    let status = (RESOURCE_EXHAUSTED, "request too large".to_string());
+                    return self.fail(frame.stream, status);
+                }
+
+                if frame.has(http2::FLAG_END_STREAM) {
+                    self.dispatch(frame.stream)?;
+                } else if len > 0 {
+                    self.write(http2::WINDOW_UPDATE, 0, frame.stream, &len.to_be_bytes())?;
+                }
+            }
+            http2::RST_STREAM => {
+                self.streams.remove(&frame.stream);
+            }
+            // Settings and ping acknowledgements, GOAWAY (the client closes
+            // the socket when it's done) and PRIORITY are nothing to act on
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn handle_headers(&mut self, id: u32, block: &[u8], end_stream: bool) -> anyhow::Result<()> {
+        // Decoding keeps the header table in sync, even for streams we don't serve
+        let headers = self.decoder.decode(block).context("decoding headers")?;
+
+        if self.streams.contains_key(&id) {
+            // Trailers at the end of the request
+            if end_stream {
+                self.dispatch(id)?;
+            }
+            return Ok(());
+        }
+
+        anyhow::ensure!(
+            id % 2 == 1 && id > self.last_stream,
+            "invalid stream id {id}"
+        );
+        self.last_stream = id;
+
+        if self.streams.len() >= MAX_STREAMS as usize {
+            let code = http2::REFUSED_STREAM.to_be_bytes();
+            return Ok(self.write(http2::RST_STREAM, 0, id, &code)?);
+        }
+
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let is_grpc = header(":method") == Some("POST")
+            && header("content-type").is_some_and(|t| t.starts_with("application/grpc"));
+        if !is_grpc {
+            let block = hpack::encode(&[(":status", "415")]);
+            let flags = http2::FLAG_END_HEADERS | http2::FLAG_END_STREAM;
+            return Ok(self.write(http2::HEADERS, flags, id, &block)?);
+        }
+
+        let path = header(":path").unwrap_or_default().to_string();
+        debug!("gRPC call: {path}");
+        self.streams.insert(
+            id,
+            Stream {
+                path,
+                body: vec![],
+                send_window: Window(self.initial_window),
+                pending: None,
+                status: None,
+                watching: false,
+                last_sent: vec![],
+            },
+        );
+
+        if end_stream {
+            self.dispatch(id)?;
+        }
+        Ok(())
+    }
+
+    /// Runs the call once the whole request has arrived
+    fn dispatch(&mut self, id: u32) -> anyhow::Result<()> {
+        let Some(stream) = self.streams.get(&id) else {
+            return Ok(());
+        };
+        let method = stream
+            .path
+            .strip_prefix(SERVICE_PREFIX)
+            .unwrap_or_default()
+            .to_string();
+
+        let request = match request_message(&stream.body) {
+            Ok(request) => request,
+            Err(status) => return self.fail(id, status),
+        };
+
+        let response = match method.as_str() {
+            "ListDevices" => Ok(proto::encode_device_list(&self.shared.device_list())),
+            "WatchDevices" => {
+                self.write_response_headers(id)?;
+                if let Some(stream) = self.streams.get_mut(&id) {
+                    stream.watching = true;
+                }
+                return self.update_watchers();
+            }
+            "Refresh" => self.shared.send_event(UserEvent::Refresh).map(|()| vec![]),
+            "GetSettings" => Ok(proto::encode_settings(&self.shared.settings())),
+            "UpdateSettings" => proto::decode_update_settings(&request)
+                .map_err(|e| (INVALID_ARGUMENT, format!("{e:#}")))
+                .and_then(|request| {
+                    let selection_changed = request.selected_device_id.is_some();
+                    let settings = self.shared.update_settings(request)?;
+                    if selection_changed {
+                        self.shared.notify(|| Event::DevicesChanged);
+                    }
+                    Ok(proto::encode_settings(&settings))
+                }),
+            _ => Err((UNIMPLEMENTED, format!("unknown method {method}"))),
+        };
+
+        match response {
+            Ok(message) => {
+                self.write_response_headers(id)?;
+                if let Some(stream) = self.streams.get_mut(&id) {
+                    stream.pending = Some(length_prefixed(&message));
+                    stream.status = Some((OK, String::new()));
+                }
+                self.flush(id)
+            }
+            Err(status) => self.fail(id, status),
+        }
+    }
+
+    /// Ends the call with an error status, without a response message
+    fn fail(&mut self, id: u32, (code, message): Status) -> anyhow::Result<()> {
+        debug!("gRPC call failed with status {code}: {message}");
+        self.streams.remove(&id);
+
+        let code = code.to_string();
+        let message = percent_encode(&message);
+        let block = hpack::encode(&[
+            (":status", "200"),
+            ("content-type", "application/grpc"),
+            ("grpc-status", code.as_str()),
+            ("grpc-message", message.as_str()),
+        ]);
+        let flags = http2::FLAG_END_HEADERS | http2::FLAG_END_STREAM;
+        Ok(self.write(http2::HEADERS, flags, id, &block)?)
+    }
+
+    fn write_response_headers(&mut self, id: u32) -> anyhow::Result<()> {
+        let block = hpack::encode(&[(":status", "200"), ("content-type", "application/grpc")]);
+        Ok(self.write(http2::HEADERS, http2::FLAG_END_HEADERS, id, &block)?)
+    }
+
+    /// Queues the device list on the watching streams that haven't seen it yet
+    fn update_watchers(&mut self) -> anyhow::Result<()> {
+        let message = length_prefixed(&proto::encode_device_list(&self.shared.device_list()));
+
+        let mut changed = vec![];
+        for (id, stream) in &mut self.streams {
+            if stream.watching && stream.last_sent != message {
+                // A change the client hasn't made room for yet is just replaced
+                stream.pending = Some(message.clone());
+                stream.last_sent = message.clone();
+                changed.push(*id);
+            }
+        }
+
+        for id in changed {
+            self.flush(id)?;
+        }
+        Ok(())
+    }
+
+    fn flush_all(&mut self) -> anyhow::Result<()> {
+        let ids: Vec<u32> = self.streams.keys().copied().collect();
+        for id in ids {
+            self.flush(id)?;
+        }
+        Ok(())
+    }
+
+    /// Sends the pending message if the flow control windows allow it,
+    /// followed by the trailers for finished calls
+    fn flush(&mut self, id: u32) -> anyhow::Result<()> {
+        let Some(stream) = self.streams.get_mut(&id) else {
+            return Ok(());
+        };
+
+        if let Some(message) = &stream.pending {
+            if !http2::reserve(
+                message.len(),
+                &mut stream.send_window,
+                &mut self.send_window,
+            ) {
+                return Ok(());
+            }
+
+            for chunk in message.chunks(http2::MAX_FRAME_SIZE) {
+                http2::write_frame(&mut self.socket, http2::DATA, 0, id, chunk)?;
+            }
+            stream.pending = None;
+        }
+
+        if let Some((code, message)) = stream.status.take() {
+            let code = code.to_string();
+            let message = percent_encode(&message);
+            let mut trailers = vec![("grpc-status", code.as_str())];
+            if !message.is_empty() {
+                trailers.push(("grpc-message", message.as_str()));
+            }
+            let flags = http2::FLAG_END_HEADERS | http2::FLAG_END_STREAM;
+            http2::write_frame(
+                &mut self.socket,
+                http2::HEADERS,
+                flags,
+                id,
+                &hpack::encode(&trailers),
+            )?;
+            self.streams.remove(&id);
+        }
+
+        Ok(())
+    }
+
+    fn go_away(&mut self, code: u32) {
+        let mut payload = self.last_stream.to_be_bytes().to_vec();
+        payload.extend_from_slice(&code.to_be_bytes());
+        let _ = self.write(http2::GOAWAY, 0, 0, &payload);
+    }
+
+    fn write(&mut self, kind: u8, flags: u8, stream: u32, payload: &[u8]) -> std::io::Result<()> {
+        http2::write_frame(&mut self.socket, kind, flags, stream, payload)
+    }
+}
+
+/// The message of a unary request, without the gRPC length prefix
+fn request_message(body: &[u8]) -> Result<Vec<u8>, Status> {
+    // Clients may leave out empty messages entirely
+    if body.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let invalid = || (INVALID_ARGUMENT, "invalid request message".to_string());
+    let (header, message) = body.split_at_checked(5).ok_or_else(invalid)?;
+    if header[0] != 0 {
+        return Err((UNIMPLEMENTED, "compression is not supported".to_string()));
+    }
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if message.len() != len {
+        return Err(invalid());
+    }
+
+    Ok(message.to_vec())
+}
+
+fn length_prefixed(message: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(message.len() + 5);
+    framed.push(0);
+    framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    framed.extend_from_slice(message);
+    framed
+}
+
+/// `grpc-message` is percent-encoded, leaving printable ASCII as is
+fn percent_encode(message: &str) -> String {
+    let mut encoded = String::with_capacity(message.len());
+    for byte in message.bytes() {
+        if (b' '..=b'~').contains(&byte) && byte != b'%' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_messages() {
+        assert_eq!(request_message(&[]).unwrap(), b"");
+        assert_eq!(request_message(&length_prefixed(b"")).unwrap(), b"");
+        assert_eq!(request_message(&length_prefixed(b"abc")).unwrap(), b"abc");
+        assert_eq!(length_prefixed(b"abc"), [0, 0, 0, 0, 3, b'a', b'b', b'c']);
+    }
+
+    #[test]
+    fn invalid_request_messages() {
+        let code = |body: &[u8]| request_message(body).unwrap_err().0;
+        assert_eq!(code(&[0, 0, 0]), INVALID_ARGUMENT);
+        // Shorter and longer than the prefix says
+        assert_eq!(code(&[0, 0, 0, 0, 3, b'a']), INVALID_ARGUMENT);
+        assert_eq!(code(&[0, 0, 0, 0, 1, b'a', b'b']), INVALID_ARGUMENT);
+        assert_eq!(code(&[1, 0, 0, 0, 1, b'a']), UNIMPLEMENTED);
+    }
+
+    #[test]
+    fn grpc_message_encoding() {
+        assert_eq!(
+            percent_encode("not found: 1038:12ad"),
+            "not found: 1038:12ad"
+        );
+        assert_eq!(percent_encode("100%\n"), "100%25%0A");
+        assert_eq!(percent_encode("ä"), "%C3%A4");
+    }
+}
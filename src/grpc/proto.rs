@@ -0,0 +1,294 @@
+//! Protocol Buffers encoding of the messages in headset_battery.proto
+
+use anyhow::Context;
+
+use crate::api::DeviceResponse;
+use crate::headset_control::BatteryState;
+
+const VARINT: u64 = 0;
+const FIXED64: u64 = 1;
+const LENGTH_DELIMITED: u64 = 2;
+const FIXED32: u64 = 5;
+
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    pub notifications_enabled: bool,
+    pub selected_device_id: String,
+}
+
+/// Fields that aren't set are left unchanged
+#[derive(Debug, Default)]
+pub struct UpdateSettingsRequest {
+    pub notifications_enabled: Option<bool>,
+    pub selected_device_id: Option<String>,
+}
+
+pub fn encode_device_list(devices: &[DeviceResponse]) -> Vec<u8> {
+    let mut message = vec![];
+    for device in devices {
+        write_message(&mut message, 1, &encode_device(device));
+    }
+    message
+}
+
+fn encode_device(device: &DeviceResponse) -> Vec<u8> {
+    let mut battery = vec![];
+    write_varint_field(&mut battery, 1, device.battery.level as i32 as i64 as u64);
+    write_varint_field(&mut battery, 2, battery_status(device.battery.status));
+    write_varint_field(&mut battery, 3, device.battery.charging as u64);
+
+    let mut message = vec![];
+    write_string(&mut message, 1, &device.id);
+    write_string(&mut message, 2, &device.name);
+    write_varint_field(&mut message, 3, device.selected as u64);
+    write_message(&mut message, 4, &battery);
+    message
+}
+
+fn battery_status(status: BatteryState) -> u64 {
+    match status {
+        BatteryState::BatteryAvailable => 1,
+        BatteryState::BatteryCharging => 2,
+        BatteryState::BatteryUnavailable => 3,
+        BatteryState::BatteryHiderror | BatteryState::BatteryTimeout => 4,
+    }
+}
+
+pub fn encode_settings(settings: &Settings) -> Vec<u8> {
+    let mut message = vec![];
+    write_varint_field(&mut message, 1, settings.notifications_enabled as u64);
+    write_string(&mut message, 2, &settings.selected_device_id);
+    message
+}
+
+pub fn decode_update_settings(mut message: &[u8]) -> anyhow::Result<UpdateSettingsRequest> {
+    let mut request = UpdateSettingsRequest::default();
+
+    while !message.is_empty() {
+        let key = read_varint(&mut message)?;
+        match (key >> 3, key & 7) {
+            (1, VARINT) => request.notifications_enabled = Some(read_varint(&mut message)? != 0),
+            (2, LENGTH_DELIMITED) => {
+                let bytes = read_bytes(&mut message)?;
+                request.selected_device_id =
+                    Some(String::from_utf8(bytes.to_vec()).context("invalid device id")?);
+            }
+            (_, wire_type) => skip_field(&mut message, wire_type)?,
+        }
+    }
+
+    Ok(request)
+}
+
+/// Proto3 leaves out fields with default values
+fn write_varint_field(message: &mut Vec<u8>, field: u64, value: u64) {
+    if value != 0 {
+        write_varint(message, field << 3 | VARINT);
+        write_varint(message, value);
+    }
+}
+
+fn write_string(message: &mut Vec<u8>, field: u64, value: &str) {
+    if !value.is_empty() {
+        write_message(message, field, value.as_bytes());
+    }
+}
+
+fn write_message(message: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_varint(message, field << 3 | LENGTH_DELIMITED);
+    write_varint(message, bytes.len() as u64);
+    message.extend_from_slice(bytes);
+}
+
+fn write_varint(message: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        message.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    message.push(value as u8);
+}
+
+fn read_varint(message: &mut &[u8]) -> anyhow::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = message.split_first().context("truncated varint")?;
+        *message = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    anyhow::bail!("varint too long")
+}
+
+fn read_bytes<'a>(message: &mut &'a [u8]) -> anyhow::Result<&'a [u8]> {
+    let len = read_varint(message)? as usize;
+    anyhow::ensure!(message.len() >= len, "truncated field");
+    let (bytes, rest) = message.split_at(len);
+    *message = rest;
+    Ok(bytes)
+}
+
+/// Unknown fields are ignored, so newer clients can talk to older servers
+fn skip_field(message: &mut &[u8], wire_type: u64) -> anyhow::Result<()> {
+    let len = match wire_type {
+        VARINT => {
+            read_varint(message)?;
+            return Ok(());
+        }
+        LENGTH_DELIMITED => {
+            read_bytes(message)?;
+            return Ok(());
+        }
+        FIXED64 => 8,
+        FIXED32 => 4,
+        _ => anyhow::bail!("unsupported wire type {wire_type}"),
+    };
+    anyhow::ensure!(message.len() >= len, "truncated field");
+    *message = &message[len..];
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::BatteryResponse;
+
+    fn device(
+        id: &str,
+        name: &str,
+        selected: bool,
+        level: isize,
+        status: BatteryState,
+    ) -> DeviceResponse {
+        DeviceResponse {
+            id: id.to_string(),
+            name: name.to_string(),
+            selected,
+            battery: BatteryResponse {
+                level,
+                status,
+                charging: status == BatteryState::BatteryCharging,
+            },
+        }
+    }
+
+    #[test]
+    fn varint_round_trip() {
+        for (value, encoded) in [
+            (0, &[0x00][..]),
+            (1, &[0x01]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (300, &[0xac, 0x02]),
+            (
+                u64::MAX,
+                &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+            ),
+        ] {
+            let mut message = vec![];
+            write_varint(&mut message, value);
+            assert_eq!(message, encoded);
+
+            let mut rest = &message[..];
+            assert_eq!(read_varint(&mut rest).unwrap(), value);
+            assert!(rest.is_empty());
+        }
+    }
+
+    #[test]
+    fn varint_errors() {
+        assert!(read_varint(&mut &[0x80, 0x80][..]).is_err());
+        assert!(read_varint(&mut &[0x80; 11][..]).is_err());
+    }
+
+    #[test]
+    fn device_list() {
+        let devices = [
+            device(
+                "1038:12ad",
+                "Arctis",
+                true,
+                50,
+                BatteryState::BatteryAvailable,
+            ),
+            device("1", "", false, -1, BatteryState::BatteryUnavailable),
+        ];
+
+        let mut expected = vec![0x0a, 27, 0x0a, 9];
+        expected.extend_from_slice(b"1038:12ad");
+        expected.extend_from_slice(&[0x12, 6]);
+        expected.extend_from_slice(b"Arctis");
+        // Selected, then the battery with its level and status but without charging
+        expected.extend_from_slice(&[0x18, 1, 0x22, 4, 0x08, 50, 0x10, 1]);
+        // The empty name and false are left out, and -1 is sign extended to 10 bytes
+        expected.extend_from_slice(&[0x0a, 18, 0x0a, 1, b'1', 0x22, 13, 0x08]);
+        expected.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+        expected.extend_from_slice(&[0x10, 3]);
+
+        assert_eq!(encode_device_list(&devices), expected);
+        assert!(encode_device_list(&[]).is_empty());
+    }
+
+    #[test]
+    fn charging_device() {
+        let devices = [device("1", "", false, 100, BatteryState::BatteryCharging)];
+        assert_eq!(
+            encode_device_list(&devices),
+            [
+                0x0a, 11, 0x0a, 1, b'1', 0x22, 6, 0x08, 100, 0x10, 2, 0x18, 1
+            ]
+        );
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = Settings {
+            notifications_enabled: true,
+            selected_device_id: "1038:12ad".to_string(),
+        };
+        let request = decode_update_settings(&encode_settings(&settings)).unwrap();
+        assert_eq!(request.notifications_enabled, Some(true));
+        assert_eq!(request.selected_device_id.as_deref(), Some("1038:12ad"));
+
+        // Proto3 can't tell false from a field that isn't set
+        let request = decode_update_settings(&encode_settings(&Settings::default())).unwrap();
+        assert_eq!(request.notifications_enabled, None);
+        assert_eq!(request.selected_device_id, None);
+    }
+
+    #[test]
+    fn explicit_false() {
+        let request = decode_update_settings(&[0x08, 0x00]).unwrap();
+        assert_eq!(request.notifications_enabled, Some(false));
+    }
+
+    #[test]
+    fn unknown_fields_are_skipped() {
+        let mut message = vec![];
+        // Field 3 as a varint, 4 as fixed64, 5 as fixed32 and 6 as bytes
+        message.extend_from_slice(&[0x18, 0x96, 0x01]);
+        message.extend_from_slice(&[0x21, 1, 2, 3, 4, 5, 6, 7, 8]);
+        message.extend_from_slice(&[0x2d, 1, 2, 3, 4]);
+        message.extend_from_slice(&[0x32, 2, b'h', b'i']);
+        // Field 1 with the wrong wire type is skipped too
+        message.extend_from_slice(&[0x0a, 1, b'x']);
+        message.extend_from_slice(&[0x08, 0x01]);
+
+        let request = decode_update_settings(&message).unwrap();
+        assert_eq!(request.notifications_enabled, Some(true));
+        assert_eq!(request.selected_device_id, None);
+    }
+
+    #[test]
+    fn invalid_messages() {
+        // Truncated string, fixed64 and varint
+        assert!(decode_update_settings(&[0x12, 5, b'a']).is_err());
+        assert!(decode_update_settings(&[0x21, 1, 2, 3]).is_err());
+        assert!(decode_update_settings(&[0x08]).is_err());
+        // Groups aren't supported
+        assert!(decode_update_settings(&[0x1b]).is_err());
+        // A device id that isn't UTF-8
+        assert!(decode_update_settings(&[0x12, 1, 0xff]).is_err());
+    }
+}
@@ -1,6 +1,7 @@
+#[cfg(windows)]
 use std::os::windows::process::CommandExt;
+use std::path::{Path, PathBuf};
 use std::process;
-use std::process::Stdio;
 
 use anyhow::Context;
 use serde_derive::Deserialize;
@@ -9,45 +10,218 @@ use serde_derive::Serialize;
 use crate::lang;
 use crate::lang::Key::*;
 
+mod backends;
+mod registry;
+mod watchdog;
+
+#[cfg(windows)]
+pub use backends::AirPodsWatcher;
+pub use backends::backends;
+pub use registry::{BatteryBackend, Registry};
+use watchdog::Backend;
+
 // const CREATE_NO_WINDOW: u32 = 0x08000000;
+#[cfg(windows)]
 const DETACHED_PROCESS: u32 = 0x00000008;
 
-pub fn query_devices(vec: &mut Vec<Device>) -> anyhow::Result<()> {
+/// Shipped next to the executable on Windows
+#[cfg(windows)]
+const HEADSETCONTROL: &str = "./headsetcontrol.exe";
+/// Installed from the distribution's packages or Homebrew, and found on the `PATH`
+#[cfg(not(windows))]
+const HEADSETCONTROL: &str = "headsetcontrol";
+
+/// Vendor id of the headsets read over Bluetooth, which have no USB ids
+pub const BLUETOOTH_VENDOR: &str = "bt";
+
+/// Queries one backend
+pub type Query = fn() -> anyhow::Result<Vec<Device>>;
+
+/// Where to find headsetcontrol and how to run it, when it's not the one in the usual place
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeadsetControlConfig {
+    /// Instead of `HEADSETCONTROL`
+    pub path: Option<PathBuf>,
+    /// Passed after `--battery --output json`, like `--test-device`
+    pub args: Vec<String>,
+}
+
+impl HeadsetControlConfig {
+    fn program(&self) -> &Path {
+        self.path.as_deref().unwrap_or(Path::new(HEADSETCONTROL))
+    }
+
+    /// Whether the executable at `path` is there, before it's saved or used
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(path) = &self.path {
+            anyhow::ensure!(path.is_file(), "{} isn't a file", path.display());
+        }
+        Ok(())
+    }
+}
+
+struct HeadsetControl {
+    config: HeadsetControlConfig,
+    watchdog: bool,
+}
+
+impl BatteryBackend for HeadsetControl {
+    fn name(&self) -> &'static str {
+        "HeadsetControl"
+    }
+
+    fn poll(&mut self, out: &mut Vec<Device>) -> anyhow::Result<()> {
+        let mut devices = Vec::new();
+        if self.watchdog {
+            watchdog::guard(Backend::HeadsetControl, || {
+                query_headsetcontrol(&self.config, &mut devices)
+            })?;
+        } else {
+            query_headsetcontrol(&self.config, &mut devices)?;
+        }
+        out.extend(devices);
+        Ok(())
+    }
+}
+
+/// Whether a poll failed because headsetcontrol isn't installed, and there was nothing else
+/// to read a battery from
+pub fn is_missing(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+    })
+}
+
+/// Adds the headsets that aren't in `vec` already, since one connected over a USB dongle
+/// can be paired over Bluetooth too, and headsetcontrol reads some of the dongles that are
+/// read directly as well
+fn merge(vec: &mut Vec<Device>, others: Vec<Device>) {
+    for device in others {
+        if !vec.iter().any(|known| {
+            known.id() == device.id() || known.product.eq_ignore_ascii_case(&device.product)
+        }) {
+            vec.push(device);
+        }
+    }
+}
+
+/// headsetcontrol, run from the executable's folder so the bundled one is found on Windows
+fn headsetcontrol_command(config: &HeadsetControlConfig) -> anyhow::Result<process::Command> {
     let exe_dir = std::env::current_exe()
         .context("getting current executable path")?
         .parent()
         .map(|p| p.to_path_buf())
         .context("getting current executable directory")?;
 
-    let res = process::Command::new("./headsetcontrol.exe")
-        .current_dir(exe_dir)
+    let mut command = process::Command::new(config.program());
+    command.current_dir(exe_dir);
+    // Apps started from Finder don't get the shell's `PATH`, which has Homebrew's prefix
+    #[cfg(target_os = "macos")]
+    command.env(
+        "PATH",
+        format!(
+            "/opt/homebrew/bin:/usr/local/bin:{}",
+            std::env::var("PATH").unwrap_or_default()
+        ),
+    );
+    #[cfg(windows)]
+    command.creation_flags(DETACHED_PROCESS);
+    Ok(command)
+}
+
+fn query_headsetcontrol(
+    config: &HeadsetControlConfig,
+    vec: &mut Vec<Device>,
+) -> anyhow::Result<()> {
+    let program = config.program();
+    let mut command = headsetcontrol_command(config)?;
+    command
         .args(["--battery", "--output", "json"])
-        .stdout(Stdio::piped())
-        .creation_flags(DETACHED_PROCESS)
-        .output()
-        .context("Failed to execute headsetcontrol.exe --battery --output json")?;
+        .args(&config.args);
+    let res = watchdog::output(&mut command, HEADSETCONTROL)?;
 
     let response: Output = match serde_json::from_slice(&res.stdout) {
         Ok(json) => json,
         Err(e) => {
             log::debug!(
-                "./headsetcontrol.exe --battery --output json:\n{}",
+                "{} --battery --output json {}:\n{}",
+                program.display(),
+                config.args.join(" "),
                 String::from_utf8_lossy(&res.stdout)
             );
             return Err(anyhow::anyhow!(
-                "Failed to parse JSON from headsetcontrol.exe: {}",
+                "Failed to parse JSON from {}: {}",
+                program.display(),
                 e
             ));
         }
     };
 
-    vec.clear();
-    for device in response.devices {
+    *vec = battery_devices(response);
+
+    // Another run, since asking a headset without the dial for it would fail the battery
+    if vec
+        .iter()
+        .any(|device| device.capabilities.contains(Capabilities::CHATMIX))
+        && let Err(e) = query_chatmix(config, vec)
+    {
+        log::debug!("Failed to read the chatmix: {e:?}");
+    }
+
+    Ok(())
+}
+
+/// The devices with a battery that headsetcontrol listed
+fn battery_devices(output: Output) -> Vec<Device> {
+    let mut devices = vec![];
+    // One by one, so a device in a shape this doesn't know doesn't hide the others
+    for value in output.devices {
+        // What headsetcontrol couldn't read from the device, only logged
+        let errors = value.get("errors").cloned().unwrap_or_default();
+        let mut device: Device = match serde_json::from_value(value) {
+            Ok(device) => device,
+            Err(e) => {
+                log::debug!("Skipping a device headsetcontrol listed: {e}");
+                continue;
+            }
+        };
+        // Like a battery request that timed out, which leaves the battery status with it
+        if device.status != "success" {
+            log::debug!(
+                "headsetcontrol answered {} for {}: {}",
+                device.status,
+                device.product,
+                errors
+            );
+        }
         if device.capabilities_str.iter().any(|cap| cap == "battery") {
-            vec.push(device);
+            device.capabilities = Capabilities::from_names(&device.capabilities_str);
+            devices.push(device);
         }
     }
+    devices
+}
 
+/// Sets the chatmix of the headsets in `vec` that have the dial
+fn query_chatmix(config: &HeadsetControlConfig, vec: &mut [Device]) -> anyhow::Result<()> {
+    let mut command = headsetcontrol_command(config)?;
+    command
+        .args(["--chatmix", "--output", "json"])
+        .args(&config.args);
+    let res = watchdog::output(&mut command, HEADSETCONTROL)?;
+    let response: Output =
+        serde_json::from_slice(&res.stdout).context("parsing the chatmix JSON")?;
+    for value in response.devices {
+        // Left out of the ones that couldn't be read
+        let Ok(read) = serde_json::from_value::<Device>(value) else {
+            continue;
+        };
+        if let Some(device) = vec.iter_mut().find(|device| device.id() == read.id()) {
+            device.chatmix = read.chatmix;
+        }
+    }
     Ok(())
 }
 
@@ -61,7 +235,9 @@ pub struct Output {
     // #[serde(rename = "hidapi_version")]
     // pub hidapi_version: String,
     // pub device_count: i64,
-    pub devices: Vec<Device>,
+    /// Parsed into `Device` one at a time
+    #[serde(default)]
+    pub devices: Vec<serde_json::Value>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -69,28 +245,73 @@ pub struct Output {
 pub struct Device {
     pub status: String,
     // pub device: String,
-    // pub vendor: String,
+    #[serde(default)]
+    pub vendor: String,
     pub product: String,
     #[serde(rename = "id_vendor")]
     pub id_vendor: String,
     #[serde(rename = "id_product")]
     pub id_product: String,
     // pub capabilities: Vec<String>,
-    #[serde(rename = "capabilities_str")]
+    #[serde(default, rename = "capabilities_str")]
     pub capabilities_str: Vec<String>,
+    /// Left out by headsetcontrol for devices without a battery
+    #[serde(default)]
     pub battery: Battery,
+    /// Whether the headset sits in its base station, only the Astro backend can tell
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub docked: bool,
+    /// The earbuds and the case of AirPods, only the AirPods backend reads them
+    #[serde(
+        default,
+        rename = "sub_batteries",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub sub_batteries: Vec<SubBattery>,
+    /// The settings besides the battery, only headsetcontrol reads them
+    #[serde(skip)]
+    pub capabilities: Capabilities,
     // pub equalizer: Equalizer,
     // #[serde(rename = "equalizer_presets_count")]
     // pub equalizer_presets_count: i64,
     // #[serde(rename = "equalizer_presets")]
     // pub equalizer_presets: EqualizerPresets,
-    // pub chatmix: i64,
+    /// The dial between game and chat audio, from 0 for all game to 128 for all chat. Only
+    /// read by headsetcontrol, for the headsets that have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chatmix: Option<u8>,
 }
 
 impl Device {
+    pub fn is_bluetooth(&self) -> bool {
+        self.id_vendor == BLUETOOTH_VENDOR
+    }
+
+    /// Like `ChatMix: 60% game`, for the headsets with the dial
+    pub fn chatmix_text(&self) -> Option<String> {
+        let level = u32::from(self.chatmix?.min(128));
+        let (key, percent) = match level.cmp(&64) {
+            std::cmp::Ordering::Less => (chatmix_game, (128 - level) * 100 / 128),
+            std::cmp::Ordering::Greater => (chatmix_chat, level * 100 / 128),
+            std::cmp::Ordering::Equal => return Some(lang::t(chatmix_balanced).to_string()),
+        };
+        Some(lang::t(key).replace("{percent}", &percent.to_string()))
+    }
+
+    /// Stable identifier made of the USB vendor and product ids, e.g. `1038:12ad`
+    pub fn id(&self) -> String {
+        format!(
+            "{}:{}",
+            self.id_vendor.trim_start_matches("0x"),
+            self.id_product.trim_start_matches("0x")
+        )
+    }
+
     pub fn status_text(&self) -> Option<&'static str> {
         match self.battery.status {
+            BatteryState::BatteryCharging if self.docked => Some(lang::t(device_docked_charging)),
             BatteryState::BatteryCharging => Some(lang::t(device_charging)),
+            BatteryState::BatteryAvailable if self.docked => Some(lang::t(device_docked)),
             BatteryState::BatteryAvailable => None,
             BatteryState::BatteryUnavailable => Some(lang::t(battery_unavailable)),
             _ => Some(lang::t(device_disconnected)),
@@ -116,6 +337,16 @@ impl std::fmt::Display for Device {
             write!(f, " {status}")?;
         }
 
+        // Like `(Left 80%, Right 90%, Case 50%)`
+        if !self.sub_batteries.is_empty() {
+            let parts: Vec<String> = self
+                .sub_batteries
+                .iter()
+                .map(|sub| format!("{} {}%", sub.part.label(), sub.battery.level))
+                .collect();
+            write!(f, " ({})", parts.join(", "))?;
+        }
+
         Ok(())
     }
 }
@@ -128,6 +359,146 @@ pub struct Battery {
     pub level: isize,
 }
 
+/// What a headset has besides its battery, a bit for each
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities(u8);
+
+impl Capabilities {
+    pub const SIDETONE: Capabilities = Capabilities(1 << 0);
+    pub const LIGHTS: Capabilities = Capabilities(1 << 1);
+    pub const INACTIVE_TIME: Capabilities = Capabilities(1 << 2);
+    pub const CHATMIX: Capabilities = Capabilities(1 << 3);
+    pub const VOICE_PROMPTS: Capabilities = Capabilities(1 << 4);
+
+    /// With the names headsetcontrol lists them by in `capabilities_str`
+    const NAMES: [(Capabilities, &str); 5] = [
+        (Capabilities::SIDETONE, "sidetone"),
+        (Capabilities::LIGHTS, "lights"),
+        (Capabilities::INACTIVE_TIME, "inactive time"),
+        (Capabilities::CHATMIX, "chatmix"),
+        (Capabilities::VOICE_PROMPTS, "voice prompts"),
+    ];
+
+    fn from_names(names: &[String]) -> Self {
+        Self::NAMES
+            .iter()
+            .filter(|(_, name)| names.iter().any(|known| known == name))
+            .fold(Self::default(), |all, (capability, _)| {
+                Capabilities(all.0 | capability.0)
+            })
+    }
+
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Translated, like `Sidetone, Chatmix`
+    pub fn labels(self) -> String {
+        let labels: Vec<&str> = Self::NAMES
+            .iter()
+            .filter(|(capability, _)| self.contains(*capability))
+            .map(|(capability, _)| capability.label())
+            .collect();
+        labels.join(", ")
+    }
+
+    /// Of a single capability
+    pub fn label(self) -> &'static str {
+        lang::t(match self {
+            Capabilities::SIDETONE => sidetone,
+            Capabilities::LIGHTS => lights,
+            Capabilities::INACTIVE_TIME => inactive_time,
+            Capabilities::CHATMIX => chatmix,
+            _ => voice_prompts,
+        })
+    }
+}
+
+/// A setting headsetcontrol changes on a headset, picked in the tray menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// From 0 to 128
+    Sidetone(u8),
+    Lights(bool),
+    /// Minutes until the headset turns itself off, from 0 for never to 90
+    InactiveTime(u8),
+    VoicePrompts(bool),
+}
+
+impl Control {
+    fn args(self) -> [String; 2] {
+        let (flag, value) = match self {
+            Control::Sidetone(level) => ("--sidetone", level),
+            Control::Lights(on) => ("--light", u8::from(on)),
+            Control::InactiveTime(minutes) => ("--inactive-time", minutes),
+            Control::VoicePrompts(on) => ("--voice-prompt", u8::from(on)),
+        };
+        [flag.to_string(), value.to_string()]
+    }
+}
+
+/// Changes `control` on `device` on a thread of its own, since headsetcontrol can take seconds
+/// to reach the headset. Failures are only logged.
+pub fn apply(config: &HeadsetControlConfig, device: &Device, control: Control) {
+    let config = config.clone();
+    let id = format!("{}:{}", device.id_vendor, device.id_product);
+    let product = device.product.clone();
+    let spawned = std::thread::Builder::new()
+        .name("headset-control".to_string())
+        .spawn(move || {
+            let result = headsetcontrol_command(&config).and_then(|mut command| {
+                command
+                    .args(["--device", &id])
+                    .args(control.args())
+                    .args(&config.args);
+                let output = watchdog::output(&mut command, HEADSETCONTROL)?;
+                anyhow::ensure!(
+                    output.status.success(),
+                    "headsetcontrol exited with {}",
+                    output.status
+                );
+                Ok(())
+            });
+            match result {
+                Ok(()) => log::info!("Set {control:?} on {product}"),
+                Err(e) => log::error!("Failed to set {control:?} on {product}: {e:?}"),
+            }
+        });
+    if let Err(e) = spawned {
+        log::error!("Failed to spawn headsetcontrol thread: {e:?}");
+    }
+}
+
+/// A battery of its own in a headset, like each AirPod and their case
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubBattery {
+    pub part: Part,
+    pub battery: Battery,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Part {
+    Left,
+    Right,
+    Case,
+}
+
+impl Part {
+    pub fn label(self) -> &'static str {
+        lang::t(match self {
+            Part::Left => part_left,
+            Part::Right => part_right,
+            Part::Case => part_case,
+        })
+    }
+}
+
 // #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 // #[serde(rename_all = "camelCase")]
 // pub struct Equalizer {
@@ -147,13 +518,196 @@ pub struct Battery {
 //     pub smiley: Vec<f64>,
 // }
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum BatteryState {
-    #[default]
-    BatteryUnavailable,
     BatteryCharging,
     BatteryAvailable,
     BatteryHiderror,
     BatteryTimeout,
+    /// Also a status newer headsetcontrol versions might add
+    #[default]
+    #[serde(other)]
+    BatteryUnavailable,
+}
+
+impl BatteryState {
+    /// Short, untranslated name used in command line output and MQTT payloads
+    pub fn label(self) -> &'static str {
+        match self {
+            BatteryState::BatteryAvailable => "available",
+            BatteryState::BatteryCharging => "charging",
+            BatteryState::BatteryUnavailable => "unavailable",
+            BatteryState::BatteryHiderror | BatteryState::BatteryTimeout => "disconnected",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `headsetcontrol --battery --output json` prints, with `devices` filled in
+    fn output(devices: &str) -> Output {
+        let json = format!(
+            r#"{{
+                "name": "HeadsetControl",
+                "version": "3.0.0",
+                "api_version": "1.1",
+                "hidapi_version": "0.14.0",
+                "device_count": 3,
+                "devices": [{devices}]
+            }}"#
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    const NOVA_7: &str = r#"{
+        "status": "success",
+        "device": "SteelSeries Arctis Nova 7",
+        "vendor": "SteelSeries",
+        "product": "Arctis Nova 7",
+        "id_vendor": "0x1038",
+        "id_product": "0x2202",
+        "capabilities": ["CAP_SIDETONE", "CAP_BATTERY_STATUS", "CAP_CHATMIX_STATUS"],
+        "capabilities_str": ["sidetone", "battery", "chatmix"],
+        "battery": { "status": "BATTERY_AVAILABLE", "level": 80 }
+    }"#;
+
+    const CLOUD_ALPHA: &str = r#"{
+        "status": "success",
+        "device": "HyperX Cloud Alpha Wireless",
+        "vendor": "HyperX",
+        "product": "Cloud Alpha Wireless",
+        "id_vendor": "0x03f0",
+        "id_product": "0x098d",
+        "capabilities": ["CAP_SIDETONE", "CAP_BATTERY_STATUS", "CAP_INACTIVE_TIME"],
+        "capabilities_str": ["sidetone", "battery", "inactive time"],
+        "battery": { "status": "BATTERY_CHARGING", "level": 45 }
+    }"#;
+
+    #[test]
+    fn devices_with_a_battery() {
+        let devices = battery_devices(output(&format!("{NOVA_7}, {CLOUD_ALPHA}")));
+        assert_eq!(devices.len(), 2);
+
+        assert_eq!(devices[0].id(), "1038:2202");
+        assert_eq!(devices[0].product, "Arctis Nova 7");
+        assert_eq!(
+            devices[0].battery,
+            Battery {
+                status: BatteryState::BatteryAvailable,
+                level: 80,
+            }
+        );
+        assert_eq!(
+            devices[0].capabilities,
+            Capabilities::from_names(&["sidetone".to_string(), "chatmix".to_string()])
+        );
+        assert!(devices[0].sub_batteries.is_empty());
+
+        assert_eq!(devices[1].battery.status, BatteryState::BatteryCharging);
+        assert_eq!(devices[1].battery.level, 45);
+    }
+
+    #[test]
+    fn unknown_status_is_unavailable() {
+        let device = r#"{
+            "status": "success",
+            "product": "Arctis 7",
+            "id_vendor": "0x1038",
+            "id_product": "0x12ad",
+            "capabilities_str": ["battery"],
+            "battery": { "status": "BATTERY_SOMETHING_NEW", "level": -1 }
+        }"#;
+        let devices = battery_devices(output(device));
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].battery.status, BatteryState::BatteryUnavailable);
+    }
+
+    #[test]
+    fn failed_battery_request_keeps_the_device() {
+        let device = r#"{
+            "status": "partial",
+            "product": "Arctis 7",
+            "id_vendor": "0x1038",
+            "id_product": "0x12ad",
+            "capabilities_str": ["sidetone", "battery"],
+            "battery": { "status": "BATTERY_TIMEOUT", "level": -1 },
+            "errors": { "battery": "Timed out" }
+        }"#;
+        let devices = battery_devices(output(device));
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].battery.status, BatteryState::BatteryTimeout);
+    }
+
+    #[test]
+    fn malformed_device_is_skipped() {
+        // No product, and a level that isn't a number
+        let malformed = r#"{
+            "status": "success",
+            "id_vendor": "0x1038",
+            "id_product": "0x12ad",
+            "capabilities_str": ["battery"],
+            "battery": { "status": "BATTERY_AVAILABLE", "level": "high" }
+        }"#;
+        let devices = battery_devices(output(&format!("{NOVA_7}, {malformed}, {CLOUD_ALPHA}")));
+        let products: Vec<_> = devices
+            .iter()
+            .map(|device| device.product.as_str())
+            .collect();
+        assert_eq!(products, ["Arctis Nova 7", "Cloud Alpha Wireless"]);
+    }
+
+    #[test]
+    fn devices_without_a_battery_are_left_out() {
+        let device = r#"{
+            "status": "success",
+            "product": "Arctis Pro",
+            "id_vendor": "0x1038",
+            "id_product": "0x1252",
+            "capabilities_str": ["sidetone", "lights"]
+        }"#;
+        let devices = battery_devices(output(&format!("{device}, {NOVA_7}")));
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].product, "Arctis Nova 7");
+    }
+
+    #[test]
+    fn sub_batteries() {
+        let device = r#"{
+            "status": "success",
+            "product": "AirPods Pro",
+            "id_vendor": "0x004c",
+            "id_product": "0x200e",
+            "capabilities_str": ["battery"],
+            "battery": { "status": "BATTERY_AVAILABLE", "level": 60 },
+            "sub_batteries": [
+                { "part": "left", "battery": { "status": "BATTERY_AVAILABLE", "level": 60 } },
+                { "part": "right", "battery": { "status": "BATTERY_AVAILABLE", "level": 70 } },
+                { "part": "case", "battery": { "status": "BATTERY_CHARGING", "level": 90 } }
+            ]
+        }"#;
+        let devices = battery_devices(output(device));
+        assert_eq!(devices.len(), 1);
+        let parts: Vec<_> = devices[0]
+            .sub_batteries
+            .iter()
+            .map(|sub| (sub.part, sub.battery.level))
+            .collect();
+        assert_eq!(
+            parts,
+            [(Part::Left, 60), (Part::Right, 70), (Part::Case, 90)]
+        );
+        assert_eq!(
+            devices[0].sub_batteries[2].battery.status,
+            BatteryState::BatteryCharging
+        );
+    }
+
+    #[test]
+    fn no_devices() {
+        let output: Output = serde_json::from_str(r#"{ "name": "HeadsetControl" }"#).unwrap();
+        assert!(battery_devices(output).is_empty());
+    }
 }
@@ -0,0 +1,59 @@
+//! The ways headsets are read besides headsetcontrol, one module for each vendor or OS service,
+//! and the list of every backend in the order they're polled in
+
+#[cfg(windows)]
+mod airpods;
+mod astro;
+mod battery_strength;
+mod corsair;
+#[cfg(windows)]
+mod gatt;
+#[cfg(windows)]
+mod hfp;
+mod hyperx;
+#[cfg(target_os = "macos")]
+mod iokit;
+mod logitech;
+mod razer;
+#[cfg(target_os = "linux")]
+mod upower;
+
+use super::registry::{BatteryBackend, QueryBackend};
+use super::{HeadsetControl, HeadsetControlConfig};
+
+#[cfg(windows)]
+pub use airpods::AirPodsWatcher;
+
+#[cfg(target_os = "macos")]
+use iokit as bluetooth;
+#[cfg(target_os = "linux")]
+use upower as bluetooth;
+
+/// Every backend, in the order their headsets are preferred in. The USB ones are headsetcontrol,
+/// then the dongles it might not know, ending with the fallback for any dongle. With `watchdog`,
+/// headsetcontrol is skipped for a while after it kept timing out.
+pub fn backends(
+    headsetcontrol: &HeadsetControlConfig,
+    watchdog: bool,
+) -> Vec<Box<dyn BatteryBackend>> {
+    vec![
+        Box::new(HeadsetControl {
+            config: headsetcontrol.clone(),
+            watchdog,
+        }),
+        QueryBackend::usb("Logitech", logitech::query_devices),
+        QueryBackend::usb("Corsair", corsair::query_devices),
+        QueryBackend::usb("HyperX", hyperx::query_devices),
+        QueryBackend::usb("Razer", razer::query_devices),
+        QueryBackend::usb("Astro", astro::query_devices),
+        QueryBackend::usb("BatteryStrength", battery_strength::query_devices),
+        #[cfg(windows)]
+        QueryBackend::bluetooth("AirPods", airpods::query_devices),
+        #[cfg(windows)]
+        Box::new(hfp::HandsFree),
+        #[cfg(windows)]
+        QueryBackend::bluetooth("BluetoothLE", gatt::query_devices),
+        #[cfg(not(windows))]
+        QueryBackend::bluetooth("Bluetooth", bluetooth::query_devices),
+    ]
+}
@@ -0,0 +1,220 @@
+//! AirPods, read from the proximity advertisements they send to nearby Apple devices. The
+//! advertisement has the level of each AirPod and the case, in steps of 10%, and which of them
+//! are charging. Off by default, since it keeps Bluetooth scanning while the app runs.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use windows::{
+    Devices::Bluetooth::Advertisement::{
+        BluetoothLEAdvertisementReceivedEventArgs, BluetoothLEAdvertisementWatcher,
+        BluetoothLEScanningMode,
+    },
+    Foundation::TypedEventHandler,
+    Storage::Streams::DataReader,
+};
+
+use crate::headset_control::{
+    BLUETOOTH_VENDOR, Battery, BatteryState, Capabilities, Device, Part, SubBattery,
+};
+
+/// Apple's Bluetooth company id, which the manufacturer data of the advertisement is sent with
+const APPLE_COMPANY_ID: u16 = 0x004c;
+/// Type and length of the proximity message, the rest of the manufacturer data
+const PROXIMITY_MESSAGE: [u8; 2] = [0x07, 0x19];
+const MODEL_OFFSET: usize = 3;
+const STATUS_OFFSET: usize = 5;
+/// The left and right levels, one in each half of the byte
+const BUDS_OFFSET: usize = 6;
+/// The charging flags in the high half of the byte, and the case level in the low one
+const CASE_OFFSET: usize = 7;
+/// In the status byte, when the left AirPod is the one sending
+const LEFT_SENDING: u8 = 0x20;
+/// The level of a part that's out of reach, like the case while an AirPod is in the ear
+const LEVEL_UNKNOWN: u8 = 0x0f;
+
+/// Someone else's AirPods nearby are weaker than this, in dBm
+const MIN_SIGNAL_STRENGTH: i16 = -60;
+/// A weaker advertisement replaces the last one after this long
+const STALE_AFTER: Duration = Duration::from_secs(5);
+/// AirPods stop advertising when they're put away in the closed case
+const SHOWN_FOR: Duration = Duration::from_secs(60);
+
+/// Product ids of the models
+const MODELS: &[(u16, &str)] = &[
+    (0x0220, "AirPods"),
+    (0x0f20, "AirPods (2nd generation)"),
+    (0x1320, "AirPods (3rd generation)"),
+    (0x0e20, "AirPods Pro"),
+    (0x1420, "AirPods Pro (2nd generation)"),
+    (0x2420, "AirPods Pro (2nd generation)"),
+    (0x0a20, "AirPods Max"),
+];
+
+struct Sighting {
+    model: u16,
+    seen: Instant,
+    signal_strength: i16,
+    batteries: Vec<SubBattery>,
+}
+
+/// The last advertisement of each model
+static SIGHTINGS: Mutex<Vec<Sighting>> = Mutex::new(Vec::new());
+
+/// Scans for the advertisements until it's dropped
+pub struct AirPodsWatcher {
+    watcher: BluetoothLEAdvertisementWatcher,
+}
+
+impl AirPodsWatcher {
+    pub fn start() -> anyhow::Result<Self> {
+        let watcher =
+            BluetoothLEAdvertisementWatcher::new().context("creating advertisement watcher")?;
+        // The advertisements are meant for every device nearby, there's nothing to ask for
+        watcher.SetScanningMode(BluetoothLEScanningMode::Passive)?;
+        watcher.Received(&TypedEventHandler::new(
+            |_, args: &Option<BluetoothLEAdvertisementReceivedEventArgs>| {
+                if let Some(args) = args {
+                    received(args)?;
+                }
+                Ok(())
+            },
+        ))?;
+        watcher.Start().context("starting advertisement watcher")?;
+        log::info!("Watching for AirPods advertisements");
+        Ok(Self { watcher })
+    }
+}
+
+impl Drop for AirPodsWatcher {
+    fn drop(&mut self) {
+        let _ = self.watcher.Stop();
+    }
+}
+
+fn received(args: &BluetoothLEAdvertisementReceivedEventArgs) -> windows::core::Result<()> {
+    let signal_strength = args.RawSignalStrengthInDBm()?;
+    if signal_strength < MIN_SIGNAL_STRENGTH {
+        return Ok(());
+    }
+    for data in args
+        .Advertisement()?
+        .GetManufacturerDataByCompanyId(APPLE_COMPANY_ID)?
+    {
+        let reader = DataReader::FromBuffer(&data.Data()?)?;
+        let mut message = vec![0; reader.UnconsumedBufferLength()? as usize];
+        reader.ReadBytes(&mut message)?;
+        let Some((model, batteries)) = decode(&message) else {
+            continue;
+        };
+
+        let mut sightings = SIGHTINGS.lock().unwrap_or_else(|e| e.into_inner());
+        let sighting = Sighting {
+            model,
+            seen: Instant::now(),
+            signal_strength,
+            batteries,
+        };
+        match sightings.iter_mut().find(|known| known.model == model) {
+            Some(known)
+                if signal_strength >= known.signal_strength
+                    || known.seen.elapsed() >= STALE_AFTER =>
+            {
+                *known = sighting;
+            }
+            Some(_) => {}
+            None => sightings.push(sighting),
+        }
+    }
+    Ok(())
+}
+
+/// The model and the batteries in a proximity message
+fn decode(message: &[u8]) -> Option<(u16, Vec<SubBattery>)> {
+    if !message.starts_with(&PROXIMITY_MESSAGE) || message.len() <= CASE_OFFSET {
+        return None;
+    }
+    let model = u16::from_be_bytes([message[MODEL_OFFSET], message[MODEL_OFFSET + 1]]);
+    let (first, second) = (message[BUDS_OFFSET] >> 4, message[BUDS_OFFSET] & 0x0f);
+    let (charging, case) = (message[CASE_OFFSET] >> 4, message[CASE_OFFSET] & 0x0f);
+    // The halves are swapped when the right AirPod is the one sending
+    let left_sending = message[STATUS_OFFSET] & LEFT_SENDING != 0;
+    let (left, right, left_charging, right_charging) = if left_sending {
+        (first, second, charging & 0x01, charging & 0x02)
+    } else {
+        (second, first, charging & 0x02, charging & 0x01)
+    };
+
+    let batteries = [
+        (Part::Left, left, left_charging != 0),
+        (Part::Right, right, right_charging != 0),
+        (Part::Case, case, charging & 0x04 != 0),
+    ]
+    .into_iter()
+    .filter(|&(_, level, _)| level != LEVEL_UNKNOWN)
+    .map(|(part, level, charging)| SubBattery {
+        part,
+        battery: Battery {
+            status: if charging {
+                BatteryState::BatteryCharging
+            } else {
+                BatteryState::BatteryAvailable
+            },
+            level: (isize::from(level) * 10).min(100),
+        },
+    })
+    .collect();
+    Some((model, batteries))
+}
+
+/// The AirPods advertised lately, nothing when the watcher isn't running. The battery of the
+/// headset is the lower of the AirPods', charging when each of them is.
+pub fn query_devices() -> anyhow::Result<Vec<Device>> {
+    let sightings = SIGHTINGS.lock().unwrap_or_else(|e| e.into_inner());
+    let devices = sightings
+        .iter()
+        .filter(|sighting| sighting.seen.elapsed() < SHOWN_FOR)
+        .map(|sighting| {
+            let name = MODELS
+                .iter()
+                .find(|(model, _)| *model == sighting.model)
+                .map_or("AirPods", |(_, name)| name);
+            let buds: Vec<Battery> = sighting
+                .batteries
+                .iter()
+                .filter(|sub| sub.part != Part::Case)
+                .map(|sub| sub.battery)
+                .collect();
+            let battery = match buds.iter().map(|battery| battery.level).min() {
+                Some(level) => Battery {
+                    status: if buds
+                        .iter()
+                        .all(|battery| battery.status == BatteryState::BatteryCharging)
+                    {
+                        BatteryState::BatteryCharging
+                    } else {
+                        BatteryState::BatteryAvailable
+                    },
+                    level,
+                },
+                None => Battery::default(),
+            };
+            Device {
+                status: "success".to_string(),
+                vendor: "Apple".to_string(),
+                product: name.to_string(),
+                id_vendor: BLUETOOTH_VENDOR.to_string(),
+                // The address changes every few minutes, so the model stands in for it
+                id_product: format!("airpods-{:04x}", sighting.model),
+                capabilities_str: vec!["battery".to_string()],
+                battery,
+                docked: false,
+                sub_batteries: sighting.batteries.clone(),
+                capabilities: Capabilities::default(),
+                chatmix: None,
+            }
+        })
+        .collect();
+    Ok(devices)
+}
@@ -0,0 +1,183 @@
+//! Astro A50 headsets, read without headsetcontrol from their base station. Besides the battery
+//! level, the base station tells whether the headset is docked, so it can be shown as
+//! "Docked" when it sits there full and "Docked, charging" while it charges.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use hidapi::{HidApi, HidDevice};
+
+use crate::headset_control::{Battery, BatteryState, Capabilities, Device};
+
+const ASTRO_VENDOR: u16 = 0x9886;
+/// The collection of the base station that takes the status request
+const STATUS_USAGE_PAGE: u16 = 0xff00;
+
+/// Report id and the status command, answered with a report that starts the same
+const STATUS_REQUEST: [u8; 2] = [0x02, 0x03];
+const REPORT_LEN: usize = 32;
+const FLAGS_OFFSET: usize = 2;
+const LEVEL_OFFSET: usize = 3;
+/// The flags of the status answer
+const FLAG_POWERED_ON: u8 = 0x01;
+const FLAG_DOCKED: u8 = 0x02;
+const FLAG_CHARGING: u8 = 0x04;
+
+/// The base station answers within milliseconds
+const ANSWER_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Product ids of the base stations
+const MODELS: &[(u16, &str)] = &[
+    (0x0027, "Astro A50"),
+    (0x002c, "Astro A50"),
+    (0x0036, "Astro A50"),
+];
+
+/// The headsets of the base stations that are plugged in, with the base station's USB ids like
+/// headsetcontrol reports them
+pub fn query_devices() -> anyhow::Result<Vec<Device>> {
+    let api = HidApi::new().context("listing HID devices")?;
+
+    let mut devices: Vec<Device> = Vec::new();
+    for info in api
+        .device_list()
+        .filter(|info| info.vendor_id() == ASTRO_VENDOR && info.usage_page() == STATUS_USAGE_PAGE)
+    {
+        let Some(&(product_id, name)) = MODELS.iter().find(|(id, _)| *id == info.product_id())
+        else {
+            continue;
+        };
+        let mut device = Device {
+            status: "success".to_string(),
+            vendor: "Astro".to_string(),
+            product: info
+                .product_string()
+                .map(str::trim)
+                .filter(|product| !product.is_empty())
+                .unwrap_or(name)
+                .to_string(),
+            id_vendor: format!("0x{ASTRO_VENDOR:04x}"),
+            id_product: format!("0x{product_id:04x}"),
+            capabilities_str: vec!["battery".to_string()],
+            battery: Battery::default(),
+            docked: false,
+            sub_batteries: Vec::new(),
+            capabilities: Capabilities::default(),
+            chatmix: None,
+        };
+        // Each base station is only read once, even when it's listed more than once
+        if devices.iter().any(|known| known.id() == device.id()) {
+            continue;
+        }
+        let status = info
+            .open_device(&api)
+            .context("opening base station")
+            .and_then(|hid| read_status(&hid));
+        match status {
+            Ok((battery, docked)) => {
+                device.battery = battery;
+                device.docked = docked;
+                devices.push(device);
+            }
+            Err(e) => log::debug!("Failed to read the battery of {name}: {e:?}"),
+        }
+    }
+    Ok(devices)
+}
+
+/// The battery, and whether the headset is docked
+fn read_status(hid: &HidDevice) -> anyhow::Result<(Battery, bool)> {
+    let mut request = [0; REPORT_LEN];
+    request[..STATUS_REQUEST.len()].copy_from_slice(&STATUS_REQUEST);
+    hid.write(&request).context("writing status request")?;
+
+    let deadline = Instant::now() + ANSWER_TIMEOUT;
+    let mut answer = [0; REPORT_LEN];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        anyhow::ensure!(
+            !left.is_zero(),
+            "no status answer within {ANSWER_TIMEOUT:?}"
+        );
+        let len = hid
+            .read_timeout(&mut answer, left.as_millis() as i32)
+            .context("reading status answer")?;
+        // Other reports, like the game and voice balance changing, come on the same collection
+        if len > LEVEL_OFFSET && answer.starts_with(&STATUS_REQUEST) {
+            break;
+        }
+    }
+
+    log::debug!("Astro status answer {answer:02x?}");
+    Ok(status(&answer))
+}
+
+/// The battery in the answer to the status request, and whether the headset is docked
+fn status(answer: &[u8; REPORT_LEN]) -> (Battery, bool) {
+    let flags = answer[FLAGS_OFFSET];
+    let docked = flags & FLAG_DOCKED != 0;
+    // A headset in the dock counts as on, even when it's turned off to charge
+    if flags & FLAG_POWERED_ON == 0 && !docked {
+        let battery = Battery {
+            status: BatteryState::BatteryUnavailable,
+            level: 0,
+        };
+        return (battery, false);
+    }
+    let battery = Battery {
+        status: if flags & FLAG_CHARGING != 0 {
+            BatteryState::BatteryCharging
+        } else {
+            BatteryState::BatteryAvailable
+        },
+        level: isize::from(answer[LEVEL_OFFSET]).min(100),
+    };
+    (battery, docked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(flags: u8, level: u8) -> [u8; REPORT_LEN] {
+        let mut answer = [0; REPORT_LEN];
+        answer[..STATUS_REQUEST.len()].copy_from_slice(&STATUS_REQUEST);
+        answer[FLAGS_OFFSET] = flags;
+        answer[LEVEL_OFFSET] = level;
+        answer
+    }
+
+    fn battery(status: BatteryState, level: isize) -> Battery {
+        Battery { status, level }
+    }
+
+    #[test]
+    fn on_the_head() {
+        let (on, docked) = status(&answer(FLAG_POWERED_ON, 62));
+        assert_eq!(on, battery(BatteryState::BatteryAvailable, 62));
+        assert!(!docked);
+    }
+
+    #[test]
+    fn off_and_not_docked() {
+        let (off, docked) = status(&answer(0, 62));
+        assert_eq!(off, battery(BatteryState::BatteryUnavailable, 0));
+        assert!(!docked);
+    }
+
+    #[test]
+    fn docked_counts_as_on() {
+        let (charging, docked) = status(&answer(FLAG_DOCKED | FLAG_CHARGING, 80));
+        assert_eq!(charging, battery(BatteryState::BatteryCharging, 80));
+        assert!(docked);
+
+        let (full, docked) = status(&answer(FLAG_POWERED_ON | FLAG_DOCKED, 100));
+        assert_eq!(full, battery(BatteryState::BatteryAvailable, 100));
+        assert!(docked);
+    }
+
+    #[test]
+    fn level_is_at_most_100() {
+        assert_eq!(status(&answer(FLAG_POWERED_ON, 0xc8)).0.level, 100);
+    }
+}
@@ -0,0 +1,395 @@
+//! Any headset whose dongle reports the standard Battery Strength usage (`0x20` in the Generic
+//! Device Controls page `0x06`), found in the report descriptors of its collections. This is
+//! the fallback for dongles without a backend of their own, and only those with a telephony
+//! collection, like a microphone mute or hook switch, are read so mice and keyboards are left
+//! out. Whether the headset is charging isn't part of the usage.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use hidapi::{DeviceInfo, HidApi, HidDevice, MAX_REPORT_DESCRIPTOR_SIZE};
+
+use crate::headset_control::{Battery, BatteryState, Capabilities, Device};
+
+const GENERIC_DEVICE_CONTROLS_PAGE: u16 = 0x06;
+const BATTERY_STRENGTH: u16 = 0x20;
+/// The usage page of the collections that tell a headset from other devices
+const TELEPHONY_PAGE: u16 = 0x0b;
+
+/// Input reports come on their own, the dongle might not send one within this long
+const ANSWER_TIMEOUT: Duration = Duration::from_millis(500);
+/// Larger than any report with the battery strength
+const REPORT_LEN: usize = 64;
+
+/// Where the battery strength is in a report
+#[derive(Debug, Clone, Copy)]
+struct Field {
+    feature: bool,
+    report_id: u8,
+    /// From the start of the report data, after the report id
+    bit_offset: usize,
+    bit_size: usize,
+    logical_min: i32,
+    logical_max: i32,
+}
+
+/// The headsets of the dongles that are plugged in, with the dongle's USB ids like
+/// headsetcontrol reports them
+pub fn query_devices() -> anyhow::Result<Vec<Device>> {
+    let api = HidApi::new().context("listing HID devices")?;
+    let headsets: Vec<(u16, u16)> = api
+        .device_list()
+        .filter(|info| info.usage_page() == TELEPHONY_PAGE)
+        .map(|info| (info.vendor_id(), info.product_id()))
+        .collect();
+
+    let mut devices: Vec<Device> = Vec::new();
+    for info in api
+        .device_list()
+        .filter(|info| headsets.contains(&(info.vendor_id(), info.product_id())))
+    {
+        let mut device = Device {
+            status: "success".to_string(),
+            vendor: info
+                .manufacturer_string()
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+            product: info
+                .product_string()
+                .map(str::trim)
+                .filter(|product| !product.is_empty())
+                .unwrap_or("Headset")
+                .to_string(),
+            id_vendor: format!("0x{:04x}", info.vendor_id()),
+            id_product: format!("0x{:04x}", info.product_id()),
+            capabilities_str: vec!["battery".to_string()],
+            battery: Battery::default(),
+            docked: false,
+            sub_batteries: Vec::new(),
+            capabilities: Capabilities::default(),
+            chatmix: None,
+        };
+        // The battery strength is only in one of the collections
+        if devices.iter().any(|known| known.id() == device.id()) {
+            continue;
+        }
+        match read_battery(&api, info) {
+            Ok(Some(battery)) => {
+                device.battery = battery;
+                devices.push(device);
+            }
+            Ok(None) => {}
+            Err(e) => log::debug!(
+                "Failed to read the battery strength of {} on usage page {:#06x}: {e:?}",
+                device.product,
+                info.usage_page()
+            ),
+        }
+    }
+    Ok(devices)
+}
+
+/// `None` when the collection has no battery strength
+fn read_battery(api: &HidApi, info: &DeviceInfo) -> anyhow::Result<Option<Battery>> {
+    let hid = info.open_device(api).context("opening dongle")?;
+    let mut descriptor = [0; MAX_REPORT_DESCRIPTOR_SIZE];
+    let len = hid
+        .get_report_descriptor(&mut descriptor)
+        .context("reading report descriptor")?;
+    let Some(field) = find_battery_strength(&descriptor[..len]) else {
+        return Ok(None);
+    };
+    log::debug!(
+        "Battery strength of {:?} in {field:?}",
+        info.product_string()
+    );
+
+    let value = read_field(&hid, field)?;
+    let level = level(value, field).with_context(|| format!("empty logical range in {field:?}"))?;
+    Ok(Some(Battery {
+        status: BatteryState::BatteryAvailable,
+        level,
+    }))
+}
+
+/// The percent of the logical range that `value` is at, `None` when the range is empty
+fn level(value: i32, field: Field) -> Option<isize> {
+    let range = i64::from(field.logical_max) - i64::from(field.logical_min);
+    if range <= 0 {
+        return None;
+    }
+    let level = (i64::from(value) - i64::from(field.logical_min)) * 100 / range;
+    Some(level.clamp(0, 100) as isize)
+}
+
+fn read_field(hid: &HidDevice, field: Field) -> anyhow::Result<i32> {
+    let mut report = [0; REPORT_LEN + 1];
+    // Feature reports always start with the report id, input reports only when it's not 0
+    let data = if field.feature {
+        report[0] = field.report_id;
+        let len = hid
+            .get_feature_report(&mut report)
+            .context("reading feature report")?;
+        &report[1..len]
+    } else {
+        let deadline = Instant::now() + ANSWER_TIMEOUT;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            anyhow::ensure!(!left.is_zero(), "no input report within {ANSWER_TIMEOUT:?}");
+            let len = hid
+                .read_timeout(&mut report, left.as_millis() as i32)
+                .context("reading input report")?;
+            if field.report_id == 0 {
+                break &report[..len];
+            }
+            if len > 0 && report[0] == field.report_id {
+                break &report[1..len];
+            }
+        }
+    };
+    extract(data, field).context("report too short for the battery strength")
+}
+
+/// The value of `field` in the report data, sign extended when the logical range is negative
+fn extract(data: &[u8], field: Field) -> Option<i32> {
+    let mut value: u32 = 0;
+    for bit in 0..field.bit_size.min(32) {
+        let at = field.bit_offset + bit;
+        if data.get(at / 8)? >> (at % 8) & 1 != 0 {
+            value |= 1 << bit;
+        }
+    }
+    if field.logical_min < 0
+        && (1..32).contains(&field.bit_size)
+        && value >> (field.bit_size - 1) & 1 != 0
+    {
+        value |= u32::MAX << field.bit_size;
+    }
+    Some(value as i32)
+}
+
+/// The state of the descriptor that lasts from one main item to the next
+#[derive(Debug, Clone, Copy, Default)]
+struct Globals {
+    usage_page: u16,
+    logical_min: i32,
+    logical_max: i32,
+    report_size: usize,
+    report_count: usize,
+    report_id: u8,
+}
+
+/// The first input or feature field with the battery strength, from the items of the report
+/// descriptor as laid out in the HID specification
+fn find_battery_strength(descriptor: &[u8]) -> Option<Field> {
+    let mut globals = Globals::default();
+    let mut stack: Vec<Globals> = Vec::new();
+    // Full usages with the page in the high half
+    let mut usages: Vec<u32> = Vec::new();
+    // Bits used so far by each report, by whether it's a feature report and its id
+    let mut offsets: Vec<((bool, u8), usize)> = Vec::new();
+
+    let mut rest = descriptor;
+    while let Some((&prefix, after)) = rest.split_first() {
+        // Long items aren't used by any device, only skipped
+        if prefix == 0xfe {
+            let len = usize::from(*after.first()?);
+            rest = after.get(len + 2..)?;
+            continue;
+        }
+        let size = match prefix & 0x03 {
+            3 => 4,
+            size => usize::from(size),
+        };
+        let bytes = after.get(..size)?;
+        rest = &after[size..];
+        let unsigned = bytes
+            .iter()
+            .rev()
+            .fold(0u32, |value, &byte| value << 8 | u32::from(byte));
+        let signed = match size {
+            1 => i32::from(bytes[0] as i8),
+            2 => i32::from(i16::from_le_bytes([bytes[0], bytes[1]])),
+            _ => unsigned as i32,
+        };
+
+        match (prefix >> 2 & 0x03, prefix >> 4) {
+            // Input and feature
+            (0, tag @ (0x08 | 0x0b)) => {
+                let feature = tag == 0x0b;
+                let key = (feature, globals.report_id);
+                let index = match offsets.iter().position(|(known, _)| *known == key) {
+                    Some(index) => index,
+                    None => {
+                        offsets.push((key, 0));
+                        offsets.len() - 1
+                    }
+                };
+                let offset = &mut offsets[index].1;
+                for field in 0..globals.report_count {
+                    let usage = usages.get(field).or(usages.last()).copied();
+                    let battery_strength =
+                        u32::from(GENERIC_DEVICE_CONTROLS_PAGE) << 16 | u32::from(BATTERY_STRENGTH);
+                    if usage == Some(battery_strength) {
+                        return Some(Field {
+                            feature,
+                            report_id: globals.report_id,
+                            bit_offset: *offset + field * globals.report_size,
+                            bit_size: globals.report_size,
+                            logical_min: globals.logical_min,
+                            logical_max: globals.logical_max,
+                        });
+                    }
+                }
+                *offset += globals.report_count * globals.report_size;
+                usages.clear();
+            }
+            // Output, collection and end collection
+            (0, _) => usages.clear(),
+            (1, 0x00) => globals.usage_page = unsigned as u16,
+            (1, 0x01) => globals.logical_min = signed,
+            (1, 0x02) => {
+                // The maximum is unsigned when the minimum isn't negative
+                globals.logical_max = if globals.logical_min >= 0 {
+                    unsigned as i32
+                } else {
+                    signed
+                };
+            }
+            (1, 0x07) => globals.report_size = unsigned as usize,
+            (1, 0x08) => globals.report_id = unsigned as u8,
+            (1, 0x09) => globals.report_count = unsigned as usize,
+            (1, 0x0a) => stack.push(globals),
+            (1, 0x0b) => globals = stack.pop()?,
+            (2, 0x00) if size == 4 => usages.push(unsigned),
+            (2, 0x00) => usages.push(u32::from(globals.usage_page) << 16 | unsigned),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A headset collection with a mute button in report 2, then the battery strength
+    const HEADSET: &[u8] = &[
+        0x05, 0x0b, // Usage Page (Telephony)
+        0x09, 0x05, // Usage (Headset)
+        0xa1, 0x01, // Collection (Application)
+        0x85, 0x02, //   Report ID (2)
+        0x15, 0x00, //   Logical Minimum (0)
+        0x25, 0x01, //   Logical Maximum (1)
+        0x09, 0x2f, //   Usage (Phone Mute)
+        0x75, 0x01, //   Report Size (1)
+        0x95, 0x01, //   Report Count (1)
+        0x81, 0x06, //   Input (Data, Variable, Relative)
+        0x75, 0x07, //   Report Size (7)
+        0x81, 0x03, //   Input (Constant), the padding
+        0x05, 0x06, //   Usage Page (Generic Device Controls)
+        0x09, 0x20, //   Usage (Battery Strength)
+        0x26, 0xff, 0x00, //   Logical Maximum (255)
+        0x75, 0x08, //   Report Size (8)
+        0x81, 0x02, //   Input (Data, Variable, Absolute)
+        0xc0, // End Collection
+    ];
+
+    fn field(bit_size: usize, logical_min: i32, logical_max: i32) -> Field {
+        Field {
+            feature: false,
+            report_id: 0,
+            bit_offset: 0,
+            bit_size,
+            logical_min,
+            logical_max,
+        }
+    }
+
+    #[test]
+    fn input_field_after_other_fields() {
+        let field = find_battery_strength(HEADSET).unwrap();
+        assert!(!field.feature);
+        assert_eq!(field.report_id, 2);
+        assert_eq!((field.bit_offset, field.bit_size), (8, 8));
+        assert_eq!((field.logical_min, field.logical_max), (0, 255));
+    }
+
+    #[test]
+    fn feature_reports_have_their_own_offsets() {
+        let descriptor = [
+            0x05, 0x0b, 0x09, 0x05, 0xa1, 0x01, // Telephony headset collection
+            0x85, 0x01, // Report ID (1)
+            0x15, 0x00, 0x25, 0x01, 0x75, 0x08, 0x95, 0x02, // Two bytes from 0 to 1
+            0x09, 0x2f, 0x09, 0x20, 0x81, 0x02, // Phone Mute and Flash in an input
+            0x05, 0x06, 0x09, 0x20, // Battery Strength
+            0x25, 0x64, // Logical Maximum (100)
+            0x95, 0x01, 0xb1, 0x02, // One byte in a feature
+            0xc0,
+        ];
+        let field = find_battery_strength(&descriptor).unwrap();
+        assert!(field.feature);
+        assert_eq!((field.report_id, field.bit_offset), (1, 0));
+        assert_eq!(field.logical_max, 100);
+    }
+
+    #[test]
+    fn pushed_globals_are_restored() {
+        let descriptor = [
+            0x15, 0x00, 0x26, 0xff, 0x00, 0x75, 0x08, 0x95, 0x01, // A byte from 0 to 255
+            0xa4, // Push
+            0x25, 0x0a, 0x75, 0x04, // 4 bits from 0 to 10
+            0x09, 0x01, 0x81, 0x02, 0x81, 0x03, // A field and its padding
+            0xb4, // Pop
+            0x05, 0x06, 0x09, 0x20, 0x81, 0x02, // Battery Strength
+        ];
+        let field = find_battery_strength(&descriptor).unwrap();
+        assert_eq!((field.bit_offset, field.bit_size), (8, 8));
+        assert_eq!(field.logical_max, 255);
+    }
+
+    #[test]
+    fn no_battery_strength() {
+        assert!(find_battery_strength(&HEADSET[..HEADSET.len() - 10]).is_none());
+        // Cut off in the middle of an item
+        assert!(find_battery_strength(&HEADSET[..HEADSET.len() - 4]).is_none());
+        assert!(find_battery_strength(&[]).is_none());
+    }
+
+    #[test]
+    fn extracts_bits() {
+        let data = [0xa5, 0x3c];
+        let nibble = Field {
+            bit_offset: 4,
+            ..field(4, 0, 15)
+        };
+        assert_eq!(extract(&data, nibble), Some(0xa));
+        let across_bytes = Field {
+            bit_offset: 4,
+            ..field(8, 0, 255)
+        };
+        assert_eq!(extract(&data, across_bytes), Some(0xca));
+        assert_eq!(extract(&data[..1], across_bytes), None);
+    }
+
+    #[test]
+    fn sign_extends_negative_ranges() {
+        assert_eq!(extract(&[0xff], field(8, -128, 127)), Some(-1));
+        assert_eq!(extract(&[0x80], field(8, -128, 127)), Some(-128));
+        assert_eq!(extract(&[0xff], field(8, 0, 255)), Some(255));
+    }
+
+    #[test]
+    fn level_in_the_logical_range() {
+        assert_eq!(level(0, field(8, 0, 255)), Some(0));
+        assert_eq!(level(128, field(8, 0, 255)), Some(50));
+        assert_eq!(level(255, field(8, 0, 255)), Some(100));
+        assert_eq!(level(3, field(8, 0, 4)), Some(75));
+        assert_eq!(level(0, field(8, -100, 100)), Some(50));
+        // Outside of the range
+        assert_eq!(level(300, field(16, 0, 255)), Some(100));
+        assert_eq!(level(-5, field(8, 0, 100)), Some(0));
+        assert_eq!(level(1, field(8, 1, 1)), None);
+    }
+}
@@ -0,0 +1,189 @@
+//! Corsair Void and HS70 wireless headsets, read without headsetcontrol. Their dongles answer a
+//! status request with the battery level and state, the same report the Linux kernel's
+//! `hid-corsair-void` driver reads.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use hidapi::{HidApi, HidDevice};
+
+use crate::headset_control::{Battery, BatteryState, Capabilities, Device};
+
+const CORSAIR_VENDOR: u16 = 0x1b1c;
+/// The collection of the dongle that takes the status request
+const STATUS_USAGE_PAGE: u16 = 0xffc5;
+
+/// Output report that asks for the status report
+const STATUS_REQUEST: [u8; 2] = [0xc9, 0x64];
+/// Input report id of the answer: id, unused, level, unused, state
+const STATUS_REPORT: u8 = 0x64;
+const STATUS_REPORT_LEN: usize = 5;
+/// Set in the level byte while the microphone is flipped up
+const MIC_UP: u8 = 0x80;
+
+/// The state byte of the status report
+const STATE_DISCONNECTED: u8 = 0;
+const STATE_CHARGED: u8 = 4;
+const STATE_CHARGING: u8 = 5;
+
+/// The dongle answers within milliseconds
+const ANSWER_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Product ids of the wireless dongles
+const MODELS: &[(u16, &str)] = &[
+    (0x1b23, "Corsair Void Wireless"),
+    (0x1b27, "Corsair Void Wireless"),
+    (0x0a14, "Corsair Void Pro Wireless"),
+    (0x0a1a, "Corsair Void Pro Wireless"),
+    (0x0a55, "Corsair Void Elite Wireless"),
+    (0x0a38, "Corsair HS70 Wireless"),
+    (0x0a4f, "Corsair HS70 Pro Wireless"),
+];
+
+/// The headsets of the dongles that are plugged in, with the dongle's USB ids like
+/// headsetcontrol reports them
+pub fn query_devices() -> anyhow::Result<Vec<Device>> {
+    let api = HidApi::new().context("listing HID devices")?;
+
+    let mut devices: Vec<Device> = Vec::new();
+    for info in api
+        .device_list()
+        .filter(|info| info.vendor_id() == CORSAIR_VENDOR && info.usage_page() == STATUS_USAGE_PAGE)
+    {
+        let Some(&(product_id, name)) = MODELS.iter().find(|(id, _)| *id == info.product_id())
+        else {
+            continue;
+        };
+        let mut device = Device {
+            status: "success".to_string(),
+            vendor: "Corsair".to_string(),
+            product: info
+                .product_string()
+                .map(str::trim)
+                .filter(|product| !product.is_empty())
+                .unwrap_or(name)
+                .to_string(),
+            id_vendor: format!("0x{CORSAIR_VENDOR:04x}"),
+            id_product: format!("0x{product_id:04x}"),
+            capabilities_str: vec!["battery".to_string()],
+            battery: Battery::default(),
+            docked: false,
+            sub_batteries: Vec::new(),
+            capabilities: Capabilities::default(),
+            chatmix: None,
+        };
+        // Each dongle is only read once, even when it's listed more than once
+        if devices.iter().any(|known| known.id() == device.id()) {
+            continue;
+        }
+        let battery = info
+            .open_device(&api)
+            .context("opening dongle")
+            .and_then(|hid| read_battery(&hid));
+        match battery {
+            Ok(battery) => {
+                device.battery = battery;
+                devices.push(device);
+            }
+            Err(e) => log::debug!("Failed to read the battery of {name}: {e:?}"),
+        }
+    }
+    Ok(devices)
+}
+
+fn read_battery(hid: &HidDevice) -> anyhow::Result<Battery> {
+    hid.write(&STATUS_REQUEST)
+        .context("writing status request")?;
+
+    let deadline = Instant::now() + ANSWER_TIMEOUT;
+    let mut report = [0; STATUS_REPORT_LEN];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        anyhow::ensure!(
+            !left.is_zero(),
+            "no status report within {ANSWER_TIMEOUT:?}"
+        );
+        let len = hid
+            .read_timeout(&mut report, left.as_millis() as i32)
+            .context("reading status report")?;
+        // Other reports, like the microphone being flipped up, come on the same collection
+        if len == STATUS_REPORT_LEN && report[0] == STATUS_REPORT {
+            break;
+        }
+    }
+
+    log::debug!("Corsair status report {report:02x?}");
+    Ok(battery(&report))
+}
+
+/// The battery in a status report
+fn battery(report: &[u8; STATUS_REPORT_LEN]) -> Battery {
+    let level = isize::from(report[2] & !MIC_UP).min(100);
+    match report[4] {
+        // The dongle is plugged in, but the headset is off or out of range
+        STATE_DISCONNECTED => Battery {
+            status: BatteryState::BatteryUnavailable,
+            level: 0,
+        },
+        STATE_CHARGED => Battery {
+            status: BatteryState::BatteryCharging,
+            level: 100,
+        },
+        STATE_CHARGING => Battery {
+            status: BatteryState::BatteryCharging,
+            level,
+        },
+        // Normal, low and critical
+        _ => Battery {
+            status: BatteryState::BatteryAvailable,
+            level,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(level: u8, state: u8) -> [u8; STATUS_REPORT_LEN] {
+        [STATUS_REPORT, 0x00, level, 0xb1, state]
+    }
+
+    #[test]
+    fn states() {
+        let cases = [
+            (report(64, 1), BatteryState::BatteryAvailable, 64),
+            // Low and critical
+            (report(15, 2), BatteryState::BatteryAvailable, 15),
+            (report(4, 3), BatteryState::BatteryAvailable, 4),
+            (
+                report(64, STATE_CHARGING),
+                BatteryState::BatteryCharging,
+                64,
+            ),
+            (
+                report(97, STATE_CHARGED),
+                BatteryState::BatteryCharging,
+                100,
+            ),
+            (
+                report(64, STATE_DISCONNECTED),
+                BatteryState::BatteryUnavailable,
+                0,
+            ),
+        ];
+        for (report, status, level) in cases {
+            assert_eq!(battery(&report), Battery { status, level }, "{report:02x?}");
+        }
+    }
+
+    #[test]
+    fn microphone_up_is_not_part_of_the_level() {
+        assert_eq!(battery(&report(MIC_UP | 55, 1)).level, 55);
+    }
+
+    #[test]
+    fn level_is_at_most_100() {
+        assert_eq!(battery(&report(0x7f, 1)).level, 100);
+    }
+}
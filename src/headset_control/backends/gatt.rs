@@ -0,0 +1,145 @@
+//! Bluetooth LE headsets with the standard GATT Battery Service, read through the WinRT
+//! Bluetooth APIs. Any paired headset that's connected and has the service is listed, without
+//! code for its vendor.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use windows::{
+    Devices::{
+        Bluetooth::{
+            BluetoothCacheMode, BluetoothConnectionStatus, BluetoothLEAppearanceCategories,
+            BluetoothLEDevice,
+            GenericAttributeProfile::{
+                GattCharacteristicUuids, GattCommunicationStatus, GattServiceUuids,
+            },
+        },
+        Enumeration::DeviceInformation,
+    },
+    Foundation::{AsyncStatus, IAsyncOperation},
+    Storage::Streams::DataReader,
+    Win32::System::Com::{COINIT_MULTITHREADED, CoInitializeEx},
+    core::{HSTRING, RuntimeType},
+};
+
+use crate::headset_control::watchdog::{QUERY_TIMEOUT, TimedOut};
+use crate::headset_control::{BLUETOOTH_VENDOR, Battery, BatteryState, Capabilities, Device};
+
+/// How often a running WinRT operation is checked on
+const CHECK_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The paired headsets that are connected and report their battery, with the Bluetooth address
+/// as the product id
+pub fn query_devices() -> anyhow::Result<Vec<Device>> {
+    init_com();
+
+    let selector = BluetoothLEDevice::GetDeviceSelectorFromPairingState(true)
+        .context("creating Bluetooth LE device selector")?;
+    let infos = wait(DeviceInformation::FindAllAsyncAqsFilter(&selector)?)
+        .context("listing paired Bluetooth LE devices")?;
+
+    let mut devices = Vec::new();
+    for info in infos {
+        match headset(&info.Id()?) {
+            Ok(Some(device)) => devices.push(device),
+            Ok(None) => {}
+            Err(e) => log::debug!("Failed to read the battery of {}: {e:?}", info.Name()?),
+        }
+    }
+    Ok(devices)
+}
+
+/// The device with `id`, when it's connected, isn't a keyboard, mouse or game controller, and
+/// has a battery level
+fn headset(id: &HSTRING) -> anyhow::Result<Option<Device>> {
+    let device = wait(BluetoothLEDevice::FromIdAsync(id)?).context("opening device")?;
+    if device.ConnectionStatus()? != BluetoothConnectionStatus::Connected
+        || device.Appearance()?.Category()?
+            == BluetoothLEAppearanceCategories::HumanInterfaceDevice()?
+    {
+        return Ok(None);
+    }
+
+    // The level the headset reports now, not the one Windows read when it connected
+    let services = wait(device.GetGattServicesForUuidWithCacheModeAsync(
+        GattServiceUuids::Battery()?,
+        BluetoothCacheMode::Uncached,
+    )?)
+    .context("finding Battery Service")?;
+    if services.Status()? != GattCommunicationStatus::Success {
+        return Ok(None);
+    }
+    let Some(service) = services.Services()?.into_iter().next() else {
+        return Ok(None);
+    };
+    let characteristics = wait(service.GetCharacteristicsForUuidWithCacheModeAsync(
+        GattCharacteristicUuids::BatteryLevel()?,
+        BluetoothCacheMode::Uncached,
+    )?)
+    .context("finding Battery Level characteristic")?;
+    let Some(characteristic) = characteristics.Characteristics()?.into_iter().next() else {
+        return Ok(None);
+    };
+    let read = wait(characteristic.ReadValueWithCacheModeAsync(BluetoothCacheMode::Uncached)?)
+        .context("reading Battery Level")?;
+    anyhow::ensure!(
+        read.Status()? == GattCommunicationStatus::Success,
+        "reading Battery Level failed with {:?}",
+        read.Status()?
+    );
+    let level = DataReader::FromBuffer(&read.Value()?)?.ReadByte()?;
+    let address = address(device.BluetoothAddress()?);
+    let name = device.Name()?.to_string_lossy();
+
+    Ok(Some(Device {
+        status: "success".to_string(),
+        vendor: String::new(),
+        product: if name.is_empty() {
+            address.clone()
+        } else {
+            name
+        },
+        id_vendor: BLUETOOTH_VENDOR.to_string(),
+        id_product: address,
+        capabilities_str: vec!["battery".to_string()],
+        // The Battery Service doesn't say whether the headset is charging
+        battery: Battery {
+            status: BatteryState::BatteryAvailable,
+            level: isize::from(level).min(100),
+        },
+        docked: false,
+        sub_batteries: Vec::new(),
+        capabilities: Capabilities::default(),
+        chatmix: None,
+    }))
+}
+
+/// Sets up COM on the query thread for the WinRT calls. Fails when it's already set up
+/// differently on this thread, which works too.
+pub(super) fn init_com() {
+    let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+}
+
+/// Like `AA:BB:CC:DD:EE:FF`, from the 48 lowest bits
+pub(super) fn address(address: u64) -> String {
+    address.to_be_bytes()[2..]
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Waits for a WinRT operation, and cancels it when the headset takes longer than
+/// `QUERY_TIMEOUT` to answer, like when it's going out of range
+pub(super) fn wait<T: RuntimeType + 'static>(operation: IAsyncOperation<T>) -> anyhow::Result<T> {
+    let started = Instant::now();
+    while operation.Status()? == AsyncStatus::Started {
+        if started.elapsed() >= QUERY_TIMEOUT {
+            let _ = operation.Cancel();
+            return Err(TimedOut("Bluetooth").into());
+        }
+        thread::sleep(CHECK_INTERVAL);
+    }
+    Ok(operation.GetResults()?)
+}
@@ -0,0 +1,171 @@
+//! Bluetooth Classic headsets on the hands-free profile. They tell the battery level with the
+//! `+IPHONEACCEV` and `+XAPL` commands, and Windows keeps it as a property of their hands-free
+//! device, the one its Bluetooth settings show. Whether they're charging isn't kept.
+
+use anyhow::Context;
+use windows::{
+    Devices::Bluetooth::{BluetoothConnectionStatus, BluetoothDevice},
+    Win32::{
+        Devices::{
+            DeviceAndDriverInstallation::{
+                DIGCF_ALLCLASSES, DIGCF_PRESENT, HDEVINFO, SP_DEVINFO_DATA,
+                SetupDiDestroyDeviceInfoList, SetupDiEnumDeviceInfo, SetupDiGetClassDevsW,
+                SetupDiGetDeviceInstanceIdW, SetupDiGetDevicePropertyW,
+            },
+            Properties::{DEVPROP_TYPE_BYTE, DEVPROPKEY, DEVPROPTYPE},
+        },
+        Foundation::HWND,
+    },
+    core::{GUID, w},
+};
+
+use super::gatt::{self, wait};
+use crate::headset_control::{
+    BLUETOOTH_VENDOR, Battery, BatteryBackend, BatteryState, Capabilities, Device,
+};
+
+/// The battery level in percent, as a byte
+const DEVPKEY_BLUETOOTH_BATTERY: DEVPROPKEY = DEVPROPKEY {
+    fmtid: GUID::from_u128(0x104ea319_6ee2_4701_bd47_8ddbf425bbe5),
+    pid: 2,
+};
+/// Instance ids of hands-free devices start with this, the hands-free profile's service UUID
+const HANDS_FREE_PREFIX: &str = r"BTHENUM\{0000111E-0000-1000-8000-00805F9B34FB}";
+
+/// The hands-free headsets, after the AirPods backend
+pub struct HandsFree;
+
+impl BatteryBackend for HandsFree {
+    fn name(&self) -> &'static str {
+        "HandsFree"
+    }
+
+    fn is_bluetooth(&self) -> bool {
+        true
+    }
+
+    fn poll(&mut self, out: &mut Vec<Device>) -> anyhow::Result<()> {
+        let mut devices = query_devices()?;
+        // AirPods are named by their owner here, so they'd be shown twice when they were
+        // advertised, without the level of each AirPod the second time
+        if out.iter().any(|device| !device.sub_batteries.is_empty()) {
+            devices.retain(|device| !device.product.contains("AirPods"));
+        }
+        out.extend(devices);
+        Ok(())
+    }
+}
+
+/// The hands-free headsets that are connected and report their battery, with the Bluetooth
+/// address as the product id
+fn query_devices() -> anyhow::Result<Vec<Device>> {
+    gatt::init_com();
+    let set = unsafe {
+        SetupDiGetClassDevsW(
+            None,
+            w!("BTHENUM"),
+            HWND::default(),
+            DIGCF_PRESENT | DIGCF_ALLCLASSES,
+        )
+    }
+    .context("listing Bluetooth devices")?;
+    let devices = hands_free(set);
+    let _ = unsafe { SetupDiDestroyDeviceInfoList(set) };
+
+    let mut connected = Vec::new();
+    for (address, level) in devices {
+        match headset(address, level) {
+            Ok(Some(device)) => connected.push(device),
+            Ok(None) => {}
+            Err(e) => log::debug!("Failed to read the battery of {address:012X}: {e:?}"),
+        }
+    }
+    Ok(connected)
+}
+
+/// The address and battery level of the hands-free devices in `set` that have one
+fn hands_free(set: HDEVINFO) -> Vec<(u64, u8)> {
+    let mut devices = Vec::new();
+    let mut info = SP_DEVINFO_DATA {
+        cbSize: size_of::<SP_DEVINFO_DATA>() as u32,
+        ..Default::default()
+    };
+    let mut index = 0;
+    while unsafe { SetupDiEnumDeviceInfo(set, index, &mut info) }.is_ok() {
+        index += 1;
+        let mut id = [0; 256];
+        let mut len = 0;
+        if unsafe { SetupDiGetDeviceInstanceIdW(set, &info, Some(&mut id), Some(&mut len)) }
+            .is_err()
+        {
+            continue;
+        }
+        let id = String::from_utf16_lossy(&id[..(len as usize).saturating_sub(1)]);
+        let Some(address) = address(&id) else {
+            continue;
+        };
+
+        let mut kind = DEVPROPTYPE::default();
+        let mut level = [0; 1];
+        let read = unsafe {
+            SetupDiGetDevicePropertyW(
+                set,
+                &info,
+                &DEVPKEY_BLUETOOTH_BATTERY,
+                &mut kind,
+                Some(&mut level),
+                None,
+                0,
+            )
+        };
+        // Headsets that never told their battery don't have the property
+        if read.is_ok() && kind == DEVPROP_TYPE_BYTE {
+            devices.push((address, level[0]));
+        }
+    }
+    devices
+}
+
+/// The address at the end of the instance id of a hands-free device, like
+/// `BTHENUM\{0000111E-...}_LOCALMFG&0002\7&2C7B6E8&0&38184C1A2B3C_C00000000`
+fn address(id: &str) -> Option<u64> {
+    if !id.to_uppercase().starts_with(HANDS_FREE_PREFIX) {
+        return None;
+    }
+    let (_, address) = id.rsplit_once('&')?;
+    let (address, _) = address.split_once('_')?;
+    u64::from_str_radix(address, 16).ok()
+}
+
+/// The headset with `address` when it's connected, since Windows keeps the last level it was
+/// told after the headset disconnects
+fn headset(address: u64, level: u8) -> anyhow::Result<Option<Device>> {
+    let device =
+        wait(BluetoothDevice::FromBluetoothAddressAsync(address)?).context("opening device")?;
+    if device.ConnectionStatus()? != BluetoothConnectionStatus::Connected {
+        return Ok(None);
+    }
+
+    let address = gatt::address(address);
+    let name = device.Name()?.to_string_lossy();
+    Ok(Some(Device {
+        status: "success".to_string(),
+        vendor: String::new(),
+        product: if name.is_empty() {
+            address.clone()
+        } else {
+            name
+        },
+        id_vendor: BLUETOOTH_VENDOR.to_string(),
+        id_product: address,
+        capabilities_str: vec!["battery".to_string()],
+        battery: Battery {
+            status: BatteryState::BatteryAvailable,
+            level: isize::from(level).min(100),
+        },
+        docked: false,
+        sub_batteries: Vec::new(),
+        capabilities: Capabilities::default(),
+        chatmix: None,
+    }))
+}
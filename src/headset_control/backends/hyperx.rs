@@ -0,0 +1,171 @@
+//! HyperX Cloud Flight and Cloud II Wireless headsets, read without headsetcontrol. Their
+//! dongles answer a vendor-specific battery request with the level, and whether the headset is
+//! charging in a byte of its own.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use hidapi::{HidApi, HidDevice};
+
+use crate::headset_control::{Battery, BatteryState, Capabilities, Device};
+
+/// Kingston made the first ones, HP the ones since it bought HyperX
+const KINGSTON_VENDOR: u16 = 0x0951;
+const HP_VENDOR: u16 = 0x03f0;
+
+/// Report id, then the battery command
+const BATTERY_REQUEST: [u8; 4] = [0x06, 0xff, 0xbb, 0x02];
+const REPORT_LEN: usize = 20;
+/// Where the answer, which starts like the request, has the charging flag and the level
+const CHARGING_OFFSET: usize = 4;
+const LEVEL_OFFSET: usize = 7;
+
+/// The dongle answers within milliseconds, and not at all while the headset is off
+const ANSWER_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Vendor and product ids of the dongles
+const MODELS: &[(u16, u16, &str)] = &[
+    (KINGSTON_VENDOR, 0x16c4, "HyperX Cloud Flight"),
+    (KINGSTON_VENDOR, 0x1723, "HyperX Cloud Flight"),
+    (KINGSTON_VENDOR, 0x1718, "HyperX Cloud II Wireless"),
+    (HP_VENDOR, 0x0696, "HyperX Cloud II Wireless"),
+];
+
+/// The headsets of the dongles that are plugged in, with the dongle's USB ids like
+/// headsetcontrol reports them
+pub fn query_devices() -> anyhow::Result<Vec<Device>> {
+    let api = HidApi::new().context("listing HID devices")?;
+
+    let mut devices: Vec<Device> = Vec::new();
+    // Dongles where no collection answered, because the headset is off
+    let mut silent: Vec<Device> = Vec::new();
+    for info in api.device_list() {
+        let Some(&(vendor_id, product_id, name)) = MODELS.iter().find(|(vendor, product, _)| {
+            (*vendor, *product) == (info.vendor_id(), info.product_id())
+        }) else {
+            continue;
+        };
+        let mut device = Device {
+            status: "success".to_string(),
+            vendor: "HyperX".to_string(),
+            product: info
+                .product_string()
+                .map(str::trim)
+                .filter(|product| !product.is_empty())
+                .unwrap_or(name)
+                .to_string(),
+            id_vendor: format!("0x{vendor_id:04x}"),
+            id_product: format!("0x{product_id:04x}"),
+            capabilities_str: vec!["battery".to_string()],
+            battery: Battery::default(),
+            docked: false,
+            sub_batteries: Vec::new(),
+            capabilities: Capabilities::default(),
+            chatmix: None,
+        };
+        // The dongles have a few collections, only one of which answers the request
+        if devices.iter().any(|known| known.id() == device.id()) {
+            continue;
+        }
+        let battery = info
+            .open_device(&api)
+            .context("opening dongle")
+            .and_then(|hid| read_battery(&hid));
+        match battery {
+            Ok(Some(battery)) => {
+                device.battery = battery;
+                devices.push(device);
+            }
+            Ok(None) => silent.push(device),
+            Err(e) => log::debug!(
+                "Failed to read the battery of {name} on usage page {:#06x}: {e:?}",
+                info.usage_page()
+            ),
+        }
+    }
+    for mut device in silent {
+        if !devices.iter().any(|known| known.id() == device.id()) {
+            device.battery.status = BatteryState::BatteryUnavailable;
+            devices.push(device);
+        }
+    }
+    Ok(devices)
+}
+
+/// `None` when there's no answer
+fn read_battery(hid: &HidDevice) -> anyhow::Result<Option<Battery>> {
+    let mut request = [0; REPORT_LEN];
+    request[..BATTERY_REQUEST.len()].copy_from_slice(&BATTERY_REQUEST);
+    hid.write(&request).context("writing battery request")?;
+
+    let deadline = Instant::now() + ANSWER_TIMEOUT;
+    let mut answer = [0; REPORT_LEN];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(None);
+        }
+        let len = hid
+            .read_timeout(&mut answer, left.as_millis() as i32)
+            .context("reading battery answer")?;
+        // Other reports, like the volume wheel turning, come on the same collection
+        if len > LEVEL_OFFSET && answer.starts_with(&BATTERY_REQUEST) {
+            break;
+        }
+    }
+
+    log::debug!("HyperX battery answer {answer:02x?}");
+    Ok(Some(battery(&answer)))
+}
+
+/// The battery in the answer to the battery request
+fn battery(answer: &[u8; REPORT_LEN]) -> Battery {
+    Battery {
+        status: if answer[CHARGING_OFFSET] != 0 {
+            BatteryState::BatteryCharging
+        } else {
+            BatteryState::BatteryAvailable
+        },
+        level: isize::from(answer[LEVEL_OFFSET]).min(100),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(charging: u8, level: u8) -> [u8; REPORT_LEN] {
+        let mut answer = [0; REPORT_LEN];
+        answer[..BATTERY_REQUEST.len()].copy_from_slice(&BATTERY_REQUEST);
+        answer[CHARGING_OFFSET] = charging;
+        answer[LEVEL_OFFSET] = level;
+        answer
+    }
+
+    #[test]
+    fn discharging() {
+        assert_eq!(
+            battery(&answer(0, 73)),
+            Battery {
+                status: BatteryState::BatteryAvailable,
+                level: 73,
+            }
+        );
+    }
+
+    #[test]
+    fn charging() {
+        assert_eq!(
+            battery(&answer(1, 40)),
+            Battery {
+                status: BatteryState::BatteryCharging,
+                level: 40,
+            }
+        );
+    }
+
+    #[test]
+    fn level_is_at_most_100() {
+        assert_eq!(battery(&answer(0, 0xff)).level, 100);
+    }
+}
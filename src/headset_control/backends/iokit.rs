@@ -0,0 +1,79 @@
+//! Bluetooth headsets that macOS reads the battery of, from the `BatteryPercent` property in
+//! the IOKit registry. `ioreg` prints the registry, so no IOKit bindings are needed.
+
+use std::collections::HashMap;
+use std::process::Command;
+
+use crate::headset_control::{
+    BLUETOOTH_VENDOR, Battery, BatteryState, Capabilities, Device, watchdog,
+};
+
+/// Apple's keyboards, mice and trackpads have a `BatteryPercent` too
+const INPUT_DEVICES: [&str; 3] = ["keyboard", "mouse", "trackpad"];
+
+/// The headsets in the IOKit registry with a battery level, with the Bluetooth address as the
+/// product id
+pub fn query_devices() -> anyhow::Result<Vec<Device>> {
+    let output = watchdog::output(
+        Command::new("ioreg").args(["-r", "-d", "1", "-k", "BatteryPercent"]),
+        "ioreg",
+    )?;
+    anyhow::ensure!(output.status.success(), "ioreg failed: {}", output.status);
+
+    Ok(parse(&String::from_utf8_lossy(&output.stdout))
+        .iter()
+        .filter_map(headset)
+        .collect())
+}
+
+/// The properties of each object, from lines like `"BatteryPercent" = 80` between the
+/// `+-o Name <class ...>` lines
+fn parse(ioreg: &str) -> Vec<HashMap<String, String>> {
+    let mut objects = Vec::new();
+
+    for line in ioreg.lines().map(str::trim) {
+        if line.starts_with("+-o") {
+            objects.push(HashMap::new());
+        } else if let Some(object) = objects.last_mut()
+            && let Some((key, value)) = line
+                .strip_prefix('"')
+                .and_then(|line| line.split_once("\" = "))
+        {
+            object.insert(key.to_string(), value.trim_matches('"').to_string());
+        }
+    }
+
+    objects
+}
+
+fn headset(properties: &HashMap<String, String>) -> Option<Device> {
+    let level: isize = properties.get("BatteryPercent")?.parse().ok()?;
+    let product = properties.get("Product")?;
+    let lowercase = product.to_lowercase();
+    if INPUT_DEVICES.iter().any(|kind| lowercase.contains(kind)) {
+        return None;
+    }
+    // e.g. aa-bb-cc-dd-ee-ff
+    let address = properties
+        .get("DeviceAddress")
+        .map(|address| address.replace('-', ":").to_uppercase())
+        .unwrap_or_else(|| product.clone());
+
+    Some(Device {
+        status: "success".to_string(),
+        vendor: String::new(),
+        product: product.clone(),
+        id_vendor: BLUETOOTH_VENDOR.to_string(),
+        id_product: address,
+        capabilities_str: vec!["battery".to_string()],
+        // The registry doesn't say whether the headset is charging
+        battery: Battery {
+            status: BatteryState::BatteryAvailable,
+            level: level.clamp(0, 100),
+        },
+        docked: false,
+        sub_batteries: Vec::new(),
+        capabilities: Capabilities::default(),
+        chatmix: None,
+    })
+}
@@ -0,0 +1,441 @@
+//! Logitech G535, G733 and G935 headsets, read over HID++ 2.0 through their LIGHTSPEED
+//! receivers without headsetcontrol. They report the battery voltage instead of a percentage,
+//! which is turned into one with a discharge curve for each model.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use hidapi::{DeviceInfo, HidApi, HidDevice};
+
+use crate::headset_control::{Battery, BatteryState, Capabilities, Device};
+
+const LOGITECH_VENDOR: u16 = 0x046d;
+/// The collection of the receiver that HID++ is spoken on
+const HIDPP_USAGE_PAGE: u16 = 0xff43;
+const HIDPP_USAGE: u16 = 0x0202;
+
+/// Report id, device index, feature index, function and software id, then the parameters
+const LONG_REPORT: u8 = 0x11;
+const LONG_REPORT_LEN: usize = 20;
+/// HID++ 1.0 answers, which the receiver sends when it can't reach the headset
+const SHORT_REPORT: u8 = 0x10;
+const HIDPP10_ERROR: u8 = 0x8f;
+/// Feature index of HID++ 2.0 error answers
+const HIDPP20_ERROR: u8 = 0xff;
+/// The headset itself, rather than a device paired to a receiver
+const DEVICE_INDEX: u8 = 0xff;
+/// Tells the answers apart from what the headset sends on its own, which has 0
+const SOFTWARE_ID: u8 = 0x0a;
+
+/// The root feature, which finds the index of the others
+const ROOT_INDEX: u8 = 0x00;
+const GET_FEATURE: u8 = 0x00;
+/// Battery voltage, and whether the headset is on and charging
+const ADC_MEASUREMENT: u16 = 0x1f20;
+const GET_ADC_MEASUREMENT: u8 = 0x00;
+const FLAG_CONNECTED: u8 = 0x01;
+const FLAG_CHARGING: u8 = 0x02;
+
+/// The receiver answers within milliseconds, a headset that's off doesn't answer at all
+const ANSWER_TIMEOUT: Duration = Duration::from_millis(500);
+
+struct Model {
+    product_id: u16,
+    name: &'static str,
+    /// Battery voltage in millivolts and the percent left at it, from full to empty
+    curve: &'static [(u16, isize)],
+}
+
+const G535_CURVE: &[(u16, isize)] = &[
+    (4150, 100),
+    (3860, 50),
+    (3780, 30),
+    (3730, 20),
+    (3620, 5),
+    (3500, 0),
+];
+const G733_CURVE: &[(u16, isize)] = &[
+    (4186, 100),
+    (3900, 50),
+    (3800, 30),
+    (3750, 20),
+    (3650, 5),
+    (3500, 0),
+];
+const G935_CURVE: &[(u16, isize)] = &[
+    (4175, 100),
+    (3830, 50),
+    (3750, 30),
+    (3710, 20),
+    (3590, 5),
+    (3511, 0),
+];
+
+const MODELS: &[Model] = &[
+    Model {
+        product_id: 0x0ac4,
+        name: "Logitech G535",
+        curve: G535_CURVE,
+    },
+    Model {
+        product_id: 0x0ab5,
+        name: "Logitech G733",
+        curve: G733_CURVE,
+    },
+    // The receiver of the newer G733
+    Model {
+        product_id: 0x0afe,
+        name: "Logitech G733",
+        curve: G733_CURVE,
+    },
+    Model {
+        product_id: 0x0a87,
+        name: "Logitech G935",
+        curve: G935_CURVE,
+    },
+];
+
+/// The headsets of the receivers that are plugged in, with the receiver's USB ids like
+/// headsetcontrol reports them
+pub fn query_devices() -> anyhow::Result<Vec<Device>> {
+    let api = HidApi::new().context("listing HID devices")?;
+
+    let mut devices: Vec<Device> = Vec::new();
+    for info in api
+        .device_list()
+        .filter(|info| info.usage_page() == HIDPP_USAGE_PAGE && info.usage() == HIDPP_USAGE)
+        .filter(|info| info.vendor_id() == LOGITECH_VENDOR)
+    {
+        let Some(model) = MODELS
+            .iter()
+            .find(|model| model.product_id == info.product_id())
+        else {
+            continue;
+        };
+        let device = device(model, info, Battery::default());
+        // Each receiver is only read once, even when it's listed more than once
+        if devices.iter().any(|known| known.id() == device.id()) {
+            continue;
+        }
+        let battery = info
+            .open_device(&api)
+            .context("opening receiver")
+            .and_then(|hid| read_battery(&hid, model));
+        match battery {
+            Ok(battery) => devices.push(Device { battery, ..device }),
+            Err(e) => log::debug!("Failed to read the battery of {}: {e:?}", model.name),
+        }
+    }
+    Ok(devices)
+}
+
+fn device(model: &Model, info: &DeviceInfo, battery: Battery) -> Device {
+    Device {
+        status: "success".to_string(),
+        vendor: "Logitech".to_string(),
+        product: info
+            .product_string()
+            .map(str::trim)
+            .filter(|product| !product.is_empty())
+            .unwrap_or(model.name)
+            .to_string(),
+        id_vendor: format!("0x{LOGITECH_VENDOR:04x}"),
+        id_product: format!("0x{:04x}", model.product_id),
+        capabilities_str: vec!["battery".to_string()],
+        battery,
+        docked: false,
+        sub_batteries: Vec::new(),
+        capabilities: Capabilities::default(),
+        chatmix: None,
+    }
+}
+
+fn read_battery(hid: &HidDevice, model: &Model) -> anyhow::Result<Battery> {
+    let off = Battery {
+        status: BatteryState::BatteryUnavailable,
+        level: 0,
+    };
+    let [high, low] = ADC_MEASUREMENT.to_be_bytes();
+    let Some(answer) = request(hid, ROOT_INDEX, GET_FEATURE, &[high, low])? else {
+        return Ok(off);
+    };
+    let index = answer[0];
+    anyhow::ensure!(index != 0, "the headset doesn't report its battery voltage");
+
+    let Some(answer) = request(hid, index, GET_ADC_MEASUREMENT, &[])? else {
+        return Ok(off);
+    };
+    log::debug!("{} ADC measurement {:02x?}", model.name, &answer[..3]);
+    Ok(adc_battery(model.curve, &answer))
+}
+
+/// The battery from the answer to the ADC measurement, the voltage followed by the flags
+fn adc_battery(curve: &[(u16, isize)], answer: &[u8]) -> Battery {
+    let voltage = u16::from_be_bytes([answer[0], answer[1]]);
+    let flags = answer[2];
+    if flags & FLAG_CONNECTED == 0 {
+        return Battery {
+            status: BatteryState::BatteryUnavailable,
+            level: 0,
+        };
+    }
+    Battery {
+        status: if flags & FLAG_CHARGING != 0 {
+            BatteryState::BatteryCharging
+        } else {
+            BatteryState::BatteryAvailable
+        },
+        level: level(curve, voltage),
+    }
+}
+
+/// The percent left at `voltage`, in between the points of the curve
+fn level(curve: &[(u16, isize)], voltage: u16) -> isize {
+    for pair in curve.windows(2) {
+        let ((high, high_level), (low, low_level)) = (pair[0], pair[1]);
+        if voltage >= high {
+            return high_level;
+        }
+        if voltage > low {
+            let above = (voltage - low) as isize;
+            return low_level + above * (high_level - low_level) / (high - low) as isize;
+        }
+    }
+    0
+}
+
+/// Sends a HID++ 2.0 request to the headset and returns the parameters of its answer, or
+/// `None` when the headset is off or out of range
+fn request(
+    hid: &HidDevice,
+    feature: u8,
+    function: u8,
+    params: &[u8],
+) -> anyhow::Result<Option<[u8; LONG_REPORT_LEN - 4]>> {
+    let function = function << 4 | SOFTWARE_ID;
+    let mut report = [0; LONG_REPORT_LEN];
+    report[..4].copy_from_slice(&[LONG_REPORT, DEVICE_INDEX, feature, function]);
+    report[4..4 + params.len()].copy_from_slice(params);
+    hid.write(&report).context("writing HID++ request")?;
+
+    let deadline = Instant::now() + ANSWER_TIMEOUT;
+    let mut report = [0; LONG_REPORT_LEN];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(None);
+        }
+        let len = hid
+            .read_timeout(&mut report, left.as_millis() as i32)
+            .context("reading HID++ answer")?;
+        match answer(&report, len, feature, function) {
+            Some(Answer::Params(params)) => return Ok(Some(params)),
+            Some(Answer::Error(code)) => {
+                anyhow::bail!("HID++ error {code:#04x} from feature {feature}")
+            }
+            Some(Answer::Unreachable) => return Ok(None),
+            // Something the headset sent on its own, like a button press
+            None => {}
+        }
+    }
+}
+
+/// What a report from the receiver says about a request
+#[derive(Debug, PartialEq)]
+enum Answer {
+    Params([u8; LONG_REPORT_LEN - 4]),
+    /// A HID++ 2.0 error code
+    Error(u8),
+    /// The receiver couldn't reach the headset
+    Unreachable,
+}
+
+/// The answer to the request for `function` of `feature` in the `len` bytes of `report`, or
+/// `None` when it's about something else. `function` includes the software id.
+fn answer(report: &[u8; LONG_REPORT_LEN], len: usize, feature: u8, function: u8) -> Option<Answer> {
+    if len < 7 || report[1] != DEVICE_INDEX {
+        return None;
+    }
+    match *report {
+        [LONG_REPORT, _, f, fun, ..] if f == feature && fun == function => {
+            let mut params = [0; LONG_REPORT_LEN - 4];
+            params.copy_from_slice(&report[4..]);
+            Some(Answer::Params(params))
+        }
+        [LONG_REPORT, _, HIDPP20_ERROR, f, fun, code, ..] if f == feature && fun == function => {
+            Some(Answer::Error(code))
+        }
+        [SHORT_REPORT, _, HIDPP10_ERROR, f, ..] if f == feature => Some(Answer::Unreachable),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FUNCTION: u8 = GET_ADC_MEASUREMENT << 4 | SOFTWARE_ID;
+
+    fn report(bytes: &[u8]) -> [u8; LONG_REPORT_LEN] {
+        let mut report = [0; LONG_REPORT_LEN];
+        report[..bytes.len()].copy_from_slice(bytes);
+        report
+    }
+
+    #[test]
+    fn voltage_above_the_curve_is_full() {
+        for model in MODELS {
+            assert_eq!(
+                level(model.curve, model.curve[0].0 + 1),
+                100,
+                "{}",
+                model.name
+            );
+            assert_eq!(level(model.curve, u16::MAX), 100, "{}", model.name);
+        }
+    }
+
+    #[test]
+    fn voltage_below_the_curve_is_empty() {
+        for model in MODELS {
+            let empty = model.curve.last().unwrap().0;
+            assert_eq!(level(model.curve, empty - 1), 0, "{}", model.name);
+            assert_eq!(level(model.curve, 0), 0, "{}", model.name);
+        }
+    }
+
+    #[test]
+    fn points_of_the_curve() {
+        for model in MODELS {
+            for &(voltage, percent) in model.curve {
+                assert_eq!(
+                    level(model.curve, voltage),
+                    percent,
+                    "{} at {voltage}",
+                    model.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn in_between_the_points() {
+        // Halfway between each pair of points
+        let check = |curve, midpoints: [(u16, isize); 5]| {
+            for (voltage, percent) in midpoints {
+                assert_eq!(level(curve, voltage), percent, "at {voltage}");
+            }
+        };
+        check(
+            G535_CURVE,
+            [(4005, 75), (3820, 40), (3755, 25), (3675, 12), (3560, 2)],
+        );
+        check(
+            G733_CURVE,
+            [(4043, 75), (3850, 40), (3775, 25), (3700, 12), (3575, 2)],
+        );
+        check(
+            G935_CURVE,
+            [(4002, 74), (3790, 40), (3730, 25), (3650, 12), (3550, 2)],
+        );
+    }
+
+    #[test]
+    fn lower_voltage_never_shows_more() {
+        for model in MODELS {
+            let levels: Vec<_> = (3400..4300)
+                .rev()
+                .map(|mv| level(model.curve, mv))
+                .collect();
+            assert!(levels.is_sorted_by(|a, b| a >= b), "{}", model.name);
+        }
+    }
+
+    #[test]
+    fn adc_measurement() {
+        let [high, low] = 3860u16.to_be_bytes();
+        let battery = adc_battery(G535_CURVE, &[high, low, FLAG_CONNECTED]);
+        assert_eq!(battery.status, BatteryState::BatteryAvailable);
+        assert_eq!(battery.level, 50);
+
+        let battery = adc_battery(G535_CURVE, &[high, low, FLAG_CONNECTED | FLAG_CHARGING]);
+        assert_eq!(battery.status, BatteryState::BatteryCharging);
+
+        let battery = adc_battery(G535_CURVE, &[high, low, 0]);
+        assert_eq!(battery.status, BatteryState::BatteryUnavailable);
+    }
+
+    #[test]
+    fn answer_with_parameters() {
+        let report = report(&[LONG_REPORT, DEVICE_INDEX, 0x05, FUNCTION, 0x0f, 0x14, 0x03]);
+        let Some(Answer::Params(params)) = answer(&report, LONG_REPORT_LEN, 0x05, FUNCTION) else {
+            panic!("no answer in {report:02x?}");
+        };
+        assert_eq!(params[..3], [0x0f, 0x14, 0x03]);
+    }
+
+    #[test]
+    fn error_answers() {
+        let error = report(&[
+            LONG_REPORT,
+            DEVICE_INDEX,
+            HIDPP20_ERROR,
+            0x05,
+            FUNCTION,
+            0x05,
+        ]);
+        assert_eq!(
+            answer(&error, LONG_REPORT_LEN, 0x05, FUNCTION),
+            Some(Answer::Error(0x05))
+        );
+        // The receiver's own answer while the headset is off
+        let off = report(&[
+            SHORT_REPORT,
+            DEVICE_INDEX,
+            HIDPP10_ERROR,
+            0x05,
+            FUNCTION,
+            0x09,
+            0,
+        ]);
+        assert_eq!(answer(&off, 7, 0x05, FUNCTION), Some(Answer::Unreachable));
+    }
+
+    #[test]
+    fn other_reports_are_not_answers() {
+        let reports = [
+            // Another feature, another function, or sent by the headset on its own
+            report(&[LONG_REPORT, DEVICE_INDEX, 0x06, FUNCTION, 1, 2, 3]),
+            report(&[LONG_REPORT, DEVICE_INDEX, 0x05, 0x1a, 1, 2, 3]),
+            report(&[
+                LONG_REPORT,
+                DEVICE_INDEX,
+                0x05,
+                GET_ADC_MEASUREMENT << 4,
+                1,
+                2,
+                3,
+            ]),
+            // A device paired to the receiver rather than the headset
+            report(&[LONG_REPORT, 0x01, 0x05, FUNCTION, 1, 2, 3]),
+            report(&[
+                SHORT_REPORT,
+                DEVICE_INDEX,
+                HIDPP10_ERROR,
+                0x06,
+                FUNCTION,
+                0x09,
+                0,
+            ]),
+        ];
+        for report in reports {
+            assert_eq!(
+                answer(&report, LONG_REPORT_LEN, 0x05, FUNCTION),
+                None,
+                "{report:02x?}"
+            );
+        }
+        let short = report(&[LONG_REPORT, DEVICE_INDEX, 0x05, FUNCTION, 1, 2]);
+        assert_eq!(answer(&short, 6, 0x05, FUNCTION), None);
+    }
+}
@@ -0,0 +1,277 @@
+//! Razer BlackShark V2 Pro and Barracuda headsets, read without headsetcontrol. Their dongles
+//! take the 90-byte commands of Razer's other devices as feature reports, the USB control
+//! transfers that Synapse and OpenRazer use, with one command for the battery level and one
+//! for whether it's charging.
+
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use hidapi::{HidApi, HidDevice};
+
+use crate::headset_control::{Battery, BatteryState, Capabilities, Device};
+
+const RAZER_VENDOR: u16 = 0x1532;
+
+/// Status, transaction id, remaining packets, protocol type, data size, command class,
+/// command id, 80 bytes of arguments, checksum and a reserved byte
+const REPORT_LEN: usize = 90;
+const TRANSACTION_ID: u8 = 0x1f;
+const POWER_CLASS: u8 = 0x07;
+const GET_BATTERY_LEVEL: u8 = 0x80;
+const GET_CHARGING: u8 = 0x84;
+/// The first argument is the zone, the second the answer
+const ARGUMENTS_SIZE: u8 = 0x02;
+const ARGUMENTS_OFFSET: usize = 8;
+
+/// The status byte of an answer
+const STATUS_BUSY: u8 = 0x01;
+const STATUS_OK: u8 = 0x02;
+/// The dongle couldn't reach the headset
+const STATUS_TIMEOUT: u8 = 0x04;
+
+/// The dongle needs a moment before the answer can be read, and longer while it's busy
+const ANSWER_DELAY: Duration = Duration::from_millis(15);
+const MAX_BUSY_RETRIES: usize = 10;
+
+/// Product ids of the dongles
+const MODELS: &[(u16, &str)] = &[
+    (0x0528, "Razer BlackShark V2 Pro"),
+    (0x0555, "Razer BlackShark V2 Pro"),
+    (0x053c, "Razer Barracuda X"),
+    (0x0566, "Razer Barracuda"),
+];
+
+/// The headsets of the dongles that are plugged in, with the dongle's USB ids like
+/// headsetcontrol reports them
+pub fn query_devices() -> anyhow::Result<Vec<Device>> {
+    let api = HidApi::new().context("listing HID devices")?;
+
+    let mut devices: Vec<Device> = Vec::new();
+    for info in api
+        .device_list()
+        .filter(|info| info.vendor_id() == RAZER_VENDOR)
+    {
+        let Some(&(product_id, name)) = MODELS.iter().find(|(id, _)| *id == info.product_id())
+        else {
+            continue;
+        };
+        let mut device = Device {
+            status: "success".to_string(),
+            vendor: "Razer".to_string(),
+            product: info
+                .product_string()
+                .map(str::trim)
+                .filter(|product| !product.is_empty())
+                .unwrap_or(name)
+                .to_string(),
+            id_vendor: format!("0x{RAZER_VENDOR:04x}"),
+            id_product: format!("0x{product_id:04x}"),
+            capabilities_str: vec!["battery".to_string()],
+            battery: Battery::default(),
+            docked: false,
+            sub_batteries: Vec::new(),
+            capabilities: Capabilities::default(),
+            chatmix: None,
+        };
+        // Only one of the collections of the dongle takes the commands
+        if devices.iter().any(|known| known.id() == device.id()) {
+            continue;
+        }
+        let battery = info
+            .open_device(&api)
+            .context("opening dongle")
+            .and_then(|hid| read_battery(&hid));
+        match battery {
+            Ok(battery) => {
+                device.battery = battery;
+                devices.push(device);
+            }
+            Err(e) => log::debug!(
+                "Failed to read the battery of {name} on interface {}: {e:?}",
+                info.interface_number()
+            ),
+        }
+    }
+    Ok(devices)
+}
+
+fn read_battery(hid: &HidDevice) -> anyhow::Result<Battery> {
+    let Some(level) = command(hid, GET_BATTERY_LEVEL)? else {
+        return Ok(Battery {
+            status: BatteryState::BatteryUnavailable,
+            level: 0,
+        });
+    };
+    let charging = command(hid, GET_CHARGING)?.is_some_and(|charging| charging == 1);
+    Ok(battery(level, charging))
+}
+
+/// The battery from the answers to the level and charging commands, the level is from 0 to 255
+fn battery(level: u8, charging: bool) -> Battery {
+    Battery {
+        status: if charging {
+            BatteryState::BatteryCharging
+        } else {
+            BatteryState::BatteryAvailable
+        },
+        level: (isize::from(level) * 100 + 127) / 255,
+    }
+}
+
+/// Sends a command of the power class and returns its answer, or `None` when the headset is
+/// off or out of range
+fn command(hid: &HidDevice, id: u8) -> anyhow::Result<Option<u8>> {
+    hid.send_feature_report(&request(id))
+        .context("sending command")?;
+
+    let mut report = [0; REPORT_LEN + 1];
+    for _ in 0..MAX_BUSY_RETRIES {
+        thread::sleep(ANSWER_DELAY);
+        report[0] = 0;
+        hid.get_feature_report(&mut report)
+            .context("reading answer")?;
+        match answer(&report[1..], id)? {
+            Answer::Value(value) => return Ok(Some(value)),
+            Answer::Busy => {}
+            Answer::Unreachable => return Ok(None),
+        }
+    }
+    anyhow::bail!("still busy after {MAX_BUSY_RETRIES} tries")
+}
+
+/// The feature report with command `id` of the power class
+fn request(id: u8) -> [u8; REPORT_LEN + 1] {
+    // Feature reports start with the report id, which is 0 for these dongles
+    let mut report = [0; REPORT_LEN + 1];
+    report[2] = TRANSACTION_ID;
+    report[6] = ARGUMENTS_SIZE;
+    report[7] = POWER_CLASS;
+    report[8] = id;
+    report[REPORT_LEN - 1] = checksum(&report[1..]);
+    report
+}
+
+/// What the dongle answered command `id` with
+#[derive(Debug, PartialEq)]
+enum Answer {
+    Value(u8),
+    /// Not done yet, asked again after a moment
+    Busy,
+    /// The dongle couldn't reach the headset
+    Unreachable,
+}
+
+fn answer(answer: &[u8], id: u8) -> anyhow::Result<Answer> {
+    match answer[0] {
+        STATUS_OK => {
+            anyhow::ensure!(
+                answer[6..8] == [POWER_CLASS, id],
+                "answer to another command {:#04x}",
+                answer[7]
+            );
+            Ok(Answer::Value(answer[ARGUMENTS_OFFSET + 1]))
+        }
+        STATUS_BUSY => Ok(Answer::Busy),
+        STATUS_TIMEOUT => Ok(Answer::Unreachable),
+        status => anyhow::bail!("command {id:#04x} failed with status {status:#04x}"),
+    }
+}
+
+/// XOR of the bytes from the remaining packets to the last argument
+fn checksum(report: &[u8]) -> u8 {
+    report[2..REPORT_LEN - 2]
+        .iter()
+        .fold(0, |sum, byte| sum ^ byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The answer as the dongle sends it, without the report id
+    fn answer_report(status: u8, class: u8, id: u8, value: u8) -> [u8; REPORT_LEN] {
+        let mut report = [0; REPORT_LEN];
+        report[0] = status;
+        report[1] = TRANSACTION_ID;
+        report[5] = ARGUMENTS_SIZE;
+        report[6] = class;
+        report[7] = id;
+        report[ARGUMENTS_OFFSET + 1] = value;
+        report
+    }
+
+    #[test]
+    fn request_layout() {
+        let report = request(GET_BATTERY_LEVEL);
+        assert_eq!(
+            report[..9],
+            [
+                0,
+                0,
+                TRANSACTION_ID,
+                0,
+                0,
+                0,
+                ARGUMENTS_SIZE,
+                POWER_CLASS,
+                GET_BATTERY_LEVEL
+            ]
+        );
+        assert!(report[9..REPORT_LEN - 1].iter().all(|&byte| byte == 0));
+        // Data size, class and id, the only bytes set in the checksummed range
+        assert_eq!(report[REPORT_LEN - 1], 0x02 ^ 0x07 ^ 0x80);
+        assert_eq!(request(GET_CHARGING)[REPORT_LEN - 1], 0x02 ^ 0x07 ^ 0x84);
+    }
+
+    #[test]
+    fn checksum_leaves_out_status_and_transaction_id() {
+        let mut report = [0; REPORT_LEN];
+        report[0] = 0xff;
+        report[1] = 0xff;
+        report[REPORT_LEN - 2] = 0xff;
+        report[REPORT_LEN - 1] = 0xff;
+        assert_eq!(checksum(&report), 0);
+        report[2] = 0x12;
+        report[REPORT_LEN - 3] = 0x30;
+        assert_eq!(checksum(&report), 0x22);
+    }
+
+    #[test]
+    fn answers() {
+        let ok = answer_report(STATUS_OK, POWER_CLASS, GET_BATTERY_LEVEL, 200);
+        assert_eq!(answer(&ok, GET_BATTERY_LEVEL).unwrap(), Answer::Value(200));
+        let busy = answer_report(STATUS_BUSY, POWER_CLASS, GET_BATTERY_LEVEL, 0);
+        assert_eq!(answer(&busy, GET_BATTERY_LEVEL).unwrap(), Answer::Busy);
+        let off = answer_report(STATUS_TIMEOUT, POWER_CLASS, GET_BATTERY_LEVEL, 0);
+        assert_eq!(
+            answer(&off, GET_BATTERY_LEVEL).unwrap(),
+            Answer::Unreachable
+        );
+    }
+
+    #[test]
+    fn wrong_answers_fail() {
+        let other_command = answer_report(STATUS_OK, POWER_CLASS, GET_CHARGING, 1);
+        assert!(answer(&other_command, GET_BATTERY_LEVEL).is_err());
+        let other_class = answer_report(STATUS_OK, 0x0f, GET_BATTERY_LEVEL, 1);
+        assert!(answer(&other_class, GET_BATTERY_LEVEL).is_err());
+        // Not supported
+        let failed = answer_report(0x05, POWER_CLASS, GET_BATTERY_LEVEL, 0);
+        assert!(answer(&failed, GET_BATTERY_LEVEL).is_err());
+    }
+
+    #[test]
+    fn level_in_percent() {
+        let levels = [(0, 0), (1, 0), (2, 1), (128, 50), (254, 100), (255, 100)];
+        for (value, percent) in levels {
+            assert_eq!(battery(value, false).level, percent, "{value}");
+        }
+    }
+
+    #[test]
+    fn charging() {
+        assert_eq!(battery(90, true).status, BatteryState::BatteryCharging);
+        assert_eq!(battery(90, false).status, BatteryState::BatteryAvailable);
+    }
+}
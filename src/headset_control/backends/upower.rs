@@ -0,0 +1,139 @@
+//! Bluetooth headsets whose battery BlueZ reads over the Bluetooth battery service or the
+//! hands-free profile. UPower lists them next to the laptop battery and mice.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use zbus::blocking::{Connection, connection};
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+
+use crate::headset_control::watchdog::{QUERY_TIMEOUT, TimedOut};
+use crate::headset_control::{BLUETOOTH_VENDOR, Battery, BatteryState, Capabilities, Device};
+
+const UPOWER: &str = "org.freedesktop.UPower";
+const UPOWER_PATH: &str = "/org/freedesktop/UPower";
+const DEVICE_INTERFACE: &str = "org.freedesktop.UPower.Device";
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+/// `Type` values of headsets and headphones
+const HEADSET_TYPES: [u32; 2] = [17, 19];
+/// `State` value while charging
+const STATE_CHARGING: u32 = 1;
+
+/// The headsets UPower knows the battery of, with the Bluetooth address as the product id
+pub fn query_devices() -> anyhow::Result<Vec<Device>> {
+    let conn = connection()?;
+
+    let paths: Vec<OwnedObjectPath> = conn
+        .call_method(
+            Some(UPOWER),
+            UPOWER_PATH,
+            Some(UPOWER),
+            "EnumerateDevices",
+            &(),
+        )
+        .map_err(timed_out)
+        .context("listing UPower devices")?
+        .body()
+        .deserialize()
+        .context("parsing UPower devices")?;
+
+    let mut devices = Vec::new();
+    for path in paths {
+        let properties: HashMap<String, OwnedValue> = conn
+            .call_method(
+                Some(UPOWER),
+                &path,
+                Some(PROPERTIES_INTERFACE),
+                "GetAll",
+                &(DEVICE_INTERFACE,),
+            )
+            .and_then(|reply| reply.body().deserialize())
+            .map_err(timed_out)
+            .with_context(|| format!("reading properties of {}", path.as_str()))?;
+
+        if let Some(device) = headset(&properties) {
+            devices.push(device);
+        }
+    }
+
+    Ok(devices)
+}
+
+fn headset(properties: &HashMap<String, OwnedValue>) -> Option<Device> {
+    let get = |name: &str| {
+        properties
+            .get(name)
+            .and_then(|value| value.try_clone().ok())
+    };
+
+    let kind: u32 = get("Type")?.try_into().ok()?;
+    if !HEADSET_TYPES.contains(&kind) {
+        return None;
+    }
+    let native_path: String = get("NativePath")?.try_into().ok()?;
+    // e.g. /org/bluez/hci0/dev_AA_BB_CC_DD_EE_FF
+    let address = native_path
+        .strip_prefix("/org/bluez/")?
+        .rsplit_once("dev_")?
+        .1
+        .replace('_', ":");
+
+    let percentage: f64 = get("Percentage")?.try_into().ok()?;
+    let state: u32 = get("State")?.try_into().ok()?;
+    let string = |name: &str| {
+        get(name)
+            .and_then(|value| String::try_from(value).ok())
+            .unwrap_or_default()
+    };
+    let model = string("Model");
+
+    Some(Device {
+        status: "success".to_string(),
+        vendor: string("Vendor"),
+        product: if model.is_empty() {
+            address.clone()
+        } else {
+            model
+        },
+        id_vendor: BLUETOOTH_VENDOR.to_string(),
+        id_product: address,
+        capabilities_str: vec!["battery".to_string()],
+        battery: Battery {
+            status: if state == STATE_CHARGING {
+                BatteryState::BatteryCharging
+            } else {
+                BatteryState::BatteryAvailable
+            },
+            level: percentage.round() as isize,
+        },
+        docked: false,
+        sub_batteries: Vec::new(),
+        capabilities: Capabilities::default(),
+        chatmix: None,
+    })
+}
+
+/// UPower asks BlueZ for the battery, which can hang on a headset going out of range
+fn timed_out(e: zbus::Error) -> anyhow::Error {
+    match &e {
+        zbus::Error::InputOutput(io) if io.kind() == std::io::ErrorKind::TimedOut => {
+            TimedOut("UPower").into()
+        }
+        _ => e.into(),
+    }
+}
+
+/// Connecting to the system bus on every poll would be wasteful, so the connection is kept
+fn connection() -> anyhow::Result<Connection> {
+    static CONNECTION: OnceLock<Connection> = OnceLock::new();
+
+    if let Some(conn) = CONNECTION.get() {
+        return Ok(conn.clone());
+    }
+    let conn = connection::Builder::system()
+        .and_then(|builder| builder.method_timeout(QUERY_TIMEOUT).build())
+        .context("connecting to the D-Bus system bus")?;
+    Ok(CONNECTION.get_or_init(|| conn).clone())
+}
@@ -0,0 +1,206 @@
+//! Runs the backends that are enabled and merges what they read into one list of headsets
+
+use anyhow::Context;
+
+use super::watchdog::{self, Backend};
+use super::{Device, HeadsetControlConfig, Query, backends, merge};
+
+/// One way of reading headsets, like headsetcontrol or the dongles of one brand
+pub trait BatteryBackend: Send {
+    /// Shown in the log and by `--bench-polls`, and the value that disables it under the
+    /// `Backends` subkey
+    fn name(&self) -> &'static str;
+
+    /// Whether it reads Bluetooth headsets through the OS, on a thread of its own next to the
+    /// USB backends
+    fn is_bluetooth(&self) -> bool {
+        false
+    }
+
+    /// Adds the headsets it read to `out`, which has those of the backends before it
+    fn poll(&mut self, out: &mut Vec<Device>) -> anyhow::Result<()>;
+}
+
+/// A backend that's a single query, like most of them
+pub struct QueryBackend {
+    name: &'static str,
+    bluetooth: bool,
+    query: Query,
+}
+
+impl QueryBackend {
+    pub fn usb(name: &'static str, query: Query) -> Box<dyn BatteryBackend> {
+        Box::new(Self {
+            name,
+            bluetooth: false,
+            query,
+        })
+    }
+
+    pub fn bluetooth(name: &'static str, query: Query) -> Box<dyn BatteryBackend> {
+        Box::new(Self {
+            name,
+            bluetooth: true,
+            query,
+        })
+    }
+}
+
+impl BatteryBackend for QueryBackend {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn is_bluetooth(&self) -> bool {
+        self.bluetooth
+    }
+
+    fn poll(&mut self, out: &mut Vec<Device>) -> anyhow::Result<()> {
+        out.extend((self.query)()?);
+        Ok(())
+    }
+}
+
+/// The enabled backends, in the order their headsets are preferred in when one is read by
+/// more than one of them
+pub struct Registry {
+    usb: Vec<Box<dyn BatteryBackend>>,
+    bluetooth: Vec<Box<dyn BatteryBackend>>,
+}
+
+impl Registry {
+    /// Every backend but those named in `disabled`
+    pub fn new(disabled: &[String], headsetcontrol: &HeadsetControlConfig) -> Self {
+        let (bluetooth, usb) = backends(headsetcontrol, true)
+            .into_iter()
+            .filter(|backend| {
+                let enabled = !disabled.iter().any(|name| name == backend.name());
+                if !enabled {
+                    log::info!("The {} backend is disabled", backend.name());
+                }
+                enabled
+            })
+            .partition(|backend| backend.is_bluetooth());
+        Self { usb, bluetooth }
+    }
+
+    /// Polls the USB and the Bluetooth backends at the same time. When one of them answers
+    /// first, `partial` gets its headsets along with the other's headsets in `vec` from the
+    /// last poll, so a slow backend doesn't hold up the other.
+    pub fn poll(
+        &mut self,
+        vec: &mut Vec<Device>,
+        mut partial: impl FnMut(Vec<Device>),
+    ) -> anyhow::Result<()> {
+        enum Answer {
+            Usb(anyhow::Result<Vec<Device>>),
+            Bluetooth(Vec<Device>),
+        }
+
+        let (sender, answers) = std::sync::mpsc::channel();
+        let bluetooth_sender = sender.clone();
+        let Registry { usb, bluetooth } = self;
+
+        let (usb, bluetooth) = std::thread::scope(|scope| {
+            std::thread::Builder::new()
+                .name("usb-query".to_string())
+                .spawn_scoped(scope, move || {
+                    let mut devices = Vec::new();
+                    let result = poll_all(usb, &mut devices);
+                    let _ = sender.send(Answer::Usb(result.map(|()| devices)));
+                })
+                .context("spawning USB query thread")?;
+            // Bluetooth headsets that headsetcontrol can't reach, which the OS reads the
+            // battery of
+            std::thread::Builder::new()
+                .name("bluetooth-query".to_string())
+                .spawn_scoped(scope, move || {
+                    let mut devices = Vec::new();
+                    let result =
+                        watchdog::guard(Backend::Bluetooth, || poll_all(bluetooth, &mut devices));
+                    if let Err(e) = result {
+                        log::debug!("Failed to query Bluetooth batteries: {e:?}");
+                    }
+                    let _ = bluetooth_sender.send(Answer::Bluetooth(devices));
+                })
+                .context("spawning Bluetooth query thread")?;
+
+            let (mut usb, mut bluetooth) = (None, None);
+            // Ends when both threads are done
+            for answer in answers {
+                match answer {
+                    Answer::Usb(result) => usb = Some(result),
+                    Answer::Bluetooth(devices) => bluetooth = Some(devices),
+                }
+
+                let last = |bluetooth: bool| -> Vec<Device> {
+                    vec.iter()
+                        .filter(|device| device.is_bluetooth() == bluetooth)
+                        .cloned()
+                        .collect()
+                };
+                match (&usb, &bluetooth) {
+                    (Some(Ok(devices)), None) => {
+                        let mut devices = devices.clone();
+                        merge(&mut devices, last(true));
+                        if !devices.is_empty() {
+                            partial(devices);
+                        }
+                    }
+                    (None, Some(devices)) if !devices.is_empty() => {
+                        let mut known = last(false);
+                        merge(&mut known, devices.clone());
+                        partial(known);
+                    }
+                    _ => {}
+                }
+            }
+            anyhow::Ok((usb, bluetooth))
+        })?;
+
+        let result = match usb.context("USB query thread stopped")? {
+            Ok(devices) => {
+                *vec = devices;
+                Ok(())
+            }
+            Err(e) => Err(e),
+        };
+
+        let bluetooth = bluetooth.unwrap_or_default();
+        if !bluetooth.is_empty() {
+            if let Err(e) = &result {
+                log::debug!("Showing only Bluetooth headsets: {e:?}");
+                vec.clear();
+            }
+            merge(vec, bluetooth);
+            return Ok(());
+        }
+
+        result
+    }
+}
+
+/// Polls each of `backends` in turn, and adds the headsets that the ones before didn't read.
+/// When none is found, the error of the first backend that failed is returned, so a missing
+/// headsetcontrol is still told apart from no headset being plugged in.
+fn poll_all(backends: &mut [Box<dyn BatteryBackend>], out: &mut Vec<Device>) -> anyhow::Result<()> {
+    let mut failure = None;
+    for backend in backends {
+        let known = out.len();
+        match backend.poll(out) {
+            Ok(()) => {
+                let found = out.split_off(known);
+                merge(out, found);
+            }
+            Err(e) => {
+                out.truncate(known);
+                log::debug!("Failed to query {}: {e:?}", backend.name());
+                failure.get_or_insert(e);
+            }
+        }
+    }
+    match failure {
+        Some(e) if out.is_empty() => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,154 @@
+//! Keeps a headset or dongle that stops answering from holding up the polls. Queries that
+//! run too long are killed, and a backend that keeps timing out is skipped for a while.
+
+use std::io::Read;
+use std::process::{Command, Output, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, anyhow};
+
+/// headsetcontrol takes a few seconds at most, even while it wakes up a dongle
+pub const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often a running query is checked on
+const CHECK_INTERVAL: Duration = Duration::from_millis(50);
+/// Timeouts in a row before the backend is skipped
+const MAX_TIMEOUTS: u32 = 3;
+const SKIPPED_FOR: Duration = Duration::from_secs(5 * 60);
+
+/// A query that took longer than `QUERY_TIMEOUT`
+#[derive(Debug)]
+pub struct TimedOut(pub &'static str);
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} didn't answer within {QUERY_TIMEOUT:?}", self.0)
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Backend {
+    HeadsetControl = 0,
+    Bluetooth = 1,
+}
+
+impl Backend {
+    fn name(self) -> &'static str {
+        match self {
+            Backend::HeadsetControl => "headsetcontrol",
+            Backend::Bluetooth => "Bluetooth",
+        }
+    }
+}
+
+struct Health {
+    timeouts: u32,
+    skipped_until: Option<Instant>,
+}
+
+impl Health {
+    const fn new() -> Self {
+        Self {
+            timeouts: 0,
+            skipped_until: None,
+        }
+    }
+}
+
+static HEALTH: Mutex<[Health; 2]> = Mutex::new([Health::new(), Health::new()]);
+
+/// Runs `query` unless `backend` timed out too often lately, and counts its timeouts
+pub fn guard<T>(backend: Backend, query: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+    if let Some(until) = health(backend, |health| health.skipped_until)
+        && Instant::now() < until
+    {
+        anyhow::bail!(
+            "{} is skipped for {:?} after timing out {MAX_TIMEOUTS} times in a row",
+            backend.name(),
+            until - Instant::now()
+        );
+    }
+
+    let result = query();
+    let timed_out = result
+        .as_ref()
+        .is_err_and(|e| e.chain().any(|cause| cause.is::<TimedOut>()));
+
+    health(backend, |health| {
+        health.skipped_until = None;
+        if !timed_out {
+            health.timeouts = 0;
+            return;
+        }
+        health.timeouts += 1;
+        log::warn!(
+            "{} timed out, {} times in a row",
+            backend.name(),
+            health.timeouts
+        );
+        if health.timeouts >= MAX_TIMEOUTS {
+            log::error!("Skipping {} for {SKIPPED_FOR:?}", backend.name());
+            health.timeouts = 0;
+            health.skipped_until = Some(Instant::now() + SKIPPED_FOR);
+        }
+    });
+
+    result
+}
+
+fn health<T>(backend: Backend, f: impl FnOnce(&mut Health) -> T) -> T {
+    let mut health = HEALTH.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut health[backend as usize])
+}
+
+/// Like `Command::output`, but kills the process when it runs longer than `QUERY_TIMEOUT`.
+/// Only stdout is read.
+pub fn output(command: &mut Command, name: &'static str) -> anyhow::Result<Output> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to execute {name}"))?;
+
+    // Read on another thread, so a full pipe can't keep the process from exiting
+    let mut stdout = child.stdout.take().context("taking stdout")?;
+    let reader = thread::Builder::new()
+        .name("query-output".to_string())
+        .spawn(move || {
+            let mut buffer = Vec::new();
+            stdout.read_to_end(&mut buffer).map(|_| buffer)
+        })
+        .context("spawning output reader thread")?;
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child
+            .try_wait()
+            .with_context(|| format!("waiting for {name}"))?
+        {
+            break status;
+        }
+        if started.elapsed() >= QUERY_TIMEOUT {
+            if let Err(e) = child.kill() {
+                log::error!("Failed to kill {name}: {e}");
+            }
+            let _ = child.wait();
+            return Err(TimedOut(name).into());
+        }
+        thread::sleep(CHECK_INTERVAL);
+    };
+
+    let stdout = reader
+        .join()
+        .map_err(|_| anyhow!("reading the output of {name} panicked"))?
+        .with_context(|| format!("reading the output of {name}"))?;
+
+    Ok(Output {
+        status,
+        stdout,
+        stderr: Vec::new(),
+    })
+}
@@ -0,0 +1,1585 @@
+//! Battery samples of every device in `history.db` in LocalAppData, and the time
+//! remaining estimated from them.
+//!
+//! A sample is only taken when a battery changes, so the `history` table of the SQLite database
+//! grows by a few rows an hour.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Context;
+use log::{debug, error, info, warn};
+use rusqlite::{Connection, Row, Transaction, params};
+
+use crate::events::AppEvent;
+use crate::headset_control::{Battery, BatteryState, Device};
+use crate::lang::{self, Key::*};
+use crate::platform::sandbox::{self, Sandbox as _};
+
+/// `status` has the same names as the JSON of the local API
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS history (
+        time INTEGER NOT NULL,
+        device TEXT NOT NULL,
+        level INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        anomaly INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS history_device_time ON history (device, time);
+";
+const INSERT: &str =
+    "INSERT INTO history (time, device, level, status, anomaly) VALUES (?1, ?2, ?3, ?4, ?5)";
+const SELECT: &str =
+    "SELECT time, device, level, status, anomaly FROM history ORDER BY time, rowid";
+
+/// Old samples are deleted and thinned out this often, and when the program starts
+const COMPACT_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Samples older than the full resolution period are thinned out to one per this long
+const DOWNSAMPLE_INTERVAL: u64 = 15 * 60;
+
+/// The weekly summary is shown from this hour on Monday
+const SUMMARY_HOUR: i64 = 9;
+/// Where the low battery notification is shown
+const LOW_LEVEL: isize = 10;
+
+/// A level changing by this much between two polls without charging is a glitch of the dongle
+const MAX_JUMP: isize = 30;
+
+/// Samples are written in batches, at the latest after this long
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const BATCH_SIZE: usize = 20;
+
+/// How far back samples count towards the rate, so it follows changes in usage
+pub const RATE_WINDOW: Duration = Duration::from_secs(6 * 60 * 60);
+/// Samples closer together than this don't give a usable rate
+const MIN_RATE_SPAN: Duration = Duration::from_secs(10 * 60);
+/// The trend follows the last two hours, so it reacts to the headset being put aside
+const TREND_WINDOW: Duration = Duration::from_secs(2 * 60 * 60);
+/// Slower changes, in percent per hour, are shown as steady
+const STEADY_RATE: f32 = 0.5;
+
+#[derive(Debug, Clone)]
+pub struct Sample {
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+    pub device: String,
+    pub level: isize,
+    pub status: BatteryState,
+    /// The level jumped implausibly far since the previous poll, kept in the history
+    /// but left out of the rate and health estimates
+    pub anomaly: bool,
+}
+
+/// How long samples are kept, from the settings
+#[derive(Debug, Clone, Copy)]
+pub struct Retention {
+    /// Older samples are deleted, `None` keeps them forever
+    pub max_age: Option<Duration>,
+    /// Older samples are thinned out to one every 15 minutes
+    pub full_resolution: Duration,
+}
+
+pub struct History {
+    db: Connection,
+    pending: Vec<Sample>,
+    last_flush: Instant,
+    retention: Retention,
+    last_compact: Instant,
+}
+
+impl History {
+    pub fn open(retention: Retention) -> anyhow::Result<Self> {
+        let path = data_dir()?.join("history.db");
+        info!("Recording battery history to {}", path.display());
+        let db = Connection::open(&path).with_context(|| format!("opening {}", path.display()))?;
+        db.execute_batch(SCHEMA)
+            .context("creating the history table")?;
+
+        let mut history = Self {
+            db,
+            pending: vec![],
+            last_flush: Instant::now(),
+            retention,
+            last_compact: Instant::now(),
+        };
+        if let Err(e) = history.compact() {
+            error!("Failed to clean up battery history: {e:?}");
+        }
+        Ok(history)
+    }
+
+    pub fn record(&mut self, samples: &[Sample]) {
+        self.pending.extend_from_slice(samples);
+
+        if self.pending.len() >= BATCH_SIZE || self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush();
+        }
+    }
+
+    /// Writes the pending samples now, e.g. before the session ends
+    pub fn flush(&mut self) {
+        self.last_flush = Instant::now();
+        if self.pending.is_empty() {
+            return;
+        }
+
+        match self.append() {
+            Ok(()) => {
+                debug!("Wrote {} history samples", self.pending.len());
+                self.pending.clear();
+
+                // The program may run for months without a restart
+                if self.last_compact.elapsed() >= COMPACT_INTERVAL {
+                    self.last_compact = Instant::now();
+                    if let Err(e) = self.compact() {
+                        error!("Failed to clean up battery history: {e:?}");
+                    }
+                }
+            }
+            // Kept for the next try, unless the database stays unwritable for long
+            Err(e) => {
+                error!("Failed to write battery history: {e:?}");
+                if self.pending.len() > BATCH_SIZE * 10 {
+                    self.pending.clear();
+                }
+            }
+        }
+    }
+
+    /// All samples written so far, oldest first
+    pub fn read(&self) -> anyhow::Result<Vec<Sample>> {
+        let mut select = self.db.prepare_cached(SELECT).context("reading history")?;
+        let rows = select
+            .query_map([], sample_from_row)
+            .context("reading history")?;
+        let mut samples = vec![];
+        for row in rows {
+            samples.extend(row.context("reading history")?);
+        }
+        Ok(samples)
+    }
+
+    fn append(&mut self) -> anyhow::Result<()> {
+        let transaction = self.db.transaction().context("starting transaction")?;
+        insert(&transaction, &self.pending).context("inserting samples")?;
+        transaction.commit().context("committing samples")
+    }
+
+    /// Deletes the samples older than the retention period and thins out the ones older than
+    /// the full resolution period
+    fn compact(&mut self) -> anyhow::Result<()> {
+        let samples = self.read()?;
+        let count = samples.len();
+        let samples = compact(samples, self.retention, now());
+        if samples.len() == count {
+            return Ok(());
+        }
+
+        // In one transaction, so a crash halfway doesn't lose the history
+        let transaction = self.db.transaction().context("starting transaction")?;
+        transaction
+            .execute("DELETE FROM history", [])
+            .context("deleting samples")?;
+        insert(&transaction, &samples).context("inserting samples")?;
+        transaction.commit().context("committing samples")?;
+        // Gives the space of the deleted rows back, so the file shrinks too
+        self.db.execute_batch("VACUUM").context("vacuuming")?;
+
+        info!(
+            "Cleaned up battery history from {count} to {} samples",
+            samples.len()
+        );
+        Ok(())
+    }
+}
+
+impl Drop for History {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+fn insert(transaction: &Transaction, samples: &[Sample]) -> rusqlite::Result<()> {
+    let mut insert = transaction.prepare_cached(INSERT)?;
+    for sample in samples {
+        insert.execute(params![
+            sample.timestamp as i64,
+            sample.device,
+            sample.level as i64,
+            status_name(sample.status),
+            sample.anomaly
+        ])?;
+    }
+    Ok(())
+}
+
+/// `None` for a status this version doesn't know
+fn sample_from_row(row: &Row) -> rusqlite::Result<Option<Sample>> {
+    let status: String = row.get(3)?;
+    let Ok(status) = serde_json::from_value(status.into()) else {
+        return Ok(None);
+    };
+    Ok(Some(Sample {
+        timestamp: row.get::<_, i64>(0)? as u64,
+        device: row.get(1)?,
+        level: row.get::<_, i64>(2)? as isize,
+        status,
+        anomaly: row.get(4)?,
+    }))
+}
+
+/// Drops the samples older than `retention.max_age`, and of the older ones than
+/// `retention.full_resolution` keeps the last of each device every 15 minutes.
+/// Status changes are always kept, so charging and the gaps while a headset was off stay visible.
+fn compact(samples: Vec<Sample>, retention: Retention, now: u64) -> Vec<Sample> {
+    let oldest = retention
+        .max_age
+        .map_or(0, |max_age| now.saturating_sub(max_age.as_secs()));
+    let full_resolution_since = now.saturating_sub(retention.full_resolution.as_secs());
+
+    let mut samples: Vec<Sample> = samples
+        .into_iter()
+        .filter(|sample| sample.timestamp >= oldest)
+        .collect();
+    samples.sort_by_key(|sample| sample.timestamp);
+
+    let mut kept: Vec<Sample> = Vec::with_capacity(samples.len());
+    // The status of the last sample kept for each device
+    let mut last_status: HashMap<String, BatteryState> = HashMap::new();
+    for (i, sample) in samples.iter().enumerate() {
+        let status_changed = last_status.get(&sample.device) != Some(&sample.status);
+        let keep = sample.timestamp >= full_resolution_since
+            || status_changed
+            || !sample.anomaly && {
+                // The last of the device in its 15 minutes
+                let bucket = sample.timestamp / DOWNSAMPLE_INTERVAL;
+                !samples[i + 1..]
+                    .iter()
+                    .take_while(|next| next.timestamp / DOWNSAMPLE_INTERVAL == bucket)
+                    .any(|next| next.device == sample.device)
+            };
+        if keep {
+            last_status.insert(sample.device.clone(), sample.status);
+            kept.push(sample.clone());
+        }
+    }
+    kept
+}
+
+/// `%LOCALAPPDATA%\HeadsetBatteryIndicator`, or `~/.local/share/HeadsetBatteryIndicator`
+/// on Linux, created if it doesn't exist
+pub fn data_dir() -> anyhow::Result<PathBuf> {
+    let dir = sandbox::Native::data_local_dir()
+        .context("getting LocalAppData directory")?
+        .join("HeadsetBatteryIndicator");
+    fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Like "5 h 10 min"
+pub fn format_minutes(minutes: u64) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{minutes} min"),
+        (hours, 0) => format!("{hours} h"),
+        (hours, minutes) => format!("{hours} h {minutes} min"),
+    }
+}
+
+/// A sample for every device that was added, removed or whose battery changed
+pub fn samples(events: &[AppEvent]) -> Vec<Sample> {
+    let timestamp = now();
+
+    events
+        .iter()
+        .filter_map(|event| {
+            let (device, status, anomaly) = match event {
+                AppEvent::DeviceAdded(device) => (device, device.battery.status, false),
+                AppEvent::BatteryChanged { previous, device } => {
+                    let anomaly = is_jump(previous, &device.battery);
+                    if anomaly {
+                        warn!(
+                            "Implausible battery level jump of {} from {}% to {}%",
+                            device.id(),
+                            previous.level,
+                            device.battery.level
+                        );
+                    }
+                    (device, device.battery.status, anomaly)
+                }
+                // Keeps the last level, so a gap in the history is visible
+                AppEvent::DeviceRemoved(device) => {
+                    (device, BatteryState::BatteryUnavailable, false)
+                }
+                // Already sampled for its BatteryChanged
+                AppEvent::ChargingChanged(_) => return None,
+            };
+            Some(Sample {
+                timestamp,
+                device: device.id(),
+                level: device.battery.level,
+                status,
+                anomaly,
+            })
+        })
+        .collect()
+}
+
+/// A level that changed by a lot from one poll to the next while the headset was on battery,
+/// like 80% to 20%, which some dongles report for a moment
+fn is_jump(previous: &Battery, current: &Battery) -> bool {
+    previous.status == BatteryState::BatteryAvailable
+        && current.status == BatteryState::BatteryAvailable
+        && (previous.level - current.level).abs() >= MAX_JUMP
+}
+
+/// The samples taken in the last `max_age`, from samples sorted oldest first
+pub fn recent(samples: &[Sample], max_age: Duration) -> &[Sample] {
+    let since = now().saturating_sub(max_age.as_secs());
+    let start = samples.partition_point(|sample| sample.timestamp < since);
+    &samples[start..]
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Estimate {
+    TimeToEmpty(Duration),
+    TimeToFull(Duration),
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (template, duration) = match *self {
+            Estimate::TimeToEmpty(duration) => (lang::t(time_to_empty), duration),
+            Estimate::TimeToFull(duration) => (lang::t(time_to_full), duration),
+        };
+        // Rounded to 10 minutes, anything more exact would be made up
+        let minutes = (duration.as_secs() / 60).div_ceil(10) * 10;
+        let time = format_minutes(minutes);
+        write!(f, "{}", template.replace("{time}", &time))
+    }
+}
+
+/// Which way the battery level is heading, and how fast in percent per hour
+#[derive(Debug, Clone, Copy)]
+pub struct Trend(pub f32);
+
+impl fmt::Display for Trend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Trend(per_hour) = *self;
+        if per_hour.abs() < STEADY_RATE {
+            write!(f, "→")
+        } else if per_hour < 0.0 {
+            write!(f, "↘ ~{:.0}%/h", -per_hour)
+        } else {
+            write!(f, "↗ ~{per_hour:.0}%/h")
+        }
+    }
+}
+
+/// Estimates the time to empty or to full from how fast the battery level changed recently.
+///
+/// Each device has a run of samples taken while it kept discharging or charging, which starts
+/// over whenever that changes, since the rates have nothing to do with each other.
+#[derive(Default)]
+pub struct Estimator {
+    runs: HashMap<String, Run>,
+}
+
+struct Run {
+    charging: bool,
+    /// Timestamps and levels of the level changes, oldest first
+    samples: VecDeque<(u64, isize)>,
+}
+
+impl Estimator {
+    pub fn add(&mut self, samples: &[Sample]) {
+        for sample in samples.iter().filter(|sample| !sample.anomaly) {
+            let charging = match sample.status {
+                BatteryState::BatteryAvailable => false,
+                BatteryState::BatteryCharging => true,
+                _ => {
+                    self.runs.remove(&sample.device);
+                    continue;
+                }
+            };
+
+            let run = self
+                .runs
+                .entry(sample.device.clone())
+                .or_insert_with(|| Run {
+                    charging,
+                    samples: VecDeque::new(),
+                });
+            // Charged while the app wasn't running, or the reported level went back a step
+            let reversed = run.samples.back().is_some_and(|&(_, level)| {
+                if charging {
+                    sample.level < level
+                } else {
+                    sample.level > level
+                }
+            });
+            if run.charging != charging || reversed {
+                run.charging = charging;
+                run.samples.clear();
+            }
+
+            if run
+                .samples
+                .back()
+                .is_some_and(|&(_, level)| level == sample.level)
+            {
+                continue;
+            }
+            run.samples.push_back((sample.timestamp, sample.level));
+
+            let since = sample.timestamp.saturating_sub(RATE_WINDOW.as_secs());
+            while run.samples.len() > 2 && run.samples.front().is_some_and(|&(t, _)| t < since) {
+                run.samples.pop_front();
+            }
+        }
+    }
+
+    /// `None` until the level has changed over a long enough time in the current run
+    pub fn estimate(&self, device: &Device) -> Option<Estimate> {
+        let run = self.runs.get(&device.id())?;
+        let charging = device.battery.status == BatteryState::BatteryCharging;
+        if run.charging != charging {
+            return None;
+        }
+
+        let (&(first_time, first_level), &(last_time, last_level)) =
+            (run.samples.front()?, run.samples.back()?);
+        let span = last_time.saturating_sub(first_time);
+        let change = (last_level - first_level).unsigned_abs();
+        if span < MIN_RATE_SPAN.as_secs() || change == 0 {
+            return None;
+        }
+
+        let remaining = if charging {
+            100 - device.battery.level
+        } else {
+            device.battery.level
+        };
+        // The level was last seen changing at `last_time`, part of the next step is already used
+        let seconds = (remaining.max(0) as u64 * span / change as u64)
+            .saturating_sub(now().saturating_sub(last_time));
+        if seconds == 0 {
+            return None;
+        }
+
+        let duration = Duration::from_secs(seconds);
+        Some(if charging {
+            Estimate::TimeToFull(duration)
+        } else {
+            Estimate::TimeToEmpty(duration)
+        })
+    }
+
+    /// The change from the level at the start of the trend window until now. Unlike the
+    /// estimate it counts the time since the last change, so it levels off when nothing changes.
+    pub fn trend(&self, device: &Device) -> Option<Trend> {
+        let run = self.runs.get(&device.id())?;
+        if run.charging != (device.battery.status == BatteryState::BatteryCharging) {
+            return None;
+        }
+
+        let now = now();
+        let since = now.saturating_sub(TREND_WINDOW.as_secs());
+        // The last change before the window is the level the window starts at
+        let &(first_time, first_level) = run
+            .samples
+            .iter()
+            .rev()
+            .find(|&&(timestamp, _)| timestamp <= since)
+            .or(run.samples.front())?;
+        let span = now.saturating_sub(first_time.max(since));
+        if span < MIN_RATE_SPAN.as_secs() {
+            return None;
+        }
+
+        let change = (device.battery.level - first_level) as f32;
+        Some(Trend(change * 3600.0 / span as f32))
+    }
+}
+
+/// Counts the charge sessions of every device, from charging start until the battery is
+/// full or the headset is unplugged, as a rough indicator of battery wear
+#[derive(Default)]
+pub struct ChargeCycles {
+    devices: HashMap<String, DeviceCycles>,
+}
+
+#[derive(Default)]
+struct DeviceCycles {
+    sessions: u32,
+    /// Battery percent gained over all sessions, 100 makes a full cycle
+    charged: u32,
+    state: ChargeState,
+}
+
+#[derive(Default)]
+enum ChargeState {
+    #[default]
+    NotCharging,
+    Charging {
+        start_level: isize,
+        max_level: isize,
+    },
+    /// Still on the charger after the session ended at 100 %
+    Full,
+}
+
+impl ChargeCycles {
+    pub fn add(&mut self, samples: &[Sample]) {
+        for sample in samples {
+            let device = self.devices.entry(sample.device.clone()).or_default();
+            let charging = sample.status == BatteryState::BatteryCharging;
+
+            device.state = match (std::mem::take(&mut device.state), charging) {
+                (ChargeState::NotCharging, true) => ChargeState::Charging {
+                    start_level: sample.level,
+                    max_level: sample.level,
+                },
+                (
+                    ChargeState::Charging {
+                        start_level,
+                        max_level,
+                    },
+                    true,
+                ) if sample.level < 100 => ChargeState::Charging {
+                    start_level,
+                    max_level: max_level.max(sample.level),
+                },
+                (ChargeState::Charging { start_level, .. }, true) => {
+                    device.end_session(start_level, 100);
+                    ChargeState::Full
+                }
+                (
+                    ChargeState::Charging {
+                        start_level,
+                        max_level,
+                    },
+                    false,
+                ) => {
+                    device.end_session(start_level, max_level);
+                    ChargeState::NotCharging
+                }
+                (ChargeState::Full, true) => ChargeState::Full,
+                (_, false) => ChargeState::NotCharging,
+            };
+        }
+    }
+
+    /// The charge sessions and the full cycles they add up to, e.g. two charges
+    /// from 50 % to 100 % make one cycle
+    pub fn get(&self, device: &Device) -> Option<(u32, f32)> {
+        let device = self.devices.get(&device.id())?;
+        Some((device.sessions, device.charged as f32 / 100.0))
+    }
+}
+
+impl DeviceCycles {
+    fn end_session(&mut self, start_level: isize, end_level: isize) {
+        // Plugged in and out again without charging anything
+        if end_level <= start_level {
+            return;
+        }
+        self.sessions += 1;
+        self.charged += (end_level - start_level) as u32;
+    }
+}
+
+/// How long a full charge lasts now compared to the best seen, as the battery wears out.
+/// Runtimes are measured over discharges starting near full, while the headset is on.
+#[derive(Default)]
+pub struct BatteryHealth {
+    devices: HashMap<String, DeviceHealth>,
+}
+
+#[derive(Default)]
+struct DeviceHealth {
+    run: Option<Discharge>,
+    /// Hours a full charge would last, from each discharge, oldest first
+    runtimes: Vec<f32>,
+}
+
+struct Discharge {
+    start_level: isize,
+    level: isize,
+    /// Seconds the headset was on, in total and when the level last dropped
+    seconds_on: u64,
+    seconds_at_level: u64,
+    /// When the headset was last seen on, `None` while it's off
+    last_on: Option<u64>,
+}
+
+/// Discharges starting lower than this don't count
+const HEALTH_MIN_START_LEVEL: isize = 90;
+/// Shorter discharges are too coarse with levels reported in steps
+const HEALTH_MIN_DRAIN: isize = 30;
+/// The typical runtime is the median of this many recent discharges
+const HEALTH_RECENT_RUNS: usize = 5;
+/// Fewer discharges than this don't give a usable estimate
+const HEALTH_MIN_RUNS: usize = 3;
+
+impl BatteryHealth {
+    /// Returns the devices whose health was estimated again, with the previous and new estimate
+    pub fn add(&mut self, samples: &[Sample]) -> Vec<(String, Option<u8>, u8)> {
+        let mut changed = vec![];
+
+        for sample in samples.iter().filter(|sample| !sample.anomaly) {
+            let device = self.devices.entry(sample.device.clone()).or_default();
+            let ended = match (&mut device.run, sample.status) {
+                (Some(run), BatteryState::BatteryAvailable) if sample.level <= run.level => {
+                    if let Some(last_on) = run.last_on {
+                        run.seconds_on += sample.timestamp.saturating_sub(last_on);
+                    }
+                    if sample.level < run.level {
+                        run.level = sample.level;
+                        run.seconds_at_level = run.seconds_on;
+                    }
+                    run.last_on = Some(sample.timestamp);
+                    false
+                }
+                // Charging, or a level going up
+                (Some(_), BatteryState::BatteryAvailable | BatteryState::BatteryCharging) => true,
+                // Turned off, the time until it's on again doesn't count
+                (Some(run), _) => {
+                    if let Some(last_on) = run.last_on.take() {
+                        run.seconds_on += sample.timestamp.saturating_sub(last_on);
+                    }
+                    false
+                }
+                (None, _) => false,
+            };
+
+            if ended
+                && let Some(run) = device.run.take()
+                && let Some(runtime) = run.full_runtime()
+            {
+                let previous = device.health();
+                device.runtimes.push(runtime);
+                if let Some(health) = device.health() {
+                    changed.push((sample.device.clone(), previous, health));
+                }
+            }
+
+            if device.run.is_none()
+                && sample.status == BatteryState::BatteryAvailable
+                && sample.level >= HEALTH_MIN_START_LEVEL
+            {
+                device.run = Some(Discharge {
+                    start_level: sample.level,
+                    level: sample.level,
+                    seconds_on: 0,
+                    seconds_at_level: 0,
+                    last_on: Some(sample.timestamp),
+                });
+            }
+        }
+
+        changed
+    }
+
+    /// Percent of the best runtime a full charge lasts now
+    pub fn get(&self, device: &Device) -> Option<u8> {
+        self.devices.get(&device.id())?.health()
+    }
+}
+
+impl DeviceHealth {
+    /// Hours a full charge lasted on average
+    #[cfg(windows)]
+    fn average_runtime(&self) -> Option<f32> {
+        if self.runtimes.is_empty() {
+            return None;
+        }
+        Some(self.runtimes.iter().sum::<f32>() / self.runtimes.len() as f32)
+    }
+
+    fn health(&self) -> Option<u8> {
+        if self.runtimes.len() < HEALTH_MIN_RUNS {
+            return None;
+        }
+        let best = self.runtimes.iter().copied().fold(0.0, f32::max);
+
+        let mut recent =
+            self.runtimes[self.runtimes.len().saturating_sub(HEALTH_RECENT_RUNS)..].to_vec();
+        recent.sort_by(f32::total_cmp);
+        let typical = recent[recent.len() / 2];
+
+        Some((typical / best * 100.0).round().min(100.0) as u8)
+    }
+}
+
+impl Discharge {
+    /// Hours a charge from 100 % to 0 % would last at the rate of this discharge
+    fn full_runtime(&self) -> Option<f32> {
+        let drained = self.start_level - self.level;
+        if drained < HEALTH_MIN_DRAIN || self.seconds_at_level == 0 {
+            return None;
+        }
+        Some(self.seconds_at_level as f32 / drained as f32 * 100.0 / 3600.0)
+    }
+}
+
+/// The long-term stats of one device, for comparing headsets in the history window
+#[cfg(windows)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Comparison {
+    /// Hours a full charge lasts on average, over the discharges the health is measured on
+    pub runtime: Option<f32>,
+    pub cycles: f32,
+    pub health: Option<u8>,
+}
+
+/// The stats of every device in `samples`, by history id
+#[cfg(windows)]
+pub fn compare(samples: &[Sample]) -> HashMap<String, Comparison> {
+    let mut cycles = ChargeCycles::default();
+    cycles.add(samples);
+    let mut health = BatteryHealth::default();
+    health.add(samples);
+
+    cycles
+        .devices
+        .iter()
+        .map(|(id, device)| {
+            let health = health.devices.get(id);
+            let comparison = Comparison {
+                runtime: health.and_then(DeviceHealth::average_runtime),
+                cycles: device.charged as f32 / 100.0,
+                health: health.and_then(DeviceHealth::health),
+            };
+            (id.clone(), comparison)
+        })
+        .collect()
+}
+
+/// Time each device spent powered on and charging, per day in local time
+#[derive(Default)]
+pub struct Usage {
+    devices: HashMap<String, DeviceUsage>,
+}
+
+#[derive(Default)]
+struct DeviceUsage {
+    /// The last sample, whose status lasts until the next one
+    last: Option<(u64, BatteryState)>,
+    /// Seconds powered on and seconds charging, by days since 1970-01-01
+    days: BTreeMap<i64, (u64, u64)>,
+}
+
+/// Hours powered on and hours charging, today and since Monday
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UsageSummary {
+    pub today: (f32, f32),
+    pub week: (f32, f32),
+}
+
+impl Usage {
+    pub fn add(&mut self, samples: &[Sample]) {
+        let offset = utc_offset();
+        // Enough to cover this week
+        let oldest_day = day_of(now(), offset) - 7;
+
+        for sample in samples {
+            let device = self.devices.entry(sample.device.clone()).or_default();
+            if let Some((since, status)) = device.last {
+                for (day, seconds) in split_days(since, sample.timestamp, offset) {
+                    if day >= oldest_day {
+                        add_usage(device.days.entry(day).or_default(), status, seconds);
+                    }
+                }
+            }
+            device.last = Some((sample.timestamp, sample.status));
+        }
+
+        for device in self.devices.values_mut() {
+            device.days.retain(|&day, _| day >= oldest_day);
+        }
+    }
+
+    pub fn get(&self, device: &Device) -> Option<UsageSummary> {
+        let device = self.devices.get(&device.id())?;
+        let offset = utc_offset();
+        let today = day_of(now(), offset);
+        // 1970-01-01 was a Thursday
+        let monday = today - (today + 3).rem_euclid(7);
+
+        let mut days = device.days.clone();
+        // The current status counts until now
+        if let Some((since, status)) = device.last {
+            for (day, seconds) in split_days(since, now(), offset) {
+                add_usage(days.entry(day).or_default(), status, seconds);
+            }
+        }
+
+        let hours = |(on, charging): (u64, u64)| (on as f32 / 3600.0, charging as f32 / 3600.0);
+        let week = days
+            .range(monday..)
+            .fold((0, 0), |sum, (_, &(on, charging))| {
+                (sum.0 + on, sum.1 + charging)
+            });
+        Some(UsageSummary {
+            today: hours(days.get(&today).copied().unwrap_or_default()),
+            week: hours(week),
+        })
+    }
+}
+
+/// Last week of one device, for the summary shown on Monday mornings
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WeekSummary {
+    /// Hours on battery between two charges on average, `None` without a charge
+    pub runtime_per_charge: Option<f32>,
+    /// How many times the level dropped to the low battery notification's level
+    pub low_battery: u32,
+    pub cycles: f32,
+}
+
+impl fmt::Display for WeekSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some(hours) = self.runtime_per_charge {
+            parts.push(lang::t(summary_runtime).replace("{hours}", &format!("{hours:.0}")));
+        }
+        parts.push(lang::t(summary_low_battery).replace("{count}", &self.low_battery.to_string()));
+        parts.push(lang::t(summary_cycles).replace("{cycles}", &format!("{:.1}", self.cycles)));
+        write!(f, "{}: {}", lang::t(summary_last_week), parts.join(", "))
+    }
+}
+
+/// The Monday of this week, in days since 1970-01-01, when it's Monday morning or later
+/// and the summary for it wasn't shown yet
+pub fn summary_due(last_shown: Option<i64>) -> Option<i64> {
+    let offset = utc_offset();
+    let today = day_of(now(), offset);
+    let monday = today - (today + 3).rem_euclid(7);
+    let hour = (now() as i64 + offset).rem_euclid(24 * 60 * 60) / (60 * 60);
+    let due = today > monday || hour >= SUMMARY_HOUR;
+    (due && last_shown != Some(monday)).then_some(monday)
+}
+
+/// The summaries of the week before `monday`, for every device seen in it
+pub fn week_summaries(samples: &[Sample], monday: i64) -> Vec<(String, WeekSummary)> {
+    const DAY: i64 = 24 * 60 * 60;
+    let offset = utc_offset();
+    let start = ((monday - 7) * DAY - offset) as u64;
+    let end = (monday * DAY - offset) as u64;
+    let week: Vec<&Sample> = samples
+        .iter()
+        .filter(|sample| (start..end).contains(&sample.timestamp) && !sample.anomaly)
+        .collect();
+
+    let mut devices: Vec<&str> = week.iter().map(|sample| sample.device.as_str()).collect();
+    devices.sort_unstable();
+    devices.dedup();
+
+    devices
+        .into_iter()
+        .map(|device| {
+            let samples: Vec<Sample> = week
+                .iter()
+                .filter(|sample| sample.device == device)
+                .map(|&sample| sample.clone())
+                .collect();
+
+            let mut on_battery = 0;
+            let mut low_battery = 0;
+            for pair in samples.windows(2) {
+                let (previous, sample) = (&pair[0], &pair[1]);
+                if previous.status == BatteryState::BatteryAvailable {
+                    on_battery += sample.timestamp - previous.timestamp;
+                }
+                if sample.status == BatteryState::BatteryAvailable
+                    && sample.level <= LOW_LEVEL
+                    && previous.level > LOW_LEVEL
+                {
+                    low_battery += 1;
+                }
+            }
+
+            let mut cycles = ChargeCycles::default();
+            cycles.add(&samples);
+            let (sessions, charged) = cycles
+                .devices
+                .get(device)
+                .map_or((0, 0), |cycles| (cycles.sessions, cycles.charged));
+
+            let summary = WeekSummary {
+                runtime_per_charge: (sessions > 0)
+                    .then(|| on_battery as f32 / 3600.0 / sessions as f32),
+                low_battery,
+                cycles: charged as f32 / 100.0,
+            };
+            (device.to_string(), summary)
+        })
+        .collect()
+}
+
+fn add_usage(usage: &mut (u64, u64), status: BatteryState, seconds: u64) {
+    match status {
+        BatteryState::BatteryAvailable => usage.0 += seconds,
+        BatteryState::BatteryCharging => {
+            usage.0 += seconds;
+            usage.1 += seconds;
+        }
+        _ => {}
+    }
+}
+
+/// The Unix timestamp of the last local midnight
+#[cfg(windows)]
+pub fn today_start() -> u64 {
+    const DAY: i64 = 24 * 60 * 60;
+    let offset = utc_offset();
+    (day_of(now(), offset) * DAY - offset) as u64
+}
+
+/// The local day of a Unix timestamp, as days since 1970-01-01
+fn day_of(timestamp: u64, offset: i64) -> i64 {
+    (timestamp as i64 + offset).div_euclid(24 * 60 * 60)
+}
+
+/// The seconds from `start` to `end` falling on each local day
+fn split_days(start: u64, end: u64, offset: i64) -> Vec<(i64, u64)> {
+    let mut days = vec![];
+    let mut start = start;
+    while start < end {
+        let day = day_of(start, offset);
+        let next_day = ((day + 1) * 24 * 60 * 60 - offset) as u64;
+        let until = end.min(next_day);
+        days.push((day, until - start));
+        start = until;
+    }
+    days
+}
+
+/// Seconds local time is ahead of UTC, for the current daylight saving time
+#[cfg(windows)]
+fn utc_offset() -> i64 {
+    use windows::Win32::System::Time::{
+        GetTimeZoneInformation, TIME_ZONE_ID_INVALID, TIME_ZONE_INFORMATION,
+    };
+    const TIME_ZONE_ID_DAYLIGHT: u32 = 2;
+
+    let mut zone = TIME_ZONE_INFORMATION::default();
+    // The bias is in minutes, and subtracted from local time to get UTC
+    let bias = match unsafe { GetTimeZoneInformation(&mut zone) } {
+        TIME_ZONE_ID_INVALID => 0,
+        TIME_ZONE_ID_DAYLIGHT => zone.Bias + zone.DaylightBias,
+        _ => zone.Bias + zone.StandardBias,
+    };
+    -i64::from(bias) * 60
+}
+
+/// Seconds local time is ahead of UTC, read from `date` which knows the zone rules.
+/// It's needed on every poll, so it's only read again after a minute.
+#[cfg(not(windows))]
+fn utc_offset() -> i64 {
+    use std::sync::Mutex;
+
+    static CACHED: Mutex<Option<(Instant, i64)>> = Mutex::new(None);
+
+    let mut cached = CACHED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((read, offset)) = *cached
+        && read.elapsed() < Duration::from_secs(60)
+    {
+        return offset;
+    }
+
+    // Like +0300 or -0430
+    let offset = std::process::Command::new("date")
+        .arg("+%z")
+        .output()
+        .ok()
+        .and_then(|output| {
+            let zone = String::from_utf8(output.stdout).ok()?;
+            let zone = zone.trim();
+            let sign = if zone.starts_with('-') { -1 } else { 1 };
+            let hours: i64 = zone.get(1..3)?.parse().ok()?;
+            let minutes: i64 = zone.get(3..5)?.parse().ok()?;
+            Some(sign * (hours * 60 + minutes) * 60)
+        })
+        .unwrap_or_default();
+    *cached = Some((Instant::now(), offset));
+    offset
+}
+
+/// Unix timestamp in seconds
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The same names as in the JSON of the local API
+fn status_name(status: BatteryState) -> String {
+    match serde_json::to_value(status) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => format!("{status:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use BatteryState::*;
+
+    const ID: &str = "1038:12ad";
+    const HOUR: u64 = 60 * 60;
+    const DAY: u64 = 24 * HOUR;
+
+    fn sample(timestamp: u64, level: isize, status: BatteryState) -> Sample {
+        Sample {
+            timestamp,
+            device: ID.to_string(),
+            level,
+            status,
+            anomaly: false,
+        }
+    }
+
+    fn device(level: isize, status: BatteryState) -> Device {
+        Device {
+            id_vendor: "0x1038".to_string(),
+            id_product: "0x12ad".to_string(),
+            battery: Battery { status, level },
+            ..Default::default()
+        }
+    }
+
+    /// The estimate in seconds, allowing for the clock ticking during the test
+    fn seconds_to_empty(estimator: &Estimator, level: isize) -> Option<u64> {
+        match estimator.estimate(&device(level, BatteryAvailable))? {
+            Estimate::TimeToEmpty(duration) => Some(duration.as_secs()),
+            Estimate::TimeToFull(_) => panic!("estimated the time to full while discharging"),
+        }
+    }
+
+    #[test]
+    fn estimate_from_discharge_rate() {
+        let now = now();
+        let mut estimator = Estimator::default();
+        estimator.add(&[
+            sample(now - HOUR, 80, BatteryAvailable),
+            sample(now - HOUR / 2, 70, BatteryAvailable),
+            sample(now, 60, BatteryAvailable),
+        ]);
+
+        // 20 % an hour, with 60 % left
+        let seconds = seconds_to_empty(&estimator, 60).unwrap();
+        assert!((3 * HOUR - 5..=3 * HOUR).contains(&seconds), "{seconds}");
+    }
+
+    #[test]
+    fn estimate_while_charging() {
+        let now = now();
+        let mut estimator = Estimator::default();
+        estimator.add(&[
+            sample(now - HOUR, 20, BatteryCharging),
+            sample(now, 60, BatteryCharging),
+        ]);
+
+        let Some(Estimate::TimeToFull(duration)) = estimator.estimate(&device(60, BatteryCharging))
+        else {
+            panic!("no time to full");
+        };
+        assert!((HOUR - 5..=HOUR).contains(&duration.as_secs()));
+        // Unplugged since the last sample
+        assert!(seconds_to_empty(&estimator, 60).is_none());
+    }
+
+    #[test]
+    fn no_estimate_from_short_span() {
+        let now = now();
+        let mut estimator = Estimator::default();
+        estimator.add(&[
+            sample(now - 5 * 60, 80, BatteryAvailable),
+            sample(now, 70, BatteryAvailable),
+        ]);
+        assert!(seconds_to_empty(&estimator, 70).is_none());
+    }
+
+    #[test]
+    fn turning_off_starts_over() {
+        let now = now();
+        let mut estimator = Estimator::default();
+        estimator.add(&[
+            sample(now - 3 * HOUR, 80, BatteryAvailable),
+            sample(now - 2 * HOUR, 70, BatteryAvailable),
+            sample(now - 2 * HOUR, 70, BatteryUnavailable),
+            sample(now, 70, BatteryAvailable),
+        ]);
+        assert!(seconds_to_empty(&estimator, 70).is_none());
+
+        // The time while it was off doesn't count towards the rate
+        estimator.add(&[sample(now, 60, BatteryAvailable)]);
+        assert!(seconds_to_empty(&estimator, 60).is_none());
+    }
+
+    #[test]
+    fn samples_outside_rate_window_are_dropped() {
+        let now = now();
+        let mut estimator = Estimator::default();
+        // Slow at first, then 10 % an hour over the last hour
+        estimator.add(&[
+            sample(now - 10 * HOUR, 90, BatteryAvailable),
+            sample(now - 9 * HOUR, 80, BatteryAvailable),
+            sample(now - HOUR, 70, BatteryAvailable),
+            sample(now, 60, BatteryAvailable),
+        ]);
+
+        let seconds = seconds_to_empty(&estimator, 60).unwrap();
+        assert!((6 * HOUR - 5..=6 * HOUR).contains(&seconds), "{seconds}");
+    }
+
+    #[test]
+    fn level_going_up_while_discharging_starts_over() {
+        let now = now();
+        let mut estimator = Estimator::default();
+        estimator.add(&[
+            sample(now - 2 * HOUR, 80, BatteryAvailable),
+            sample(now - HOUR, 70, BatteryAvailable),
+            sample(now, 75, BatteryAvailable),
+        ]);
+        assert!(seconds_to_empty(&estimator, 75).is_none());
+    }
+
+    #[test]
+    fn anomalies_are_left_out_of_the_rate() {
+        let now = now();
+        let mut estimator = Estimator::default();
+        estimator.add(&[
+            sample(now - HOUR, 80, BatteryAvailable),
+            Sample {
+                anomaly: true,
+                ..sample(now - HOUR / 2, 20, BatteryAvailable)
+            },
+            sample(now, 70, BatteryAvailable),
+        ]);
+
+        let seconds = seconds_to_empty(&estimator, 70).unwrap();
+        assert!((7 * HOUR - 5..=7 * HOUR).contains(&seconds), "{seconds}");
+    }
+
+    #[test]
+    fn jumps_on_battery() {
+        let battery = |level, status| Battery { status, level };
+        let available = |level| battery(level, BatteryAvailable);
+
+        assert!(is_jump(&available(80), &available(50)));
+        assert!(is_jump(&available(20), &available(50)));
+        assert!(!is_jump(&available(80), &available(51)));
+        // Plugged in or out, the level may well change a lot
+        assert!(!is_jump(&battery(80, BatteryCharging), &available(20)));
+        assert!(!is_jump(&available(20), &battery(80, BatteryCharging)));
+        assert!(!is_jump(&battery(80, BatteryUnavailable), &available(20)));
+    }
+
+    #[test]
+    fn jump_is_sampled_as_anomaly() {
+        let events = [
+            AppEvent::BatteryChanged {
+                previous: Battery {
+                    status: BatteryAvailable,
+                    level: 80,
+                },
+                device: device(20, BatteryAvailable),
+            },
+            AppEvent::BatteryChanged {
+                previous: Battery {
+                    status: BatteryAvailable,
+                    level: 20,
+                },
+                device: device(15, BatteryAvailable),
+            },
+        ];
+
+        let anomalies: Vec<bool> = samples(&events).iter().map(|s| s.anomaly).collect();
+        assert_eq!(anomalies, [true, false]);
+    }
+
+    fn cycles(samples: &[Sample]) -> (u32, f32) {
+        let mut cycles = ChargeCycles::default();
+        cycles.add(samples);
+        cycles.get(&device(0, BatteryUnavailable)).unwrap()
+    }
+
+    #[test]
+    fn partial_charge_counts_as_part_of_a_cycle() {
+        assert_eq!(
+            cycles(&[
+                sample(0, 40, BatteryAvailable),
+                sample(10, 40, BatteryCharging),
+                sample(20, 70, BatteryCharging),
+                sample(30, 70, BatteryAvailable),
+            ]),
+            (1, 0.3)
+        );
+    }
+
+    #[test]
+    fn two_half_charges_make_a_cycle() {
+        assert_eq!(
+            cycles(&[
+                sample(0, 50, BatteryCharging),
+                sample(10, 100, BatteryCharging),
+                // Left on the charger once full
+                sample(20, 100, BatteryCharging),
+                sample(30, 100, BatteryAvailable),
+                sample(40, 50, BatteryAvailable),
+                sample(50, 50, BatteryCharging),
+                sample(60, 100, BatteryCharging),
+            ]),
+            (2, 1.0)
+        );
+    }
+
+    #[test]
+    fn charge_ends_when_turned_off() {
+        assert_eq!(
+            cycles(&[
+                sample(0, 20, BatteryCharging),
+                sample(10, 45, BatteryCharging),
+                sample(20, 45, BatteryUnavailable),
+            ]),
+            (1, 0.25)
+        );
+    }
+
+    #[test]
+    fn plugging_in_without_charging_is_no_session() {
+        assert_eq!(
+            cycles(&[
+                sample(0, 60, BatteryAvailable),
+                sample(10, 60, BatteryCharging),
+                sample(20, 60, BatteryAvailable),
+            ]),
+            (0, 0.0)
+        );
+    }
+
+    #[test]
+    fn split_at_local_midnight() {
+        // 23:00 to 01:00 UTC
+        let start = 10 * DAY - HOUR;
+        assert_eq!(
+            split_days(start, start + 2 * HOUR, 0),
+            [(9, HOUR), (10, HOUR)]
+        );
+        // Two hours ahead of UTC it's all on day 10, from 01:00 to 03:00
+        assert_eq!(
+            split_days(start, start + 2 * HOUR, 2 * HOUR as i64),
+            [(10, 2 * HOUR)]
+        );
+        assert!(split_days(start, start, 0).is_empty());
+        assert_eq!(day_of(10 * DAY, -1), 9);
+    }
+
+    #[test]
+    fn usage_counts_on_and_charging_time() {
+        let now = now();
+        let mut usage = Usage::default();
+        usage.add(&[
+            sample(now - 3 * HOUR, 80, BatteryAvailable),
+            sample(now - 2 * HOUR, 70, BatteryUnavailable),
+            sample(now - HOUR, 70, BatteryCharging),
+            sample(now - HOUR / 2, 85, BatteryAvailable),
+            sample(now, 85, BatteryUnavailable),
+        ]);
+
+        let days = &usage.devices[ID].days;
+        let total = days.values().fold((0, 0), |sum, &(on, charging)| {
+            (sum.0 + on, sum.1 + charging)
+        });
+        assert_eq!(total, (2 * HOUR, HOUR / 2));
+    }
+
+    #[test]
+    fn usage_forgets_older_days() {
+        let now = now();
+        let mut usage = Usage::default();
+        usage.add(&[
+            sample(now - 30 * DAY, 80, BatteryAvailable),
+            sample(now - 29 * DAY, 70, BatteryUnavailable),
+        ]);
+
+        assert!(usage.devices[ID].days.is_empty());
+    }
+
+    /// A discharge from 100 % to 60 % losing 10 % every `step`, then charged again
+    fn discharge(start: u64, step: u64) -> Vec<Sample> {
+        let mut samples: Vec<Sample> = (0..5)
+            .map(|i| sample(start + i * step, 100 - 10 * i as isize, BatteryAvailable))
+            .collect();
+        samples.push(sample(start + 5 * step, 60, BatteryCharging));
+        samples
+    }
+
+    #[test]
+    fn health_needs_three_discharges() {
+        let mut health = BatteryHealth::default();
+        assert!(health.add(&discharge(0, HOUR)).is_empty());
+        assert!(health.add(&discharge(DAY, HOUR)).is_empty());
+        assert_eq!(health.get(&device(0, BatteryUnavailable)), None);
+
+        // A full charge lasting 10 hours every time
+        assert_eq!(
+            health.add(&discharge(2 * DAY, HOUR)),
+            [(ID.to_string(), None, 100)]
+        );
+    }
+
+    #[test]
+    fn health_drops_with_shorter_runtimes() {
+        let mut health = BatteryHealth::default();
+        for day in 0..3 {
+            health.add(&discharge(day * DAY, HOUR));
+        }
+        // The median of the last five, two short ones aren't enough yet
+        for day in 3..5 {
+            health.add(&discharge(day * DAY, HOUR / 2));
+        }
+        assert_eq!(health.get(&device(0, BatteryUnavailable)), Some(100));
+
+        assert_eq!(
+            health.add(&discharge(5 * DAY, HOUR / 2)),
+            [(ID.to_string(), Some(100), 50)]
+        );
+    }
+
+    #[test]
+    fn health_ignores_time_turned_off() {
+        let mut health = BatteryHealth::default();
+        for day in 0..2 {
+            health.add(&discharge(day * DAY, HOUR));
+        }
+
+        // Off for a night halfway through
+        let start = 2 * DAY;
+        let samples = [
+            sample(start, 100, BatteryAvailable),
+            sample(start + HOUR, 90, BatteryAvailable),
+            sample(start + 2 * HOUR, 80, BatteryAvailable),
+            sample(start + 2 * HOUR, 80, BatteryUnavailable),
+            sample(start + 12 * HOUR, 80, BatteryAvailable),
+            sample(start + 13 * HOUR, 70, BatteryAvailable),
+            sample(start + 14 * HOUR, 60, BatteryAvailable),
+            sample(start + 15 * HOUR, 60, BatteryCharging),
+        ];
+        assert_eq!(health.add(&samples), [(ID.to_string(), None, 100)]);
+    }
+
+    #[test]
+    fn health_only_counts_long_discharges_from_near_full() {
+        let mut health = BatteryHealth::default();
+        for day in 0..3 {
+            let start = day * DAY;
+            health.add(&[
+                // Starting too low
+                sample(start, 80, BatteryAvailable),
+                sample(start + 4 * HOUR, 40, BatteryAvailable),
+                sample(start + 5 * HOUR, 40, BatteryCharging),
+                // Too short
+                sample(start + 8 * HOUR, 100, BatteryAvailable),
+                sample(start + 10 * HOUR, 80, BatteryAvailable),
+                sample(start + 11 * HOUR, 80, BatteryCharging),
+            ]);
+        }
+        assert_eq!(health.get(&device(0, BatteryUnavailable)), None);
+    }
+
+    const RETENTION: Retention = Retention {
+        max_age: Some(Duration::from_secs(90 * DAY)),
+        full_resolution: Duration::from_secs(30 * DAY),
+    };
+
+    fn timestamps(samples: &[Sample]) -> Vec<u64> {
+        samples.iter().map(|sample| sample.timestamp).collect()
+    }
+
+    #[test]
+    fn compact_drops_samples_past_retention() {
+        let now = 100 * DAY;
+        let samples = vec![
+            sample(10 * DAY - 1, 80, BatteryAvailable),
+            sample(10 * DAY, 80, BatteryCharging),
+            sample(now, 90, BatteryAvailable),
+        ];
+        assert_eq!(
+            timestamps(&compact(samples.clone(), RETENTION, now)),
+            [10 * DAY, now]
+        );
+
+        let forever = Retention {
+            max_age: None,
+            ..RETENTION
+        };
+        assert_eq!(compact(samples, forever, now).len(), 3);
+    }
+
+    #[test]
+    fn compact_keeps_last_sample_every_15_minutes() {
+        let start = 20 * DAY;
+        let other = Sample {
+            device: "046d:0af7".to_string(),
+            ..sample(start + 400, 50, BatteryAvailable)
+        };
+        let samples = vec![
+            sample(start, 80, BatteryAvailable),
+            sample(start + 300, 79, BatteryAvailable),
+            other.clone(),
+            sample(start + 600, 78, BatteryAvailable),
+            sample(start + 900, 77, BatteryAvailable),
+        ];
+
+        let kept = compact(samples, RETENTION, 100 * DAY);
+        assert_eq!(
+            timestamps(&kept),
+            [start, start + 400, start + 600, start + 900]
+        );
+        assert_eq!(kept[1].device, other.device);
+    }
+
+    #[test]
+    fn compact_keeps_status_changes() {
+        let start = 20 * DAY;
+        let samples = vec![
+            sample(start, 30, BatteryAvailable),
+            sample(start + 100, 30, BatteryCharging),
+            sample(start + 200, 35, BatteryCharging),
+            sample(start + 300, 35, BatteryUnavailable),
+            sample(start + 400, 35, BatteryAvailable),
+            sample(start + 500, 34, BatteryAvailable),
+        ];
+
+        assert_eq!(
+            timestamps(&compact(samples, RETENTION, 100 * DAY)),
+            [start, start + 100, start + 300, start + 400, start + 500]
+        );
+    }
+
+    #[test]
+    fn compact_drops_old_anomalies() {
+        let start = 20 * DAY;
+        let samples = vec![
+            sample(start, 80, BatteryAvailable),
+            Sample {
+                anomaly: true,
+                ..sample(start + 1000, 20, BatteryAvailable)
+            },
+        ];
+
+        assert_eq!(timestamps(&compact(samples, RETENTION, 100 * DAY)), [start]);
+    }
+
+    #[test]
+    fn compact_keeps_full_resolution_of_recent_samples() {
+        let now = 100 * DAY;
+        let since = 70 * DAY;
+        let samples = vec![
+            sample(since - 900, 80, BatteryAvailable),
+            sample(since - 600, 79, BatteryAvailable),
+            sample(since - 300, 78, BatteryAvailable),
+            sample(since, 77, BatteryAvailable),
+            sample(since + 100, 76, BatteryAvailable),
+            Sample {
+                anomaly: true,
+                ..sample(since + 200, 20, BatteryAvailable)
+            },
+        ];
+
+        assert_eq!(
+            timestamps(&compact(samples, RETENTION, now)),
+            [since - 900, since - 300, since, since + 100, since + 200]
+        );
+    }
+
+    /// 2024-10-07, a Monday
+    const MONDAY: i64 = 20003;
+
+    /// The local start of the week before `MONDAY`
+    fn week_start() -> u64 {
+        ((MONDAY - 7) * DAY as i64 - utc_offset()) as u64
+    }
+
+    #[test]
+    fn week_summary() {
+        let start = week_start();
+        let samples = [
+            sample(start + HOUR, 100, BatteryAvailable),
+            sample(start + 5 * HOUR, 50, BatteryAvailable),
+            sample(start + 9 * HOUR, 10, BatteryAvailable),
+            Sample {
+                anomaly: true,
+                ..sample(start + 9 * HOUR + 60, 60, BatteryAvailable)
+            },
+            sample(start + 10 * HOUR, 5, BatteryAvailable),
+            sample(start + 11 * HOUR, 5, BatteryCharging),
+            sample(start + 13 * HOUR, 100, BatteryCharging),
+        ];
+
+        let summaries = week_summaries(&samples, MONDAY);
+        assert_eq!(summaries.len(), 1);
+        let (device, summary) = &summaries[0];
+        assert_eq!(device, ID);
+        assert_eq!(summary.runtime_per_charge, Some(10.0));
+        assert_eq!(summary.low_battery, 1);
+        assert_eq!(summary.cycles, 0.95);
+    }
+
+    #[test]
+    fn week_summary_without_charge() {
+        let start = week_start();
+        let samples = [
+            sample(start, 100, BatteryAvailable),
+            sample(start + HOUR, 90, BatteryAvailable),
+        ];
+
+        let summary = week_summaries(&samples, MONDAY)[0].1;
+        assert_eq!(summary.runtime_per_charge, None);
+        assert_eq!(summary.cycles, 0.0);
+    }
+
+    #[test]
+    fn week_summary_only_has_last_week() {
+        let start = week_start();
+        let end = start + 7 * DAY;
+        let samples = [
+            sample(start - 1, 100, BatteryAvailable),
+            sample(start, 9, BatteryAvailable),
+            sample(end - 1, 8, BatteryAvailable),
+            // Already this week
+            sample(end, 100, BatteryCharging),
+            Sample {
+                device: "046d:0af7".to_string(),
+                ..sample(end, 5, BatteryAvailable)
+            },
+        ];
+
+        let summaries = week_summaries(&samples, MONDAY);
+        assert_eq!(summaries.len(), 1);
+        // The drop below the low level happened before the week started
+        assert_eq!(summaries[0].1.low_battery, 0);
+        assert_eq!(summaries[0].1.cycles, 0.0);
+    }
+
+    #[test]
+    fn summary_due_once_a_week_on_monday() {
+        let offset = utc_offset();
+        let today = day_of(now(), offset);
+        let monday = today - (today + 3).rem_euclid(7);
+        assert_eq!((monday + 3).rem_euclid(7), 0);
+
+        // Not before 9:00 on Monday
+        let due = summary_due(Some(monday - 7));
+        assert!(due.is_none_or(|due| due == monday));
+        assert_eq!(due.is_some(), summary_due(None).is_some());
+        assert_eq!(summary_due(Some(monday)), None);
+    }
+}
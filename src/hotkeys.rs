@@ -0,0 +1,203 @@
+//! System-wide keyboard shortcuts, registered with `RegisterHotKey` on the message window and
+//! read from the `Hotkeys` subkey, like `Announce` = `Ctrl+Alt+B`
+
+use std::fmt;
+
+use anyhow::Context;
+use log::{error, info};
+use windows::Win32::{
+    Foundation::{ERROR_HOTKEY_ALREADY_REGISTERED, HWND},
+    UI::Input::KeyboardAndMouse::{
+        HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN, RegisterHotKey,
+        UnregisterHotKey, VK_DELETE, VK_DOWN, VK_END, VK_F1, VK_HOME, VK_INSERT, VK_LEFT, VK_NEXT,
+        VK_PAUSE, VK_PRIOR, VK_RIGHT, VK_SPACE, VK_UP,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Shows or hides the battery in the RTSS on-screen display
+    ToggleOverlay,
+    /// Reads the battery of the selected headset aloud
+    Announce,
+    /// Polls the headsets now
+    Refresh,
+    /// Turns the battery notifications and sounds off until pressed again or the program restarts
+    MuteAlerts,
+}
+
+impl Action {
+    pub const ALL: [Action; 4] = [
+        Action::ToggleOverlay,
+        Action::Announce,
+        Action::Refresh,
+        Action::MuteAlerts,
+    ];
+
+    /// The value name in the `Hotkeys` subkey
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::ToggleOverlay => "ToggleOverlay",
+            Action::Announce => "Announce",
+            Action::Refresh => "Refresh",
+            Action::MuteAlerts => "MuteAlerts",
+        }
+    }
+
+    /// The id it's registered with, sent back in the `wParam` of WM_HOTKEY
+    fn id(self) -> i32 {
+        self as i32 + 1
+    }
+
+    pub fn from_id(id: usize) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.id() as usize == id)
+    }
+}
+
+/// A key with modifiers, parsed from text like `Ctrl+Shift+F9`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkey {
+    modifiers: HOT_KEY_MODIFIERS,
+    key: u16,
+}
+
+impl Hotkey {
+    /// `Ctrl`, `Alt`, `Shift` and `Win` joined with `+` to a letter, a digit, `F1` to `F24`,
+    /// `Space`, `Insert`, `Delete`, `Home`, `End`, `PageUp`, `PageDown`, `Pause` or an arrow key
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut modifiers = HOT_KEY_MODIFIERS(0);
+        let mut key = None;
+        for part in text.split('+').map(str::trim) {
+            let modifier = match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => Some(MOD_CONTROL),
+                "alt" => Some(MOD_ALT),
+                "shift" => Some(MOD_SHIFT),
+                "win" => Some(MOD_WIN),
+                _ => None,
+            };
+            match modifier {
+                Some(modifier) => modifiers |= modifier,
+                None if key.is_none() => {
+                    let code =
+                        virtual_key(part).with_context(|| format!("unknown key {part:?}"))?;
+                    key = Some(code);
+                }
+                None => anyhow::bail!("more than one key in {text:?}"),
+            }
+        }
+        let key = key.with_context(|| format!("no key in {text:?}"))?;
+        Ok(Self { modifiers, key })
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (MOD_CONTROL, "Ctrl"),
+            (MOD_ALT, "Alt"),
+            (MOD_SHIFT, "Shift"),
+            (MOD_WIN, "Win"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{name}+")?;
+            }
+        }
+        match self.key {
+            key @ (0x30..=0x39 | 0x41..=0x5a) => write!(f, "{}", key as u8 as char),
+            key if (VK_F1.0..VK_F1.0 + 24).contains(&key) => write!(f, "F{}", key - VK_F1.0 + 1),
+            key => write!(f, "{key:#04x}"),
+        }
+    }
+}
+
+fn virtual_key(name: &str) -> Option<u16> {
+    let upper = name.to_ascii_uppercase();
+    // Letters and digits are their own virtual key codes
+    if let [c] = upper.as_bytes()
+        && c.is_ascii_alphanumeric()
+    {
+        return Some(u16::from(*c));
+    }
+    if let Some(n) = upper.strip_prefix('F').and_then(|n| n.parse::<u16>().ok())
+        && (1..=24).contains(&n)
+    {
+        return Some(VK_F1.0 + n - 1);
+    }
+    let key = match upper.as_str() {
+        "SPACE" => VK_SPACE,
+        "INSERT" => VK_INSERT,
+        "DELETE" => VK_DELETE,
+        "HOME" => VK_HOME,
+        "END" => VK_END,
+        "PAGEUP" => VK_PRIOR,
+        "PAGEDOWN" => VK_NEXT,
+        "PAUSE" => VK_PAUSE,
+        "UP" => VK_UP,
+        "DOWN" => VK_DOWN,
+        "LEFT" => VK_LEFT,
+        "RIGHT" => VK_RIGHT,
+        _ => return None,
+    };
+    Some(key.0)
+}
+
+/// The hotkeys that could be registered, unregistered when dropped
+pub struct Hotkeys {
+    hwnd: HWND,
+    registered: Vec<Action>,
+}
+
+impl Hotkeys {
+    /// Registers the bindings to the message window. A binding that's taken by an earlier one,
+    /// or by another program, is logged and left out.
+    pub fn register(hwnd: HWND, bindings: &[(Action, Hotkey)]) -> Self {
+        let mut hotkeys = Self {
+            hwnd,
+            registered: Vec::new(),
+        };
+        for (i, &(action, hotkey)) in bindings.iter().enumerate() {
+            if let Some((other, _)) = bindings[..i].iter().find(|(_, other)| *other == hotkey) {
+                error!(
+                    "Hotkey {hotkey} of {} is already used for {}",
+                    action.name(),
+                    other.name()
+                );
+                continue;
+            }
+            let res = unsafe {
+                RegisterHotKey(
+                    hwnd,
+                    action.id(),
+                    hotkey.modifiers | MOD_NOREPEAT,
+                    u32::from(hotkey.key),
+                )
+            };
+            match res {
+                Ok(()) => {
+                    info!("Registered hotkey {hotkey} for {}", action.name());
+                    hotkeys.registered.push(action);
+                }
+                Err(e) if e.code() == ERROR_HOTKEY_ALREADY_REGISTERED.to_hresult() => {
+                    error!(
+                        "Hotkey {hotkey} of {} is already used by another program",
+                        action.name()
+                    );
+                }
+                Err(e) => error!("Failed to register hotkey {hotkey}: {e:?}"),
+            }
+        }
+        hotkeys
+    }
+}
+
+impl Drop for Hotkeys {
+    fn drop(&mut self) {
+        for action in &self.registered {
+            unsafe {
+                let _ = UnregisterHotKey(self.hwnd, action.id());
+            }
+        }
+    }
+}
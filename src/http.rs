@@ -0,0 +1,350 @@
+//! Minimal HTTP(S) client on top of WinHTTP, which handles TLS and the system proxy settings.
+//! On Linux the requests are sent with `curl`, which does the same there.
+
+#[cfg(windows)]
+use std::ffi::c_void;
+use std::time::Duration;
+
+use anyhow::Context;
+#[cfg(windows)]
+use windows::{
+    Win32::Networking::WinHttp::{
+        URL_COMPONENTS, WINHTTP_ACCESS_TYPE_AUTOMATIC_PROXY, WINHTTP_FLAG_SECURE,
+        WINHTTP_INTERNET_SCHEME_HTTPS, WINHTTP_OPEN_REQUEST_FLAGS, WINHTTP_QUERY_FLAG_NUMBER,
+        WINHTTP_QUERY_STATUS_CODE, WinHttpCloseHandle, WinHttpConnect, WinHttpCrackUrl,
+        WinHttpOpen, WinHttpOpenRequest, WinHttpQueryDataAvailable, WinHttpQueryHeaders,
+        WinHttpReadData, WinHttpReceiveResponse, WinHttpSendRequest, WinHttpSetTimeouts,
+    },
+    core::{HSTRING, PCWSTR},
+};
+
+/// Closes the WinHTTP handle when dropped
+#[cfg(windows)]
+struct Handle(*mut c_void);
+
+#[cfg(windows)]
+impl Handle {
+    fn new(handle: *mut c_void, what: &str) -> anyhow::Result<Self> {
+        if handle.is_null() {
+            return Err(windows::core::Error::from_win32()).context(what.to_string());
+        }
+        Ok(Self(handle))
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = WinHttpCloseHandle(self.0);
+        }
+    }
+}
+
+/// Responses larger than this are cut off, except for downloads
+const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+
+/// Sends a POST request and returns the response status code
+pub fn post(url: &str, content_type: &str, body: &[u8], timeout: Duration) -> anyhow::Result<u16> {
+    send(url, Some((content_type, body)), &[], timeout, None)
+}
+
+/// Sends a POST request with extra headers, like `Authorization`, and returns the response
+/// status code
+pub fn post_with_headers(
+    url: &str,
+    content_type: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    timeout: Duration,
+) -> anyhow::Result<u16> {
+    send(url, Some((content_type, body)), headers, timeout, None)
+}
+
+/// Sends a POST request and returns the response status code and body
+pub fn post_with_response(
+    url: &str,
+    content_type: &str,
+    body: &[u8],
+    timeout: Duration,
+) -> anyhow::Result<(u16, Vec<u8>)> {
+    let mut response = vec![];
+    let status = send(
+        url,
+        Some((content_type, body)),
+        &[],
+        timeout,
+        Some((&mut response, MAX_RESPONSE_SIZE)),
+    )?;
+    Ok((status, response))
+}
+
+/// Sends a GET request with extra headers, like `Accept`, and returns the response status code
+/// and body
+pub fn get(
+    url: &str,
+    headers: &[(&str, &str)],
+    timeout: Duration,
+) -> anyhow::Result<(u16, Vec<u8>)> {
+    let mut response = vec![];
+    let status = send(
+        url,
+        None,
+        headers,
+        timeout,
+        Some((&mut response, MAX_RESPONSE_SIZE)),
+    )?;
+    Ok((status, response))
+}
+
+/// Downloads a file of up to `max_size` bytes, following redirects, and returns the response
+/// status code and body. Fails for larger files rather than returning part of them.
+/// Only the self-update on Windows downloads files.
+#[cfg(windows)]
+pub fn download(url: &str, max_size: usize, timeout: Duration) -> anyhow::Result<(u16, Vec<u8>)> {
+    let mut response = vec![];
+    // Reading a byte more tells a file that's too large from one of exactly `max_size`
+    let status = send(url, None, &[], timeout, Some((&mut response, max_size + 1)))?;
+    anyhow::ensure!(
+        response.len() <= max_size,
+        "{url} is larger than {max_size} bytes"
+    );
+    Ok((status, response))
+}
+
+#[cfg(windows)]
+/// A POST of `body` with its content type, or a GET without it
+fn send(
+    url: &str,
+    body: Option<(&str, &[u8])>,
+    extra_headers: &[(&str, &str)],
+    timeout: Duration,
+    // The body and the size it's cut off at
+    response: Option<(&mut Vec<u8>, usize)>,
+) -> anyhow::Result<u16> {
+    let url_wide: Vec<u16> = url.encode_utf16().collect();
+
+    // With the lengths set to non-zero and no buffers, the components point into `url_wide`
+    let mut components = URL_COMPONENTS {
+        dwStructSize: size_of::<URL_COMPONENTS>() as u32,
+        dwHostNameLength: u32::MAX,
+        dwUrlPathLength: u32::MAX,
+        dwExtraInfoLength: u32::MAX,
+        ..Default::default()
+    };
+    unsafe { WinHttpCrackUrl(&url_wide, 0, &mut components) }
+        .with_context(|| format!("invalid URL {url}"))?;
+
+    let host = unsafe {
+        String::from_utf16_lossy(std::slice::from_raw_parts(
+            components.lpszHostName.0,
+            components.dwHostNameLength as usize,
+        ))
+    };
+    // The path and the query string are next to each other in the URL
+    let path = unsafe {
+        String::from_utf16_lossy(std::slice::from_raw_parts(
+            components.lpszUrlPath.0,
+            (components.dwUrlPathLength + components.dwExtraInfoLength) as usize,
+        ))
+    };
+    let secure = components.nScheme == WINHTTP_INTERNET_SCHEME_HTTPS;
+
+    let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+
+    unsafe {
+        let session = Handle::new(
+            WinHttpOpen(
+                &HSTRING::from(format!("HeadsetBatteryIndicator/{}", crate::VERSION)),
+                WINHTTP_ACCESS_TYPE_AUTOMATIC_PROXY,
+                PCWSTR::null(),
+                PCWSTR::null(),
+                0,
+            ),
+            "WinHttpOpen",
+        )?;
+        WinHttpSetTimeouts(session.0, timeout_ms, timeout_ms, timeout_ms, timeout_ms)
+            .context("WinHttpSetTimeouts")?;
+
+        let connection = Handle::new(
+            WinHttpConnect(session.0, &HSTRING::from(&host), components.nPort, 0),
+            "WinHttpConnect",
+        )?;
+
+        let flags = if secure {
+            WINHTTP_FLAG_SECURE
+        } else {
+            WINHTTP_OPEN_REQUEST_FLAGS(0)
+        };
+        let request = Handle::new(
+            WinHttpOpenRequest(
+                connection.0,
+                &HSTRING::from(if body.is_some() { "POST" } else { "GET" }),
+                &HSTRING::from(&path),
+                PCWSTR::null(),
+                PCWSTR::null(),
+                std::ptr::null(),
+                flags,
+            ),
+            "WinHttpOpenRequest",
+        )?;
+
+        let mut headers = String::new();
+        if let Some((content_type, _)) = body {
+            headers += &format!("Content-Type: {content_type}\r\n");
+        }
+        for (name, value) in extra_headers {
+            headers += &format!("{name}: {value}\r\n");
+        }
+        let headers: Vec<u16> = headers.encode_utf16().collect();
+        let body = body.map_or(&[][..], |(_, body)| body);
+        WinHttpSendRequest(
+            request.0,
+            Some(&headers),
+            Some(body.as_ptr() as *const c_void),
+            body.len() as u32,
+            body.len() as u32,
+            0,
+        )
+        .with_context(|| format!("sending request to {host}"))?;
+        WinHttpReceiveResponse(request.0, std::ptr::null_mut())
+            .with_context(|| format!("receiving response from {host}"))?;
+
+        let mut status: u32 = 0;
+        let mut size = size_of::<u32>() as u32;
+        WinHttpQueryHeaders(
+            request.0,
+            WINHTTP_QUERY_STATUS_CODE | WINHTTP_QUERY_FLAG_NUMBER,
+            PCWSTR::null(),
+            Some(&mut status as *mut u32 as *mut c_void),
+            &mut size,
+            std::ptr::null_mut(),
+        )
+        .context("reading status code")?;
+
+        if let Some((response, max_size)) = response {
+            loop {
+                let mut available = 0;
+                WinHttpQueryDataAvailable(request.0, &mut available).context("reading response")?;
+                if available == 0 || response.len() >= max_size {
+                    break;
+                }
+
+                let start = response.len();
+                response.resize(start + available as usize, 0);
+                let mut read = 0;
+                WinHttpReadData(
+                    request.0,
+                    response[start..].as_mut_ptr() as *mut c_void,
+                    available,
+                    &mut read,
+                )
+                .context("reading response")?;
+                response.truncate(start + read as usize);
+            }
+        }
+
+        Ok(status as u16)
+    }
+}
+
+#[cfg(not(windows))]
+/// A POST of `body` with its content type, or a GET without it
+fn send(
+    url: &str,
+    body: Option<(&str, &[u8])>,
+    extra_headers: &[(&str, &str)],
+    timeout: Duration,
+    // The body and the size it's cut off at
+    response: Option<(&mut Vec<u8>, usize)>,
+) -> anyhow::Result<u16> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    // Passed on stdin rather than as arguments, where tokens would show up in `ps`
+    let mut config = format!("url = {}\n", curl_quote(url));
+    if let Some((content_type, _)) = body {
+        config += &format!(
+            "header = {}\n",
+            curl_quote(&format!("Content-Type: {content_type}"))
+        );
+    }
+    for (name, value) in extra_headers {
+        config += &format!("header = {}\n", curl_quote(&format!("{name}: {value}")));
+    }
+    // curl sends a GET without data, which follows redirects like WinHTTP does
+    match body {
+        Some((_, body)) => {
+            config += &format!(
+                "data-raw = {}\n",
+                curl_quote(&String::from_utf8_lossy(body))
+            )
+        }
+        None => config += "location\n",
+    }
+
+    let mut curl = Command::new("curl")
+        .args(["--config", "-", "--silent", "--show-error"])
+        .arg("--max-time")
+        .arg(timeout.as_secs().max(1).to_string())
+        .arg("--user-agent")
+        .arg(format!("HeadsetBatteryIndicator/{}", crate::VERSION))
+        .args([
+            "--output",
+            if response.is_some() { "-" } else { "/dev/null" },
+        ])
+        // After the response body, so it's always the last 3 bytes
+        .args(["--write-out", "%{http_code}"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("starting curl")?;
+    curl.stdin
+        .take()
+        .context("opening curl stdin")?
+        .write_all(config.as_bytes())
+        .context("writing curl config")?;
+
+    let output = curl.wait_with_output().context("waiting for curl")?;
+    anyhow::ensure!(
+        output.status.success(),
+        "sending request to {url} failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    let mut stdout = output.stdout;
+    let status = stdout
+        .split_off(stdout.len().saturating_sub(3))
+        .iter()
+        .try_fold(0u16, |status, &digit| {
+            digit
+                .is_ascii_digit()
+                .then(|| status * 10 + u16::from(digit - b'0'))
+        })
+        .context("reading status code")?;
+    if let Some((response, max_size)) = response {
+        stdout.truncate(max_size);
+        *response = stdout;
+    }
+
+    Ok(status)
+}
+
+/// Double-quoted string in a curl config file
+#[cfg(not(windows))]
+fn curl_quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
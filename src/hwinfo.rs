@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use log::{debug, error, info};
+use winreg::{RegKey, enums::HKEY_CURRENT_USER};
+
+use crate::headset_control::{BatteryState, Device};
+
+/// HWiNFO reads user defined sensors from the subkeys of this key
+const CUSTOM_SENSORS_KEY: &str = "Software\\HWiNFO64\\Sensors\\Custom";
+
+/// Battery level, charging and connected flags
+type Reading = (u32, bool, bool);
+
+/// Publishes every headset as a HWiNFO custom sensor, so its battery shows up
+/// next to the CPU and GPU sensors and can be used in HWiNFO's OSD, logging and alerts.
+///
+/// Each headset gets its own sensor key, which is removed again when the headset
+/// goes away or the program exits.
+pub struct HwInfoSensors {
+    custom: RegKey,
+    /// Sensor key names and the values last written to them
+    published: HashMap<String, Reading>,
+}
+
+impl HwInfoSensors {
+    pub fn new() -> anyhow::Result<Self> {
+        let (custom, _) = RegKey::predef(HKEY_CURRENT_USER)
+            .create_subkey(CUSTOM_SENSORS_KEY)
+            .context("creating HWiNFO custom sensors key")?;
+        info!("Publishing HWiNFO custom sensors");
+
+        Ok(Self {
+            custom,
+            published: HashMap::new(),
+        })
+    }
+
+    pub fn publish(&mut self, devices: &[Device]) {
+        let readings: HashMap<String, Reading> = devices
+            .iter()
+            .map(|device| (sensor_name(device, devices), reading(device)))
+            .collect();
+
+        for name in self.published.keys() {
+            if !readings.contains_key(name) {
+                debug!("Removing HWiNFO sensor {name}");
+                let _ = self.custom.delete_subkey_all(name);
+            }
+        }
+        self.published.retain(|name, _| readings.contains_key(name));
+
+        for (name, reading) in readings {
+            if self.published.get(&name) == Some(&reading) {
+                continue;
+            }
+            match self.write(&name, reading) {
+                Ok(()) => {
+                    self.published.insert(name, reading);
+                }
+                Err(e) => error!("Failed to write HWiNFO sensor {name}: {e:?}"),
+            }
+        }
+    }
+
+    fn write(&self, name: &str, (level, charging, connected): Reading) -> anyhow::Result<()> {
+        let (sensor, _) = self.custom.create_subkey(name)?;
+
+        // The entry type decides the default unit, Usage entries are in percent
+        let entries = [
+            ("Usage0", "Battery", level),
+            ("Other0", "Charging", charging as u32),
+            ("Other1", "Connected", connected as u32),
+        ];
+        for (entry, label, value) in entries {
+            let (key, _) = sensor.create_subkey(entry)?;
+            key.set_value("Name", &label)?;
+            key.set_value("Value", &value)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for HwInfoSensors {
+    fn drop(&mut self) {
+        for name in self.published.keys() {
+            let _ = self.custom.delete_subkey_all(name);
+        }
+    }
+}
+
+/// The product name, plus the device id when the same model is connected twice
+fn sensor_name(device: &Device, devices: &[Device]) -> String {
+    // Backslashes would create nested keys
+    let product = device.product.replace('\\', "-");
+    let same_model = devices.iter().filter(|d| d.product == device.product);
+    if same_model.count() > 1 {
+        format!("{product} {}", device.id())
+    } else {
+        product
+    }
+}
+
+fn reading(device: &Device) -> Reading {
+    let connected = matches!(
+        device.battery.status,
+        BatteryState::BatteryAvailable | BatteryState::BatteryCharging
+    );
+    let level = if connected {
+        device.battery.level.clamp(0, 100) as u32
+    } else {
+        0
+    };
+
+    (
+        level,
+        device.battery.status == BatteryState::BatteryCharging,
+        connected,
+    )
+}
@@ -0,0 +1,195 @@
+//! Battery history samples written to InfluxDB in line protocol, for graphing in Grafana
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use log::{debug, error, warn};
+
+use crate::headset_control::BatteryState;
+use crate::history::Sample;
+use crate::http;
+
+pub const DEFAULT_MEASUREMENT: &str = "headset_battery";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+/// Lines kept while the server is unreachable, the oldest are dropped after that
+const MAX_PENDING: usize = 10_000;
+
+/// Read from the `InfluxDb` registry subkey, where the token is stored encrypted
+#[derive(Debug, Clone)]
+pub struct InfluxConfig {
+    /// Like `http://localhost:8086`
+    pub url: String,
+    pub org: String,
+    pub bucket: String,
+    pub token: String,
+    pub measurement: String,
+}
+
+/// Writes each battery history sample to an InfluxDB v2 bucket.
+///
+/// Writing happens on its own thread, and samples are kept and sent again later
+/// while the server can't be reached.
+pub struct InfluxExporter {
+    sender: mpsc::Sender<Vec<Sample>>,
+}
+
+impl InfluxExporter {
+    pub fn start(config: InfluxConfig) -> anyhow::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("influxdb".to_string())
+            .spawn(move || Worker::new(config).run(receiver))
+            .context("spawning InfluxDB thread")?;
+
+        Ok(Self { sender })
+    }
+
+    pub fn write(&self, samples: &[Sample]) {
+        if samples.is_empty() {
+            return;
+        }
+        if self.sender.send(samples.to_vec()).is_err() {
+            error!("InfluxDB thread has stopped");
+        }
+    }
+}
+
+struct Worker {
+    config: InfluxConfig,
+    url: String,
+    /// Lines that weren't written yet, oldest first
+    pending: VecDeque<String>,
+    last_failure: Option<Instant>,
+}
+
+impl Worker {
+    fn new(config: InfluxConfig) -> Self {
+        let url = format!(
+            "{}/api/v2/write?org={}&bucket={}&precision=s",
+            config.url.trim_end_matches('/'),
+            query_encode(&config.org),
+            query_encode(&config.bucket)
+        );
+        Self {
+            config,
+            url,
+            pending: VecDeque::new(),
+            last_failure: None,
+        }
+    }
+
+    fn run(mut self, receiver: mpsc::Receiver<Vec<Sample>>) {
+        loop {
+            match receiver.recv_timeout(RETRY_DELAY) {
+                Ok(samples) => {
+                    let measurement = escape(&self.config.measurement, ", ");
+                    self.pending
+                        .extend(samples.iter().map(|sample| line(&measurement, sample)));
+                    if self.pending.len() > MAX_PENDING {
+                        let dropped = self.pending.len() - MAX_PENDING;
+                        self.pending.drain(..dropped);
+                        warn!("Dropped {dropped} samples that couldn't be written to InfluxDB");
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.flush();
+                    return;
+                }
+            }
+
+            if self
+                .last_failure
+                .is_none_or(|failure| failure.elapsed() >= RETRY_DELAY)
+            {
+                self.flush();
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let body = self
+            .pending
+            .iter()
+            .fold(String::new(), |body, line| body + line + "\n");
+        let authorization = format!("Token {}", self.config.token);
+        match http::post_with_headers(
+            &self.url,
+            "text/plain; charset=utf-8",
+            &[("Authorization", &authorization)],
+            body.as_bytes(),
+            TIMEOUT,
+        ) {
+            Ok(status) if (200..300).contains(&status) => {
+                debug!("Wrote {} samples to InfluxDB", self.pending.len());
+                self.pending.clear();
+                self.last_failure = None;
+            }
+            // Malformed lines or a wrong bucket won't go away by retrying
+            Ok(status)
+                if (400..500).contains(&status) && ![401, 403, 404, 429].contains(&status) =>
+            {
+                error!(
+                    "InfluxDB rejected {} samples with status {status}",
+                    self.pending.len()
+                );
+                self.pending.clear();
+            }
+            Ok(status) => {
+                warn!("Writing to InfluxDB failed with status {status}");
+                self.last_failure = Some(Instant::now());
+            }
+            Err(e) => {
+                warn!("Writing to InfluxDB failed: {e:?}");
+                self.last_failure = Some(Instant::now());
+            }
+        }
+    }
+}
+
+/// Like `headset_battery,device=1038:12ad level=75i,status="available",charging=false,anomaly=false 1700000000`
+fn line(measurement: &str, sample: &Sample) -> String {
+    format!(
+        "{measurement},device={} level={}i,status=\"{}\",charging={},anomaly={} {}",
+        escape(&sample.device, ", ="),
+        sample.level,
+        sample.status.label(),
+        sample.status == BatteryState::BatteryCharging,
+        sample.anomaly,
+        sample.timestamp
+    )
+}
+
+/// Backslash-escapes the characters that are special in measurement names and tag values
+fn escape(value: &str, special: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '\\' || special.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn query_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
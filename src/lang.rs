@@ -7,17 +7,94 @@ pub enum Lang {
 }
 
 #[allow(non_camel_case_types)]
+// Some texts are only used by Windows-only parts, like the autostart menu
+#[cfg_attr(not(windows), allow(dead_code))]
 pub enum Key {
     battery_remaining,
     no_adapter_found,
+    no_adapter_remote_session,
+    no_adapter_usb_permission,
+    headsetcontrol_missing,
+    get_headsetcontrol,
+    choose_headsetcontrol,
+    reset_headsetcontrol,
+    headsetcontrol_not_found,
     view_logs,
     view_updates,
+    update_available,
+    update_available_message,
+    install_update,
+    downloading_update,
+    update_failed,
+    alerts_muted,
+    alerts_unmuted,
     quit_program,
     device_charging,
+    device_docked,
+    device_docked_charging,
+    part_left,
+    part_right,
+    part_case,
+    sidetone,
+    lights,
+    inactive_time,
+    chatmix,
+    voice_prompts,
+    control_on,
+    control_off,
+    sidetone_low,
+    sidetone_medium,
+    sidetone_high,
+    sidetone_max,
+    inactive_disabled,
+    inactive_after,
+    device_features,
+    lights_off_when_low,
+    chatmix_game,
+    chatmix_chat,
+    chatmix_balanced,
     device_disconnected,
     battery_unavailable,
     show_notifications,
     notifications_enabled_message,
+    start_with_windows,
+    autostart_startup_folder,
+    autostart_run_key,
+    autostart_task_scheduler,
+    enable_local_api,
+    discord_battery,
+    discord_charging,
+    time_to_empty,
+    time_to_full,
+    details_submenu,
+    charge_cycle_count,
+    usage_today,
+    usage_this_week,
+    battery_health,
+    battery_health_low,
+    unplug_reminder,
+    alert_low,
+    alert_critical,
+    alert_full,
+    summary_last_week,
+    summary_runtime,
+    summary_low_battery,
+    summary_cycles,
+    used_today,
+    last_known,
+    export_history,
+    export_last_day,
+    export_last_week,
+    export_last_month,
+    export_everything,
+    export_selected_only,
+    history_window,
+    history_now,
+    history_empty,
+    compare_headset,
+    compare_runtime,
+    compare_cycles,
+    compare_health,
     version,
 }
 
@@ -42,53 +119,381 @@ pub fn t(key: Key) -> &'static str {
         Lang::En => match key {
             battery_remaining => "remaining",
             no_adapter_found => "No headphone adapter found",
+            no_adapter_remote_session => "No headphone adapter found (Remote Desktop session)",
+            no_adapter_usb_permission => {
+                "No headphone adapter found (no permission to access USB devices)"
+            }
+            headsetcontrol_missing => "headsetcontrol isn't installed",
+            get_headsetcontrol => "Download headsetcontrol...",
+            choose_headsetcontrol => "Choose headsetcontrol executable...",
+            reset_headsetcontrol => "Use the installed headsetcontrol",
+            headsetcontrol_not_found => "headsetcontrol wasn't found at {path}",
             view_logs => "View logs",
             view_updates => "View updates",
+            update_available => "Update available ({version})",
+            update_available_message => {
+                "Version {version} is available, click to open the release page"
+            }
+            install_update => "Download and install update",
+            downloading_update => "Downloading update...",
+            update_failed => "Couldn't install the update, see the log for details",
+            alerts_muted => "Battery notifications muted",
+            alerts_unmuted => "Battery notifications on",
             quit_program => "Close",
             device_charging => "(Charging)",
+            device_docked => "(Docked)",
+            device_docked_charging => "(Docked, charging)",
+            part_left => "Left",
+            part_right => "Right",
+            part_case => "Case",
+            sidetone => "Sidetone",
+            lights => "Lights",
+            inactive_time => "Turn off when idle",
+            chatmix => "Chatmix",
+            voice_prompts => "Voice prompts",
+            control_on => "On",
+            control_off => "Off",
+            sidetone_low => "Low",
+            sidetone_medium => "Medium",
+            sidetone_high => "High",
+            sidetone_max => "Max",
+            inactive_disabled => "Disabled",
+            inactive_after => "After {minutes} min",
+            device_features => "Features: {features}",
+            lights_off_when_low => "Off while the battery is below {level}%",
+            chatmix_game => "ChatMix: {percent}% game",
+            chatmix_chat => "ChatMix: {percent}% chat",
+            chatmix_balanced => "ChatMix: balanced",
             device_disconnected => "(Disconnected)",
             battery_unavailable => "(Battery unavailable)",
             show_notifications => "Show notifications",
             notifications_enabled_message => "Notifications enabled",
+            start_with_windows => "Start with Windows",
+            autostart_startup_folder => "Startup folder",
+            autostart_run_key => "Registry (Run key)",
+            autostart_task_scheduler => "Task Scheduler",
+            enable_local_api => "Enable local API",
+            discord_battery => "🎧 {level}% battery",
+            discord_charging => "Charging",
+            time_to_empty => "about {time} left",
+            time_to_full => "full in about {time}",
+            details_submenu => "Device details",
+            charge_cycle_count => "{cycles} charge cycles ({sessions} charges)",
+            usage_today => "Today: {on} h on, {charging} h charging",
+            usage_this_week => "This week: {on} h on, {charging} h charging",
+            battery_health => "Battery health about {health}%",
+            battery_health_low => {
+                "Battery health is down to about {health}%, it lasts noticeably shorter than it used to"
+            }
+            unplug_reminder => "Fully charged for {time}, it can be unplugged",
+            alert_low => "Battery low ({level}%)",
+            alert_critical => "Battery critical ({level}%)",
+            alert_full => "Battery full",
+            summary_last_week => "Last week",
+            summary_runtime => "about {hours} h per charge",
+            summary_low_battery => "{count}× low battery",
+            summary_cycles => "{cycles} charge cycles",
+            used_today => "Used for {time} today",
+            last_known => "(last known)",
+            export_history => "Export history",
+            export_last_day => "Last 24 hours",
+            export_last_week => "Last 7 days",
+            export_last_month => "Last 30 days",
+            export_everything => "Everything",
+            export_selected_only => "Only the selected headset",
+            history_window => "Battery history",
+            history_now => "now",
+            history_empty => "No battery history recorded yet",
+            compare_headset => "Headset",
+            compare_runtime => "Full charge lasts",
+            compare_cycles => "Charge cycles",
+            compare_health => "Battery health",
             version => "Version",
         },
         Lang::Fi => match key {
             battery_remaining => "jäljellä",
             no_adapter_found => "Kuulokeadapteria ei löytynyt",
+            no_adapter_remote_session => "Kuulokeadapteria ei löytynyt (etätyöpöytäistunto)",
+            no_adapter_usb_permission => {
+                "Kuulokeadapteria ei löytynyt (ei oikeutta USB-laitteisiin)"
+            }
+            headsetcontrol_missing => "headsetcontrol ei ole asennettu",
+            get_headsetcontrol => "Lataa headsetcontrol...",
+            choose_headsetcontrol => "Valitse headsetcontrolin ohjelmatiedosto...",
+            reset_headsetcontrol => "Käytä asennettua headsetcontrolia",
+            headsetcontrol_not_found => "headsetcontrolia ei löytynyt polusta {path}",
             view_logs => "Näytä lokitiedostot",
             view_updates => "Näytä päivitykset",
+            update_available => "Päivitys saatavilla ({version})",
+            update_available_message => {
+                "Versio {version} on saatavilla, avaa julkaisusivu napsauttamalla"
+            }
+            install_update => "Lataa ja asenna päivitys",
+            downloading_update => "Ladataan päivitystä...",
+            update_failed => "Päivityksen asentaminen epäonnistui, katso lisätiedot lokista",
+            alerts_muted => "Akkuilmoitukset mykistetty",
+            alerts_unmuted => "Akkuilmoitukset käytössä",
             quit_program => "Sulje",
             device_charging => "(Latautuu)",
+            device_docked => "(Telakassa)",
+            device_docked_charging => "(Telakassa, latautuu)",
+            part_left => "Vasen",
+            part_right => "Oikea",
+            part_case => "Kotelo",
+            sidetone => "Sivuääni",
+            lights => "Valot",
+            inactive_time => "Sammutus käyttämättömänä",
+            chatmix => "Chatmix",
+            voice_prompts => "Ääniopasteet",
+            control_on => "Päällä",
+            control_off => "Pois",
+            sidetone_low => "Matala",
+            sidetone_medium => "Keskitaso",
+            sidetone_high => "Korkea",
+            sidetone_max => "Suurin",
+            inactive_disabled => "Ei käytössä",
+            inactive_after => "{minutes} min jälkeen",
+            device_features => "Ominaisuudet: {features}",
+            lights_off_when_low => "Pois, kun akun varaus on alle {level} %",
+            chatmix_game => "ChatMix: {percent} % peli",
+            chatmix_chat => "ChatMix: {percent} % puhe",
+            chatmix_balanced => "ChatMix: tasapainossa",
             device_disconnected => "(Ei yhteyttä)",
             battery_unavailable => "(Akku ei saatavilla)",
             show_notifications => "Näytä ilmoitukset",
             notifications_enabled_message => "Ilmoitukset käytössä",
+            start_with_windows => "Käynnistä Windowsin mukana",
+            autostart_startup_folder => "Käynnistyskansio",
+            autostart_run_key => "Rekisteri (Run-avain)",
+            autostart_task_scheduler => "Tehtävien ajoitus",
+            enable_local_api => "Ota paikallinen API käyttöön",
+            discord_battery => "🎧 Akkua {level} %",
+            discord_charging => "Latautuu",
+            time_to_empty => "noin {time} jäljellä",
+            time_to_full => "täynnä noin {time} kuluttua",
+            details_submenu => "Laitteen tiedot",
+            charge_cycle_count => "{cycles} latausjaksoa ({sessions} latauskertaa)",
+            usage_today => "Tänään: {on} h päällä, {charging} h latautumassa",
+            usage_this_week => "Tällä viikolla: {on} h päällä, {charging} h latautumassa",
+            battery_health => "Akun kunto noin {health} %",
+            battery_health_low => {
+                "Akun kunto on laskenut noin {health} %:iin, akku kestää selvästi aiempaa lyhyemmän ajan"
+            }
+            unplug_reminder => "Ollut täynnä {time}, laturin voi irrottaa",
+            alert_low => "Akku vähissä ({level} %)",
+            alert_critical => "Akku lähes tyhjä ({level} %)",
+            alert_full => "Akku täynnä",
+            summary_last_week => "Viime viikko",
+            summary_runtime => "noin {hours} h latausta kohden",
+            summary_low_battery => "{count}× akku vähissä",
+            summary_cycles => "{cycles} latausjaksoa",
+            used_today => "Käytössä tänään {time}",
+            last_known => "(viimeisin tiedossa)",
+            export_history => "Vie akkuhistoria",
+            export_last_day => "Viimeiset 24 tuntia",
+            export_last_week => "Viimeiset 7 päivää",
+            export_last_month => "Viimeiset 30 päivää",
+            export_everything => "Kaikki",
+            export_selected_only => "Vain valitut kuulokkeet",
+            history_window => "Akkuhistoria",
+            history_now => "nyt",
+            history_empty => "Akkuhistoriaa ei ole vielä tallennettu",
+            compare_headset => "Kuulokkeet",
+            compare_runtime => "Täysi lataus kestää",
+            compare_cycles => "Latausjaksot",
+            compare_health => "Akun kunto",
             version => "Versio",
         },
         Lang::De => match key {
             battery_remaining => "verbleibend",
             no_adapter_found => "Kein Kopfhöreradapter gefunden",
+            no_adapter_remote_session => "Kein Kopfhöreradapter gefunden (Remotedesktopsitzung)",
+            no_adapter_usb_permission => {
+                "Kein Kopfhöreradapter gefunden (kein Zugriff auf USB-Geräte)"
+            }
+            headsetcontrol_missing => "headsetcontrol ist nicht installiert",
+            get_headsetcontrol => "headsetcontrol herunterladen...",
+            choose_headsetcontrol => "headsetcontrol-Programmdatei auswählen...",
+            reset_headsetcontrol => "Installiertes headsetcontrol verwenden",
+            headsetcontrol_not_found => "headsetcontrol wurde unter {path} nicht gefunden",
             view_logs => "Protokolle anzeigen",
             view_updates => "Updates anzeigen",
+            update_available => "Update verfügbar ({version})",
+            update_available_message => {
+                "Version {version} ist verfügbar, klicken, um die Release-Seite zu öffnen"
+            }
+            install_update => "Update herunterladen und installieren",
+            downloading_update => "Update wird heruntergeladen...",
+            update_failed => "Das Update konnte nicht installiert werden, Details stehen im Log",
+            alerts_muted => "Akkubenachrichtigungen stummgeschaltet",
+            alerts_unmuted => "Akkubenachrichtigungen aktiviert",
             quit_program => "Beenden",
             device_charging => "(Wird geladen)",
+            device_docked => "(Angedockt)",
+            device_docked_charging => "(Angedockt, wird geladen)",
+            part_left => "Links",
+            part_right => "Rechts",
+            part_case => "Etui",
+            sidetone => "Mithörton",
+            lights => "Beleuchtung",
+            inactive_time => "Bei Inaktivität ausschalten",
+            chatmix => "Chatmix",
+            voice_prompts => "Sprachansagen",
+            control_on => "An",
+            control_off => "Aus",
+            sidetone_low => "Niedrig",
+            sidetone_medium => "Mittel",
+            sidetone_high => "Hoch",
+            sidetone_max => "Maximal",
+            inactive_disabled => "Deaktiviert",
+            inactive_after => "Nach {minutes} Min.",
+            device_features => "Funktionen: {features}",
+            lights_off_when_low => "Aus, solange der Akku unter {level} % ist",
+            chatmix_game => "ChatMix: {percent} % Spiel",
+            chatmix_chat => "ChatMix: {percent} % Chat",
+            chatmix_balanced => "ChatMix: ausgeglichen",
             device_disconnected => "(Getrennt)",
             battery_unavailable => "(Akkustand nicht verfügbar)",
             show_notifications => "Benachrichtigungen aktivieren",
             notifications_enabled_message => "Benachrichtigungen aktiviert",
+            start_with_windows => "Mit Windows starten",
+            autostart_startup_folder => "Autostart-Ordner",
+            autostart_run_key => "Registrierung (Run-Schlüssel)",
+            autostart_task_scheduler => "Aufgabenplanung",
+            enable_local_api => "Lokale API aktivieren",
+            discord_battery => "🎧 {level} % Akku",
+            discord_charging => "Wird geladen",
+            time_to_empty => "noch etwa {time}",
+            time_to_full => "voll in etwa {time}",
+            details_submenu => "Gerätedetails",
+            charge_cycle_count => "{cycles} Ladezyklen ({sessions} Ladevorgänge)",
+            usage_today => "Heute: {on} h eingeschaltet, {charging} h geladen",
+            usage_this_week => "Diese Woche: {on} h eingeschaltet, {charging} h geladen",
+            battery_health => "Akkuzustand etwa {health} %",
+            battery_health_low => {
+                "Der Akkuzustand ist auf etwa {health} % gesunken, der Akku hält deutlich kürzer als früher"
+            }
+            unplug_reminder => "Seit {time} voll geladen, das Ladekabel kann abgezogen werden",
+            alert_low => "Akku schwach ({level} %)",
+            alert_critical => "Akku fast leer ({level} %)",
+            alert_full => "Akku voll",
+            summary_last_week => "Letzte Woche",
+            summary_runtime => "etwa {hours} h pro Ladung",
+            summary_low_battery => "{count}× Akku schwach",
+            summary_cycles => "{cycles} Ladezyklen",
+            used_today => "Heute {time} benutzt",
+            last_known => "(zuletzt bekannt)",
+            export_history => "Akkuverlauf exportieren",
+            export_last_day => "Letzte 24 Stunden",
+            export_last_week => "Letzte 7 Tage",
+            export_last_month => "Letzte 30 Tage",
+            export_everything => "Alles",
+            export_selected_only => "Nur das ausgewählte Headset",
+            history_window => "Akkuverlauf",
+            history_now => "jetzt",
+            history_empty => "Noch kein Akkuverlauf aufgezeichnet",
+            compare_headset => "Headset",
+            compare_runtime => "Volle Ladung hält",
+            compare_cycles => "Ladezyklen",
+            compare_health => "Akkuzustand",
             version => "Version",
         },
         Lang::It => match key {
             battery_remaining => "rimanente",
             no_adapter_found => "Nessun adattatore per cuffie trovato",
+            no_adapter_remote_session => {
+                "Nessun adattatore per cuffie trovato (sessione Desktop remoto)"
+            }
+            no_adapter_usb_permission => {
+                "Nessun adattatore per cuffie trovato (nessun permesso per i dispositivi USB)"
+            }
+            headsetcontrol_missing => "headsetcontrol non è installato",
+            get_headsetcontrol => "Scarica headsetcontrol...",
+            choose_headsetcontrol => "Scegli l'eseguibile di headsetcontrol...",
+            reset_headsetcontrol => "Usa headsetcontrol installato",
+            headsetcontrol_not_found => "headsetcontrol non trovato in {path}",
             view_logs => "Visualizza file di log",
             view_updates => "Controlla aggiornamenti",
+            update_available => "Aggiornamento disponibile ({version})",
+            update_available_message => {
+                "La versione {version} è disponibile, fai clic per aprire la pagina della release"
+            }
+            install_update => "Scarica e installa l'aggiornamento",
+            downloading_update => "Download dell'aggiornamento in corso...",
+            update_failed => {
+                "Impossibile installare l'aggiornamento, consulta il log per i dettagli"
+            }
+            alerts_muted => "Notifiche della batteria disattivate",
+            alerts_unmuted => "Notifiche della batteria attivate",
             quit_program => "Chiudi",
             device_charging => "(In carica)",
+            device_docked => "(Nella base)",
+            device_docked_charging => "(Nella base, in carica)",
+            part_left => "Sinistro",
+            part_right => "Destro",
+            part_case => "Custodia",
+            sidetone => "Sidetone",
+            lights => "Luci",
+            inactive_time => "Spegni se inattive",
+            chatmix => "Chatmix",
+            voice_prompts => "Messaggi vocali",
+            control_on => "Attivo",
+            control_off => "Disattivo",
+            sidetone_low => "Basso",
+            sidetone_medium => "Medio",
+            sidetone_high => "Alto",
+            sidetone_max => "Massimo",
+            inactive_disabled => "Disattivato",
+            inactive_after => "Dopo {minutes} min",
+            device_features => "Funzioni: {features}",
+            lights_off_when_low => "Spente con la batteria sotto il {level}%",
+            chatmix_game => "ChatMix: {percent}% gioco",
+            chatmix_chat => "ChatMix: {percent}% chat",
+            chatmix_balanced => "ChatMix: bilanciato",
             device_disconnected => "(Disconnesso)",
             battery_unavailable => "(Batteria non disponibile)",
             show_notifications => "Mostra notifiche",
             notifications_enabled_message => "Notifiche attivate",
+            start_with_windows => "Avvia con Windows",
+            autostart_startup_folder => "Cartella Esecuzione automatica",
+            autostart_run_key => "Registro di sistema (chiave Run)",
+            autostart_task_scheduler => "Utilità di pianificazione",
+            enable_local_api => "Abilita API locale",
+            discord_battery => "🎧 {level}% di batteria",
+            discord_charging => "In carica",
+            time_to_empty => "circa {time} rimanenti",
+            time_to_full => "carica completa tra circa {time}",
+            details_submenu => "Dettagli dispositivo",
+            charge_cycle_count => "{cycles} cicli di ricarica ({sessions} ricariche)",
+            usage_today => "Oggi: {on} h acceso, {charging} h in carica",
+            usage_this_week => "Questa settimana: {on} h acceso, {charging} h in carica",
+            battery_health => "Salute della batteria circa {health}%",
+            battery_health_low => {
+                "La salute della batteria è scesa a circa {health}%, l'autonomia è sensibilmente più breve di prima"
+            }
+            unplug_reminder => "Carica completa da {time}, il cavo può essere scollegato",
+            alert_low => "Batteria scarica ({level}%)",
+            alert_critical => "Batteria quasi esaurita ({level}%)",
+            alert_full => "Batteria carica",
+            summary_last_week => "Settimana scorsa",
+            summary_runtime => "circa {hours} h per carica",
+            summary_low_battery => "{count}× batteria scarica",
+            summary_cycles => "{cycles} cicli di carica",
+            used_today => "Usato oggi per {time}",
+            last_known => "(ultimo valore noto)",
+            export_history => "Esporta cronologia batteria",
+            export_last_day => "Ultime 24 ore",
+            export_last_week => "Ultimi 7 giorni",
+            export_last_month => "Ultimi 30 giorni",
+            export_everything => "Tutto",
+            export_selected_only => "Solo le cuffie selezionate",
+            history_window => "Cronologia batteria",
+            history_now => "ora",
+            history_empty => "Nessuna cronologia della batteria registrata",
+            compare_headset => "Cuffie",
+            compare_runtime => "Una carica completa dura",
+            compare_cycles => "Cicli di carica",
+            compare_health => "Salute batteria",
             version => "Versione",
         },
     }
@@ -1,12 +1,64 @@
+#[cfg(windows)]
+mod accessibility;
+mod actions;
+mod api;
+#[cfg(windows)]
+mod audio;
+mod bench;
+mod cli;
+mod demo;
+mod discord;
+#[cfg(windows)]
+mod dpapi;
+mod events;
+mod export;
+#[cfg(windows)]
+mod graph;
+mod grpc;
 mod headset_control;
+mod history;
+#[cfg(windows)]
+mod hotkeys;
+mod http;
+#[cfg(windows)]
+mod hwinfo;
+mod influx;
 mod lang;
+mod lights;
 mod menu;
+#[cfg(windows)]
+mod message_window;
+mod mqtt;
 mod notify;
+#[cfg(target_os = "linux")]
+mod overlay;
+mod palette;
+#[cfg(windows)]
+mod perf;
+mod picker;
+#[cfg(windows)]
+mod pipe;
+mod platform;
+mod poller;
+mod push;
+#[cfg(windows)]
+mod rtss;
+mod scripts;
+#[cfg(windows)]
+mod sessions;
 mod settings;
-
 #[cfg(windows)]
-use anyhow::Result;
+mod sound;
+#[cfg(windows)]
+mod speech;
+mod status_file;
+mod telegram;
+mod update;
+mod webhook;
+
 use lang::Key::*;
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::time::{Duration, Instant};
 
 use anyhow::Context;
@@ -15,83 +67,863 @@ use tray_icon::{TrayIcon, TrayIconBuilder, menu::MenuEvent};
 use winit::{
     application::ApplicationHandler,
     event::{StartCause, WindowEvent},
-    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop, EventLoopProxy},
     window::Theme,
 };
 
+use crate::platform::autostart::{self, Autostart as _};
+use crate::platform::features::{self, Features as _};
+use crate::platform::sandbox::{self, Sandbox as _};
+use crate::platform::tray::{self, Tray as _};
 use crate::{headset_control::BatteryState, notify::Notifier};
+
+/// Events sent to the event loop from outside of it
+#[derive(Debug)]
+// Most are sent by the message window, which only exists on Windows
+#[allow(dead_code)]
+pub(crate) enum UserEvent {
+    /// The user clicked an item of the tray menu
+    Menu(MenuEvent),
+    /// Windows asked if the session can end (logoff, shutdown)
+    SessionEnding,
+    /// The session is ending or the app was asked to close
+    SessionEnded,
+    /// The user switched between light and dark mode
+    ThemeChanged,
+    /// Explorer restarted, and the tray icon is gone
+    TaskbarCreated,
+    /// It's time to poll the devices again
+    PollTimer,
+    /// The poller has a new snapshot of the devices
+    DevicesPolled,
+    /// A device was plugged in or removed
+    DevicesChanged,
+    /// Monitors were added/removed, or the resolution or scaling changed
+    DisplayChanged,
+    /// A gRPC client asked for the devices to be polled now
+    Refresh,
+    /// A gRPC client turned notifications on or off
+    SetNotifications(bool),
+    /// A gRPC client selected the device with this id for the tray icon
+    SelectDevice(String),
+    /// The user clicked one of our toasts
+    NotificationActivated,
+    /// The update check found the release with this tag to be the latest
+    UpdateChecked(String),
+    /// Handled by the features of the platform
+    Platform(features::Event),
+}
+
+/// Theme, level as far as the icon shows it, state and size of the icon in the tray
+type ShownIcon = (Theme, isize, BatteryState, Option<(u32, u32)>);
+
 struct AppState {
     tray_icon: TrayIcon,
     devices: Vec<headset_control::Device>,
+    poller: poller::Poller,
     context_menu: menu::ContextMenu,
     settings: settings::Settings,
     notifier: Notifier,
+    events: events::EventBus,
+    api: Option<api::ApiServer>,
+    grpc: Option<grpc::GrpcServer>,
+    mqtt: Option<mqtt::MqttPublisher>,
+    discord: Option<discord::DiscordPresence>,
+    telegram: Option<telegram::TelegramBot>,
+    influx: Option<influx::InfluxExporter>,
+    status_file: Option<status_file::StatusFile>,
+    history: Option<history::History>,
+    estimator: history::Estimator,
+    charge_cycles: history::ChargeCycles,
+    usage: history::Usage,
+    health: history::BatteryHealth,
+    unplug_reminder: notify::UnplugReminder,
+    lights: lights::LightsKeeper,
 
-    last_update: Instant,
-    should_update_icon: bool,
+    icons: tray::IconCache,
+    shown_icon: Option<ShownIcon>,
+    shown_tooltip: Option<String>,
+    /// Polls in a row that found no headset, for backing off
+    empty_polls: u32,
+    /// Added to the backoff, so the retries don't line up with other periodic work
+    backoff_jitter: f64,
+    /// Whether the tray still shows the last reading from the previous run
+    showing_cached: bool,
+    /// Whether the made-up headset of `--demo` is shown, which isn't saved on exit
+    demo: bool,
+    /// Size of the tray icon in pixels for the current DPI, or the system default
+    icon_size: Option<(u32, u32)>,
+    /// Whether the state was saved for the end of the session, and isn't saved again on exit
+    session_ended: bool,
+
+    features: features::Native,
 }
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub use bench::{BENCH_POLLS_ARG, bench_polls};
+pub use cli::{JSON_ARG, ONCE_ARG, QUERY_ARG, once, query};
+pub use demo::DEMO_ARG;
+pub use platform::autostart::AUTOSTART_ARG;
+
+/// Where the log file goes in Flatpak and Snap, since the executable's folder is read-only
+pub fn sandbox_log_dir() -> Option<std::path::PathBuf> {
+    sandbox::Native::is_sandboxed()
+        .then(history::data_dir)
+        .and_then(Result::ok)
+}
 
-pub fn run() -> anyhow::Result<()> {
+/// While a battery is charging or below `LOW_POLL_LEVEL`
+const FAST_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+/// While every battery is above `HIGH_POLL_LEVEL`, when a poll every minute is plenty
+const SLOW_POLL_INTERVAL: Duration = Duration::from_secs(60);
+const LOW_POLL_LEVEL: isize = 20;
+const HIGH_POLL_LEVEL: isize = 50;
+/// The first interval while no headset is found, doubled after every poll that finds none
+const NO_DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_NO_DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// The backoff is made up to this much longer at random
+const MAX_BACKOFF_JITTER: f64 = 0.2;
+/// USB dongles are rarely forwarded to remote desktop sessions,
+/// so the backoff starts from a longer interval there
+const REMOTE_SESSION_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// How many headsets the last reading is remembered for
+const MAX_LAST_DEVICES: usize = 8;
+
+/// With `demo`, shows a made-up headset instead of the real ones
+pub fn run(demo: bool) -> anyhow::Result<()> {
     info!("Starting application");
     info!("Version {VERSION}");
     debug!("Using locale {:?}", *lang::LANG);
 
-    if let Err(err) = enable_dark_mode_support() {
-        warn!("Failed to enable dark mode support: {:?}", err);
+    autostart::Native::preload();
+    tray::Native::init()?;
+
+    let mut builder = EventLoop::<UserEvent>::with_user_event();
+    features::Native::configure_event_loop(&mut builder);
+    let event_loop = builder.build().context("Error initializing event loop")?;
+
+    // The clicks wake up the event loop, which doesn't get a message for them on
+    // Linux and macOS
+    let menu_proxy = event_loop.create_proxy();
+    MenuEvent::set_event_handler(Some(move |event| {
+        let _ = menu_proxy.send_event(UserEvent::Menu(event));
+    }));
+
+    let mut launcher = Launcher {
+        proxy: event_loop.create_proxy(),
+        demo,
+        app: Rc::default(),
+        error: None,
+    };
+
+    event_loop.run_app(&mut launcher)?;
+    if let Some(e) = launcher.error {
+        return Err(e);
     }
 
-    let event_loop = EventLoop::new().context("Error initializing event loop")?;
+    let relaunch = launcher
+        .app
+        .borrow_mut()
+        .as_mut()
+        .and_then(|app| app.features.take_relaunch());
+    // Dropping the state removes the tray icon
+    drop(launcher);
+    info!("Application exited");
+    // Only now, so the new one doesn't find the API ports taken
+    if let Some(exe) = relaunch {
+        features::Native::relaunch(&exe);
+    }
 
-    let mut app = AppState::init()?;
+    Ok(())
+}
 
-    Ok(event_loop.run_app(&mut app)?)
+/// Creates the app once the event loop is running. On macOS the menu bar icon misbehaves
+/// next to fullscreen apps when it's created before that.
+struct Launcher {
+    proxy: EventLoopProxy<UserEvent>,
+    demo: bool,
+    /// Shared with the handler that saves it when the session ends
+    app: Rc<RefCell<Option<AppState>>>,
+    error: Option<anyhow::Error>,
+}
+
+impl ApplicationHandler<UserEvent> for Launcher {
+    fn new_events(&mut self, event_loop: &ActiveEventLoop, cause: StartCause) {
+        if cause != StartCause::Init {
+            return;
+        }
+        match AppState::init(self.proxy.clone(), self.demo) {
+            Ok(app) => {
+                let shared = Rc::downgrade(&self.app);
+                app.features.on_session_end(Box::new(move || {
+                    // The event loop ends the session instead when the app is in use, like
+                    // while the tray menu is open
+                    let Some(app) = shared.upgrade() else {
+                        return false;
+                    };
+                    let Ok(mut app) = app.try_borrow_mut() else {
+                        return false;
+                    };
+                    app.as_mut().map(AppState::end_session).is_some()
+                }));
+                *self.app.borrow_mut() = Some(app);
+            }
+            Err(e) => {
+                self.error = Some(e);
+                event_loop.exit();
+            }
+        }
+    }
+
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(app) = self.app.borrow_mut().as_mut() {
+            app.resumed(event_loop);
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(app) = self.app.borrow_mut().as_mut() {
+            app.about_to_wait(event_loop);
+        }
+    }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
+        if let Some(app) = self.app.borrow_mut().as_mut() {
+            app.user_event(event_loop, event);
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        if let Some(app) = self.app.borrow_mut().as_mut() {
+            app.window_event(event_loop, window_id, event);
+        }
+    }
+
+    fn exiting(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(app) = self.app.borrow_mut().as_mut() {
+            app.exiting(event_loop);
+        }
+    }
 }
 
 impl AppState {
-    pub fn init() -> anyhow::Result<Self> {
-        let settings = settings::Settings::load().context("loading config from registry")?;
+    pub fn init(proxy: EventLoopProxy<UserEvent>, demo: bool) -> anyhow::Result<Self> {
+        let mut settings = settings::Settings::load().context("loading config from registry")?;
+        palette::set(settings.palette);
+        if demo {
+            info!("Running the demo");
+            demo::restrict(&mut settings);
+        }
 
-        let icon = Self::load_icon(Theme::Dark, 0, BatteryState::BatteryUnavailable)
-            .context("loading fallback disconnected icon")?;
+        // The last reading from the previous run is shown until the first poll is done,
+        // which can take a few seconds while headsetcontrol wakes up the dongle
+        let cached = settings
+            .selected_device
+            .as_ref()
+            .and_then(|id| settings.last_devices.iter().find(|d| d.id() == *id))
+            .or(settings.last_devices.first())
+            .filter(|_| !demo);
+        let mut icons = tray::IconCache::default();
+        let icon = match cached {
+            Some(device) => icons
+                .get::<tray::Native>(
+                    Theme::Dark,
+                    device.battery.level,
+                    device.battery.status,
+                    None,
+                )
+                .context("loading last known battery icon")?,
+            None => icons
+                .get::<tray::Native>(Theme::Dark, 0, BatteryState::BatteryUnavailable, None)
+                .context("loading fallback disconnected icon")?,
+        };
 
-        let context_menu = menu::ContextMenu::new(settings.notifications_enabled)
-            .context("creating context menu")?;
+        let context_menu = menu::ContextMenu::new(&settings).context("creating context menu")?;
 
-        let tray_icon = TrayIconBuilder::new()
+        let mut builder = TrayIconBuilder::new()
             .with_icon(icon)
-            .with_menu(Box::new(context_menu.menu.clone()))
-            .build()
-            .context("Failed to create tray icon")?;
+            .with_icon_as_template(tray::Native::TEMPLATE_ICONS)
+            .with_menu(Box::new(context_menu.menu.clone()));
+        if let Some(device) = cached {
+            builder = builder.with_tooltip(format!("{device} {}", lang::t(last_known)));
+        }
+        let showing_cached = cached.is_some();
+        let tray_icon = builder.build().context("Failed to create tray icon")?;
+        let icon_size = tray::Native::icon_size(&tray_icon);
+        if let Err(e) = icons.preload::<tray::Native>(icon_size) {
+            error!("Failed to preload tray icons: {e:?}");
+        }
+
+        let registry =
+            headset_control::Registry::new(&settings.disabled_backends, &settings.headsetcontrol);
+        let poller = poller::Poller::start(proxy.clone(), registry, demo.then(demo::Demo::start))
+            .context("starting poller")?;
+
+        let mut notifier = Notifier::new(settings.last_notification_state, proxy.clone())
+            .context("initializing notifier")?;
+        if demo {
+            notifier.set_min_interval(demo::NOTIFICATION_INTERVAL);
+        }
+        // Moved or uninstalled since it was chosen, polls fail until another one is chosen
+        if let Err(e) = settings.headsetcontrol.validate() {
+            warn!("The chosen headsetcontrol is missing: {e:?}");
+            if let Some(path) = &settings.headsetcontrol.path {
+                notify_headsetcontrol_missing(&mut notifier, path);
+            }
+        }
+
+        let api = settings.api_enabled.then(|| start_api(&settings)).flatten();
+
+        let grpc = settings
+            .grpc_enabled
+            .then(|| {
+                grpc::GrpcServer::start(settings.grpc_port, proxy.clone())
+                    .inspect_err(|e| error!("Failed to start gRPC service: {e:?}"))
+                    .ok()
+            })
+            .flatten();
+
+        let mqtt = settings.mqtt.clone().and_then(|config| {
+            mqtt::MqttPublisher::start(config)
+                .inspect_err(|e| error!("Failed to start MQTT publisher: {e:?}"))
+                .ok()
+        });
+
+        let discord = settings.discord.clone().and_then(|config| {
+            discord::DiscordPresence::start(config)
+                .inspect_err(|e| error!("Failed to start Discord presence: {e:?}"))
+                .ok()
+        });
+
+        let telegram = settings.telegram.clone().and_then(|config| {
+            telegram::TelegramBot::start(config)
+                .inspect_err(|e| error!("Failed to start Telegram bot: {e:?}"))
+                .ok()
+        });
+
+        let influx = settings.influx.clone().and_then(|config| {
+            influx::InfluxExporter::start(config)
+                .inspect_err(|e| error!("Failed to start InfluxDB export: {e:?}"))
+                .ok()
+        });
+
+        let status_file = settings
+            .status_file_enabled
+            .then(|| {
+                status_file::StatusFile::new()
+                    .inspect_err(|e| error!("Failed to set up status file: {e:?}"))
+                    .ok()
+            })
+            .flatten();
+
+        let history = settings
+            .history_enabled
+            .then(|| {
+                history::History::open(settings.history_retention)
+                    .inspect_err(|e| error!("Failed to set up battery history: {e:?}"))
+                    .ok()
+            })
+            .flatten();
 
-        let notifier = Notifier::new().context("initializing notifier")?;
+        // Picks up where the last run left off, so there's an estimate right away
+        let mut estimator = history::Estimator::default();
+        let mut charge_cycles = history::ChargeCycles::default();
+        let mut usage = history::Usage::default();
+        let mut health = history::BatteryHealth::default();
+        if let Some(history) = &history {
+            match history.read() {
+                Ok(samples) => {
+                    estimator.add(history::recent(&samples, history::RATE_WINDOW));
+                    charge_cycles.add(&samples);
+                    usage.add(&samples);
+                    health.add(&samples);
+                }
+                Err(e) => error!("Failed to read battery history: {e:?}"),
+            }
+        }
+
+        if settings.update_check
+            && let Err(e) = update::start(settings.last_update_check, proxy.clone())
+        {
+            error!("Failed to start update check: {e:?}");
+        }
+
+        let features = features::Native::start(&settings, proxy).context("starting features")?;
 
         Ok(Self {
             tray_icon,
             context_menu,
             settings,
             notifier,
+            events: events::EventBus::default(),
+            api,
+            grpc,
+            mqtt,
+            discord,
+            telegram,
+            influx,
+            status_file,
+            history,
+            estimator,
+            charge_cycles,
+            usage,
+            health,
+            unplug_reminder: notify::UnplugReminder::default(),
+            lights: lights::LightsKeeper::default(),
 
             devices: vec![],
-            last_update: Instant::now(),
-            should_update_icon: true,
+            poller,
+            icons,
+            shown_icon: None,
+            shown_tooltip: None,
+            empty_polls: 0,
+            backoff_jitter: 0.0,
+            showing_cached,
+            demo,
+            icon_size,
+            session_ended: false,
+
+            features,
         })
     }
 
-    fn update(&mut self, event_loop: &ActiveEventLoop) -> anyhow::Result<()> {
+    /// Saves the state and removes the tray icon right away, since the process can be ended as
+    /// soon as the window procedure that was told about the end of the session returns
+    fn end_session(&mut self) {
+        if self.session_ended {
+            return;
+        }
+        info!("Session ended, saving state");
+        self.persist(true);
+        if let Err(e) = self.tray_icon.set_visible(false) {
+            error!("Failed to remove tray icon: {e:?}");
+        }
+        self.session_ended = true;
+    }
+
+    /// Saves everything that should survive a restart. With `exiting` the app won't poll again,
+    /// otherwise it's only asked whether the session can end, which can still be cancelled.
+    fn persist(&mut self, exiting: bool) {
+        if self.demo {
+            return;
+        }
+        self.settings.last_notification_state = self.notifier.last_state();
+        // Headsets that are off keep their reading from when they were last on
+        let mut last_devices: Vec<headset_control::Device> = self
+            .devices
+            .iter()
+            .filter(|device| {
+                matches!(
+                    device.battery.status,
+                    BatteryState::BatteryAvailable | BatteryState::BatteryCharging
+                )
+            })
+            .cloned()
+            .collect();
+        for device in &self.settings.last_devices {
+            if !last_devices.iter().any(|last| last.id() == device.id()) {
+                last_devices.push(device.clone());
+            }
+        }
+        last_devices.truncate(MAX_LAST_DEVICES);
+        self.settings.last_devices = last_devices;
+        if let Some(device) = self.devices.get(self.context_menu.selected_device_idx) {
+            self.settings.selected_device = Some(device.id());
+        }
+        if let Some(history) = &mut self.history {
+            // The headsets count as off from now on, so the time the program isn't running
+            // isn't counted as usage. The devices are only forgotten when it really exits,
+            // otherwise the next poll would find them all added again.
+            if exiting {
+                history.record(&history::samples(&self.events.update(&[])));
+            }
+            history.flush();
+        }
+        self.features.persist();
+
+        if let Err(e) = self.settings.save() {
+            error!("Failed to save settings: {e:?}");
+        }
+    }
+
+    fn toggle_api(&mut self) {
+        self.settings.api_enabled = !self.settings.api_enabled;
+
+        // Dropping the server stops it
+        self.api = None;
+        if self.settings.api_enabled {
+            self.api = start_api(&self.settings);
+            match &self.api {
+                Some(api) => api.publish(&self.devices, self.context_menu.selected_device_idx, &[]),
+                None => self.settings.api_enabled = false,
+            }
+        }
+
+        self.context_menu
+            .menu_api
+            .set_checked(self.settings.api_enabled);
+        if let Err(e) = self.settings.save() {
+            error!("Failed to save settings: {e:?}");
+        }
+    }
+
+    /// Runs the headsetcontrol at `path` from now on, or the installed one when it's `None`.
+    /// A path that isn't there is only shown in a notification, and not saved.
+    fn set_headsetcontrol(&mut self, path: Option<std::path::PathBuf>) {
+        let config = headset_control::HeadsetControlConfig {
+            path,
+            ..self.settings.headsetcontrol.clone()
+        };
+        if let Err(e) = config.validate() {
+            error!("Not using the chosen headsetcontrol: {e:?}");
+            if let Some(path) = &config.path {
+                notify_headsetcontrol_missing(&mut self.notifier, path);
+            }
+            return;
+        }
+        info!("Using headsetcontrol at {:?}", config.path);
+        self.settings.headsetcontrol = config;
+        self.context_menu
+            .menu_reset_headsetcontrol
+            .set_enabled(self.settings.headsetcontrol.path.is_some());
+        self.poller.set_registry(headset_control::Registry::new(
+            &self.settings.disabled_backends,
+            &self.settings.headsetcontrol,
+        ));
+        if let Err(e) = self.settings.save() {
+            error!("Failed to save settings: {e:?}");
+        }
+    }
+
+    /// Turns the lights of the headsets on or off, when the menu or their battery asks for it
+    fn update_lights(&mut self) {
+        let changes = self.lights.update(
+            &self.devices,
+            self.settings.lights,
+            self.settings.lights_off_when_low,
+        );
+        for (device, on) in changes {
+            let control = headset_control::Control::Lights(on);
+            headset_control::apply(&self.settings.headsetcontrol, device, control);
+        }
+    }
+
+    fn toggle_lights(&mut self) {
+        let on = !self.settings.lights.unwrap_or(true);
+        self.settings.lights = Some(on);
+        self.context_menu.menu_lights.set_checked(on);
+        self.update_lights();
+        if let Err(e) = self.settings.save() {
+            error!("Failed to save settings: {e:?}");
+        }
+    }
+
+    fn toggle_lights_off_when_low(&mut self) {
+        self.settings.lights_off_when_low = !self.settings.lights_off_when_low;
+        self.context_menu
+            .menu_lights_off_when_low
+            .set_checked(self.settings.lights_off_when_low);
+        self.update_lights();
+        if let Err(e) = self.settings.save() {
+            error!("Failed to save settings: {e:?}");
+        }
+    }
+
+    /// Sets how long the selected headset waits before turning itself off when idle
+    fn set_inactive_time(&mut self, minutes: u8) {
+        // The click toggled the item, which stays as it was without a headset to set
+        self.context_menu
+            .set_inactive_time(self.settings.inactive_time);
+        let Some(device) = self.devices.get(self.context_menu.selected_device_idx) else {
+            return;
+        };
+        let control = headset_control::Control::InactiveTime(minutes);
+        headset_control::apply(&self.settings.headsetcontrol, device, control);
+
+        self.settings.inactive_time = Some(minutes);
+        self.context_menu.set_inactive_time(Some(minutes));
+        if let Err(e) = self.settings.save() {
+            error!("Failed to save settings: {e:?}");
+        }
+    }
+
+    fn set_notifications(&mut self, enabled: bool) {
+        self.settings.notifications_enabled = enabled;
+        self.context_menu
+            .menu_notifications
+            .set_checked(self.settings.notifications_enabled);
+        if let Err(e) = self.settings.save() {
+            error!("Failed to save settings: {e:?}");
+        }
+
+        if self.settings.notifications_enabled {
+            let msg = lang::t(notifications_enabled_message);
+            if let Err(err) = self
+                .notifier
+                .show_notification("Headset Battery Indicator", msg)
+            {
+                error!("Failed to show notification: {:?}", err);
+            }
+        }
+    }
+
+    /// Polls the devices now, the tray is updated when the snapshot arrives
+    fn refresh(&mut self) {
+        self.poller.request();
+        self.schedule_polling();
+    }
+
+    /// Polls right away without reusing the last poll, and restarts the backoff
+    fn poll_now(&mut self) {
+        self.empty_polls = 0;
+        self.poller.request_now();
+        self.schedule_polling();
+    }
+
+    /// Shows the newest snapshot from the poller
+    fn devices_polled(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(snapshot) = self.poller.latest() else {
+            return;
+        };
+        // A poll that fails, like when headsetcontrol isn't installed, backs off too
+        if snapshot
+            .devices
+            .as_ref()
+            .is_ok_and(|devices| !devices.is_empty())
+        {
+            self.empty_polls = 0;
+        } else if !snapshot.cached {
+            self.empty_polls = self.empty_polls.saturating_add(1);
+            self.backoff_jitter = random_fraction() * MAX_BACKOFF_JITTER;
+        }
+        if let Err(e) = self.update(event_loop, snapshot) {
+            error!("Failed to update status: {e:?}");
+        };
+        // The interval depends on the batteries, and on whether a headset was found
+        self.schedule_polling();
+    }
+
+    /// Asks where to save the history of the selected headset, or of all headsets, and saves it
+    fn export_history(&mut self, range: export::Range) {
+        let Some(history) = &mut self.history else {
+            return;
+        };
+        // The samples not written yet are exported too
+        history.flush();
+        let samples = match history.read() {
+            Ok(samples) => samples,
+            Err(e) => {
+                error!("Failed to read battery history: {e:?}");
+                return;
+            }
+        };
+
+        let device = self
+            .context_menu
+            .export_selected_only()
+            .then(|| self.devices.get(self.context_menu.selected_device_idx))
+            .flatten();
+        let samples = export::select(&samples, range, device.map(|d| d.id()).as_deref());
+
+        let (path, format) = match export::ask_path(&export::file_name(device)) {
+            Ok(Some(choice)) => choice,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Failed to ask where to export the history: {e:?}");
+                return;
+            }
+        };
+        match export::write(&path, format, &samples) {
+            Ok(()) => info!("Exported {} samples to {}", samples.len(), path.display()),
+            Err(e) => error!("Failed to export battery history: {e:?}"),
+        }
+    }
+
+    /// Polls the devices at the interval that fits them now
+    fn schedule_polling(&mut self) {
+        let interval = self.poll_interval();
+        self.features.schedule_polling(interval);
+    }
+
+    fn update(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        snapshot: poller::DeviceSnapshot,
+    ) -> anyhow::Result<()> {
         let old_device_count = self.devices.len();
-        headset_control::query_devices(&mut self.devices)?;
+        self.features.record_poll(snapshot.devices.is_ok());
+        // Without headsetcontrol there's nothing to poll until it's installed
+        let setup_needed = snapshot
+            .devices
+            .as_ref()
+            .is_err_and(headset_control::is_missing);
+        self.devices = match snapshot.devices {
+            Err(e) if setup_needed => {
+                debug!("Setup needed: {e:?}");
+                Vec::new()
+            }
+            devices => devices?,
+        };
+        self.context_menu
+            .show_setup(setup_needed)
+            .context("Updating setup menu item")?;
+        if let Some(recording) = self.features.microphone_in_use(&self.settings)
+            && recording != self.notifier.is_quiet()
+        {
+            if recording {
+                info!("Microphone in use, holding back the notifications and sounds");
+            } else {
+                info!("Microphone no longer in use, resuming the notifications and sounds");
+            }
+            self.notifier.set_quiet(recording);
+        }
 
         if self.devices.len() != old_device_count {
             self.context_menu
                 .update_device_menu(&self.devices)
                 .context("Updating context menu")?;
+            // The headset that was selected on the last run, once it's found again
+            if let Some(idx) = self
+                .settings
+                .selected_device
+                .as_ref()
+                .and_then(|id| self.devices.iter().position(|device| device.id() == *id))
+            {
+                self.context_menu.set_selected(idx);
+                self.settings.selected_device = None;
+            }
+        } else {
+            self.context_menu
+                .update_device_labels(&self.devices)
+                .context("Updating device labels")?;
         }
 
+        let app_events = self.events.update(&self.devices);
+        let samples = history::samples(&app_events);
+        if let Some(history) = &mut self.history {
+            history.record(&samples);
+        }
+        if let Some(influx) = &self.influx {
+            influx.write(&samples);
+        }
+        self.estimator.add(&samples);
+        self.charge_cycles.add(&samples);
+        self.usage.add(&samples);
+        for (id, previous, health) in self.health.add(&samples) {
+            info!("Battery health of {id} is about {health}%");
+            // Only once when it drops below the threshold, not after every discharge
+            let threshold = self.settings.health_warning;
+            let dropped =
+                health < threshold && previous.is_none_or(|previous| previous >= threshold);
+            if dropped
+                && self.settings.notifications_enabled
+                && let Some(device) = self.devices.iter().find(|device| device.id() == id)
+            {
+                let msg = lang::t(battery_health_low).replace("{health}", &health.to_string());
+                if let Err(err) = self.notifier.show_notification(&device.product, &msg) {
+                    error!("Failed to show notification: {:?}", err);
+                }
+            }
+        }
+        if let Some(after) = self.settings.unplug_reminder
+            && self.settings.notifications_enabled
+            && !self.notifier.is_muted()
+            // Waits for the call or recording to end rather than being left out
+            && !self.notifier.is_quiet()
+        {
+            let msg = lang::t(unplug_reminder)
+                .replace("{time}", &history::format_minutes(after.as_secs() / 60));
+            for device in self
+                .unplug_reminder
+                .update(&self.devices, after, Instant::now())
+            {
+                info!("{} has been charging at 100% for {after:?}", device.product);
+                if let Err(err) = self.notifier.show_notification(&device.product, &msg) {
+                    error!("Failed to show notification: {:?}", err);
+                }
+            }
+        }
+        self.update_lights();
+        self.features.add(&self.devices, &samples);
+        self.show_weekly_summary();
+
+        let details: Vec<String> = self
+            .devices
+            .iter()
+            .flat_map(|device| self.device_details(device))
+            .collect();
+        self.context_menu
+            .update_device_details(&details)
+            .context("Updating device details")?;
+
+        let battery_events = actions::update(&app_events, &self.settings.actions);
+        for (event, device) in &battery_events {
+            if let Some(script) = self.settings.scripts.get(event) {
+                scripts::run(script, *event, device);
+            }
+            if let Some(webhook) = self.settings.webhooks.get(event) {
+                webhook::send(webhook, *event, device);
+            }
+            push::send(&self.settings.push, *event, device);
+            if let Some(telegram) = &self.telegram {
+                telegram.alert(*event, device);
+            }
+            let quiet = self.notifier.is_muted() || self.notifier.is_quiet();
+            self.features.alert(*event, device, quiet);
+        }
+
+        if let Some(api) = &self.api {
+            api.publish(
+                &self.devices,
+                self.context_menu.selected_device_idx,
+                &app_events,
+            );
+        }
+        if let Some(grpc) = &self.grpc {
+            grpc.publish(
+                &self.devices,
+                self.context_menu.selected_device_idx,
+                self.settings.notifications_enabled,
+            );
+        }
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.publish(&self.devices);
+        }
+        if let Some(telegram) = &self.telegram {
+            telegram.publish(&self.devices);
+        }
+        if let Some(status_file) = &mut self.status_file {
+            status_file.publish(&self.devices, self.context_menu.selected_device_idx);
+        }
+        if let Some(discord) = &self.discord {
+            let selected = self.context_menu.selected_device_idx;
+            discord.publish(self.devices.get(selected));
+        }
+        self.features
+            .publish(&self.devices, self.context_menu.selected_device_idx);
+
         if self.devices.is_empty() {
-            self.tray_icon
-                .set_tooltip(Some(lang::t(no_adapter_found)))?;
+            let tooltip = if setup_needed {
+                lang::t(headsetcontrol_missing)
+            } else if tray::Native::is_remote_session() {
+                lang::t(no_adapter_remote_session)
+            } else if sandbox::Native::usb_access_denied() {
+                lang::t(no_adapter_usb_permission)
+            } else {
+                lang::t(no_adapter_found)
+            };
+            self.set_tooltip(tooltip)?;
+            if self.showing_cached || setup_needed {
+                let theme = event_loop.system_theme().unwrap_or(Theme::Dark);
+                self.set_icon(theme, 0, BatteryState::BatteryUnavailable)?;
+                self.showing_cached = false;
+            }
             return Ok(());
         }
 
@@ -103,6 +935,7 @@ impl AppState {
         let battery_level;
         let battery_status;
         let product_name;
+        let estimate;
         let tooltip_text;
 
         {
@@ -110,9 +943,18 @@ impl AppState {
             battery_level = device.battery.level;
             battery_status = device.battery.status;
             product_name = device.product.clone();
+            estimate = self.estimator.estimate(device);
 
-            #[allow(unused_mut)]
             let mut text = device.to_string();
+            if let Some(trend) = self.estimator.trend(device) {
+                text += &format!(" {trend}");
+            }
+            if let Some(estimate) = estimate {
+                text += &format!("\n{estimate}");
+            }
+            if let Some(mix) = device.chatmix_text() {
+                text += &format!("\n{mix}");
+            }
 
             #[cfg(debug_assertions)]
             {
@@ -123,185 +965,381 @@ impl AppState {
         }
 
         self.notifier
-            .update(battery_level, battery_status, &product_name);
+            .update(battery_level, battery_status, &product_name, estimate);
 
-        self.tray_icon
-            .set_tooltip(Some(&tooltip_text))
+        self.set_tooltip(&tooltip_text)
             .with_context(|| format!("setting tooltip text: {tooltip_text}"))?;
 
-        match Self::load_icon(
-            event_loop.system_theme().unwrap_or(Theme::Dark),
-            battery_level,
+        let theme = event_loop.system_theme().unwrap_or(Theme::Dark);
+        if let Err(err) = self.set_icon(theme, battery_level, battery_status) {
+            error!("Failed to set icon: {err:?}");
+        }
+
+        self.showing_cached = false;
+
+        Ok(())
+    }
+
+    /// Loads and sets the icon, unless the tray already shows the same one
+    fn set_icon(
+        &mut self,
+        theme: Theme,
+        battery_level: isize,
+        battery_status: BatteryState,
+    ) -> anyhow::Result<()> {
+        let shown = (
+            theme,
+            tray::Native::icon_level(battery_level),
             battery_status,
-        ) {
-            Ok(icon) => self.tray_icon.set_icon(Some(icon))?,
-            Err(err) => error!("Failed to load icon: {err:?}"),
+            self.icon_size,
+        );
+        if self.shown_icon == Some(shown) {
+            return Ok(());
         }
 
-        self.should_update_icon = false;
+        let icon =
+            self.icons
+                .get::<tray::Native>(theme, battery_level, battery_status, self.icon_size)?;
+        self.tray_icon
+            .set_icon_with_as_template(Some(icon), tray::Native::TEMPLATE_ICONS)?;
+        self.shown_icon = Some(shown);
+        Ok(())
+    }
 
+    /// Sets the tooltip, unless it's unchanged
+    fn set_tooltip(&mut self, tooltip: &str) -> anyhow::Result<()> {
+        if self.shown_tooltip.as_deref() == Some(tooltip) {
+            return Ok(());
+        }
+
+        self.tray_icon.set_tooltip(Some(tooltip))?;
+        self.shown_tooltip = Some(tooltip.to_string());
         Ok(())
     }
 
-    fn load_icon(
-        theme: winit::window::Theme,
-        battery_percent: isize,
-        state: BatteryState,
-    ) -> anyhow::Result<tray_icon::Icon> {
-        let res_id = battery_res_id_for(theme, battery_percent, state);
+    /// Shows the summary of last week for every headset, once on Monday morning or later that week
+    fn show_weekly_summary(&mut self) {
+        if !self.settings.weekly_summary || !self.settings.notifications_enabled {
+            return;
+        }
+        let Some(history) = &mut self.history else {
+            return;
+        };
+        let Some(monday) = history::summary_due(self.settings.last_weekly_summary) else {
+            return;
+        };
+        // Not tried again when reading the history fails, it would fail on every poll
+        self.settings.last_weekly_summary = Some(monday);
+        if let Err(e) = self.settings.save() {
+            error!("Failed to save settings: {e:?}");
+        }
 
-        tray_icon::Icon::from_resource(res_id, None)
-            .with_context(|| format!("loading icon from resource {res_id}"))
+        history.flush();
+        let samples = match history.read() {
+            Ok(samples) => samples,
+            Err(e) => {
+                error!("Failed to read battery history: {e:?}");
+                return;
+            }
+        };
+        for (id, summary) in history::week_summaries(&samples, monday) {
+            info!("Weekly summary of {id}: {summary:?}");
+            let name = match self.devices.iter().find(|device| device.id() == id) {
+                Some(device) => device.product.clone(),
+                None => id,
+            };
+            if let Err(err) = self.notifier.show_notification(&name, &summary.to_string()) {
+                error!("Failed to show notification: {:?}", err);
+            }
+        }
     }
-}
 
-impl ApplicationHandler<()> for AppState {
-    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        // Kick off polling every 1 second
-        event_loop.set_control_flow(ControlFlow::WaitUntil(
-            Instant::now() + Duration::from_secs(1),
-        ));
+    /// Shows a newer release in the menu, and notifies about it once
+    fn update_checked(&mut self, tag: String) {
+        self.settings.last_update_check = Some(history::now());
+        let seen = self.settings.latest_release.as_ref() == Some(&tag);
+        if update::is_newer(&tag) {
+            info!("Update available: {tag}");
+            self.context_menu.show_update(&tag);
+            if !seen && self.settings.notifications_enabled {
+                let body = lang::t(update_available_message)
+                    .replace("{version}", &update::version_label(&tag));
+                if let Err(err) = self.notifier.show_link(
+                    "Headset Battery Indicator",
+                    &body,
+                    update::release_url(&tag),
+                ) {
+                    error!("Failed to show notification: {:?}", err);
+                }
+            }
+        }
+        self.settings.latest_release = Some(tag);
+        if let Err(e) = self.settings.save() {
+            error!("Failed to save settings: {e:?}");
+        }
     }
-    fn new_events(&mut self, event_loop: &ActiveEventLoop, cause: winit::event::StartCause) {
-        if let StartCause::ResumeTimeReached { .. } = cause {
-            // Overwrite the current polling time
-            //
-            // If not overwritten, it starts polling multiple times a second
-            // since the timer is already elapsed.
-            event_loop.set_control_flow(ControlFlow::WaitUntil(
-                Instant::now() + Duration::from_secs(1),
-            ));
+
+    /// The lines about the device under "Device details" in the tray menu
+    fn device_details(&self, device: &headset_control::Device) -> Vec<String> {
+        let (sessions, cycles) = self.charge_cycles.get(device).unwrap_or_default();
+        let usage = self.usage.get(device).unwrap_or_default();
+        let hours = |template, (on, charging): (f32, f32)| {
+            lang::t(template)
+                .replace("{on}", &format!("{on:.1}"))
+                .replace("{charging}", &format!("{charging:.1}"))
+        };
+
+        let mut lines = vec![
+            lang::t(charge_cycle_count)
+                .replace("{cycles}", &format!("{cycles:.0}"))
+                .replace("{sessions}", &sessions.to_string()),
+            hours(usage_today, usage.today),
+            hours(usage_this_week, usage.week),
+        ];
+        if !device.capabilities.is_empty() {
+            lines.push(
+                lang::t(device_features).replace("{features}", &device.capabilities.labels()),
+            );
         }
+        if let Some(health) = self.health.get(device) {
+            lines.push(lang::t(battery_health).replace("{health}", &health.to_string()));
+        }
+        lines.extend(self.features.device_details(device));
+        lines
+            .into_iter()
+            .map(|line| format!("{}: {line}", device.product))
+            .collect()
     }
 
-    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
-        // This will be called at least every second
-        if self.last_update.elapsed() > Duration::from_millis(1000) {
-            if let Err(e) = self.update(event_loop) {
-                error!("Failed to update status: {e:?}");
+    /// Often while a battery is charging or low, rarely while they're all fine, and less and
+    /// less often while no headset is found. Clicks in the menu still poll right away.
+    fn poll_interval(&self) -> Duration {
+        if self.demo {
+            return demo::POLL_INTERVAL;
+        }
+        if self.devices.is_empty() {
+            let first = if tray::Native::is_remote_session() {
+                REMOTE_SESSION_POLL_INTERVAL
+            } else {
+                NO_DEVICE_POLL_INTERVAL
             };
-            self.last_update = Instant::now();
-        }
-        if let Ok(event) = MenuEvent::receiver().try_recv() {
-            match event.id {
-                id if id == self.context_menu.menu_notifications.id() => {
-                    self.settings.notifications_enabled = !self.settings.notifications_enabled;
-                    self.context_menu
-                        .menu_notifications
-                        .set_checked(self.settings.notifications_enabled);
-                    if let Err(e) = self.settings.save() {
-                        error!("Failed to save settings: {e:?}");
-                    }
+            let backoff = 2u32.saturating_pow(self.empty_polls.saturating_sub(1));
+            return first
+                .saturating_mul(backoff)
+                .min(MAX_NO_DEVICE_POLL_INTERVAL)
+                .mul_f64(1.0 + self.backoff_jitter);
+        }
 
-                    if self.settings.notifications_enabled {
-                        let msg = lang::t(notifications_enabled_message);
-                        if let Err(err) = self
-                            .notifier
-                            .show_notification("Headset Battery Indicator", msg)
-                        {
-                            error!("Failed to show notification: {:?}", err);
-                        }
-                    }
+        self.devices
+            .iter()
+            .map(|device| match device.battery.status {
+                BatteryState::BatteryCharging => FAST_POLL_INTERVAL,
+                BatteryState::BatteryAvailable if device.battery.level < LOW_POLL_LEVEL => {
+                    FAST_POLL_INTERVAL
                 }
+                BatteryState::BatteryAvailable if device.battery.level > HIGH_POLL_LEVEL => {
+                    SLOW_POLL_INTERVAL
+                }
+                _ => POLL_INTERVAL,
+            })
+            .min()
+            .unwrap_or(POLL_INTERVAL)
+    }
 
-                id if id == self.context_menu.menu_trigger_notification.id() => {
-                    #[cfg(debug_assertions)]
-                    {
-                        self.notifier
-                            .show_notification("Test Device", "Battery critical (50%)")
-                            .expect("Sending test notification");
-                    }
+    /// Handles a click on the tray menu
+    fn menu_event(&mut self, event_loop: &ActiveEventLoop, event: MenuEvent) {
+        if features::Native::menu_event(self, event_loop, &event.id) {
+            return;
+        }
+        match event.id {
+            id if id == self.context_menu.menu_notifications.id() => {
+                self.set_notifications(!self.settings.notifications_enabled);
+            }
+
+            id if id == self.context_menu.menu_api.id() => self.toggle_api(),
+
+            id if id == self.context_menu.menu_choose_headsetcontrol.id() => {
+                match picker::ask_headsetcontrol() {
+                    Ok(Some(path)) => self.set_headsetcontrol(Some(path)),
+                    Ok(None) => {}
+                    Err(e) => error!("Failed to ask for the headsetcontrol executable: {e:?}"),
+                }
+            }
+
+            id if id == self.context_menu.menu_lights.id() => self.toggle_lights(),
+
+            id if id == self.context_menu.menu_lights_off_when_low.id() => {
+                self.toggle_lights_off_when_low();
+            }
+
+            id if id == self.context_menu.menu_reset_headsetcontrol.id() => {
+                self.set_headsetcontrol(None);
+            }
+
+            id if id == self.context_menu.menu_trigger_notification.id() => {
+                #[cfg(debug_assertions)]
+                {
+                    self.notifier
+                        .show_notification("Test Device", "Battery critical (50%)")
+                        .expect("Sending test notification");
+                }
+            }
+
+            _ => {
+                if let Some(range) = self.context_menu.export_range(&event.id) {
+                    self.export_history(range);
+                } else if let Some(minutes) = self.context_menu.inactive_time(&event.id) {
+                    self.set_inactive_time(minutes);
+                } else if let Some((device, control)) = self.context_menu.control(&event.id) {
+                    headset_control::apply(&self.settings.headsetcontrol, device, control);
+                } else {
+                    self.context_menu.handle_event(event, event_loop);
                 }
+            }
+        }
+    }
+}
 
-                _ => self.context_menu.handle_event(event, event_loop),
+/// winit runs the event loop on every platform, and opens the battery history window on
+/// Windows. A bare Win32 message loop wouldn't save wakeups there: the loop only waits for
+/// messages, and polling is driven by the message window's coalescable timer.
+impl ApplicationHandler<UserEvent> for AppState {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        // The loop only wakes up for messages, polling is driven by the
+        // poll timer which is started by the first refresh
+        event_loop.set_control_flow(ControlFlow::Wait);
+        self.refresh();
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if self.features.about_to_wait() {
+            self.refresh();
+        }
+        self.features.wait(event_loop);
+    }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
+        match event {
+            UserEvent::Menu(event) => self.menu_event(event_loop, event),
+            UserEvent::SessionEnding => self.persist(false),
+            UserEvent::SessionEnded => event_loop.exit(),
+            UserEvent::ThemeChanged => {
+                info!("System theme changed");
+                if let Err(err) = tray::Native::refresh_menu_theme() {
+                    warn!("Failed to refresh menu theme: {:?}", err);
+                }
+                // Swap to the icons matching the new theme right away
+                self.refresh();
+            }
+            UserEvent::PollTimer | UserEvent::Refresh => self.refresh(),
+            UserEvent::DevicesPolled => self.devices_polled(event_loop),
+            UserEvent::SetNotifications(enabled) => {
+                if enabled != self.settings.notifications_enabled {
+                    self.set_notifications(enabled);
+                }
+            }
+            UserEvent::SelectDevice(id) => {
+                if let Some(idx) = self.devices.iter().position(|d| d.id() == id) {
+                    self.context_menu.set_selected(idx);
+                    self.refresh();
+                }
+            }
+            UserEvent::NotificationActivated => {
+                if let Some(url) = self.notifier.take_link() {
+                    info!("Notification clicked, opening {url}");
+                    menu::open_url(&url);
+                    return;
+                }
+                info!("Notification clicked, showing the tray menu");
+                // The menu shows the last poll, and is updated when the next one is done
+                self.refresh();
+                tray::Native::show_menu(&self.tray_icon, &self.context_menu.menu);
+            }
+            UserEvent::UpdateChecked(tag) => self.update_checked(tag),
+            UserEvent::Platform(event) => features::Native::handle_event(self, event_loop, event),
+            UserEvent::DevicesChanged => {
+                // A dongle that was just plugged in shouldn't wait for the backoff
+                debug!("Devices changed, polling now");
+                self.poll_now();
+            }
+            UserEvent::DisplayChanged => {
+                let icon_size = tray::Native::icon_size(&self.tray_icon);
+                if icon_size != self.icon_size {
+                    info!("Tray icon size changed to {icon_size:?}");
+                    self.icon_size = icon_size;
+                    if let Err(e) = self.icons.preload::<tray::Native>(icon_size) {
+                        error!("Failed to preload tray icons: {e:?}");
+                    }
+                    self.refresh();
+                }
+            }
+            UserEvent::TaskbarCreated => {
+                // Re-add the icon in case it wasn't restored, then bring it up to date
+                let readd = self
+                    .tray_icon
+                    .set_visible(false)
+                    .and_then(|_| self.tray_icon.set_visible(true));
+                if let Err(e) = readd {
+                    error!("Failed to re-add tray icon: {e:?}");
+                }
+                self.shown_icon = None;
+                self.shown_tooltip = None;
+                self.refresh();
             }
         }
     }
+
     fn window_event(
         &mut self,
         _event_loop: &ActiveEventLoop,
-        _window_id: winit::window::WindowId,
-        _event: WindowEvent,
+        window_id: winit::window::WindowId,
+        event: WindowEvent,
     ) {
-        // Since we don't have a window attached, this will never be called
+        self.features.window_event(window_id, &event);
     }
 
     fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
         info!("Exiting application..");
+        if !self.session_ended {
+            self.persist(true);
+        }
     }
 }
 
-// Enable dark mode support on Windows 10/11
+/// Between 0 and 1, good enough for jitter without a random number crate
+fn random_fraction() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
 
-#[cfg(windows)]
-#[repr(C)]
-#[allow(dead_code)]
-enum PreferredAppMode {
-    Default = 0,
-    AllowDark = 1,
-    ForceDark = 2,
-    ForceLight = 3,
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    random as f64 / u64::MAX as f64
 }
 
-#[cfg(windows)]
-type SetPreferredAppModeFn = unsafe extern "system" fn(PreferredAppMode) -> i32;
-
-#[cfg(windows)]
-fn enable_dark_mode_support() -> Result<()> {
-    unsafe {
-        // Load uxtheme.dll
-
-        use windows::{
-            Win32::{
-                Foundation::HMODULE,
-                System::LibraryLoader::{GetProcAddress, LoadLibraryA},
-            },
-            core::PCSTR,
-        };
-        let module: HMODULE =
-            LoadLibraryA(windows::core::s!("uxtheme.dll")).context("loading uxtheme.dll")?;
-
-        // SetPreferredAppMode is ordinal 135 in uxtheme.dll
-        let ordinal = 135u16;
-        let proc = GetProcAddress(module, PCSTR::from_raw(ordinal as *const u8))
-            .context("Failed to get proc address")?;
-
-        let set_preferred_app_mode: SetPreferredAppModeFn = std::mem::transmute(proc);
-        set_preferred_app_mode(PreferredAppMode::AllowDark);
-
-        Ok(())
+fn notify_headsetcontrol_missing(notifier: &mut Notifier, path: &std::path::Path) {
+    let msg = lang::t(headsetcontrol_not_found).replace("{path}", &path.display().to_string());
+    if let Err(err) = notifier.show_notification("Headset Battery Indicator", &msg) {
+        error!("Failed to show notification: {:?}", err);
     }
 }
 
-fn battery_res_id_for(theme: Theme, battery_percent: isize, state: BatteryState) -> u16 {
-    let level = match battery_percent {
-        -1 => 1,
-        0..=12 => 1,  // 0%
-        13..=37 => 2, // 25%
-        38..=62 => 3, // 50%
-        63..=87 => 4, // 75%
-        _ => 5,       // 100%
-    };
-
-    // light mode icons are (10,20,...,50)
-    // dark mode icons are (15,25,...,55)
-    let theme_offset: u16 = if theme == Theme::Light { 5 } else { 0 };
-    // Charging icons are at icon id + 1
-    let charging_offset = (state == BatteryState::BatteryCharging) as u16;
-
-    if state == BatteryState::BatteryUnavailable {
-        10 + theme_offset
-    } else {
-        level * 10 + theme_offset + charging_offset
-    }
+fn start_api(settings: &settings::Settings) -> Option<api::ApiServer> {
+    api::ApiServer::start(
+        (settings.api_bind_address, settings.api_port).into(),
+        settings.api_token.clone(),
+        settings.api_allowed_origins.clone(),
+        settings.api_mdns,
+    )
+    .inspect_err(|e| error!("Failed to start local API: {e:?}"))
+    .ok()
 }
 
 #[test]
 fn load_all_icons() {
     for i in 0..=100 {
-        let _ = AppState::load_icon(Theme::Dark, i, BatteryState::BatteryAvailable);
+        let _ = tray::Native::load_icon(Theme::Dark, i, BatteryState::BatteryAvailable, None);
     }
     for i in 0..=100 {
-        let _ = AppState::load_icon(Theme::Light, i, BatteryState::BatteryAvailable);
+        let _ = tray::Native::load_icon(Theme::Light, i, BatteryState::BatteryAvailable, None);
     }
 }
@@ -0,0 +1,53 @@
+//! Keeps the lights of the headsets the way they're set in the tray menu, and turns them off
+//! while the battery is low when that's enabled, since the lights drain it faster
+
+use std::collections::HashMap;
+
+use crate::headset_control::{BatteryState, Capabilities, Device};
+
+/// The lights are turned off below this level, and back on once it's charging
+pub const LIGHTS_OFF_BELOW: isize = 20;
+
+#[derive(Default)]
+pub struct LightsKeeper {
+    /// Whether the lights of each headset were last turned on or off
+    set: HashMap<String, bool>,
+}
+
+impl LightsKeeper {
+    /// The headsets whose lights have to be turned on or off. `on` is the setting from the menu,
+    /// the lights of a headset are left alone while it's `None` and the battery isn't low.
+    pub fn update<'a>(
+        &mut self,
+        devices: &'a [Device],
+        on: Option<bool>,
+        off_when_low: bool,
+    ) -> Vec<(&'a Device, bool)> {
+        self.set
+            .retain(|id, _| devices.iter().any(|device| device.id() == *id));
+        let mut changes = Vec::new();
+        for device in devices
+            .iter()
+            .filter(|device| device.capabilities.contains(Capabilities::LIGHTS))
+        {
+            let low = match device.battery.status {
+                BatteryState::BatteryAvailable => device.battery.level < LIGHTS_OFF_BELOW,
+                BatteryState::BatteryCharging => false,
+                // A headset that drops out for a moment keeps its lights as they are
+                _ => continue,
+            };
+            let id = device.id();
+            let wanted = match on {
+                _ if low && off_when_low => false,
+                Some(on) => on,
+                // Back on after they were turned off for the low battery
+                None if self.set.get(&id) == Some(&false) => true,
+                None => continue,
+            };
+            if self.set.insert(id, wanted) != Some(wanted) {
+                changes.push((device, wanted));
+            }
+        }
+        changes
+    }
+}
@@ -2,15 +2,56 @@
 
 use std::fs::File;
 
-use headset_battery_indicator::run;
+use headset_battery_indicator::{
+    AUTOSTART_ARG, BENCH_POLLS_ARG, DEMO_ARG, JSON_ARG, ONCE_ARG, QUERY_ARG, bench_polls, once,
+    query, run, sandbox_log_dir,
+};
 use log::error;
 use simplelog::{ConfigBuilder, WriteLogger};
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    // Print the devices instead of starting the tray app, either from the
+    // running instance (--query) or by querying them directly (--once),
+    // or measure how long querying them takes (--bench-polls N)
+    let json = args.iter().any(|arg| arg == JSON_ARG);
+    let result = if args.iter().any(|arg| arg == QUERY_ARG) {
+        Some(query(json))
+    } else if args.iter().any(|arg| arg == ONCE_ARG) {
+        Some(once(json))
+    } else {
+        args.iter()
+            .position(|arg| arg == BENCH_POLLS_ARG)
+            .map(|i| bench_polls(args.get(i + 1).map(String::as_str)))
+    };
+    if let Some(result) = result {
+        if let Err(e) = result {
+            eprintln!("{e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Autostart entries may start the program in some other directory (e.g. System32),
+    // but the log file is expected to be next to the executable
+    if args.iter().any(|arg| arg == AUTOSTART_ARG)
+        && let Ok(exe) = std::env::current_exe()
+        && let Some(dir) = exe.parent()
+    {
+        let _ = std::env::set_current_dir(dir);
+    }
+    // "View logs" opens the current directory too
+    if let Some(dir) = sandbox_log_dir() {
+        let _ = std::env::set_current_dir(dir);
+    }
+
     // Cannot really log anything if initializing logging fails
     let _ = init_file_logger();
 
-    if let Err(e) = run() {
+    // A made-up headset for screenshots, instead of the real ones
+    let demo = args.iter().any(|arg| arg == DEMO_ARG);
+    if let Err(e) = run(demo) {
         error!("Application stopped unexpectedly: {e:?}");
     }
 }
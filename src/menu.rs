@@ -1,27 +1,77 @@
 use anyhow::Context;
 use log::error;
-use tray_icon::menu::MenuEvent;
-use tray_icon::menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem};
+use tray_icon::menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu};
+use tray_icon::menu::{MenuEvent, MenuId};
 use winit::event_loop;
 
-use crate::headset_control;
+use crate::export;
+use crate::headset_control::{self, Capabilities, Control};
 use crate::lang;
 use crate::lang::Key::*;
+use crate::lights;
+use crate::platform::autostart::{self, Autostart};
+use crate::settings::Settings;
+use crate::update;
+
+/// Where to get headsetcontrol when it isn't installed
+const HEADSETCONTROL_RELEASES: &str = "https://github.com/Sapd/HeadsetControl/releases";
+
+/// Opens folders and links in the file manager or the browser
+const OPENER: &str = if cfg!(windows) {
+    "explorer"
+} else if cfg!(target_os = "macos") {
+    "open"
+} else {
+    "xdg-open"
+};
 
 pub struct ContextMenu {
     pub menu: Menu,
+    /// Shown at the top while headsetcontrol isn't installed
+    menu_setup: MenuItem,
+    setup_shown: bool,
     device_menu_items: Vec<(headset_control::Device, CheckMenuItem)>,
     pub selected_device_idx: usize,
     separators: Option<(PredefinedMenuItem, PredefinedMenuItem)>, // (top, bottom)
+    /// Shown below the devices, with a line of details for each of them
+    menu_details: Submenu,
+    detail_items: Vec<MenuItem>,
+    /// A submenu for each setting headsetcontrol can change, shown below the details for the
+    /// ones the selected headset has
+    control_menus: Vec<(Capabilities, Submenu)>,
+    control_items: Vec<(Control, MenuItem)>,
+    shown_controls: Capabilities,
+    /// In the lights submenu, kept for every headset rather than set once
+    pub menu_lights: CheckMenuItem,
+    pub menu_lights_off_when_low: CheckMenuItem,
+    /// In the inactive time submenu, with the minutes of each
+    inactive_time_items: Vec<(u8, CheckMenuItem)>,
     pub menu_notifications: CheckMenuItem,
+    pub menu_api: CheckMenuItem,
+    pub menu_choose_headsetcontrol: MenuItem,
+    /// Only enabled while another executable was chosen
+    pub menu_reset_headsetcontrol: MenuItem,
+    autostart_items: Vec<(autostart::Native, CheckMenuItem)>,
+    /// The battery history window is only drawn with GDI
+    #[cfg(windows)]
+    pub menu_history_graph: MenuItem,
+    export_items: Vec<(export::Range, MenuItem)>,
+    menu_export_selected_only: CheckMenuItem,
     menu_logs: MenuItem,
     menu_github: MenuItem,
+    /// The releases, or the page of the newer release once there is one
+    github_url: String,
+    /// Shown below "Update available" when the executable can replace itself
+    #[cfg(windows)]
+    pub menu_install_update: MenuItem,
+    #[cfg(windows)]
+    install_update_shown: bool,
     menu_close: MenuItem,
     pub menu_trigger_notification: MenuItem,
 }
 
 impl ContextMenu {
-    pub fn new(notifications_enabled: bool) -> anyhow::Result<Self> {
+    pub fn new(settings: &Settings) -> anyhow::Result<Self> {
         let menu = Menu::new();
 
         menu.append(&MenuItem::new(
@@ -30,13 +80,112 @@ impl ContextMenu {
             None,
         ))?;
 
+        let menu_setup = MenuItem::new(lang::t(get_headsetcontrol), true, None);
         let device_menu_items = Vec::new();
+        let menu_details = Submenu::new(lang::t(details_submenu), true);
 
-        let menu_notifications =
-            CheckMenuItem::new(lang::t(show_notifications), true, notifications_enabled, None);
+        let menu_lights = CheckMenuItem::new(
+            lang::t(control_on),
+            true,
+            settings.lights.unwrap_or(true),
+            None,
+        );
+        let menu_lights_off_when_low = CheckMenuItem::new(
+            lang::t(lights_off_when_low).replace("{level}", &lights::LIGHTS_OFF_BELOW.to_string()),
+            true,
+            settings.lights_off_when_low,
+            None,
+        );
+        let inactive_time_items: Vec<_> = INACTIVE_TIMES
+            .into_iter()
+            .map(|minutes| {
+                let text = control_label(Control::InactiveTime(minutes));
+                let checked = settings.inactive_time == Some(minutes);
+                (minutes, CheckMenuItem::new(text, true, checked, None))
+            })
+            .collect();
+        let mut control_menus = Vec::new();
+        let mut control_items = Vec::new();
+        for (capability, controls) in CONTROLS {
+            let submenu = Submenu::new(capability.label(), true);
+            for &control in controls {
+                let item = MenuItem::new(control_label(control), true, None);
+                submenu.append(&item)?;
+                control_items.push((control, item));
+            }
+            if capability == Capabilities::LIGHTS {
+                submenu.append_items(&[&menu_lights, &menu_lights_off_when_low])?;
+            }
+            if capability == Capabilities::INACTIVE_TIME {
+                for (_, item) in &inactive_time_items {
+                    submenu.append(item)?;
+                }
+            }
+            control_menus.push((capability, submenu));
+        }
+
+        let menu_notifications = CheckMenuItem::new(
+            lang::t(show_notifications),
+            true,
+            settings.notifications_enabled,
+            None,
+        );
+        let menu_api =
+            CheckMenuItem::new(lang::t(enable_local_api), true, settings.api_enabled, None);
+
+        let menu_headsetcontrol = Submenu::new("headsetcontrol", true);
+        let menu_choose_headsetcontrol = MenuItem::new(lang::t(choose_headsetcontrol), true, None);
+        let menu_reset_headsetcontrol = MenuItem::new(
+            lang::t(reset_headsetcontrol),
+            settings.headsetcontrol.path.is_some(),
+            None,
+        );
+        menu_headsetcontrol
+            .append_items(&[&menu_choose_headsetcontrol, &menu_reset_headsetcontrol])?;
+
+        let menu_autostart = Submenu::new(lang::t(start_with_windows), true);
+        let autostart_items: Vec<_> = autostart::Native::ALL
+            .iter()
+            .map(|&method| {
+                (
+                    method,
+                    CheckMenuItem::new(method.label(), true, method.is_enabled(), None),
+                )
+            })
+            .collect();
+        for (_, item) in &autostart_items {
+            menu_autostart.append(item)?;
+        }
+
+        // Nothing to show or export when the history isn't recorded
+        #[cfg(windows)]
+        let menu_history_graph =
+            MenuItem::new(lang::t(history_window), settings.history_enabled, None);
+        let menu_export = Submenu::new(lang::t(export_history), settings.history_enabled);
+        let export_items: Vec<_> = export::Range::ALL
+            .into_iter()
+            .map(|range| {
+                let text = match range {
+                    export::Range::Day => lang::t(export_last_day),
+                    export::Range::Week => lang::t(export_last_week),
+                    export::Range::Month => lang::t(export_last_month),
+                    export::Range::All => lang::t(export_everything),
+                };
+                (range, MenuItem::new(text, true, None))
+            })
+            .collect();
+        for (_, item) in &export_items {
+            menu_export.append(item)?;
+        }
+        let menu_export_selected_only =
+            CheckMenuItem::new(lang::t(export_selected_only), true, true, None);
+        menu_export.append(&PredefinedMenuItem::separator())?;
+        menu_export.append(&menu_export_selected_only)?;
 
         let menu_logs = MenuItem::new(lang::t(view_logs), true, None);
         let menu_github = MenuItem::new(lang::t(view_updates), true, None);
+        #[cfg(windows)]
+        let menu_install_update = MenuItem::new(lang::t(install_update), true, None);
         let menu_close = MenuItem::new(lang::t(quit_program), true, None);
         let separators = None;
         let menu_trigger_notification = MenuItem::new("Trigger Test Notification", true, None);
@@ -45,35 +194,115 @@ impl ContextMenu {
         menu.append(&menu_trigger_notification)?;
 
         menu.append(&menu_notifications)?;
+        if !autostart_items.is_empty() {
+            menu.append(&menu_autostart)?;
+        }
+        menu.append(&menu_api)?;
+        menu.append(&menu_headsetcontrol)?;
+        #[cfg(windows)]
+        menu.append(&menu_history_graph)?;
+        menu.append(&menu_export)?;
         menu.append_items(&[&menu_logs, &menu_github])?;
         menu.append(&PredefinedMenuItem::separator())?;
         menu.append(&menu_close)?;
 
-        Ok(Self {
+        let mut context_menu = Self {
             menu,
+            menu_setup,
+            setup_shown: false,
             device_menu_items,
             selected_device_idx: 0,
             separators,
+            menu_details,
+            detail_items: Vec::new(),
+            control_menus,
+            control_items,
+            shown_controls: Capabilities::default(),
+            menu_lights,
+            menu_lights_off_when_low,
+            inactive_time_items,
             menu_notifications,
+            menu_api,
+            menu_choose_headsetcontrol,
+            menu_reset_headsetcontrol,
+            autostart_items,
+            #[cfg(windows)]
+            menu_history_graph,
+            export_items,
+            menu_export_selected_only,
             menu_logs,
             menu_github,
+            github_url: update::RELEASES_URL.to_string(),
+            #[cfg(windows)]
+            menu_install_update,
+            #[cfg(windows)]
+            install_update_shown: false,
             menu_close,
             menu_trigger_notification,
-        })
+        };
+        // Found by a check on an earlier run
+        if let Some(tag) = &settings.latest_release
+            && update::is_newer(tag)
+        {
+            context_menu.show_update(tag);
+        }
+        Ok(context_menu)
+    }
+
+    /// Turns "View updates" into "Update available", linking to the release with `tag`
+    pub fn show_update(&mut self, tag: &str) {
+        self.menu_github
+            .set_text(lang::t(update_available).replace("{version}", &update::version_label(tag)));
+        self.github_url = update::release_url(tag);
+
+        #[cfg(windows)]
+        if !self.install_update_shown && update::install::is_supported() {
+            let position = self
+                .menu
+                .items()
+                .iter()
+                .position(|item| item.id() == self.menu_github.id());
+            match position {
+                Some(position) => match self.menu.insert(&self.menu_install_update, position + 1) {
+                    Ok(()) => self.install_update_shown = true,
+                    Err(e) => error!("Failed to add the install update item: {e:?}"),
+                },
+                None => error!("View updates is missing from the menu"),
+            }
+        }
+    }
+
+    /// Disables "Download and install update" while the update is downloaded
+    #[cfg(windows)]
+    pub fn set_update_downloading(&self, downloading: bool) {
+        let text = if downloading {
+            downloading_update
+        } else {
+            install_update
+        };
+        self.menu_install_update.set_text(lang::t(text));
+        self.menu_install_update.set_enabled(!downloading);
     }
 
     pub fn update_device_menu(
         &mut self,
         devices: &[headset_control::Device],
     ) -> anyhow::Result<()> {
-        // Remove separators
+        // Remove separators, and the details which are only shown between them
         if let Some((top, bottom)) = &self.separators {
             self.menu.remove(top).context("Removing top separator")?;
             self.menu
                 .remove(bottom)
                 .context("Removing bottom separator")?;
+            self.menu
+                .remove(&self.menu_details)
+                .context("Removing device details")?;
             self.separators = None;
         }
+        self.remove_controls()?;
+        for item in self.detail_items.drain(..) {
+            self.menu_details.remove(&item)?;
+        }
 
         // Remove old device menu items
         for (_, item) in &self.device_menu_items {
@@ -97,18 +326,110 @@ impl ContextMenu {
         // Add new device menu items
         for (i, device) in devices.iter().enumerate() {
             let is_selected = i == self.selected_device_idx;
-            let menu_item = CheckMenuItem::new(device.product.clone(), true, is_selected, None);
+            let menu_item = CheckMenuItem::new(device.to_string(), true, is_selected, None);
             self.menu.insert(&menu_item, 2 + i)?; // Insert after version item
             self.device_menu_items.push((device.clone(), menu_item));
         }
 
-        self.menu.insert(&bottom_separator, 2 + devices.len())?;
+        // Filled in by update_device_details
+        self.menu.insert(&self.menu_details, 2 + devices.len())?;
+
+        self.menu.insert(&bottom_separator, 3 + devices.len())?;
         self.separators = Some((top_separator, bottom_separator));
 
+        self.update_controls()
+    }
+
+    /// Shows the submenus of the settings the selected headset has, below its details
+    fn update_controls(&mut self) -> anyhow::Result<()> {
+        let supported = self
+            .device_menu_items
+            .get(self.selected_device_idx)
+            .map(|(device, _)| device.capabilities)
+            .unwrap_or_default();
+        if supported == self.shown_controls {
+            return Ok(());
+        }
+        self.remove_controls()?;
+
+        // After the devices and the details
+        let mut position = 3 + self.device_menu_items.len();
+        for (capability, submenu) in &self.control_menus {
+            if supported.contains(*capability) {
+                self.menu
+                    .insert(submenu, position)
+                    .context("Inserting device control")?;
+                position += 1;
+            }
+        }
+        self.shown_controls = supported;
+        Ok(())
+    }
+
+    fn remove_controls(&mut self) -> anyhow::Result<()> {
+        for (capability, submenu) in &self.control_menus {
+            if self.shown_controls.contains(*capability) {
+                self.menu
+                    .remove(submenu)
+                    .context("Removing device control")?;
+            }
+        }
+        self.shown_controls = Capabilities::default();
+        Ok(())
+    }
+
+    /// Shows the item to get headsetcontrol below the version, or removes it once it's installed
+    pub fn show_setup(&mut self, needed: bool) -> anyhow::Result<()> {
+        if needed == self.setup_shown {
+            return Ok(());
+        }
+        if needed {
+            self.menu.insert(&self.menu_setup, 1)?;
+        } else {
+            self.menu.remove(&self.menu_setup)?;
+        }
+        self.setup_shown = needed;
+        Ok(())
+    }
+
+    /// Keeps the battery level shown next to each device up to date, and the controls of the
+    /// selected one, which a cached device doesn't have until it's polled
+    pub fn update_device_labels(
+        &mut self,
+        devices: &[headset_control::Device],
+    ) -> anyhow::Result<()> {
+        for ((known, item), device) in self.device_menu_items.iter_mut().zip(devices) {
+            let label = device.to_string();
+            if item.text() != label {
+                item.set_text(label);
+            }
+            known.clone_from(device);
+        }
+        self.update_controls()
+    }
+
+    /// A few lines for each device, in the same order as the devices
+    pub fn update_device_details(&mut self, details: &[String]) -> anyhow::Result<()> {
+        if details.len() != self.detail_items.len() {
+            for item in self.detail_items.drain(..) {
+                self.menu_details.remove(&item)?;
+            }
+            for _ in details {
+                let item = MenuItem::new("", false, None);
+                self.menu_details.append(&item)?;
+                self.detail_items.push(item);
+            }
+        }
+
+        for (item, text) in self.detail_items.iter().zip(details) {
+            if item.text() != *text {
+                item.set_text(text);
+            }
+        }
         Ok(())
     }
 
-    fn set_selected(&mut self, idx: usize) {
+    pub fn set_selected(&mut self, idx: usize) {
         if idx >= self.device_menu_items.len() {
             return;
         }
@@ -117,27 +438,80 @@ impl ContextMenu {
             item.set_checked(i == idx);
         }
         self.selected_device_idx = idx;
+        if let Err(e) = self.update_controls() {
+            error!("Failed to update device controls: {e:?}");
+        }
+    }
+
+    /// The range of the "Export history" item that was clicked
+    pub fn export_range(&self, id: &MenuId) -> Option<export::Range> {
+        self.export_items
+            .iter()
+            .find(|(_, item)| item.id() == id)
+            .map(|(range, _)| *range)
+    }
+
+    /// The control that was clicked, for the selected headset
+    pub fn control(&self, id: &MenuId) -> Option<(&headset_control::Device, Control)> {
+        let (control, _) = self
+            .control_items
+            .iter()
+            .find(|(_, item)| item.id() == id)?;
+        let (device, _) = self.device_menu_items.get(self.selected_device_idx)?;
+        Some((device, *control))
+    }
+
+    /// The minutes of the inactive time item that was clicked
+    pub fn inactive_time(&self, id: &MenuId) -> Option<u8> {
+        self.inactive_time_items
+            .iter()
+            .find(|(_, item)| item.id() == id)
+            .map(|(minutes, _)| *minutes)
+    }
+
+    /// Checks the item of `minutes` only, clicking one checks it on its own
+    pub fn set_inactive_time(&self, minutes: Option<u8>) {
+        for (item_minutes, item) in &self.inactive_time_items {
+            item.set_checked(Some(*item_minutes) == minutes);
+        }
+    }
+
+    pub fn export_selected_only(&self) -> bool {
+        self.menu_export_selected_only.is_checked()
+    }
+
+    fn toggle_autostart(&self, method: autostart::Native) {
+        // Clicking the currently enabled method turns autostart off
+        let new_method = (!method.is_enabled()).then_some(method);
+        if let Err(e) = autostart::Native::set(new_method) {
+            error!("Failed to change autostart to {new_method:?}: {e:?}");
+        }
+
+        for (method, item) in &self.autostart_items {
+            item.set_checked(method.is_enabled());
+        }
     }
 
     pub fn handle_event(&mut self, event: MenuEvent, event_loop: &event_loop::ActiveEventLoop) {
         match event.id {
             id if id == self.menu_close.id() => event_loop.exit(),
 
-            id if id == self.menu_github.id() => {
-                let url = "https://github.com/aarol/headset-battery-indicator/releases";
-
-                if let Err(e) = std::process::Command::new("explorer").arg(url).spawn() {
-                    error!("Failed to open {url}: {e:?}");
-                }
-            }
+            id if id == self.menu_github.id() => open_url(&self.github_url),
+            id if id == self.menu_setup.id() => open_url(HEADSETCONTROL_RELEASES),
             id if id == self.menu_logs.id() => {
                 if let Ok(dir) = std::env::current_dir()
-                    && let Err(e) = std::process::Command::new("explorer").arg(&dir).spawn()
+                    && let Err(e) = std::process::Command::new(OPENER).arg(&dir).spawn()
                 {
                     error!("Failed to open path {dir:?}: {e:?}");
                 }
             }
             id => {
+                if let Some((method, _)) = self.autostart_items.iter().find(|(_, m)| m.id() == &id)
+                {
+                    self.toggle_autostart(*method);
+                    return;
+                }
+
                 let idx = self
                     .device_menu_items
                     .iter()
@@ -150,3 +524,50 @@ impl ContextMenu {
         }
     }
 }
+
+/// The choices in the submenu of each setting, chatmix is only turned on the headset
+const CONTROLS: [(Capabilities, &[Control]); 4] = [
+    (
+        Capabilities::SIDETONE,
+        &[
+            Control::Sidetone(0),
+            Control::Sidetone(32),
+            Control::Sidetone(64),
+            Control::Sidetone(96),
+            Control::Sidetone(128),
+        ],
+    ),
+    // These two have check items, for what's saved in the settings
+    (Capabilities::LIGHTS, &[]),
+    (Capabilities::INACTIVE_TIME, &[]),
+    (
+        Capabilities::VOICE_PROMPTS,
+        &[Control::VoicePrompts(true), Control::VoicePrompts(false)],
+    ),
+];
+
+/// The choices of minutes until the headset turns itself off, 0 turns it off
+const INACTIVE_TIMES: [u8; 5] = [0, 5, 15, 30, 60];
+
+fn control_label(control: Control) -> String {
+    let key = match control {
+        Control::Sidetone(0) | Control::Lights(false) | Control::VoicePrompts(false) => control_off,
+        Control::Lights(true) | Control::VoicePrompts(true) => control_on,
+        Control::Sidetone(..=32) => sidetone_low,
+        Control::Sidetone(..=64) => sidetone_medium,
+        Control::Sidetone(..=96) => sidetone_high,
+        Control::Sidetone(_) => sidetone_max,
+        Control::InactiveTime(0) => inactive_disabled,
+        Control::InactiveTime(minutes) => {
+            return lang::t(inactive_after).replace("{minutes}", &minutes.to_string());
+        }
+    };
+    lang::t(key).to_string()
+}
+
+/// Opens `url` in the default browser
+pub fn open_url(url: &str) {
+    if let Err(e) = std::process::Command::new(OPENER).arg(url).spawn() {
+        error!("Failed to open {url}: {e:?}");
+    }
+}
@@ -0,0 +1,314 @@
+use std::cell::RefCell;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{debug, error, info};
+use windows::{
+    Win32::{
+        Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, WPARAM},
+        System::{DataExchange::COPYDATASTRUCT, LibraryLoader::GetModuleHandleW},
+        UI::WindowsAndMessaging::{
+            CreateWindowExW, DBT_DEVNODES_CHANGED, DefWindowProcW, DestroyWindow, IsWindow,
+            RegisterClassW, RegisterWindowMessageW, SMTO_ABORTIFHUNG, SMTO_BLOCK,
+            SendMessageTimeoutW, SetCoalescableTimer, WM_CLOSE, WM_COPYDATA, WM_DEVICECHANGE,
+            WM_DISPLAYCHANGE, WM_DPICHANGED, WM_ENDSESSION, WM_HOTKEY, WM_QUERYENDSESSION,
+            WM_SETTINGCHANGE, WM_TIMER, WNDCLASSW, WS_EX_TOOLWINDOW, WS_OVERLAPPED,
+        },
+    },
+    core::{PCWSTR, w},
+};
+use winit::event_loop::EventLoopProxy;
+
+use crate::UserEvent;
+use crate::api;
+use crate::headset_control::{BatteryState, Device};
+use crate::hotkeys;
+use crate::platform::features;
+
+// The window procedure has no access to the application state,
+// so messages are forwarded to the event loop through this proxy
+thread_local! {
+    static PROXY: RefCell<Option<EventLoopProxy<UserEvent>>> = const { RefCell::new(None) };
+    /// Saves the application state before WM_ENDSESSION returns, false when it couldn't
+    static END_SESSION: RefCell<Option<Box<dyn Fn() -> bool>>> = const { RefCell::new(None) };
+    static STATUS: RefCell<Status> = const {
+        RefCell::new(Status {
+            selected: None,
+            devices_json: String::new(),
+        })
+    };
+}
+
+/// What WM_COPYDATA requests are answered with, updated on every poll
+struct Status {
+    /// Battery level and state of the selected device
+    selected: Option<(isize, BatteryState)>,
+    /// The device list as zero terminated JSON
+    devices_json: String,
+}
+
+const POLL_TIMER_ID: usize = 1;
+
+// The `dwData` of WM_COPYDATA requests, see the README for the protocol
+/// Returns the battery level of the selected headset, or -1
+const REQUEST_LEVEL: usize = 1;
+/// Returns 0 when the selected headset is off, 1 when it's on battery and 2 when it's charging, or -1
+const REQUEST_STATUS: usize = 2;
+/// Sends the device list as JSON back to the window in `wParam`, in a WM_COPYDATA of its own
+const REQUEST_DEVICES: usize = 3;
+
+/// How long to wait for a client to accept the reply
+const REPLY_TIMEOUT_MS: u32 = 1000;
+
+/// Broadcast to all top-level windows when Explorer (re)creates the taskbar,
+/// after which all tray icons have to be added again
+static TASKBAR_CREATED: LazyLock<u32> =
+    LazyLock::new(|| unsafe { RegisterWindowMessageW(w!("TaskbarCreated")) });
+
+/// Hidden window that receives the system messages winit doesn't expose,
+/// since the app doesn't create a winit window of its own.
+///
+/// This is a regular top-level window that is never shown instead of a message-only window,
+/// because message-only windows don't receive broadcasts like WM_QUERYENDSESSION.
+pub struct MessageWindow {
+    hwnd: HWND,
+}
+
+impl MessageWindow {
+    pub fn new(proxy: EventLoopProxy<UserEvent>) -> anyhow::Result<Self> {
+        PROXY.with_borrow_mut(|p| *p = Some(proxy));
+
+        unsafe {
+            let instance: HINSTANCE = GetModuleHandleW(None)
+                .context("getting module handle")?
+                .into();
+            let class_name = w!("HeadsetBatteryIndicatorMessageWindow");
+
+            let class = WNDCLASSW {
+                lpfnWndProc: Some(wnd_proc),
+                hInstance: instance,
+                lpszClassName: class_name,
+                ..Default::default()
+            };
+            if RegisterClassW(&class) == 0 {
+                anyhow::bail!("Failed to register message window class");
+            }
+
+            let hwnd = CreateWindowExW(
+                WS_EX_TOOLWINDOW,
+                class_name,
+                w!("Headset Battery Indicator"),
+                WS_OVERLAPPED,
+                0,
+                0,
+                0,
+                0,
+                None,
+                None,
+                instance,
+                None,
+            )
+            .context("creating message window")?;
+
+            Ok(Self { hwnd })
+        }
+    }
+
+    /// Runs `handler` when the session ends or the app is asked to close, before the message
+    /// is answered
+    pub fn on_session_end(&self, handler: Box<dyn Fn() -> bool>) {
+        END_SESSION.with_borrow_mut(|h| *h = Some(handler));
+    }
+
+    /// Updates the state returned to WM_COPYDATA requests
+    pub fn publish(&self, devices: &[Device], selected: usize) {
+        let mut json = match serde_json::to_string(&api::device_list(devices, selected)) {
+            Ok(json) => json,
+            Err(e) => {
+                error!("Failed to serialize device list: {e:?}");
+                return;
+            }
+        };
+        // So clients can read it as a C string
+        json.push('\0');
+
+        STATUS.with_borrow_mut(|status| {
+            status.selected = devices
+                .get(selected)
+                .map(|device| (device.battery.level, device.battery.status));
+            status.devices_json = json;
+        });
+    }
+}
+
+impl MessageWindow {
+    /// Hotkeys are registered to this window
+    pub fn hwnd(&self) -> HWND {
+        self.hwnd
+    }
+
+    /// (Re)starts the timer that drives polling.
+    ///
+    /// Unlike winit's `ControlFlow::WaitUntil`, which arms a high resolution timer, this lets
+    /// Windows coalesce the wakeups with other timers in the system, since polling doesn't have
+    /// to happen at an exact moment.
+    pub fn set_poll_timer(&self, interval: Duration) -> anyhow::Result<()> {
+        let elapse = interval.as_millis().min(u32::MAX as u128) as u32;
+        // The timer may fire up to a quarter of the interval late
+        let tolerance = elapse / 4;
+
+        if unsafe { SetCoalescableTimer(self.hwnd, POLL_TIMER_ID, elapse, None, tolerance) } == 0 {
+            return Err(windows::core::Error::from_win32()).context("SetCoalescableTimer");
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for MessageWindow {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = DestroyWindow(self.hwnd);
+        }
+    }
+}
+
+fn send(event: UserEvent) {
+    PROXY.with_borrow(|proxy| {
+        if let Some(proxy) = proxy
+            && proxy.send_event(event).is_err()
+        {
+            debug!("Event loop closed, dropping message window event");
+        }
+    });
+}
+
+/// Saves the state and removes the tray icon before returning, and has the event loop exit
+fn end_session() {
+    let saved =
+        END_SESSION.with_borrow(|handler| handler.as_ref().is_some_and(|handler| handler()));
+    if !saved {
+        debug!("Application busy, the event loop saves it on exit");
+    }
+    send(UserEvent::SessionEnded);
+}
+
+unsafe extern "system" fn wnd_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match msg {
+        WM_QUERYENDSESSION => {
+            info!("Session is ending, saving state");
+            send(UserEvent::SessionEnding);
+            // Never block logoff/shutdown
+            LRESULT(1)
+        }
+        // The process can be ended as soon as this returns, so the state is saved right here
+        WM_ENDSESSION => {
+            if wparam.0 != 0 {
+                end_session();
+            }
+            LRESULT(0)
+        }
+        // Sent by the Restart Manager (e.g. when the installer updates the app)
+        WM_CLOSE => {
+            end_session();
+            LRESULT(0)
+        }
+        // Broadcast with "ImmersiveColorSet" when the user switches between light and dark mode
+        WM_SETTINGCHANGE if lparam.0 != 0 => {
+            let area = unsafe { PCWSTR(lparam.0 as *const u16).to_string() };
+            if area.is_ok_and(|area| area == "ImmersiveColorSet") {
+                send(UserEvent::ThemeChanged);
+            }
+            LRESULT(0)
+        }
+        WM_DISPLAYCHANGE | WM_DPICHANGED => {
+            send(UserEvent::DisplayChanged);
+            LRESULT(0)
+        }
+        // Broadcast to top-level windows when a device is added or removed
+        WM_DEVICECHANGE if wparam.0 == DBT_DEVNODES_CHANGED as usize => {
+            send(UserEvent::DevicesChanged);
+            LRESULT(0)
+        }
+        WM_TIMER if wparam.0 == POLL_TIMER_ID => {
+            send(UserEvent::PollTimer);
+            LRESULT(0)
+        }
+        WM_HOTKEY => {
+            if let Some(action) = hotkeys::Action::from_id(wparam.0) {
+                send(UserEvent::Platform(features::Event::Hotkey(action)));
+            }
+            LRESULT(0)
+        }
+        WM_COPYDATA if lparam.0 != 0 => {
+            let request = unsafe { &*(lparam.0 as *const COPYDATASTRUCT) }.dwData;
+            let client = HWND(wparam.0 as *mut _);
+            LRESULT(copy_data_request(hwnd, client, request))
+        }
+        _ if msg == *TASKBAR_CREATED => {
+            info!("Taskbar was recreated");
+            send(UserEvent::TaskbarCreated);
+            LRESULT(0)
+        }
+        _ => unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) },
+    }
+}
+
+/// Answers a WM_COPYDATA request from another program, e.g. an AutoHotkey script
+fn copy_data_request(hwnd: HWND, client: HWND, request: usize) -> isize {
+    let selected = STATUS.with_borrow(|status| status.selected);
+
+    match request {
+        REQUEST_LEVEL => match selected {
+            Some((level, BatteryState::BatteryAvailable | BatteryState::BatteryCharging)) => level,
+            _ => -1,
+        },
+        REQUEST_STATUS => match selected {
+            Some((_, BatteryState::BatteryCharging)) => 2,
+            Some((_, BatteryState::BatteryAvailable)) => 1,
+            Some(_) => 0,
+            None => -1,
+        },
+        REQUEST_DEVICES => {
+            if !unsafe { IsWindow(client) }.as_bool() {
+                debug!("WM_COPYDATA device list request without a reply window");
+                return 0;
+            }
+
+            let mut json = STATUS.with_borrow(|status| status.devices_json.clone());
+            if json.is_empty() {
+                json = "[]\0".to_string();
+            }
+            let reply = COPYDATASTRUCT {
+                dwData: REQUEST_DEVICES,
+                cbData: json.len() as u32,
+                lpData: json.as_ptr() as *mut _,
+            };
+
+            // The client is waiting for its own SendMessage to return, so it can
+            // receive the reply before the request has been answered
+            let sent = unsafe {
+                SendMessageTimeoutW(
+                    client,
+                    WM_COPYDATA,
+                    WPARAM(hwnd.0 as usize),
+                    LPARAM(&reply as *const _ as isize),
+                    SMTO_BLOCK | SMTO_ABORTIFHUNG,
+                    REPLY_TIMEOUT_MS,
+                    None,
+                )
+            };
+            (sent.0 != 0) as isize
+        }
+        _ => {
+            debug!("Unknown WM_COPYDATA request {request}");
+            0
+        }
+    }
+}
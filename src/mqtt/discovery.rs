@@ -0,0 +1,75 @@
+//! Home Assistant MQTT discovery, so each headset shows up as a device
+//! with a battery sensor and a charging binary sensor
+
+use serde_json::json;
+
+use crate::headset_control::Device;
+
+pub const DEFAULT_PREFIX: &str = "homeassistant";
+
+const NODE_ID: &str = "headset_battery_indicator";
+
+/// Retained config messages for the entities of one device
+pub fn config_messages(
+    discovery_prefix: &str,
+    topic_prefix: &str,
+    device: &Device,
+) -> Vec<(String, String)> {
+    let id = device.id();
+    // Only [a-zA-Z0-9_-] is allowed in discovery topics
+    let object_id: String = id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    let availability = json!([
+        { "topic": format!("{topic_prefix}/status") },
+        { "topic": format!("{topic_prefix}/{id}/availability") },
+    ]);
+    let ha_device = json!({
+        "identifiers": [format!("{NODE_ID}_{object_id}")],
+        "name": device.product,
+        "manufacturer": device.vendor,
+        "model": device.product,
+    });
+    let origin = json!({
+        "name": "Headset Battery Indicator",
+        "sw_version": crate::VERSION,
+        "support_url": "https://github.com/aarol/headset-battery-indicator",
+    });
+
+    let battery = json!({
+        "name": "Battery",
+        "unique_id": format!("{NODE_ID}_{object_id}_battery"),
+        "device_class": "battery",
+        "unit_of_measurement": "%",
+        "state_class": "measurement",
+        "state_topic": format!("{topic_prefix}/{id}/level"),
+        "availability": availability,
+        "availability_mode": "all",
+        "device": ha_device,
+        "origin": origin,
+    });
+    let charging = json!({
+        "name": "Charging",
+        "unique_id": format!("{NODE_ID}_{object_id}_charging"),
+        "device_class": "battery_charging",
+        "state_topic": format!("{topic_prefix}/{id}/state"),
+        "value_template": "{{ 'ON' if value == 'charging' else 'OFF' }}",
+        "availability": availability,
+        "availability_mode": "all",
+        "device": ha_device,
+        "origin": origin,
+    });
+
+    vec![
+        (
+            format!("{discovery_prefix}/sensor/{NODE_ID}/{object_id}_battery/config"),
+            battery.to_string(),
+        ),
+        (
+            format!("{discovery_prefix}/binary_sensor/{NODE_ID}/{object_id}_charging/config"),
+            charging.to_string(),
+        ),
+    ]
+}
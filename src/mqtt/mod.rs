@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use log::{error, info, warn};
+
+use crate::headset_control::{Battery, BatteryState, Device};
+
+pub mod discovery;
+mod packet;
+
+pub const DEFAULT_TOPIC_PREFIX: &str = "headset-battery-indicator";
+
+const DEFAULT_PORT: u16 = 1883;
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Read from the `Mqtt` registry subkey
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    /// `host`, `host:port` or `mqtt://host:port`
+    pub broker: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub topic_prefix: String,
+    /// Home Assistant discovery prefix, when discovery is enabled
+    pub discovery_prefix: Option<String>,
+}
+
+/// Publishes the battery state of each device to an MQTT broker.
+///
+/// The connection is handled on its own thread, so a slow or unreachable broker
+/// never holds up polling. Values are published retained whenever they change,
+/// and all of them again after reconnecting.
+pub struct MqttPublisher {
+    sender: mpsc::Sender<Vec<Device>>,
+}
+
+impl MqttPublisher {
+    pub fn start(config: MqttConfig) -> anyhow::Result<Self> {
+        let addr = broker_address(&config.broker)?;
+        let (sender, receiver) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("mqtt".to_string())
+            .spawn(move || Worker::new(config, addr).run(receiver))
+            .context("spawning MQTT thread")?;
+
+        Ok(Self { sender })
+    }
+
+    pub fn publish(&self, devices: &[Device]) {
+        if self.sender.send(devices.to_vec()).is_err() {
+            error!("MQTT thread has stopped");
+        }
+    }
+}
+
+struct Worker {
+    config: MqttConfig,
+    addr: String,
+    stream: Option<TcpStream>,
+    /// What the broker currently has for each device, by device id
+    published: HashMap<String, Battery>,
+    last_attempt: Option<Instant>,
+    last_write: Instant,
+}
+
+impl Worker {
+    fn new(config: MqttConfig, addr: String) -> Self {
+        Self {
+            config,
+            addr,
+            stream: None,
+            published: HashMap::new(),
+            last_attempt: None,
+            last_write: Instant::now(),
+        }
+    }
+
+    fn run(mut self, receiver: mpsc::Receiver<Vec<Device>>) {
+        let mut devices = vec![];
+
+        loop {
+            match receiver.recv_timeout(KEEP_ALIVE / 2) {
+                Ok(latest) => devices = latest,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.disconnect();
+                    return;
+                }
+            }
+
+            if self.stream.is_none()
+                && self
+                    .last_attempt
+                    .is_none_or(|attempt| attempt.elapsed() >= RECONNECT_DELAY)
+            {
+                self.last_attempt = Some(Instant::now());
+                if let Err(e) = self.connect() {
+                    warn!("Failed to connect to MQTT broker {}: {e:?}", self.addr);
+                }
+            }
+
+            if self.stream.is_some()
+                && let Err(e) = self.sync(&devices)
+            {
+                warn!("Lost connection to MQTT broker: {e:?}");
+                self.stream = None;
+            }
+        }
+    }
+
+    fn connect(&mut self) -> anyhow::Result<()> {
+        let addr = self
+            .addr
+            .to_socket_addrs()
+            .context("resolving broker address")?
+            .next()
+            .context("broker address didn't resolve")?;
+        let mut stream = TcpStream::connect_timeout(&addr, IO_TIMEOUT)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+        let client_id = format!(
+            "headset-battery-indicator-{}",
+            std::env::var("COMPUTERNAME").unwrap_or_else(|_| std::process::id().to_string())
+        );
+        let status_topic = self.topic("status");
+
+        packet::connect(
+            &mut stream,
+            &packet::Connect {
+                client_id: &client_id,
+                keep_alive_secs: KEEP_ALIVE.as_secs() as u16,
+                username: self.config.username.as_deref(),
+                password: self.config.password.as_deref(),
+                will: (&status_topic, "offline"),
+            },
+        )?;
+        packet::publish(&mut stream, &status_topic, b"online", true)?;
+
+        info!("Connected to MQTT broker {}", self.addr);
+        self.stream = Some(stream);
+        self.last_write = Instant::now();
+        // The broker may have lost the retained values, send everything again
+        self.published.clear();
+
+        Ok(())
+    }
+
+    /// Publishes the values that changed since the last call
+    fn sync(&mut self, devices: &[Device]) -> anyhow::Result<()> {
+        let mut messages = vec![];
+
+        for device in devices {
+            let id = device.id();
+            let last = self.published.get(&id);
+
+            // Announced once per connection, Home Assistant keeps the retained configs
+            if last.is_none()
+                && let Some(discovery_prefix) = &self.config.discovery_prefix
+            {
+                messages.extend(discovery::config_messages(
+                    discovery_prefix.trim_end_matches('/'),
+                    self.config.topic_prefix.trim_end_matches('/'),
+                    device,
+                ));
+            }
+            if last
+                .is_none_or(|last| is_available(last.status) != is_available(device.battery.status))
+            {
+                let availability = if is_available(device.battery.status) {
+                    "online"
+                } else {
+                    "offline"
+                };
+                messages.push((
+                    self.topic(&format!("{id}/availability")),
+                    availability.to_string(),
+                ));
+            }
+            if last.is_none_or(|last| last.level != device.battery.level) {
+                messages.push((
+                    self.topic(&format!("{id}/level")),
+                    device.battery.level.to_string(),
+                ));
+            }
+            if last.is_none_or(|last| last.status != device.battery.status) {
+                messages.push((
+                    self.topic(&format!("{id}/state")),
+                    device.battery.status.label().to_string(),
+                ));
+            }
+        }
+
+        // Devices that are no longer listed at all (e.g. dongle unplugged)
+        for id in self.published.keys() {
+            if !devices.iter().any(|device| device.id() == *id) {
+                messages.push((
+                    self.topic(&format!("{id}/availability")),
+                    "offline".to_string(),
+                ));
+            }
+        }
+
+        let Some(stream) = &mut self.stream else {
+            return Ok(());
+        };
+
+        if messages.is_empty() {
+            if self.last_write.elapsed() >= KEEP_ALIVE / 2 {
+                packet::ping(stream)?;
+                self.last_write = Instant::now();
+            }
+            return Ok(());
+        }
+
+        for (topic, payload) in &messages {
+            packet::publish(stream, topic, payload.as_bytes(), true)?;
+        }
+        self.last_write = Instant::now();
+
+        self.published = devices
+            .iter()
+            .map(|device| (device.id(), device.battery))
+            .collect();
+
+        Ok(())
+    }
+
+    fn disconnect(&mut self) {
+        let status_topic = self.topic("status");
+        if let Some(stream) = &mut self.stream {
+            // The will is only published when the connection drops without a DISCONNECT
+            let _ = packet::publish(stream, &status_topic, b"offline", true);
+            let _ = packet::disconnect(stream);
+        }
+        self.stream = None;
+    }
+
+    fn topic(&self, suffix: &str) -> String {
+        format!(
+            "{}/{suffix}",
+            self.config.topic_prefix.trim_end_matches('/')
+        )
+    }
+}
+
+fn is_available(status: BatteryState) -> bool {
+    matches!(
+        status,
+        BatteryState::BatteryAvailable | BatteryState::BatteryCharging
+    )
+}
+
+/// Turns the configured broker into `host:port`
+fn broker_address(broker: &str) -> anyhow::Result<String> {
+    let broker = broker.trim().trim_end_matches('/');
+
+    if broker.starts_with("mqtts://") || broker.starts_with("ssl://") {
+        anyhow::bail!("TLS connections to the MQTT broker are not supported");
+    }
+    let host = broker
+        .strip_prefix("mqtt://")
+        .or_else(|| broker.strip_prefix("tcp://"))
+        .unwrap_or(broker);
+    anyhow::ensure!(!host.is_empty(), "MQTT broker address is empty");
+
+    match host.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => Ok(host.to_string()),
+        _ => Ok(format!("{host}:{DEFAULT_PORT}")),
+    }
+}
@@ -0,0 +1,208 @@
+//! The few MQTT 3.1.1 packets needed to publish messages
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use anyhow::Context;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PINGREQ: u8 = 0xC0;
+const PINGRESP: u8 = 0xD0;
+const DISCONNECT: u8 = 0xE0;
+
+const FLAG_USERNAME: u8 = 0x80;
+const FLAG_PASSWORD: u8 = 0x40;
+const FLAG_WILL_RETAIN: u8 = 0x20;
+const FLAG_WILL: u8 = 0x04;
+const FLAG_CLEAN_SESSION: u8 = 0x02;
+
+pub struct Connect<'a> {
+    pub client_id: &'a str,
+    pub keep_alive_secs: u16,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a str>,
+    /// Retained message the broker publishes if the connection is lost
+    pub will: (&'a str, &'a str),
+}
+
+pub fn connect(stream: &mut TcpStream, packet: &Connect) -> anyhow::Result<()> {
+    write_packet(stream, CONNECT, &connect_body(packet))?;
+
+    let mut connack = [0u8; 4];
+    stream.read_exact(&mut connack).context("reading CONNACK")?;
+    anyhow::ensure!(
+        connack[0] == CONNACK,
+        "expected CONNACK, got {:#x}",
+        connack[0]
+    );
+
+    match connack[3] {
+        0 => Ok(()),
+        1 => anyhow::bail!("broker refused the connection: unsupported protocol version"),
+        2 => anyhow::bail!("broker refused the connection: client id rejected"),
+        3 => anyhow::bail!("broker refused the connection: server unavailable"),
+        4 => anyhow::bail!("broker refused the connection: bad username or password"),
+        5 => anyhow::bail!("broker refused the connection: not authorized"),
+        code => anyhow::bail!("broker refused the connection: code {code}"),
+    }
+}
+
+/// Publishes with QoS 0
+pub fn publish(
+    stream: &mut TcpStream,
+    topic: &str,
+    payload: &[u8],
+    retain: bool,
+) -> anyhow::Result<()> {
+    let mut body = Vec::with_capacity(topic.len() + payload.len() + 2);
+    write_str(&mut body, topic);
+    body.extend_from_slice(payload);
+
+    write_packet(stream, PUBLISH | retain as u8, &body)
+}
+
+pub fn ping(stream: &mut TcpStream) -> anyhow::Result<()> {
+    write_packet(stream, PINGREQ, &[])?;
+
+    let mut pingresp = [0u8; 2];
+    stream
+        .read_exact(&mut pingresp)
+        .context("reading PINGRESP")?;
+    anyhow::ensure!(
+        pingresp[0] == PINGRESP,
+        "expected PINGRESP, got {:#x}",
+        pingresp[0]
+    );
+
+    Ok(())
+}
+
+pub fn disconnect(stream: &mut TcpStream) -> anyhow::Result<()> {
+    write_packet(stream, DISCONNECT, &[])
+}
+
+fn connect_body(packet: &Connect) -> Vec<u8> {
+    let mut flags = FLAG_CLEAN_SESSION | FLAG_WILL | FLAG_WILL_RETAIN;
+
+    let mut body = vec![];
+    write_str(&mut body, "MQTT");
+    body.push(4); // Protocol level 3.1.1
+    let flags_pos = body.len();
+    body.push(0);
+    body.extend_from_slice(&packet.keep_alive_secs.to_be_bytes());
+
+    write_str(&mut body, packet.client_id);
+    write_str(&mut body, packet.will.0);
+    write_str(&mut body, packet.will.1);
+    if let Some(username) = packet.username {
+        flags |= FLAG_USERNAME;
+        write_str(&mut body, username);
+        // A password without a username isn't allowed
+        if let Some(password) = packet.password {
+            flags |= FLAG_PASSWORD;
+            write_str(&mut body, password);
+        }
+    }
+    body[flags_pos] = flags;
+    body
+}
+
+fn write_packet(stream: &mut TcpStream, header: u8, body: &[u8]) -> anyhow::Result<()> {
+    stream.write_all(&encode(header, body))?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+/// The fixed header and `body`
+fn encode(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(body.len() + 5);
+    packet.push(header);
+
+    // Remaining length, 7 bits per byte
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn write_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The fixed header of a packet with a body of `len` bytes
+    fn fixed_header(len: usize) -> Vec<u8> {
+        let packet = encode(PUBLISH, &vec![0; len]);
+        packet[..packet.len() - len].to_vec()
+    }
+
+    #[test]
+    fn remaining_length() {
+        assert_eq!(fixed_header(0), [PUBLISH, 0x00]);
+        assert_eq!(fixed_header(127), [PUBLISH, 0x7f]);
+        assert_eq!(fixed_header(128), [PUBLISH, 0x80, 0x01]);
+        assert_eq!(fixed_header(16383), [PUBLISH, 0xff, 0x7f]);
+        assert_eq!(fixed_header(16384), [PUBLISH, 0x80, 0x80, 0x01]);
+        assert_eq!(fixed_header(2_097_152), [PUBLISH, 0x80, 0x80, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn connect_with_credentials_and_will() {
+        let packet = Connect {
+            client_id: "hbi",
+            keep_alive_secs: 60,
+            username: Some("user"),
+            password: Some("pw"),
+            will: ("hbi/status", "offline"),
+        };
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            CONNECT, 46,
+            0, 4, b'M', b'Q', b'T', b'T',
+            4,
+            // Username, password, will retain, will and clean session
+            0xe6,
+            0, 60,
+            0, 3, b'h', b'b', b'i',
+            0, 10, b'h', b'b', b'i', b'/', b's', b't', b'a', b't', b'u', b's',
+            0, 7, b'o', b'f', b'f', b'l', b'i', b'n', b'e',
+            0, 4, b'u', b's', b'e', b'r',
+            0, 2, b'p', b'w',
+        ];
+        assert_eq!(encode(CONNECT, &connect_body(&packet)), expected);
+    }
+
+    #[test]
+    fn connect_without_username_leaves_out_password() {
+        let packet = Connect {
+            client_id: "hbi",
+            keep_alive_secs: 30,
+            username: None,
+            password: Some("pw"),
+            will: ("t", "x"),
+        };
+
+        let body = connect_body(&packet);
+        assert_eq!(body[7], FLAG_CLEAN_SESSION | FLAG_WILL | FLAG_WILL_RETAIN);
+        assert_eq!(&body[8..10], [0, 30]);
+        assert!(body.ends_with(&[0, 1, b't', 0, 1, b'x']));
+    }
+}
@@ -1,101 +1,542 @@
-use crate::headset_control::BatteryState;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
-use anyhow::{Context, Result};
-use win32_notif::{
-    NotificationBuilder, ToastsNotifier,
-    notification::visual::{Text, text::HintStyle},
-};
-#[cfg(windows)]
-use windows::{Win32::UI::Shell::SetCurrentProcessExplicitAppUserModelID, core::HSTRING};
+use crate::UserEvent;
+use crate::headset_control::{BatteryState, Device};
+use crate::history::Estimate;
+use crate::platform::notify::{Native, Notifications, Urgency};
 
+use anyhow::Result;
+use winit::event_loop::EventLoopProxy;
+
+/// Decides when the battery level is worth a notification, and shows it through the
+/// platform's notifications
 pub struct Notifier {
-    toast_notifier: ToastsNotifier,
+    backend: Native,
     last_notification_state: Option<(isize, BatteryState)>,
+    /// Opened when the last notification is clicked, instead of the tray menu
+    link: Option<String>,
+    /// Whether the battery notifications are held back, the state is still followed
+    muted: bool,
+    /// Whether the battery notifications are held back for now, like while the microphone is
+    /// in use. Unlike `muted` it's not up to the user, an alert held back isn't shown later.
+    quiet: bool,
+    /// Notifications closer together than this to the last one are left out
+    min_interval: Duration,
+    last_shown: Option<Instant>,
 }
 
 impl Notifier {
-    pub fn new() -> anyhow::Result<Self> {
-        let app_id = register_notifications_id().context("registering notifications id")?;
-        let toast_notifier = ToastsNotifier::new(app_id)?;
+    /// `last_state` is the state persisted on the previous run, so transitions that happened
+    /// while the app wasn't running are still notified about
+    pub fn new(
+        last_state: Option<(isize, BatteryState)>,
+        proxy: EventLoopProxy<UserEvent>,
+    ) -> anyhow::Result<Self> {
         Ok(Self {
-            toast_notifier,
-            last_notification_state: None,
+            backend: Native::new(proxy)?,
+            last_notification_state: last_state,
+            link: None,
+            muted: false,
+            quiet: false,
+            min_interval: Duration::ZERO,
+            last_shown: None,
         })
     }
 
+    pub fn last_state(&self) -> Option<(isize, BatteryState)> {
+        self.last_notification_state
+    }
+
     pub fn update(
         &mut self,
         current_level: isize,
         current_status: BatteryState,
         product_name: &str,
+        estimate: Option<Estimate>,
     ) {
-        if let Some((last_level, last_status)) = self.last_notification_state {
-            let mut msg = None;
+        let previous = self.last_notification_state;
+        let current = (current_level, current_status);
 
-            let battery_discharging = current_status == BatteryState::BatteryAvailable;
-            let battery_charging = current_status == BatteryState::BatteryCharging;
+        let kinds = if self.muted || self.quiet {
+            Vec::new()
+        } else {
+            NotificationPolicy::evaluate(previous, current)
+        };
+        for kind in kinds {
+            let (mut body, urgency) = match kind {
+                NotificationKind::Low(level) => (format!("Battery low ({level}%)"), Urgency::Low),
+                NotificationKind::Critical(level) => {
+                    (format!("Battery critical ({level}%)"), Urgency::Critical)
+                }
+                NotificationKind::ChargingStarted(level) => {
+                    (format!("Charging started ({level}%)"), Urgency::Info)
+                }
+                NotificationKind::Full => ("Battery full".to_string(), Urgency::Info),
+            };
 
-            // Low battery (10%)
-            if current_level <= 10 && last_level > 10 && battery_discharging {
-                msg = Some(format!("Battery low ({}%)", current_level));
-            }
-            // Critical battery (3%)
-            else if current_level <= 3 && last_level > 3 && battery_discharging {
-                msg = Some(format!("Battery critical ({}%)", current_level));
-            }
-            // Charging started
-            else if battery_charging && last_status != BatteryState::BatteryCharging {
-                msg = Some(format!("Charging started ({}%)", current_level));
-            }
-            // Battery full (100%)
-            else if current_level == 100 && last_level < 100 && battery_charging {
-                msg = Some("Battery full".to_string());
+            // How long the rest lasts is what matters when the battery is getting low
+            if matches!(
+                kind,
+                NotificationKind::Low(_) | NotificationKind::Critical(_)
+            ) && let Some(estimate) = estimate
+            {
+                body.push_str(&format!("\n{estimate}"));
             }
 
-            if let Some(body) = msg
-                && let Err(err) = self.show_notification(product_name, &body)
+            if self.too_soon(&body) {
+                continue;
+            }
+            self.link = None;
+            if let Err(err) = self
+                .backend
+                .show(product_name, &body, urgency, Some(current))
             {
                 log::error!("Failed to show notification: {:?}", err);
             }
         }
 
-        self.last_notification_state = Some((current_level, current_status));
+        self.last_notification_state = NotificationPolicy::next(previous, current);
+    }
+
+    /// Whether the battery notifications are held back
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Whether the battery notifications are held back for now
+    pub fn is_quiet(&self) -> bool {
+        self.quiet
+    }
+
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    /// Leaves out the notifications that come too soon after the last one, for the demo
+    pub fn set_min_interval(&mut self, min_interval: Duration) {
+        self.min_interval = min_interval;
+    }
+
+    /// Whether the notification with `body` is left out, or else counts as shown now
+    fn too_soon(&mut self, body: &str) -> bool {
+        let now = Instant::now();
+        if self
+            .last_shown
+            .is_some_and(|last| now.duration_since(last) < self.min_interval)
+        {
+            log::debug!("Left out notification, too soon after the last one: {body}");
+            return true;
+        }
+        self.last_shown = Some(now);
+        false
     }
 
     pub fn show_notification(&mut self, product_name: &str, body: &str) -> Result<()> {
-        let builder = NotificationBuilder::new()
-            .visual(Text::create(0, product_name).with_style(HintStyle::Title))
-            .visual(Text::create(1, &body).with_style(HintStyle::Body));
+        if self.too_soon(body) {
+            return Ok(());
+        }
+        self.link = None;
+        self.backend.show(product_name, body, Urgency::Info, None)
+    }
+
+    /// Shows a notification that opens `url` when it's clicked
+    pub fn show_link(&mut self, title: &str, body: &str, url: String) -> Result<()> {
+        self.backend.show(title, body, Urgency::Info, None)?;
+        self.link = Some(url);
+        Ok(())
+    }
+
+    /// The link of the last notification, taken when it's clicked
+    pub fn take_link(&mut self) -> Option<String> {
+        self.link.take()
+    }
+}
+
+/// Notices the headsets left charging at 100%, since being kept full wears the battery
+#[derive(Default)]
+pub struct UnplugReminder {
+    /// When each headset charging at 100% got there, and whether it was reminded about
+    full_since: HashMap<String, (Instant, bool)>,
+}
 
-        builder
-            .build(0, &self.toast_notifier, product_name, "battery")
-            .context("building notification")?
-            .show()
-            .context("showing notification")
+impl UnplugReminder {
+    /// The headsets that have been charging at 100% for at least `after`, each once per charge
+    pub fn update<'a>(
+        &mut self,
+        devices: &'a [Device],
+        after: Duration,
+        now: Instant,
+    ) -> Vec<&'a Device> {
+        self.full_since
+            .retain(|id, _| devices.iter().any(|device| device.id() == *id));
+        let mut due = Vec::new();
+        for device in devices {
+            match device.battery.status {
+                BatteryState::BatteryCharging if device.battery.level >= 100 => {
+                    let (since, reminded) =
+                        self.full_since.entry(device.id()).or_insert((now, false));
+                    if !*reminded && now.duration_since(*since) >= after {
+                        *reminded = true;
+                        due.push(device);
+                    }
+                }
+                // A headset that drops out for a moment wasn't unplugged
+                BatteryState::BatteryUnavailable
+                | BatteryState::BatteryHiderror
+                | BatteryState::BatteryTimeout => {}
+                _ => {
+                    self.full_since.remove(&device.id());
+                }
+            }
+        }
+        due
     }
 }
 
-#[cfg(windows)]
-pub fn register_notifications_id() -> Result<String> {
-    // Win32 Toast notifications typically require a Start Menu shortcut whose
-    // AppUserModelID matches the notifier ID. Without this, `show()` can succeed
-    // but nothing appears.
+/// What a notification is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    Low(isize),
+    Critical(isize),
+    ChargingStarted(isize),
+    Full,
+}
+
+/// Decides which notifications a new reading is worth, from the reading it's compared with.
+/// Nothing is notified before there's a reading to compare with.
+pub struct NotificationPolicy;
+
+impl NotificationPolicy {
+    pub const LOW: isize = 10;
+    pub const CRITICAL: isize = 3;
+    /// How much a discharging battery has to rise before the rise counts, so a level that
+    /// wobbles around a threshold doesn't notify every time it drops below it
+    pub const HYSTERESIS: isize = 5;
+
+    pub fn evaluate(
+        previous: Option<(isize, BatteryState)>,
+        current: (isize, BatteryState),
+    ) -> Vec<NotificationKind> {
+        let Some((last_level, last_status)) = previous else {
+            return Vec::new();
+        };
+        let (level, status) = current;
+        let crossed = |threshold| level <= threshold && last_level > threshold;
+
+        let kind = match status {
+            // The critical one says all the low one would
+            BatteryState::BatteryAvailable if crossed(Self::CRITICAL) => {
+                Some(NotificationKind::Critical(level))
+            }
+            BatteryState::BatteryAvailable if crossed(Self::LOW) => {
+                Some(NotificationKind::Low(level))
+            }
+            BatteryState::BatteryCharging if last_status != BatteryState::BatteryCharging => {
+                Some(NotificationKind::ChargingStarted(level))
+            }
+            BatteryState::BatteryCharging if level == 100 && last_level < 100 => {
+                Some(NotificationKind::Full)
+            }
+            _ => None,
+        };
+        kind.into_iter().collect()
+    }
+
+    /// The reading to compare the next one with
+    pub fn next(
+        previous: Option<(isize, BatteryState)>,
+        current: (isize, BatteryState),
+    ) -> Option<(isize, BatteryState)> {
+        let (level, status) = current;
+        match status {
+            BatteryState::BatteryAvailable => {}
+            BatteryState::BatteryCharging => return Some(current),
+            // A headset that drops out for a moment isn't unplugged or charged when it's back
+            _ => return previous,
+        }
 
-    let app_id = if cfg!(debug_assertions) {
-        // In debug mode, use a common AUMID to avoid needing a Start Menu shortcut
-        "Microsoft.Windows.Explorer"
-    } else {
-        // In release mode, AUMID can be anything because the executable is already registered to some AUMID generated by inno setup
-        "HeadsetBatteryIndicator.App"
-    };
+        match previous {
+            Some((last_level, BatteryState::BatteryAvailable))
+                if level > last_level && level - last_level < Self::HYSTERESIS =>
+            {
+                previous
+            }
+            _ => Some(current),
+        }
+    }
+}
 
-    // Ensure the system associates this running EXE with the same AUMID.
-    unsafe {
-        use anyhow::Context;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headset_control::Battery;
+    use BatteryState::*;
 
-        SetCurrentProcessExplicitAppUserModelID(&HSTRING::from(app_id))
-            .context("SetCurrentProcessExplicitAppUserModelID")?;
+    fn evaluate(
+        previous: (isize, BatteryState),
+        current: (isize, BatteryState),
+    ) -> Vec<NotificationKind> {
+        NotificationPolicy::evaluate(Some(previous), current)
     }
 
-    Ok(app_id.to_string())
+    /// The notifications for each reading in turn, like `Notifier::update` sees them
+    fn run(readings: &[(isize, BatteryState)]) -> Vec<NotificationKind> {
+        let mut previous = None;
+        let mut kinds = Vec::new();
+        for &reading in readings {
+            kinds.extend(NotificationPolicy::evaluate(previous, reading));
+            previous = NotificationPolicy::next(previous, reading);
+        }
+        kinds
+    }
+
+    #[test]
+    fn nothing_without_previous_reading() {
+        for reading in [
+            (2, BatteryAvailable),
+            (50, BatteryCharging),
+            (100, BatteryCharging),
+        ] {
+            assert!(NotificationPolicy::evaluate(None, reading).is_empty());
+        }
+    }
+
+    #[test]
+    fn low_when_dropping_to_threshold() {
+        assert_eq!(
+            evaluate((11, BatteryAvailable), (10, BatteryAvailable)),
+            [NotificationKind::Low(10)]
+        );
+        assert!(evaluate((10, BatteryAvailable), (9, BatteryAvailable)).is_empty());
+        assert!(evaluate((12, BatteryAvailable), (11, BatteryAvailable)).is_empty());
+    }
+
+    #[test]
+    fn critical_when_dropping_to_threshold() {
+        assert_eq!(
+            evaluate((4, BatteryAvailable), (3, BatteryAvailable)),
+            [NotificationKind::Critical(3)]
+        );
+        assert!(evaluate((3, BatteryAvailable), (2, BatteryAvailable)).is_empty());
+    }
+
+    #[test]
+    fn critical_instead_of_low_when_dropping_past_both() {
+        assert_eq!(
+            evaluate((50, BatteryAvailable), (2, BatteryAvailable)),
+            [NotificationKind::Critical(2)]
+        );
+    }
+
+    #[test]
+    fn no_low_while_charging() {
+        assert!(evaluate((11, BatteryCharging), (10, BatteryCharging)).is_empty());
+        assert!(evaluate((4, BatteryCharging), (3, BatteryCharging)).is_empty());
+    }
+
+    #[test]
+    fn charging_started() {
+        for status in [BatteryAvailable, BatteryUnavailable, BatteryTimeout] {
+            assert_eq!(
+                evaluate((40, status), (41, BatteryCharging)),
+                [NotificationKind::ChargingStarted(41)]
+            );
+        }
+        assert!(evaluate((40, BatteryCharging), (41, BatteryCharging)).is_empty());
+    }
+
+    #[test]
+    fn full_once_while_charging() {
+        assert_eq!(
+            evaluate((99, BatteryCharging), (100, BatteryCharging)),
+            [NotificationKind::Full]
+        );
+        assert!(evaluate((100, BatteryCharging), (100, BatteryCharging)).is_empty());
+        // Not at 100% after being unplugged
+        assert!(evaluate((99, BatteryAvailable), (100, BatteryAvailable)).is_empty());
+    }
+
+    #[test]
+    fn charging_started_before_full() {
+        assert_eq!(
+            evaluate((99, BatteryAvailable), (100, BatteryCharging)),
+            [NotificationKind::ChargingStarted(100)]
+        );
+    }
+
+    #[test]
+    fn nothing_while_unavailable() {
+        for status in [BatteryUnavailable, BatteryHiderror, BatteryTimeout] {
+            assert!(evaluate((50, BatteryAvailable), (0, status)).is_empty());
+            assert!(evaluate((11, BatteryAvailable), (-1, status)).is_empty());
+        }
+    }
+
+    #[test]
+    fn dropout_keeps_previous_reading() {
+        for status in [BatteryUnavailable, BatteryHiderror, BatteryTimeout] {
+            assert_eq!(
+                NotificationPolicy::next(Some((50, BatteryCharging)), (0, status)),
+                Some((50, BatteryCharging))
+            );
+            assert_eq!(NotificationPolicy::next(None, (0, status)), None);
+        }
+    }
+
+    #[test]
+    fn no_charging_started_after_dropout_while_charging() {
+        let readings = [
+            (50, BatteryCharging),
+            (0, BatteryUnavailable),
+            (51, BatteryCharging),
+        ];
+        assert!(run(&readings).is_empty());
+    }
+
+    #[test]
+    fn low_after_dropout_across_threshold() {
+        let readings = [
+            (12, BatteryAvailable),
+            (0, BatteryUnavailable),
+            (9, BatteryAvailable),
+        ];
+        assert_eq!(run(&readings), [NotificationKind::Low(9)]);
+    }
+
+    #[test]
+    fn wobbling_level_notifies_once() {
+        let readings = [
+            (11, BatteryAvailable),
+            (10, BatteryAvailable),
+            (11, BatteryAvailable),
+            (10, BatteryAvailable),
+            (14, BatteryAvailable),
+            (10, BatteryAvailable),
+        ];
+        assert_eq!(run(&readings), [NotificationKind::Low(10)]);
+    }
+
+    #[test]
+    fn low_again_after_rising_past_hysteresis() {
+        let readings = [
+            (11, BatteryAvailable),
+            (10, BatteryAvailable),
+            (15, BatteryAvailable),
+            (10, BatteryAvailable),
+        ];
+        assert_eq!(
+            run(&readings),
+            [NotificationKind::Low(10), NotificationKind::Low(10)]
+        );
+    }
+
+    #[test]
+    fn low_again_after_charging() {
+        let readings = [
+            (11, BatteryAvailable),
+            (10, BatteryAvailable),
+            (11, BatteryCharging),
+            (11, BatteryAvailable),
+            (10, BatteryAvailable),
+        ];
+        assert_eq!(
+            run(&readings),
+            [
+                NotificationKind::Low(10),
+                NotificationKind::ChargingStarted(11),
+                NotificationKind::Low(10),
+            ]
+        );
+    }
+
+    #[test]
+    fn discharge_from_full() {
+        let readings: Vec<_> = (0..=100)
+            .rev()
+            .map(|level| (level, BatteryAvailable))
+            .collect();
+        assert_eq!(
+            run(&readings),
+            [NotificationKind::Low(10), NotificationKind::Critical(3)]
+        );
+    }
+
+    #[test]
+    fn charge_to_full() {
+        let mut readings = vec![(20, BatteryAvailable)];
+        readings.extend((20..=100).map(|level| (level, BatteryCharging)));
+        readings.push((100, BatteryCharging));
+        assert_eq!(
+            run(&readings),
+            [
+                NotificationKind::ChargingStarted(20),
+                NotificationKind::Full
+            ]
+        );
+    }
+
+    fn device(level: isize, status: BatteryState) -> Device {
+        Device {
+            id_vendor: "0x1038".to_string(),
+            id_product: "0x12ad".to_string(),
+            battery: Battery { status, level },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unplug_reminder_once_after_delay() {
+        let mut reminder = UnplugReminder::default();
+        let after = Duration::from_secs(30 * 60);
+        let start = Instant::now();
+        let full = [device(100, BatteryCharging)];
+
+        assert!(reminder.update(&full, after, start).is_empty());
+        let later = start + Duration::from_secs(29 * 60);
+        assert!(reminder.update(&full, after, later).is_empty());
+        let later = start + after;
+        assert_eq!(reminder.update(&full, after, later).len(), 1);
+        let later = start + 2 * after;
+        assert!(reminder.update(&full, after, later).is_empty());
+    }
+
+    #[test]
+    fn unplug_reminder_not_while_still_charging_up() {
+        let mut reminder = UnplugReminder::default();
+        let after = Duration::from_secs(60);
+        let start = Instant::now();
+
+        let charging = [device(99, BatteryCharging)];
+        assert!(reminder.update(&charging, after, start).is_empty());
+        let full = [device(100, BatteryCharging)];
+        let later = start + after;
+        assert!(reminder.update(&full, after, later).is_empty());
+        assert_eq!(reminder.update(&full, after, later + after).len(), 1);
+    }
+
+    #[test]
+    fn unplug_reminder_again_after_unplugging() {
+        let mut reminder = UnplugReminder::default();
+        let after = Duration::from_secs(60);
+        let start = Instant::now();
+        let full = [device(100, BatteryCharging)];
+        let dropout = [device(0, BatteryTimeout)];
+        let unplugged = [device(100, BatteryAvailable)];
+
+        reminder.update(&full, after, start);
+        // Still counted from the start through a dropout
+        reminder.update(&dropout, after, start + after / 2);
+        assert_eq!(reminder.update(&full, after, start + after).len(), 1);
+
+        reminder.update(&unplugged, after, start + 2 * after);
+        reminder.update(&full, after, start + 3 * after);
+        assert_eq!(reminder.update(&full, after, start + 4 * after).len(), 1);
+    }
 }
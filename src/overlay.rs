@@ -0,0 +1,192 @@
+//! Shows the battery of the selected headset on top of other windows, including fullscreen
+//! games. Only Wayland compositors with the wlr-layer-shell protocol, like Hyprland and sway,
+//! are supported for now.
+
+use std::sync::mpsc;
+use std::thread;
+
+use anyhow::Context;
+use log::error;
+use winit::window::Theme;
+
+use crate::headset_control::{BatteryState, Device};
+use crate::platform::tray::{BATTERY_SIZE, draw_battery};
+
+mod wayland;
+
+pub const DEFAULT_MARGIN: u32 = 16;
+pub const DEFAULT_OPACITY: u8 = 80;
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 44;
+const BACKGROUND: [u8; 3] = [0x20, 0x20, 0x20];
+const TEXT: [u8; 3] = [0xf0, 0xf0, 0xf0];
+/// The game side of the chatmix bar
+const CHATMIX_EMPTY: [u8; 3] = [0x50, 0x50, 0x50];
+/// Height of the chatmix bar
+const CHATMIX_BAR: u32 = 3;
+
+/// Glyphs are 5x7 pixels, drawn at this scale
+const TEXT_SCALE: u32 = 3;
+
+/// Read from the `Overlay` subkey
+#[derive(Debug, Clone)]
+pub struct OverlayConfig {
+    pub anchor: Corner,
+    /// Distance from the left or right edge of the screen
+    pub margin_x: u32,
+    /// Distance from the top or bottom edge of the screen
+    pub margin_y: u32,
+    /// 0-100, the background and the text fade together
+    pub opacity: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    #[default]
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    /// Parses the `Anchor` setting, like `BottomLeft`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "topleft" => Some(Self::TopLeft),
+            "topright" => Some(Self::TopRight),
+            "bottomleft" => Some(Self::BottomLeft),
+            "bottomright" => Some(Self::BottomRight),
+            _ => None,
+        }
+    }
+}
+
+/// Level and state of the shown battery and the headset's chatmix, `None` while there's no
+/// headset
+type Battery = Option<(isize, BatteryState, Option<u8>)>;
+
+/// A small always-on-top panel with the battery icon and level.
+///
+/// The surface is drawn and kept up to date on a separate thread, which owns the
+/// connection to the compositor.
+pub struct Overlay {
+    sender: wayland::Sender,
+}
+
+impl Overlay {
+    pub fn start(config: OverlayConfig) -> anyhow::Result<Self> {
+        let (sender, receiver) = wayland::channel();
+        let (started_sender, started) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("overlay".to_string())
+            .spawn(move || {
+                if let Err(e) = wayland::run(config, receiver, started_sender) {
+                    error!("Overlay stopped: {e:?}");
+                }
+            })
+            .context("spawning overlay thread")?;
+
+        started
+            .recv()
+            .context("overlay thread stopped while starting")??;
+
+        Ok(Self { sender })
+    }
+
+    /// Shows the battery of the device, or that there's no headset
+    pub fn show(&self, device: Option<&Device>) {
+        let battery =
+            device.map(|device| (device.battery.level, device.battery.status, device.chatmix));
+        if self.sender.send(battery).is_err() {
+            error!("Overlay thread has stopped");
+        }
+    }
+}
+
+/// The overlay as premultiplied BGRA pixels, `WIDTH` wide and `HEIGHT` high
+fn draw(battery: Battery, opacity: u8) -> Vec<u8> {
+    let mut rgb = BACKGROUND.repeat((WIDTH * HEIGHT) as usize);
+
+    let (level, state, chatmix) = battery.unwrap_or((-1, BatteryState::BatteryUnavailable, None));
+    let icon = draw_battery(Theme::Dark, level, state, false);
+    let icon_top = (HEIGHT - BATTERY_SIZE) / 2;
+    for y in 0..BATTERY_SIZE {
+        for x in 0..BATTERY_SIZE {
+            let src = &icon[((y * BATTERY_SIZE + x) * 4) as usize..][..4];
+            let i = (((icon_top + y) * WIDTH + 6 + x) * 3) as usize;
+            for c in 0..3 {
+                let blended = u32::from(src[c]) * u32::from(src[3])
+                    + u32::from(rgb[i + c]) * u32::from(255 - src[3]);
+                rgb[i + c] = (blended / 255) as u8;
+            }
+        }
+    }
+
+    let text = match battery {
+        Some((level, _, _)) if level >= 0 => format!("{}%", level.min(100)),
+        _ => "-".to_string(),
+    };
+    let left = 6 + BATTERY_SIZE + 6;
+    let top = (HEIGHT - 7 * TEXT_SCALE) / 2;
+    for (n, ch) in text.chars().enumerate() {
+        let glyph_left = left + n as u32 * 6 * TEXT_SCALE;
+        for (row, bits) in glyph(ch).into_iter().enumerate() {
+            for col in 0..5 {
+                if bits & (0b10000 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..TEXT_SCALE {
+                    for dx in 0..TEXT_SCALE {
+                        let x = glyph_left + col * TEXT_SCALE + dx;
+                        let y = top + row as u32 * TEXT_SCALE + dy;
+                        let i = ((y * WIDTH + x) * 3) as usize;
+                        rgb[i..i + 3].copy_from_slice(&TEXT);
+                    }
+                }
+            }
+        }
+    }
+
+    // A bar along the bottom, filled from the left as the dial turns from game to chat
+    if let Some(chatmix) = chatmix {
+        let (left, right) = (6, WIDTH - 6);
+        let filled = left + (right - left) * u32::from(chatmix.min(128)) / 128;
+        for y in HEIGHT - CHATMIX_BAR - 2..HEIGHT - 2 {
+            for x in left..right {
+                let color = if x < filled { TEXT } else { CHATMIX_EMPTY };
+                let i = ((y * WIDTH + x) * 3) as usize;
+                rgb[i..i + 3].copy_from_slice(&color);
+            }
+        }
+    }
+
+    let alpha = u32::from(opacity.min(100)) * 255 / 100;
+    rgb.chunks_exact(3)
+        .flat_map(|px| {
+            let [r, g, b] = [px[0], px[1], px[2]].map(|c| (u32::from(c) * alpha / 255) as u8);
+            [b, g, r, alpha as u8]
+        })
+        .collect()
+}
+
+/// Rows of a 5x7 glyph, the highest bit is the leftmost pixel
+fn glyph(ch: char) -> [u8; 7] {
+    match ch {
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '-' => [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+        _ => [0; 7],
+    }
+}
@@ -0,0 +1,304 @@
+//! The overlay as a wlr-layer-shell surface, which compositors keep above fullscreen windows.
+
+use std::sync::mpsc;
+
+use anyhow::{Context, anyhow};
+use smithay_client_toolkit::{
+    compositor::{CompositorHandler, CompositorState, Region},
+    delegate_compositor, delegate_layer, delegate_output, delegate_registry, delegate_shm,
+    output::{OutputHandler, OutputState},
+    reexports::{
+        calloop::{
+            EventLoop,
+            channel::{self, Channel, Event},
+        },
+        calloop_wayland_source::WaylandSource,
+        client::{
+            Connection, QueueHandle,
+            globals::registry_queue_init,
+            protocol::{wl_output, wl_shm, wl_surface},
+        },
+    },
+    registry::{ProvidesRegistryState, RegistryState},
+    registry_handlers,
+    shell::{
+        WaylandSurface,
+        wlr_layer::{
+            Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
+            LayerSurfaceConfigure,
+        },
+    },
+    shm::{Shm, ShmHandler, slot::SlotPool},
+};
+
+use super::{Battery, Corner, HEIGHT, OverlayConfig, WIDTH};
+
+pub type Sender = channel::Sender<Battery>;
+
+pub fn channel() -> (Sender, Channel<Battery>) {
+    channel::channel()
+}
+
+/// Runs until the app exits, after telling `started` whether the surface could be created
+pub fn run(
+    config: OverlayConfig,
+    receiver: Channel<Battery>,
+    started: mpsc::Sender<anyhow::Result<()>>,
+) -> anyhow::Result<()> {
+    let mut event_loop: EventLoop<State> =
+        EventLoop::try_new().context("creating overlay event loop")?;
+
+    let mut state = match State::connect(&config, &event_loop) {
+        Ok(state) => {
+            let _ = started.send(Ok(()));
+            state
+        }
+        Err(e) => {
+            let _ = started.send(Err(e));
+            return Ok(());
+        }
+    };
+
+    event_loop
+        .handle()
+        .insert_source(receiver, |event, _, state| match event {
+            Event::Msg(battery) => state.set_battery(battery),
+            Event::Closed => state.exit = true,
+        })
+        .map_err(|e| anyhow!("listening for battery updates: {e}"))?;
+
+    while !state.exit {
+        event_loop
+            .dispatch(None, &mut state)
+            .context("dispatching Wayland events")?;
+    }
+
+    Ok(())
+}
+
+struct State {
+    registry_state: RegistryState,
+    output_state: OutputState,
+    shm: Shm,
+    pool: SlotPool,
+    layer: LayerSurface,
+    opacity: u8,
+    battery: Battery,
+    /// Nothing can be drawn before the compositor has configured the surface
+    configured: bool,
+    exit: bool,
+}
+
+impl State {
+    fn connect(config: &OverlayConfig, event_loop: &EventLoop<State>) -> anyhow::Result<Self> {
+        let conn = Connection::connect_to_env().context("connecting to the Wayland compositor")?;
+        let (globals, event_queue) =
+            registry_queue_init(&conn).context("listing Wayland globals")?;
+        let qh = event_queue.handle();
+
+        let compositor = CompositorState::bind(&globals, &qh).context("binding wl_compositor")?;
+        let layer_shell = LayerShell::bind(&globals, &qh)
+            .context("the compositor doesn't support wlr-layer-shell")?;
+        let shm = Shm::bind(&globals, &qh).context("binding wl_shm")?;
+
+        let surface = compositor.create_surface(&qh);
+        let layer = layer_shell.create_layer_surface(
+            &qh,
+            surface,
+            Layer::Overlay,
+            Some("headset-battery-indicator"),
+            None,
+        );
+
+        let (vertical, horizontal) = match config.anchor {
+            Corner::TopLeft => (Anchor::TOP, Anchor::LEFT),
+            Corner::TopRight => (Anchor::TOP, Anchor::RIGHT),
+            Corner::BottomLeft => (Anchor::BOTTOM, Anchor::LEFT),
+            Corner::BottomRight => (Anchor::BOTTOM, Anchor::RIGHT),
+        };
+        let (x, y) = (config.margin_x as i32, config.margin_y as i32);
+        layer.set_anchor(vertical | horizontal);
+        layer.set_margin(y, x, y, x);
+        layer.set_size(WIDTH, HEIGHT);
+        layer.set_exclusive_zone(-1);
+        layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+
+        // An empty input region lets clicks through to the game
+        let region = Region::new(&compositor).context("creating input region")?;
+        layer.set_input_region(Some(region.wl_region()));
+
+        // The first commit has no buffer, the compositor answers with a configure
+        layer.commit();
+
+        let pool = SlotPool::new((WIDTH * HEIGHT * 4) as usize, &shm)
+            .context("creating overlay buffer pool")?;
+
+        WaylandSource::new(conn, event_queue)
+            .insert(event_loop.handle())
+            .map_err(|e| anyhow!("listening for Wayland events: {e}"))?;
+
+        Ok(Self {
+            registry_state: RegistryState::new(&globals),
+            output_state: OutputState::new(&globals, &qh),
+            shm,
+            pool,
+            layer,
+            opacity: config.opacity,
+            battery: None,
+            configured: false,
+            exit: false,
+        })
+    }
+
+    fn set_battery(&mut self, battery: Battery) {
+        if battery != self.battery {
+            self.battery = battery;
+            self.draw();
+        }
+    }
+
+    fn draw(&mut self) {
+        if !self.configured {
+            return;
+        }
+
+        let stride = WIDTH as i32 * 4;
+        let (buffer, canvas) = match self.pool.create_buffer(
+            WIDTH as i32,
+            HEIGHT as i32,
+            stride,
+            wl_shm::Format::Argb8888,
+        ) {
+            Ok(buffer) => buffer,
+            Err(e) => {
+                log::error!("Failed to create overlay buffer: {e}");
+                return;
+            }
+        };
+        canvas.copy_from_slice(&super::draw(self.battery, self.opacity));
+
+        let surface = self.layer.wl_surface();
+        surface.damage_buffer(0, 0, WIDTH as i32, HEIGHT as i32);
+        if let Err(e) = buffer.attach_to(surface) {
+            log::error!("Failed to attach overlay buffer: {e}");
+            return;
+        }
+        self.layer.commit();
+    }
+}
+
+impl LayerShellHandler for State {
+    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {
+        log::info!("The compositor closed the overlay");
+        self.exit = true;
+    }
+
+    fn configure(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _layer: &LayerSurface,
+        _configure: LayerSurfaceConfigure,
+        _serial: u32,
+    ) {
+        self.configured = true;
+        self.draw();
+    }
+}
+
+/// The overlay is only redrawn when the battery changes, so frame callbacks aren't used
+impl CompositorHandler for State {
+    fn scale_factor_changed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _new_factor: i32,
+    ) {
+    }
+
+    fn transform_changed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _new_transform: wl_output::Transform,
+    ) {
+    }
+
+    fn frame(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _time: u32,
+    ) {
+    }
+
+    fn surface_enter(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _output: &wl_output::WlOutput,
+    ) {
+    }
+
+    fn surface_leave(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _output: &wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl OutputHandler for State {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+
+    fn new_output(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+
+    fn update_output(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+
+    fn output_destroyed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl ShmHandler for State {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
+impl ProvidesRegistryState for State {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+    registry_handlers![OutputState];
+}
+
+delegate_compositor!(State);
+delegate_output!(State);
+delegate_shm!(State);
+delegate_layer!(State);
+delegate_registry!(State);
@@ -0,0 +1,156 @@
+//! The colors that tell charging, low battery and the headsets in the history chart apart,
+//! with palettes for the common color vision deficiencies. Chosen with the `Palette` value,
+//! since red and green alone don't say "low" and "charging" to everyone.
+
+use std::sync::OnceLock;
+
+static PALETTE: OnceLock<Palette> = OnceLock::new();
+
+/// An RGB color
+pub type Rgb = [u8; 3];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Palette {
+    /// Green for charging and red for low battery
+    #[default]
+    Default,
+    /// Blue and vermilion, for red-green color blindness with weak green
+    Deuteranopia,
+    /// Blue and amber, for red-green color blindness with weak red, where red looks dark
+    Protanopia,
+    /// Teal and red, for blue-yellow color blindness
+    Tritanopia,
+}
+
+impl Palette {
+    /// In the order of the palette icons generated by build.rs
+    pub const ALL: [Palette; 4] = [
+        Palette::Default,
+        Palette::Deuteranopia,
+        Palette::Protanopia,
+        Palette::Tritanopia,
+    ];
+
+    /// Parses the `Palette` setting, like `Deuteranopia`
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|palette| palette.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Default => "Default",
+            Palette::Deuteranopia => "Deuteranopia",
+            Palette::Protanopia => "Protanopia",
+            Palette::Tritanopia => "Tritanopia",
+        }
+    }
+
+    /// The battery level while charging, in the drawn icons. The charging dot of the Windows
+    /// icons has its own colors in build.rs.
+    #[cfg(not(windows))]
+    pub fn charging(self) -> Rgb {
+        match self {
+            Palette::Default => [0x4c, 0xaf, 0x50],
+            Palette::Deuteranopia | Palette::Protanopia => [0x56, 0xb4, 0xe9],
+            Palette::Tritanopia => [0x00, 0x99, 0x88],
+        }
+    }
+
+    /// The battery level at 10% and below, in the drawn icons
+    #[cfg(not(windows))]
+    pub fn low(self) -> Rgb {
+        match self {
+            Palette::Default => [0xe5, 0x39, 0x35],
+            Palette::Deuteranopia => [0xd5, 0x5e, 0x00],
+            Palette::Protanopia => [0xff, 0xb0, 0x00],
+            Palette::Tritanopia => [0xdc, 0x32, 0x20],
+        }
+    }
+
+    /// A line for each headset in the history chart, repeating after the last
+    #[cfg(windows)]
+    pub fn lines(self, dark: bool) -> [Rgb; 5] {
+        match (self, dark) {
+            (Palette::Default, true) => [
+                [96, 205, 255],
+                [255, 170, 60],
+                [120, 220, 120],
+                [230, 120, 230],
+                [255, 230, 90],
+            ],
+            (Palette::Default, false) => [
+                [0, 103, 192],
+                [220, 110, 0],
+                [16, 137, 62],
+                [180, 50, 170],
+                [150, 120, 0],
+            ],
+            // Okabe and Ito's palette, which stays apart with either kind of red-green
+            // color blindness
+            (Palette::Deuteranopia | Palette::Protanopia, true) => [
+                [0x56, 0xb4, 0xe9],
+                [0xe6, 0x9f, 0x00],
+                [0xf0, 0xe4, 0x42],
+                [0xcc, 0x79, 0xa7],
+                [0x00, 0x9e, 0x73],
+            ],
+            (Palette::Deuteranopia | Palette::Protanopia, false) => [
+                [0x00, 0x72, 0xb2],
+                [0xd5, 0x5e, 0x00],
+                [0x00, 0x9e, 0x73],
+                [0xcc, 0x79, 0xa7],
+                [0xe6, 0x9f, 0x00],
+            ],
+            (Palette::Tritanopia, true) => [
+                [0x33, 0xbb, 0xee],
+                [0xee, 0x66, 0x77],
+                [0xdd, 0xdd, 0xdd],
+                [0xaa, 0x33, 0x77],
+                [0x44, 0xaa, 0x99],
+            ],
+            (Palette::Tritanopia, false) => [
+                [0x00, 0x77, 0xbb],
+                [0xcc, 0x33, 0x11],
+                [0x55, 0x55, 0x55],
+                [0xaa, 0x33, 0x77],
+                [0x00, 0x99, 0x88],
+            ],
+        }
+    }
+
+    /// Behind the times a headset was charging in the history chart
+    #[cfg(windows)]
+    pub fn charging_shade(self, dark: bool) -> Rgb {
+        match (self, dark) {
+            (Palette::Default, true) => [30, 70, 40],
+            (Palette::Default, false) => [215, 240, 215],
+            (Palette::Deuteranopia | Palette::Protanopia, true) => [25, 55, 75],
+            (Palette::Deuteranopia | Palette::Protanopia, false) => [210, 232, 245],
+            (Palette::Tritanopia, true) => [20, 60, 56],
+            (Palette::Tritanopia, false) => [205, 238, 234],
+        }
+    }
+
+    /// Added to the resource id of a charging tray icon, the icons of each palette are 100 apart
+    #[cfg(windows)]
+    pub fn icon_offset(self) -> u16 {
+        Self::ALL
+            .iter()
+            .position(|&palette| palette == self)
+            .unwrap_or_default() as u16
+            * 100
+    }
+}
+
+/// Chooses the palette for the rest of the run, before the first icon is drawn
+pub fn set(palette: Palette) {
+    if PALETTE.set(palette).is_err() {
+        log::warn!("The palette was already chosen");
+    }
+}
+
+pub fn current() -> Palette {
+    PALETTE.get().copied().unwrap_or_default()
+}
@@ -0,0 +1,243 @@
+//! Windows performance counters for PerfMon and monitoring agents.
+//!
+//! The counter sets are described in packaging/perfcounters.man, which has to be
+//! registered with `lodctr` before any consumer can see them.
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use log::{debug, error};
+use windows::{
+    Win32::{
+        Foundation::{HANDLE, WIN32_ERROR},
+        System::Performance::{
+            PERF_COUNTER_INFO, PERF_COUNTERSET_INFO, PERF_COUNTERSET_INSTANCE,
+            PERF_COUNTERSET_MULTI_INSTANCES, PERF_COUNTERSET_SINGLE_INSTANCE, PERF_DETAIL_NOVICE,
+            PERF_PROVIDER_CONTEXT, PerfCreateInstance, PerfDeleteInstance, PerfSetCounterSetInfo,
+            PerfSetULongCounterValue, PerfStartProviderEx, PerfStopProvider,
+        },
+    },
+    core::{GUID, HSTRING},
+};
+
+use crate::headset_control::{BatteryState, Device};
+
+// Must match packaging/perfcounters.man
+const PROVIDER: GUID = GUID::from_u128(0x9768dc80_8ec8_472f_b5c8_5001414e6ef1);
+const HEADSETS: GUID = GUID::from_u128(0x65492ad5_6c3c_4a1a_8f94_913348a3f561);
+const POLLING: GUID = GUID::from_u128(0x66012f1a_a3ba_4a7d_ba38_8d6416af4da2);
+
+const BATTERY_PERCENT: u32 = 1;
+const CHARGING: u32 = 2;
+const CONNECTED: u32 = 3;
+
+const POLLS: u32 = 1;
+const FAILED_POLLS: u32 = 2;
+const DEVICES: u32 = 3;
+
+// Counter types from winperf.h
+const PERF_COUNTER_RAWCOUNT: u32 = 0x0001_0000;
+const PERF_COUNTER_COUNTER: u32 = 0x1041_0400;
+
+/// Layout expected by `PerfSetCounterSetInfo`
+#[repr(C)]
+struct Template<const N: usize> {
+    info: PERF_COUNTERSET_INFO,
+    counters: [PERF_COUNTER_INFO; N],
+}
+
+/// Publishes the battery of every headset and the polling statistics
+pub struct PerfCounters {
+    provider: HANDLE,
+    polling: *mut PERF_COUNTERSET_INSTANCE,
+    /// Keyed by [`Device::id`]
+    instances: HashMap<String, *mut PERF_COUNTERSET_INSTANCE>,
+    next_instance_id: u32,
+    polls: u32,
+    failed_polls: u32,
+}
+
+impl PerfCounters {
+    pub fn start() -> anyhow::Result<Self> {
+        let mut provider = HANDLE::default();
+        let context = PERF_PROVIDER_CONTEXT {
+            ContextSize: size_of::<PERF_PROVIDER_CONTEXT>() as u32,
+            ..Default::default()
+        };
+        WIN32_ERROR(unsafe {
+            PerfStartProviderEx(&PROVIDER, Some(&context as *const _), &mut provider)
+        })
+        .ok()
+        .context("PerfStartProviderEx")?;
+
+        // Stops the provider if anything below fails
+        let mut counters = Self {
+            provider,
+            polling: std::ptr::null_mut(),
+            instances: HashMap::new(),
+            next_instance_id: 0,
+            polls: 0,
+            failed_polls: 0,
+        };
+
+        counters
+            .set_counter_set_info(
+                HEADSETS,
+                PERF_COUNTERSET_MULTI_INSTANCES,
+                [PERF_COUNTER_RAWCOUNT; 3],
+            )
+            .context("registering headset counters")?;
+        counters
+            .set_counter_set_info(
+                POLLING,
+                PERF_COUNTERSET_SINGLE_INSTANCE,
+                [
+                    PERF_COUNTER_COUNTER,
+                    PERF_COUNTER_RAWCOUNT,
+                    PERF_COUNTER_RAWCOUNT,
+                ],
+            )
+            .context("registering polling counters")?;
+
+        counters.polling =
+            unsafe { PerfCreateInstance(counters.provider, &POLLING, &HSTRING::from("_Total"), 0) };
+        if counters.polling.is_null() {
+            return Err(windows::core::Error::from_win32()).context("creating polling instance");
+        }
+
+        Ok(counters)
+    }
+
+    /// Counter ids are numbered from 1, in the order of `types`
+    fn set_counter_set_info<const N: usize>(
+        &self,
+        counter_set: GUID,
+        instance_type: u32,
+        types: [u32; N],
+    ) -> anyhow::Result<()> {
+        let mut template = Template {
+            info: PERF_COUNTERSET_INFO {
+                CounterSetGuid: counter_set,
+                ProviderGuid: PROVIDER,
+                NumCounters: N as u32,
+                InstanceType: instance_type,
+            },
+            counters: std::array::from_fn(|i| PERF_COUNTER_INFO {
+                CounterId: i as u32 + 1,
+                Type: types[i],
+                // Stored by value in the instance block
+                Attrib: 0,
+                Size: size_of::<u32>() as u32,
+                DetailLevel: PERF_DETAIL_NOVICE.0,
+                Scale: 0,
+                Offset: (i * size_of::<u32>()) as u32,
+            }),
+        };
+
+        WIN32_ERROR(unsafe {
+            PerfSetCounterSetInfo(
+                self.provider,
+                &mut template.info,
+                size_of::<Template<N>>() as u32,
+            )
+        })
+        .ok()
+        .context("PerfSetCounterSetInfo")
+    }
+
+    /// Counts a poll, and a failure if `ok` is false
+    pub fn record_poll(&mut self, ok: bool) {
+        self.polls = self.polls.wrapping_add(1);
+        if !ok {
+            self.failed_polls += 1;
+        }
+
+        self.set(self.polling, POLLS, self.polls);
+        self.set(self.polling, FAILED_POLLS, self.failed_polls);
+    }
+
+    pub fn publish(&mut self, devices: &[Device]) {
+        self.set(self.polling, DEVICES, devices.len() as u32);
+
+        // Headsets that are no longer found disappear from PerfMon
+        self.instances.retain(|id, instance| {
+            let found = devices.iter().any(|d| d.id() == *id);
+            if !found {
+                debug!("Removing performance counter instance for {id}");
+                unsafe { PerfDeleteInstance(self.provider, *instance) };
+            }
+            found
+        });
+
+        for device in devices {
+            let Some(instance) = self.instance(device) else {
+                continue;
+            };
+
+            let connected = matches!(
+                device.battery.status,
+                BatteryState::BatteryAvailable | BatteryState::BatteryCharging
+            );
+            let level = if connected {
+                device.battery.level.clamp(0, 100) as u32
+            } else {
+                0
+            };
+            let charging = device.battery.status == BatteryState::BatteryCharging;
+
+            self.set(instance, BATTERY_PERCENT, level);
+            self.set(instance, CHARGING, charging as u32);
+            self.set(instance, CONNECTED, connected as u32);
+        }
+    }
+
+    fn instance(&mut self, device: &Device) -> Option<*mut PERF_COUNTERSET_INSTANCE> {
+        let id = device.id();
+        if let Some(instance) = self.instances.get(&id) {
+            return Some(*instance);
+        }
+
+        // Parentheses would break counter paths like `\Headset Battery(name)\Charging`
+        let name = format!("{} {id}", device.product).replace(['(', ')'], "");
+        let instance = unsafe {
+            PerfCreateInstance(
+                self.provider,
+                &HEADSETS,
+                &HSTRING::from(&name),
+                self.next_instance_id,
+            )
+        };
+        if instance.is_null() {
+            error!(
+                "Failed to create performance counter instance for {name}: {:?}",
+                windows::core::Error::from_win32()
+            );
+            return None;
+        }
+
+        self.next_instance_id += 1;
+        self.instances.insert(id, instance);
+        Some(instance)
+    }
+
+    fn set(&self, instance: *mut PERF_COUNTERSET_INSTANCE, counter: u32, value: u32) {
+        let status = unsafe { PerfSetULongCounterValue(self.provider, instance, counter, value) };
+        if let Err(e) = WIN32_ERROR(status).ok() {
+            debug!("Failed to set performance counter {counter}: {e:?}");
+        }
+    }
+}
+
+impl Drop for PerfCounters {
+    fn drop(&mut self) {
+        unsafe {
+            for instance in self.instances.values() {
+                PerfDeleteInstance(self.provider, *instance);
+            }
+            if !self.polling.is_null() {
+                PerfDeleteInstance(self.provider, self.polling);
+            }
+            PerfStopProvider(self.provider);
+        }
+    }
+}
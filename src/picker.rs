@@ -0,0 +1,79 @@
+//! Asks for the headsetcontrol executable to run, when it isn't installed in the usual place
+
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+/// Asks for the executable, `None` when the dialog was cancelled
+#[cfg(windows)]
+pub fn ask_headsetcontrol() -> anyhow::Result<Option<PathBuf>> {
+    use windows::{
+        Win32::UI::Controls::Dialogs::{
+            CommDlgExtendedError, GetOpenFileNameW, OFN_FILEMUSTEXIST, OFN_NOCHANGEDIR,
+            OFN_PATHMUSTEXIST, OPENFILENAMEW,
+        },
+        core::{PWSTR, w},
+    };
+
+    // Pairs of description and pattern, ending with an empty string
+    let filter = w!("headsetcontrol.exe\0headsetcontrol*.exe\0Programs (*.exe)\0*.exe\0");
+
+    let mut file = vec![0u16; 1024];
+    let mut dialog = OPENFILENAMEW {
+        lStructSize: size_of::<OPENFILENAMEW>() as u32,
+        lpstrFilter: filter,
+        nFilterIndex: 1,
+        lpstrFile: PWSTR(file.as_mut_ptr()),
+        nMaxFile: file.len() as u32,
+        lpstrTitle: w!("headsetcontrol"),
+        Flags: OFN_FILEMUSTEXIST | OFN_PATHMUSTEXIST | OFN_NOCHANGEDIR,
+        ..Default::default()
+    };
+
+    if !unsafe { GetOpenFileNameW(&mut dialog) }.as_bool() {
+        let error = unsafe { CommDlgExtendedError() };
+        if error.0 != 0 {
+            anyhow::bail!("GetOpenFileNameW failed with {:#x}", error.0);
+        }
+        return Ok(None);
+    }
+
+    let len = file.iter().position(|&c| c == 0).unwrap_or(file.len());
+    Ok(Some(PathBuf::from(String::from_utf16_lossy(&file[..len]))))
+}
+
+/// Asks with zenity, which blocks the tray until the dialog is closed
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn ask_headsetcontrol() -> anyhow::Result<Option<PathBuf>> {
+    let output = std::process::Command::new("zenity")
+        .args(["--file-selection", "--title=headsetcontrol"])
+        .output()
+        .context("running zenity")?;
+    // Exits with 1 when cancelled
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(printed_path(&output.stdout)))
+}
+
+/// Asks with AppleScript's open dialog, which blocks the menu bar until it's closed
+#[cfg(target_os = "macos")]
+pub fn ask_headsetcontrol() -> anyhow::Result<Option<PathBuf>> {
+    let output = std::process::Command::new("osascript")
+        .args([
+            "-e",
+            "POSIX path of (choose file with prompt \"headsetcontrol\")",
+        ])
+        .output()
+        .context("running osascript")?;
+    // Exits with 1 when cancelled
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(printed_path(&output.stdout)))
+}
+
+#[cfg(not(windows))]
+fn printed_path(output: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(output).trim_end_matches('\n'))
+}
@@ -0,0 +1,124 @@
+use std::fs::File;
+use std::io::Write;
+use std::os::windows::io::{FromRawHandle, OwnedHandle};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+use anyhow::Context;
+use log::{debug, error, info};
+use windows::{
+    Win32::{
+        Foundation::ERROR_PIPE_CONNECTED,
+        Storage::FileSystem::PIPE_ACCESS_OUTBOUND,
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+        },
+    },
+    core::HSTRING,
+};
+
+use crate::api;
+use crate::headset_control::Device;
+
+pub const PIPE_NAME: &str = r"\\.\pipe\HeadsetBatteryIndicator";
+
+const BUFFER_SIZE: u32 = 4096;
+
+/// Named pipe that writes the device list as JSON to every client that connects, then
+/// closes the connection. Used by `--query`, and by scripts that shouldn't need a TCP port.
+pub struct PipeServer {
+    /// The device list, serialized once per poll
+    devices: Arc<RwLock<String>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl PipeServer {
+    pub fn start() -> anyhow::Result<Self> {
+        let devices = Arc::new(RwLock::new("[]".to_string()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        {
+            let devices = devices.clone();
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("pipe-server".to_string())
+                .spawn(move || serve(&devices, &stop))
+                .context("spawning pipe server thread")?;
+        }
+
+        info!("Serving device list on {PIPE_NAME}");
+
+        Ok(Self { devices, stop })
+    }
+
+    pub fn publish(&self, devices: &[Device], selected: usize) {
+        match serde_json::to_string(&api::device_list(devices, selected)) {
+            Ok(json) => *self.devices.write().unwrap_or_else(|e| e.into_inner()) = json,
+            Err(e) => error!("Failed to serialize device list: {e:?}"),
+        }
+    }
+}
+
+impl Drop for PipeServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Connect once to wake up the thread waiting for a client, so it sees the stop flag
+        let _ = File::open(PIPE_NAME);
+    }
+}
+
+fn serve(devices: &RwLock<String>, stop: &AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        if let Err(e) = serve_client(devices) {
+            error!("Named pipe failed: {e:?}");
+            break;
+        }
+    }
+}
+
+/// Creates a pipe instance, waits for a client and sends it the device list
+fn serve_client(devices: &RwLock<String>) -> anyhow::Result<()> {
+    let handle = unsafe {
+        CreateNamedPipeW(
+            &HSTRING::from(PIPE_NAME),
+            PIPE_ACCESS_OUTBOUND,
+            PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_UNLIMITED_INSTANCES,
+            BUFFER_SIZE,
+            0,
+            0,
+            None,
+        )
+    };
+    if handle.is_invalid() {
+        return Err(windows::core::Error::from_win32()).context("CreateNamedPipeW");
+    }
+    // Closes the pipe instance when dropped
+    let pipe = File::from(unsafe { OwnedHandle::from_raw_handle(handle.0) });
+
+    if let Err(e) = unsafe { ConnectNamedPipe(handle, None) } {
+        // The client connected between creating the pipe and waiting for it
+        if e.code() != ERROR_PIPE_CONNECTED.to_hresult() {
+            return Err(e).context("ConnectNamedPipe");
+        }
+    }
+
+    let json = devices.read().unwrap_or_else(|e| e.into_inner()).clone();
+    if let Err(e) = send(&pipe, json.as_bytes()) {
+        debug!("Failed to write to pipe client: {e:?}");
+    }
+
+    unsafe {
+        let _ = DisconnectNamedPipe(handle);
+    }
+
+    Ok(())
+}
+
+fn send(mut pipe: &File, data: &[u8]) -> std::io::Result<()> {
+    pipe.write_all(data)?;
+    // Wait until the client has read everything before disconnecting it
+    pipe.sync_all()
+}
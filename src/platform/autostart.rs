@@ -0,0 +1,59 @@
+//! Starting the app when the user logs in, which is only implemented on Windows so far
+
+use anyhow::Result;
+
+#[cfg(windows)]
+mod windows;
+
+#[cfg(windows)]
+pub use windows::Method;
+
+#[cfg(windows)]
+pub type Native = Method;
+#[cfg(not(windows))]
+pub type Native = Unsupported;
+
+/// Passed to the program when it's started automatically, so that it can
+/// switch to its own directory (Run key entries start in System32)
+pub const AUTOSTART_ARG: &str = "--autostart";
+
+/// A way of starting the app on login, offered in the tray menu
+pub trait Autostart: Copy + PartialEq + std::fmt::Debug + 'static {
+    /// In the order they're shown in the menu, the menu is hidden without any
+    const ALL: &'static [Self];
+
+    fn label(self) -> String;
+
+    fn is_enabled(self) -> bool;
+
+    /// Starts checking the methods that are slow to check in the background,
+    /// so building the menu doesn't have to wait for them
+    fn preload() {}
+
+    /// Enables autostart using `method` (or disables it with `None`).
+    ///
+    /// All other methods are removed, so the program isn't started twice.
+    fn set(method: Option<Self>) -> Result<()>;
+}
+
+/// No methods, so the menu is hidden
+#[cfg(not(windows))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unsupported {}
+
+#[cfg(not(windows))]
+impl Autostart for Unsupported {
+    const ALL: &'static [Self] = &[];
+
+    fn label(self) -> String {
+        match self {}
+    }
+
+    fn is_enabled(self) -> bool {
+        match self {}
+    }
+
+    fn set(_method: Option<Self>) -> Result<()> {
+        Ok(())
+    }
+}
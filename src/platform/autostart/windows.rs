@@ -0,0 +1,278 @@
+use std::os::windows::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{self, Stdio};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+
+use anyhow::{Context, Result};
+use log::{debug, info};
+use winreg::{RegKey, enums::HKEY_CURRENT_USER};
+
+use super::{AUTOSTART_ARG, Autostart};
+use crate::lang::{self, Key::*};
+
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+const RUN_KEY: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\Run";
+const RUN_VALUE: &str = "HeadsetBatteryIndicator";
+const TASK_NAME: &str = "Headset Battery Indicator";
+// Same name as the shortcut created by the installer
+const SHORTCUT_NAME: &str = "Headset Battery Indicator.lnk";
+
+/// Whether the scheduled task exists. Querying it runs schtasks, which takes a moment,
+/// so it's only queried once and then kept up to date by `enable` and `disable`.
+static TASK: Mutex<Option<TaskState>> = Mutex::new(None);
+
+enum TaskState {
+    Querying(JoinHandle<bool>),
+    Known(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Shortcut in the Startup folder, created by the installer
+    StartupFolder,
+    /// Value under HKCU\...\CurrentVersion\Run
+    RunKey,
+    /// Task Scheduler task triggered on logon. Some security software blocks
+    /// Run key entries but leaves scheduled tasks alone.
+    ScheduledTask,
+}
+
+impl Autostart for Method {
+    const ALL: &'static [Self] = &[Method::StartupFolder, Method::RunKey, Method::ScheduledTask];
+
+    fn label(self) -> String {
+        lang::t(match self {
+            Method::StartupFolder => autostart_startup_folder,
+            Method::RunKey => autostart_run_key,
+            Method::ScheduledTask => autostart_task_scheduler,
+        })
+        .to_string()
+    }
+
+    fn is_enabled(self) -> bool {
+        match self {
+            Method::StartupFolder => shortcut_path().is_ok_and(|path| path.exists()),
+            Method::RunKey => RegKey::predef(HKEY_CURRENT_USER)
+                .open_subkey(RUN_KEY)
+                .and_then(|key| key.get_value::<String, _>(RUN_VALUE))
+                .is_ok(),
+            Method::ScheduledTask => task_exists(),
+        }
+    }
+
+    fn preload() {
+        let mut task = TASK.lock().unwrap_or_else(|e| e.into_inner());
+        if task.is_some() {
+            return;
+        }
+        match thread::Builder::new()
+            .name("autostart-query".to_string())
+            .spawn(query_task)
+        {
+            Ok(query) => *task = Some(TaskState::Querying(query)),
+            Err(e) => debug!("Failed to query scheduled task in the background: {e}"),
+        }
+    }
+
+    fn set(method: Option<Self>) -> Result<()> {
+        for &other in Self::ALL {
+            if Some(other) != method && other.is_enabled() {
+                other.disable()?;
+            }
+        }
+
+        if let Some(method) = method
+            && !method.is_enabled()
+        {
+            method.enable()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Method {
+    fn enable(self) -> Result<()> {
+        let exe = std::env::current_exe().context("getting current executable path")?;
+
+        match self {
+            Method::StartupFolder => {
+                let path = shortcut_path()?;
+                let quote = |p: &std::path::Path| p.display().to_string().replace('\'', "''");
+                // Creating shortcuts natively requires COM, WScript.Shell is much simpler
+                let script = format!(
+                    "$s = (New-Object -ComObject WScript.Shell).CreateShortcut('{link}'); \
+                     $s.TargetPath = '{exe}'; $s.Arguments = '{AUTOSTART_ARG}'; $s.Save()",
+                    link = quote(&path),
+                    exe = quote(&exe),
+                );
+                run_hidden(
+                    "powershell",
+                    &["-NoProfile", "-NonInteractive", "-Command", &script],
+                )
+                .context("creating startup shortcut")?;
+            }
+            Method::RunKey => {
+                let (key, _) = RegKey::predef(HKEY_CURRENT_USER)
+                    .create_subkey(RUN_KEY)
+                    .context("accessing Run key")?;
+                key.set_value(RUN_VALUE, &format!("\"{}\" {AUTOSTART_ARG}", exe.display()))
+                    .context("setting Run key value")?;
+            }
+            Method::ScheduledTask => {
+                let xml_path = std::env::temp_dir().join("headset-battery-indicator-task.xml");
+                // schtasks expects the task definition as UTF-16
+                let mut xml = vec![0xFF, 0xFE];
+                for unit in task_xml(&exe).encode_utf16() {
+                    xml.extend_from_slice(&unit.to_le_bytes());
+                }
+                std::fs::write(&xml_path, xml).context("writing task definition")?;
+
+                let res = schtasks(&[
+                    "/Create",
+                    "/TN",
+                    TASK_NAME,
+                    "/XML",
+                    &xml_path.display().to_string(),
+                    "/F",
+                ]);
+                let _ = std::fs::remove_file(&xml_path);
+                res.context("creating scheduled task")?;
+                set_task_exists(true);
+            }
+        }
+
+        info!("Enabled autostart using {self:?}");
+        Ok(())
+    }
+
+    fn disable(self) -> Result<()> {
+        match self {
+            Method::StartupFolder => {
+                std::fs::remove_file(shortcut_path()?).context("removing startup shortcut")?
+            }
+            Method::RunKey => RegKey::predef(HKEY_CURRENT_USER)
+                .open_subkey_with_flags(RUN_KEY, winreg::enums::KEY_SET_VALUE)
+                .and_then(|key| key.delete_value(RUN_VALUE))
+                .context("removing Run key value")?,
+            Method::ScheduledTask => {
+                schtasks(&["/Delete", "/TN", TASK_NAME, "/F"])
+                    .context("deleting scheduled task")?;
+                set_task_exists(false);
+            }
+        }
+
+        info!("Disabled autostart using {self:?}");
+        Ok(())
+    }
+}
+
+fn shortcut_path() -> Result<PathBuf> {
+    let appdata = dirs::data_dir().context("getting AppData directory")?;
+    Ok(appdata
+        .join("Microsoft\\Windows\\Start Menu\\Programs\\Startup")
+        .join(SHORTCUT_NAME))
+}
+
+/// Waits for the query started by `preload`, or queries now if there was none
+fn task_exists() -> bool {
+    let mut task = TASK.lock().unwrap_or_else(|e| e.into_inner());
+    let exists = match task.take() {
+        Some(TaskState::Known(exists)) => exists,
+        Some(TaskState::Querying(query)) => query.join().unwrap_or_default(),
+        None => query_task(),
+    };
+    *task = Some(TaskState::Known(exists));
+    exists
+}
+
+fn set_task_exists(exists: bool) {
+    *TASK.lock().unwrap_or_else(|e| e.into_inner()) = Some(TaskState::Known(exists));
+}
+
+fn query_task() -> bool {
+    schtasks(&["/Query", "/TN", TASK_NAME]).is_ok()
+}
+
+fn schtasks(args: &[&str]) -> Result<()> {
+    run_hidden("schtasks", args)
+}
+
+fn run_hidden(program: &str, args: &[&str]) -> Result<()> {
+    let output = process::Command::new(program)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .with_context(|| format!("running {program}"))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        debug!("{program} {args:?} failed: {stderr}");
+        anyhow::bail!("{program} exited with {}: {}", output.status, stderr.trim());
+    }
+
+    Ok(())
+}
+
+/// Logon-triggered task for the current user, delayed by 30 seconds so it doesn't
+/// compete with everything else starting at logon, and running without elevation.
+fn task_xml(exe: &std::path::Path) -> String {
+    let user = format!(
+        "{}\\{}",
+        std::env::var("USERDOMAIN").unwrap_or_default(),
+        std::env::var("USERNAME").unwrap_or_default()
+    );
+    let exe_dir = exe.parent().unwrap_or(exe);
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <RegistrationInfo>
+    <Description>Starts Headset Battery Indicator on logon</Description>
+  </RegistrationInfo>
+  <Triggers>
+    <LogonTrigger>
+      <Enabled>true</Enabled>
+      <UserId>{user}</UserId>
+      <Delay>PT30S</Delay>
+    </LogonTrigger>
+  </Triggers>
+  <Principals>
+    <Principal id="Author">
+      <UserId>{user}</UserId>
+      <LogonType>InteractiveToken</LogonType>
+      <RunLevel>LeastPrivilege</RunLevel>
+    </Principal>
+  </Principals>
+  <Settings>
+    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>
+    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>
+    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>
+    <ExecutionTimeLimit>PT0S</ExecutionTimeLimit>
+    <Priority>7</Priority>
+  </Settings>
+  <Actions Context="Author">
+    <Exec>
+      <Command>{exe}</Command>
+      <Arguments>{AUTOSTART_ARG}</Arguments>
+      <WorkingDirectory>{exe_dir}</WorkingDirectory>
+    </Exec>
+  </Actions>
+</Task>
+"#,
+        user = xml_escape(&user),
+        exe = xml_escape(&exe.display().to_string()),
+        exe_dir = xml_escape(&exe_dir.display().to_string()),
+    )
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
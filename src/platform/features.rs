@@ -0,0 +1,210 @@
+//! The features that only some platforms have, like the named pipe, hotkeys and the history
+//! window on Windows or the overlay on Linux, and how polling is timed.
+//!
+//! They're started with the app, kept up to date after every poll and handle their own events.
+//! Since a hotkey can poll right away or mute the notifications, the events and menu items get
+//! the whole app state.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+#[cfg(not(windows))]
+use std::time::Instant;
+
+use anyhow::Result;
+use tray_icon::menu::MenuId;
+use winit::event::WindowEvent;
+#[cfg(not(windows))]
+use winit::event_loop::ControlFlow;
+use winit::event_loop::{ActiveEventLoop, EventLoopBuilder, EventLoopProxy};
+use winit::window::WindowId;
+
+#[cfg(target_os = "linux")]
+use super::tray::{self, Tray as _};
+use crate::actions::BatteryEvent;
+use crate::headset_control::Device;
+use crate::history::Sample;
+use crate::settings::Settings;
+use crate::{AppState, UserEvent};
+
+#[cfg(windows)]
+mod windows;
+
+#[cfg(windows)]
+pub use windows::Windows;
+
+#[cfg(windows)]
+pub type Native = Windows;
+#[cfg(target_os = "linux")]
+pub type Native = Linux;
+#[cfg(target_os = "macos")]
+pub type Native = MacOs;
+
+/// Sent to the event loop as `UserEvent::Platform`
+pub type Event = <Native as Features>::Event;
+
+/// GTK doesn't wake up the winit event loop, so its events are dispatched this often. The
+/// menu clicks then wake it up themselves.
+#[cfg(target_os = "linux")]
+const GTK_EVENT_INTERVAL: Duration = Duration::from_millis(500);
+
+pub trait Features: Sized {
+    type Event: std::fmt::Debug + Send;
+
+    /// Adjusts the event loop before it's built
+    fn configure_event_loop(_builder: &mut EventLoopBuilder<UserEvent>) {}
+
+    /// Starts the features turned on in `settings`, once the tray icon is shown
+    fn start(settings: &Settings, proxy: EventLoopProxy<UserEvent>) -> Result<Self>;
+
+    /// Polls the devices every `interval` from now on
+    fn schedule_polling(&mut self, interval: Duration);
+
+    /// Handles the events that don't wake up the event loop, returns whether it's time to poll
+    fn about_to_wait(&mut self) -> bool {
+        false
+    }
+
+    /// Sets when the event loop wakes up next, it only waits for messages by default
+    fn wait(&self, _event_loop: &ActiveEventLoop) {}
+
+    /// Counts a poll, and whether it found the devices
+    fn record_poll(&mut self, _ok: bool) {}
+
+    /// Whether an app is recording from the microphone, `None` where that isn't known or
+    /// the notifications aren't held back for it
+    fn microphone_in_use(&self, _settings: &Settings) -> Option<bool> {
+        None
+    }
+
+    /// The devices and samples of a poll, before the menu is updated
+    fn add(&mut self, _devices: &[Device], _samples: &[Sample]) {}
+
+    /// Shows the devices after a poll
+    fn publish(&mut self, _devices: &[Device], _selected: usize) {}
+
+    /// A device crossed one of the battery events, `quiet` while the alerts are muted
+    /// or the microphone is in use
+    fn alert(&self, _event: BatteryEvent, _device: &Device, _quiet: bool) {}
+
+    /// More lines about the device under "Device details"
+    fn device_details(&self, _device: &Device) -> Vec<String> {
+        vec![]
+    }
+
+    /// Saves everything that should survive a restart
+    fn persist(&mut self) {}
+
+    /// Runs `handler` when the session ends, before the OS may end the process. It returns
+    /// false when it couldn't save the app, which the event loop then does.
+    fn on_session_end(&self, _handler: Box<dyn Fn() -> bool>) {}
+
+    /// Handles a click on one of the menu items of these features, false for the other items
+    fn menu_event(_app: &mut AppState, _event_loop: &ActiveEventLoop, _id: &MenuId) -> bool {
+        false
+    }
+
+    fn handle_event(app: &mut AppState, event_loop: &ActiveEventLoop, event: Self::Event);
+
+    fn window_event(&mut self, _window_id: WindowId, _event: &WindowEvent) {}
+
+    /// The executable to start once the app has exited, after an update
+    fn take_relaunch(&mut self) -> Option<PathBuf> {
+        None
+    }
+
+    /// Starts the executable from `take_relaunch`
+    fn relaunch(_exe: &Path) {}
+}
+
+#[cfg(target_os = "linux")]
+pub struct Linux {
+    overlay: Option<crate::overlay::Overlay>,
+    /// When to poll the devices next, there's no message window with a poll timer
+    next_poll: Instant,
+}
+
+#[cfg(target_os = "linux")]
+impl Features for Linux {
+    type Event = std::convert::Infallible;
+
+    fn start(settings: &Settings, _proxy: EventLoopProxy<UserEvent>) -> Result<Self> {
+        let overlay = settings.overlay.clone().and_then(|config| {
+            crate::overlay::Overlay::start(config)
+                .inspect_err(|e| log::error!("Failed to start overlay: {e:?}"))
+                .ok()
+        });
+
+        Ok(Self {
+            overlay,
+            next_poll: Instant::now(),
+        })
+    }
+
+    fn schedule_polling(&mut self, interval: Duration) {
+        self.next_poll = Instant::now() + interval;
+    }
+
+    fn about_to_wait(&mut self) -> bool {
+        tray::Native::dispatch_events();
+        Instant::now() >= self.next_poll
+    }
+
+    /// Wakes up for the next poll, and for the GTK events in between
+    fn wait(&self, event_loop: &ActiveEventLoop) {
+        let wake_up = self.next_poll.min(Instant::now() + GTK_EVENT_INTERVAL);
+        event_loop.set_control_flow(ControlFlow::WaitUntil(wake_up));
+    }
+
+    fn publish(&mut self, devices: &[Device], selected: usize) {
+        if let Some(overlay) = &self.overlay {
+            overlay.show(devices.get(selected));
+        }
+    }
+
+    fn handle_event(_app: &mut AppState, _event_loop: &ActiveEventLoop, event: Self::Event) {
+        match event {}
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub struct MacOs {
+    /// When to poll the devices next, there's no message window with a poll timer
+    next_poll: Instant,
+}
+
+#[cfg(target_os = "macos")]
+impl Features for MacOs {
+    type Event = std::convert::Infallible;
+
+    /// Only in the menu bar, without a Dock icon or an application menu
+    fn configure_event_loop(builder: &mut EventLoopBuilder<UserEvent>) {
+        use winit::platform::macos::{ActivationPolicy, EventLoopBuilderExtMacOS};
+
+        builder
+            .with_activation_policy(ActivationPolicy::Accessory)
+            .with_default_menu(false);
+    }
+
+    fn start(_settings: &Settings, _proxy: EventLoopProxy<UserEvent>) -> Result<Self> {
+        Ok(Self {
+            next_poll: Instant::now(),
+        })
+    }
+
+    fn schedule_polling(&mut self, interval: Duration) {
+        self.next_poll = Instant::now() + interval;
+    }
+
+    fn about_to_wait(&mut self) -> bool {
+        Instant::now() >= self.next_poll
+    }
+
+    /// The menu bar wakes up the event loop itself, so only for the next poll
+    fn wait(&self, event_loop: &ActiveEventLoop) {
+        event_loop.set_control_flow(ControlFlow::WaitUntil(self.next_poll));
+    }
+
+    fn handle_event(_app: &mut AppState, _event_loop: &ActiveEventLoop, event: Self::Event) {
+        match event {}
+    }
+}
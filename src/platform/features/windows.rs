@@ -0,0 +1,371 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use log::{debug, error, info};
+use tray_icon::menu::MenuId;
+use winit::event::WindowEvent;
+use winit::event_loop::{ActiveEventLoop, EventLoopProxy};
+use winit::window::WindowId;
+
+use super::Features;
+use crate::actions::BatteryEvent;
+use crate::headset_control::{AirPodsWatcher, Device};
+use crate::history::{self, Sample};
+use crate::lang::{self, Key::*};
+use crate::settings::Settings;
+use crate::{AppState, UserEvent};
+use crate::{
+    accessibility, audio, graph, hotkeys, hwinfo, message_window, perf, pipe, rtss, sessions,
+    sound, speech, update,
+};
+
+pub struct Windows {
+    pipe: Option<pipe::PipeServer>,
+    hwinfo: Option<hwinfo::HwInfoSensors>,
+    perf: Option<perf::PerfCounters>,
+    rtss: Option<rtss::RtssOsd>,
+    sounds: Option<sound::Sounds>,
+    /// Scans for AirPods while it's kept, the poller reads what it found
+    _airpods: Option<AirPodsWatcher>,
+    sessions: Option<sessions::Sessions>,
+    /// Registered to the message window, so they're unregistered before it's destroyed
+    hotkeys: hotkeys::Hotkeys,
+    speech: speech::Speech,
+    announcer: accessibility::Announcer,
+    message_window: message_window::MessageWindow,
+    /// For the threads started from the menu
+    proxy: EventLoopProxy<UserEvent>,
+    /// The updated executable, started once this one has exited
+    relaunch: Option<PathBuf>,
+    /// The battery history window, while it's open
+    graph: Option<graph::GraphWindow>,
+    poll_timer_interval: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub enum Event {
+    /// The newer executable was downloaded and verified, or why it wasn't
+    UpdateDownloaded(Result<PathBuf>),
+    /// One of the hotkeys from the `Hotkeys` subkey was pressed
+    Hotkey(hotkeys::Action),
+}
+
+impl Features for Windows {
+    type Event = Event;
+
+    fn start(settings: &Settings, proxy: EventLoopProxy<UserEvent>) -> Result<Self> {
+        let hwinfo = settings
+            .hwinfo_enabled
+            .then(|| {
+                hwinfo::HwInfoSensors::new()
+                    .inspect_err(|e| error!("Failed to set up HWiNFO sensors: {e:?}"))
+                    .ok()
+            })
+            .flatten();
+
+        let rtss = settings.rtss_enabled.then(rtss::RtssOsd::new);
+
+        let sounds = settings.sounds.clone().and_then(|config| {
+            sound::Sounds::start(config)
+                .inspect_err(|e| error!("Failed to start sounds: {e:?}"))
+                .ok()
+        });
+
+        let airpods = settings
+            .airpods
+            .then(|| {
+                AirPodsWatcher::start()
+                    .inspect_err(|e| error!("Failed to start watching for AirPods: {e:?}"))
+                    .ok()
+            })
+            .flatten();
+
+        let sessions = settings
+            .history_enabled
+            .then(|| {
+                sessions::Sessions::open()
+                    .inspect_err(|e| error!("Failed to set up usage sessions: {e:?}"))
+                    .ok()
+            })
+            .flatten();
+
+        let pipe = settings
+            .pipe_enabled
+            .then(|| {
+                pipe::PipeServer::start()
+                    .inspect_err(|e| error!("Failed to start named pipe server: {e:?}"))
+                    .ok()
+            })
+            .flatten();
+
+        // Consumers only see the counters once they are registered with lodctr
+        let perf = settings
+            .perf_counters_enabled
+            .then(|| {
+                perf::PerfCounters::start()
+                    .inspect_err(|e| error!("Failed to start performance counters: {e:?}"))
+                    .ok()
+            })
+            .flatten();
+
+        update::install::clean_up();
+
+        let message_window =
+            message_window::MessageWindow::new(proxy.clone()).context("creating message window")?;
+        let hotkeys = hotkeys::Hotkeys::register(message_window.hwnd(), &settings.hotkeys);
+        let announcer = accessibility::Announcer::new(message_window.hwnd());
+
+        Ok(Self {
+            pipe,
+            hwinfo,
+            perf,
+            rtss,
+            sounds,
+            _airpods: airpods,
+            sessions,
+            hotkeys,
+            speech: speech::Speech::default(),
+            announcer,
+            message_window,
+            proxy,
+            relaunch: None,
+            graph: None,
+            poll_timer_interval: None,
+        })
+    }
+
+    /// Restarts the poll timer if the poll interval has changed
+    fn schedule_polling(&mut self, interval: Duration) {
+        if self.poll_timer_interval == Some(interval) {
+            return;
+        }
+        debug!("Polling every {interval:?}");
+        if let Err(e) = self.message_window.set_poll_timer(interval) {
+            error!("Failed to set poll timer: {e:?}");
+        }
+        self.poll_timer_interval = Some(interval);
+    }
+
+    fn record_poll(&mut self, ok: bool) {
+        if let Some(perf) = &mut self.perf {
+            perf.record_poll(ok);
+        }
+    }
+
+    fn microphone_in_use(&self, settings: &Settings) -> Option<bool> {
+        settings.quiet_while_recording.then(|| {
+            audio::microphone_in_use().unwrap_or_else(|e| {
+                debug!("Failed to check if the microphone is in use: {e:?}");
+                false
+            })
+        })
+    }
+
+    fn add(&mut self, devices: &[Device], samples: &[Sample]) {
+        if let Some(sessions) = &mut self.sessions {
+            sessions.update(devices);
+        }
+        if let Some(graph) = &mut self.graph {
+            graph.add(samples);
+        }
+    }
+
+    fn publish(&mut self, devices: &[Device], selected: usize) {
+        if let Some(pipe) = &self.pipe {
+            pipe.publish(devices, selected);
+        }
+        self.message_window.publish(devices, selected);
+        if let Some(hwinfo) = &mut self.hwinfo {
+            hwinfo.publish(devices);
+        }
+        if let Some(perf) = &mut self.perf {
+            perf.publish(devices);
+        }
+        if let Some(rtss) = &mut self.rtss {
+            rtss.publish(devices.get(selected));
+        }
+    }
+
+    /// Announced to screen readers, and played with the notifications turned off too
+    fn alert(&self, event: BatteryEvent, device: &Device, quiet: bool) {
+        self.announcer.alert(event, device);
+        if let Some(sounds) = &self.sounds
+            && !quiet
+        {
+            sounds.play(event);
+        }
+    }
+
+    fn device_details(&self, device: &Device) -> Vec<String> {
+        let Some(sessions) = &self.sessions else {
+            return vec![];
+        };
+        let minutes = sessions.today(device).as_secs() / 60;
+        vec![lang::t(used_today).replace("{time}", &history::format_minutes(minutes))]
+    }
+
+    fn persist(&mut self) {
+        if let Some(sessions) = &mut self.sessions {
+            sessions.close();
+        }
+    }
+
+    fn on_session_end(&self, handler: Box<dyn Fn() -> bool>) {
+        self.message_window.on_session_end(handler);
+    }
+
+    fn menu_event(app: &mut AppState, event_loop: &ActiveEventLoop, id: &MenuId) -> bool {
+        if *id == app.context_menu.menu_history_graph.id() {
+            show_history_graph(app, event_loop);
+        } else if *id == app.context_menu.menu_install_update.id() {
+            install_update(app);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    fn handle_event(app: &mut AppState, event_loop: &ActiveEventLoop, event: Event) {
+        match event {
+            Event::UpdateDownloaded(result) => update_downloaded(app, event_loop, result),
+            Event::Hotkey(action) => hotkey(app, action),
+        }
+    }
+
+    /// The battery history is the only winit window, the tray icon and the
+    /// message window have their own window procedures
+    fn window_event(&mut self, window_id: WindowId, event: &WindowEvent) {
+        if let Some(graph) = &mut self.graph
+            && graph.id() == window_id
+            && !graph.handle_event(event)
+        {
+            self.graph = None;
+        }
+    }
+
+    fn take_relaunch(&mut self) -> Option<PathBuf> {
+        self.relaunch.take()
+    }
+
+    fn relaunch(exe: &Path) {
+        update::install::relaunch(exe);
+    }
+}
+
+/// Opens the history graph of the selected headset, or brings it to the front when it's open
+fn show_history_graph(app: &mut AppState, event_loop: &ActiveEventLoop) {
+    if let Some(graph) = &app.features.graph {
+        graph.focus();
+        return;
+    }
+    let Some(history) = &mut app.history else {
+        return;
+    };
+    history.flush();
+    let samples = match history.read() {
+        Ok(samples) => samples,
+        Err(e) => {
+            error!("Failed to read battery history: {e:?}");
+            return;
+        }
+    };
+
+    // The headset seen last when none is connected
+    let selected = match app.devices.get(app.context_menu.selected_device_idx) {
+        Some(device) => Some(device.id()),
+        None => samples.last().map(|sample| sample.device.clone()),
+    };
+    let names = app
+        .devices
+        .iter()
+        .map(|device| (device.id(), device.product.clone()))
+        .collect();
+    match graph::GraphWindow::open(event_loop, selected, names, &samples) {
+        Ok(graph) => app.features.graph = Some(graph),
+        Err(e) => error!("Failed to open battery history: {e:?}"),
+    }
+}
+
+/// Downloads the newer release, which replaces this executable once it's verified
+fn install_update(app: &mut AppState) {
+    let Some(tag) = app
+        .settings
+        .latest_release
+        .clone()
+        .filter(|tag| update::is_newer(tag))
+    else {
+        return;
+    };
+    info!("Downloading update {tag}");
+    match update::install::download(tag, app.features.proxy.clone()) {
+        Ok(()) => app.context_menu.set_update_downloading(true),
+        Err(e) => error!("Failed to download update: {e:?}"),
+    }
+}
+
+/// Swaps in the downloaded executable and exits, `run` starts it once the state is saved
+fn update_downloaded(app: &mut AppState, event_loop: &ActiveEventLoop, result: Result<PathBuf>) {
+    match result.and_then(|path| update::install::replace(&path)) {
+        Ok(exe) => {
+            app.features.relaunch = Some(exe);
+            event_loop.exit();
+        }
+        Err(e) => {
+            error!("Failed to install update: {e:?}");
+            app.context_menu.set_update_downloading(false);
+            if let Err(err) = app
+                .notifier
+                .show_notification("Headset Battery Indicator", lang::t(update_failed))
+            {
+                error!("Failed to show notification: {:?}", err);
+            }
+        }
+    }
+}
+
+/// Runs the action bound to the hotkey that was pressed
+fn hotkey(app: &mut AppState, action: hotkeys::Action) {
+    debug!("Hotkey for {} pressed", action.name());
+    let selected = app.context_menu.selected_device_idx;
+    let features = &mut app.features;
+    match action {
+        hotkeys::Action::ToggleOverlay => {
+            // Dropping the OSD removes the battery from it
+            features.rtss = match features.rtss.take() {
+                Some(_) => None,
+                None => {
+                    let mut rtss = rtss::RtssOsd::new();
+                    rtss.publish(app.devices.get(selected));
+                    Some(rtss)
+                }
+            };
+        }
+        hotkeys::Action::Announce => {
+            let text = match app.devices.get(selected) {
+                Some(device) => device.to_string(),
+                None => lang::t(no_adapter_found).to_string(),
+            };
+            // In the screen reader's voice when there is one
+            let res = if accessibility::screen_reader_running() {
+                features.announcer.announce(&text)
+            } else {
+                features.speech.say(&text)
+            };
+            if let Err(e) = res {
+                error!("Failed to announce the battery: {e:?}");
+            }
+        }
+        hotkeys::Action::Refresh => app.poll_now(),
+        hotkeys::Action::MuteAlerts => {
+            let muted = !app.notifier.is_muted();
+            app.notifier.set_muted(muted);
+            info!("Battery notifications muted: {muted}");
+            // Said aloud, since the notifications are what's muted
+            let text = lang::t(if muted { alerts_muted } else { alerts_unmuted });
+            if let Err(e) = features.speech.say(text) {
+                error!("Failed to announce the mute: {e:?}");
+            }
+        }
+    }
+}
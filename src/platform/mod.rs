@@ -0,0 +1,13 @@
+//! What the app needs from the operating system, behind traits so the rest of the app
+//! doesn't depend on one.
+//!
+//! Each module has a trait, its implementation for every supported OS, and a `Native` alias
+//! for the one the app was built for. A new OS can start with the parts it supports, like
+//! the autostart methods that are only implemented on Windows.
+
+pub mod autostart;
+pub mod features;
+pub mod notify;
+pub mod sandbox;
+pub mod settings;
+pub mod tray;
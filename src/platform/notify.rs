@@ -0,0 +1,378 @@
+//! Notifications from the notification system of each platform
+
+use anyhow::{Context, Result};
+#[cfg(windows)]
+use win32_notif::{
+    NotificationActivatedEventHandler, NotificationBuilder, ToastsNotifier,
+    notification::{
+        Scenario,
+        visual::{Text, text::HintStyle},
+    },
+};
+#[cfg(windows)]
+use windows::{Win32::UI::Shell::SetCurrentProcessExplicitAppUserModelID, core::HSTRING};
+use winit::event_loop::EventLoopProxy;
+
+use crate::UserEvent;
+use crate::headset_control::BatteryState;
+
+#[cfg(windows)]
+pub type Native = Windows;
+#[cfg(target_os = "linux")]
+pub type Native = Linux;
+#[cfg(target_os = "macos")]
+pub type Native = MacOs;
+
+/// How much a notification needs the user's attention
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    /// Charging started or finished, and messages from the app
+    Info,
+    Low,
+    Critical,
+}
+
+pub trait Notifications: Sized {
+    /// `proxy` tells the app when a notification is clicked
+    fn new(proxy: EventLoopProxy<UserEvent>) -> Result<Self>;
+
+    /// `battery` is the level and status when the notification is about the battery
+    fn show(
+        &mut self,
+        product_name: &str,
+        body: &str,
+        urgency: Urgency,
+        battery: Option<(isize, BatteryState)>,
+    ) -> Result<()>;
+}
+
+/// Toast notifications
+#[cfg(windows)]
+pub struct Windows {
+    app_id: String,
+    /// Created for the first toast, since it loads WinRT and writes the registry, which
+    /// would hold up the tray icon at startup
+    toast_notifier: Option<ToastsNotifier>,
+    proxy: EventLoopProxy<UserEvent>,
+}
+
+#[cfg(windows)]
+impl Notifications for Windows {
+    fn new(proxy: EventLoopProxy<UserEvent>) -> Result<Self> {
+        let app_id = register_notifications_id().context("registering notifications id")?;
+        Ok(Self {
+            app_id,
+            toast_notifier: None,
+            proxy,
+        })
+    }
+
+    fn show(
+        &mut self,
+        product_name: &str,
+        body: &str,
+        urgency: Urgency,
+        _battery: Option<(isize, BatteryState)>,
+    ) -> Result<()> {
+        // Clicking the toast, or the toast in the notification center while the app
+        // is still running, shows the details from the tray menu
+        let proxy = self.proxy.clone();
+        let mut builder = NotificationBuilder::new()
+            .visual(Text::create(0, product_name).with_style(HintStyle::Title))
+            .visual(Text::create(1, &body).with_style(HintStyle::Body))
+            .on_activated(NotificationActivatedEventHandler::new(move |_, _| {
+                let _ = proxy.send_event(UserEvent::NotificationActivated);
+                Ok(())
+            }));
+
+        if urgency == Urgency::Critical {
+            // Urgent toasts break through Focus Assist and are shown on the lock screen
+            // as long as the user hasn't disabled lock screen notifications
+            builder = builder.with_scenario(Scenario::Urgent);
+        }
+
+        let toast_notifier = match &mut self.toast_notifier {
+            Some(toast_notifier) => toast_notifier,
+            slot @ None => {
+                if let Err(err) = allow_content_above_lock(&self.app_id) {
+                    log::warn!("Failed to allow notifications above lock screen: {err:?}");
+                }
+                let toast_notifier =
+                    ToastsNotifier::new(self.app_id.clone()).context("creating toast notifier")?;
+                slot.insert(toast_notifier)
+            }
+        };
+
+        builder
+            .build(0, toast_notifier, product_name, "battery")
+            .context("building notification")?
+            .show()
+            .context("showing notification")
+    }
+}
+
+/// Notifications sent to the desktop's notification server over D-Bus
+#[cfg(target_os = "linux")]
+pub struct Linux {
+    proxy: EventLoopProxy<UserEvent>,
+}
+
+#[cfg(target_os = "linux")]
+impl Notifications for Linux {
+    fn new(proxy: EventLoopProxy<UserEvent>) -> Result<Self> {
+        Ok(Self { proxy })
+    }
+
+    /// With the battery icon from the icon theme when the notification is about a battery level
+    fn show(
+        &mut self,
+        product_name: &str,
+        body: &str,
+        urgency: Urgency,
+        battery: Option<(isize, BatteryState)>,
+    ) -> Result<()> {
+        let icon = match battery {
+            Some((level, status)) => battery_icon(level, status),
+            None => "battery".to_string(),
+        };
+        let handle = notify_rust::Notification::new()
+            .appname("Headset Battery Indicator")
+            .summary(product_name)
+            .body(body)
+            .icon(&icon)
+            .hint(notify_rust::Hint::Category("device".to_string()))
+            // Critical notifications stay on screen until they're dismissed
+            .urgency(match urgency {
+                Urgency::Info => notify_rust::Urgency::Low,
+                Urgency::Low => notify_rust::Urgency::Normal,
+                Urgency::Critical => notify_rust::Urgency::Critical,
+            })
+            .action("default", "Show")
+            .show()
+            .context("showing notification")?;
+
+        // Clicking the notification shows the details from the tray menu. The thread
+        // waits until the notification is clicked, dismissed or expires.
+        let proxy = self.proxy.clone();
+        std::thread::Builder::new()
+            .name("notification".to_string())
+            .spawn(move || {
+                handle.wait_for_action(|action| {
+                    if action == "default" {
+                        let _ = proxy.send_event(UserEvent::NotificationActivated);
+                    }
+                })
+            })
+            .context("spawning notification thread")?;
+        Ok(())
+    }
+}
+
+/// Notifications in the Notification Center
+#[cfg(target_os = "macos")]
+pub struct MacOs {
+    proxy: EventLoopProxy<UserEvent>,
+    /// The images drawn so far, so repeated notifications reuse their file
+    images: std::collections::HashMap<(isize, BatteryState), std::path::PathBuf>,
+}
+
+#[cfg(target_os = "macos")]
+impl Notifications for MacOs {
+    fn new(proxy: EventLoopProxy<UserEvent>) -> Result<Self> {
+        Ok(Self {
+            proxy,
+            images: Default::default(),
+        })
+    }
+
+    /// With the battery drawn next to the text when the notification is about a battery
+    /// level. There's no urgency in the Notification Center.
+    fn show(
+        &mut self,
+        product_name: &str,
+        body: &str,
+        _urgency: Urgency,
+        battery: Option<(isize, BatteryState)>,
+    ) -> Result<()> {
+        use mac_notification_sys::NotificationResponse;
+
+        let image = battery.and_then(|(level, status)| {
+            let key = (level.clamp(0, 100), status);
+            if let Some(path) = self.images.get(&key)
+                && path.exists()
+            {
+                return Some(path.clone());
+            }
+            let path = battery_image(key.0, status)
+                .inspect_err(|e| log::warn!("Failed to draw notification image: {e:?}"))
+                .ok()?;
+            self.images.insert(key, path.clone());
+            Some(path)
+        });
+        let product_name = product_name.to_string();
+        let body = body.to_string();
+
+        // Clicking the notification shows the details from the menu bar menu. Sending
+        // blocks until the notification is clicked or dismissed.
+        let proxy = self.proxy.clone();
+        std::thread::Builder::new()
+            .name("notification".to_string())
+            .spawn(move || {
+                let mut notification = mac_notification_sys::Notification::new();
+                notification
+                    .title(&product_name)
+                    .message(&body)
+                    .wait_for_click(true);
+                if let Some(image) = image.as_deref().and_then(|path| path.to_str()) {
+                    notification.content_image(image);
+                }
+                match notification.send() {
+                    Ok(NotificationResponse::Click) => {
+                        let _ = proxy.send_event(UserEvent::NotificationActivated);
+                    }
+                    Ok(_) => {}
+                    Err(e) => log::error!("Failed to show notification: {e:?}"),
+                }
+            })
+            .context("spawning notification thread")?;
+        Ok(())
+    }
+}
+
+/// The battery like on the Linux tray icon, in color and larger than the menu bar icon,
+/// written to a PNG file since notifications can only attach files. Every level and status
+/// has its own file, so a notification still on screen keeps its image.
+#[cfg(target_os = "macos")]
+fn battery_image(level: isize, status: BatteryState) -> Result<std::path::PathBuf> {
+    use crate::platform::tray::{BATTERY_SIZE, draw_battery};
+
+    const SCALE: u32 = 4;
+    let size = BATTERY_SIZE * SCALE;
+
+    let small = draw_battery(winit::window::Theme::Light, level, status, false);
+    let mut rgba = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let i = (((y / SCALE) * BATTERY_SIZE + x / SCALE) * 4) as usize;
+            rgba.extend_from_slice(&small[i..i + 4]);
+        }
+    }
+
+    let path = std::env::temp_dir().join(format!(
+        "headset-battery-indicator-notification-{level}-{status:?}.png"
+    ));
+    let file =
+        std::fs::File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), size, size);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&rgba))
+        .with_context(|| format!("writing {}", path.display()))?;
+    Ok(path)
+}
+
+/// Named like in the freedesktop icon naming spec, so every icon theme has them
+#[cfg(target_os = "linux")]
+fn battery_icon(level: isize, status: BatteryState) -> String {
+    let name = match level {
+        ..=5 => "battery-empty",
+        6..=15 => "battery-caution",
+        16..=35 => "battery-low",
+        36..=80 => "battery-good",
+        _ => "battery-full",
+    };
+    match status {
+        BatteryState::BatteryCharging if level >= 100 => "battery-full-charged".to_string(),
+        BatteryState::BatteryCharging => format!("{name}-charging"),
+        _ => name.to_string(),
+    }
+}
+
+#[cfg(windows)]
+fn register_notifications_id() -> Result<String> {
+    // Win32 Toast notifications typically require a Start Menu shortcut whose
+    // AppUserModelID matches the notifier ID. Without this, `show()` can succeed
+    // but nothing appears.
+
+    // When registered with the sparse package (see packaging/), the process already has a
+    // package identity. Windows then uses the package's AUMID, app name and icon, and the
+    // AUMID must not be overridden.
+    if let Some(app_id) = package_app_id() {
+        log::info!("Using package identity {app_id}");
+        return Ok(app_id);
+    }
+
+    let app_id = if cfg!(debug_assertions) {
+        // In debug mode, use a common AUMID to avoid needing a Start Menu shortcut
+        "Microsoft.Windows.Explorer"
+    } else {
+        // In release mode, AUMID can be anything because the executable is already registered to some AUMID generated by inno setup
+        "HeadsetBatteryIndicator.App"
+    };
+
+    // Ensure the system associates this running EXE with the same AUMID.
+    unsafe {
+        use anyhow::Context;
+
+        SetCurrentProcessExplicitAppUserModelID(&HSTRING::from(app_id))
+            .context("SetCurrentProcessExplicitAppUserModelID")?;
+    }
+
+    Ok(app_id.to_string())
+}
+
+/// AUMID of the package the process is running in, if any
+#[cfg(windows)]
+fn package_app_id() -> Option<String> {
+    use windows::{
+        Win32::{
+            Foundation::{ERROR_INSUFFICIENT_BUFFER, ERROR_SUCCESS},
+            Storage::Packaging::Appx::GetCurrentApplicationUserModelId,
+        },
+        core::PWSTR,
+    };
+
+    let mut len = 0u32;
+    unsafe {
+        // Fails with APPMODEL_ERROR_NO_APPLICATION when the process has no package identity
+        if GetCurrentApplicationUserModelId(&mut len, PWSTR::null()) != ERROR_INSUFFICIENT_BUFFER {
+            return None;
+        }
+
+        let mut buf = vec![0u16; len as usize];
+        if GetCurrentApplicationUserModelId(&mut len, PWSTR(buf.as_mut_ptr())) != ERROR_SUCCESS {
+            return None;
+        }
+
+        // len includes the null terminator
+        Some(String::from_utf16_lossy(
+            &buf[..(len as usize).saturating_sub(1)],
+        ))
+    }
+}
+
+/// Lets toasts from `app_id` show their content on the lock screen.
+///
+/// Windows stores this per-app under the Notifications\Settings key. The value is
+/// only created if it doesn't exist yet, so a choice made by the user in the
+/// Windows notification settings is left alone.
+#[cfg(windows)]
+fn allow_content_above_lock(app_id: &str) -> Result<()> {
+    use winreg::{RegKey, enums::HKEY_CURRENT_USER};
+
+    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+    let (key, _) = hkcu
+        .create_subkey(format!(
+            "Software\\Microsoft\\Windows\\CurrentVersion\\Notifications\\Settings\\{app_id}"
+        ))
+        .context("accessing notification settings key")?;
+
+    if key.get_value::<u32, _>("AllowContentAboveLock").is_err() {
+        key.set_value("AllowContentAboveLock", &1u32)
+            .context("setting AllowContentAboveLock value")?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+//! Where the app can keep its files, and what it can reach, when it's installed from
+//! Flatpak or Snap.
+//!
+//! Flatpak points the XDG directories into `~/.var/app/<id>`, which the host and the portals
+//! can read, so the usual directories work there. Snap only changes `$HOME`, to a folder
+//! that's copied for every revision, so files go in `$SNAP_USER_COMMON` instead.
+//! Notifications use icons from the host's icon theme, so there are no image files to share.
+//! Neither lets the app open USB devices without extra permissions.
+
+use std::path::PathBuf;
+
+#[cfg(not(target_os = "linux"))]
+pub type Native = Unconfined;
+#[cfg(target_os = "linux")]
+pub type Native = Linux;
+
+pub trait Sandbox {
+    /// Whether the app runs in Flatpak or Snap, where its own folder is read-only
+    fn is_sandboxed() -> bool {
+        false
+    }
+
+    fn config_dir() -> Option<PathBuf> {
+        dirs::config_dir()
+    }
+
+    fn data_local_dir() -> Option<PathBuf> {
+        dirs::data_local_dir()
+    }
+
+    /// Whether the sandbox keeps headsetcontrol from opening the headset adapters
+    fn usb_access_denied() -> bool {
+        false
+    }
+}
+
+/// Windows and macOS apps aren't sandboxed
+#[cfg(not(target_os = "linux"))]
+pub struct Unconfined;
+
+#[cfg(not(target_os = "linux"))]
+impl Sandbox for Unconfined {}
+
+#[cfg(target_os = "linux")]
+pub struct Linux;
+
+#[cfg(target_os = "linux")]
+impl Sandbox for Linux {
+    fn is_sandboxed() -> bool {
+        std::path::Path::new("/.flatpak-info").exists() || std::env::var_os("SNAP").is_some()
+    }
+
+    fn config_dir() -> Option<PathBuf> {
+        snap_user_common(".config").or_else(dirs::config_dir)
+    }
+
+    fn data_local_dir() -> Option<PathBuf> {
+        snap_user_common(".local/share").or_else(dirs::data_local_dir)
+    }
+
+    /// Without `--device=all` Flatpak hides the hidraw devices, and without the `raw-usb`
+    /// interface Snap can see but not open them
+    fn usb_access_denied() -> bool {
+        if !Self::is_sandboxed() {
+            return false;
+        }
+        let Ok(entries) = std::fs::read_dir("/dev") else {
+            return true;
+        };
+        !entries
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("hidraw"))
+            .any(|entry| {
+                std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(entry.path())
+                    .is_ok()
+            })
+    }
+}
+
+/// Kept across Snap revisions, unlike `$HOME`
+#[cfg(target_os = "linux")]
+fn snap_user_common(dir: &str) -> Option<PathBuf> {
+    std::env::var_os("SNAP_USER_COMMON").map(|common| PathBuf::from(common).join(dir))
+}
@@ -0,0 +1,113 @@
+//! Where the settings are kept: the registry on Windows, a TOML file elsewhere
+
+#[cfg(windows)]
+use anyhow::Context;
+use anyhow::Result;
+#[cfg(windows)]
+use winreg::enums::{HKEY_CURRENT_USER, KEY_READ, KEY_WRITE, REG_BINARY};
+#[cfg(windows)]
+use winreg::{RegKey, RegValue};
+
+#[cfg(not(windows))]
+mod file;
+
+#[cfg(not(windows))]
+pub use file::FileKey;
+
+#[cfg(windows)]
+pub type Native = RegKey;
+#[cfg(not(windows))]
+pub type Native = FileKey;
+
+#[cfg(windows)]
+const REGISTRY_KEY: &str = "Software\\HeadsetBatteryIndicator";
+
+/// Subkeys hold the settings of one feature, like `Mqtt` or `Webhooks\Low`.
+pub trait Storage: Sized {
+    /// The app's own key, created when it doesn't exist yet
+    fn open() -> Result<Self>;
+    fn subkey(&self, name: &str) -> Option<Self>;
+    fn get_u32(&self, name: &str) -> Option<u32>;
+    fn get_string(&self, name: &str) -> Option<String>;
+    fn set_u32(&self, name: &str, value: u32) -> Result<()>;
+    fn set_string(&self, name: &str, value: &str) -> Result<()>;
+    /// A token or password, which may be stored encrypted
+    fn get_secret(&self, name: &str) -> Option<String>;
+    /// Writes the values set since it was opened, where they aren't written one by one
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl Storage for RegKey {
+    fn open() -> Result<Self> {
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+        let (key, _) = hkcu
+            .create_subkey(REGISTRY_KEY)
+            .context("accessing registry key")?;
+        Ok(key)
+    }
+
+    fn subkey(&self, name: &str) -> Option<Self> {
+        // Write access for replacing secrets with their encrypted values
+        self.open_subkey_with_flags(name, KEY_READ | KEY_WRITE).ok()
+    }
+
+    fn get_u32(&self, name: &str) -> Option<u32> {
+        self.get_value(name).ok()
+    }
+
+    fn get_string(&self, name: &str) -> Option<String> {
+        self.get_value(name).ok()
+    }
+
+    fn set_u32(&self, name: &str, value: u32) -> Result<()> {
+        Ok(self.set_value(name, &value)?)
+    }
+
+    fn set_string(&self, name: &str, value: &str) -> Result<()> {
+        Ok(self.set_value(name, &value)?)
+    }
+
+    fn get_secret(&self, name: &str) -> Option<String> {
+        secret_value(self, name)
+    }
+}
+
+/// Reads a value that is stored encrypted for the current user.
+///
+/// The user sets it as a plain string value, which is replaced with an encrypted
+/// `<name>Encrypted` binary value the first time it's read.
+#[cfg(windows)]
+fn secret_value(key: &RegKey, name: &str) -> Option<String> {
+    let encrypted_name = format!("{name}Encrypted");
+
+    if let Ok(value) = key.get_value::<String, _>(name)
+        && !value.trim().is_empty()
+    {
+        let value = value.trim().to_string();
+        match crate::dpapi::protect(value.as_bytes()) {
+            Ok(encrypted) => {
+                let encrypted = RegValue {
+                    bytes: encrypted,
+                    vtype: REG_BINARY,
+                };
+                if let Err(e) = key
+                    .set_raw_value(&encrypted_name, &encrypted)
+                    .and_then(|()| key.delete_value(name))
+                {
+                    log::error!("Failed to store {name} encrypted: {e:?}");
+                }
+            }
+            Err(e) => log::error!("Failed to encrypt {name}: {e:?}"),
+        }
+        return Some(value);
+    }
+
+    let encrypted = key.get_raw_value(&encrypted_name).ok()?;
+    crate::dpapi::unprotect(&encrypted.bytes)
+        .and_then(|value| String::from_utf8(value).context("decrypted value is not UTF-8"))
+        .inspect_err(|e| log::error!("Failed to decrypt {name}: {e:?}"))
+        .ok()
+}
@@ -0,0 +1,170 @@
+//! Settings outside of Windows, with the same keys and values as the registry, read from
+//! `$XDG_CONFIG_HOME/headset-battery-indicator/settings.toml`, or from
+//! `~/Library/Application Support/headset-battery-indicator/settings.toml` on macOS.
+//! In a Snap, `$SNAP_USER_COMMON/.config` is used instead of `$XDG_CONFIG_HOME`.
+//!
+//! Subkeys are tables, like `[Mqtt]` or `[Webhooks.Low]`, and `DWORD` values are integers.
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use anyhow::Context;
+use toml::{Table, Value};
+
+use super::Storage;
+use crate::platform::sandbox::{self, Sandbox as _};
+
+struct File {
+    path: PathBuf,
+    table: RefCell<Table>,
+}
+
+/// A table in the settings file
+pub struct FileKey {
+    file: Rc<File>,
+    /// Names of the tables leading to this one, empty for the top level
+    path: Vec<String>,
+}
+
+impl FileKey {
+    fn get(&self, name: &str) -> Option<Value> {
+        let table = self.file.table.borrow();
+        lookup(&table, &self.path)?.get(name).cloned()
+    }
+
+    /// Changes the value, which is written with the others by `flush`
+    fn set(&self, name: &str, value: Value) -> anyhow::Result<()> {
+        let mut table = self.file.table.borrow_mut();
+        let mut key = &mut *table;
+        for name in &self.path {
+            key = key
+                .entry(name.clone())
+                .or_insert_with(|| Value::Table(Table::new()))
+                .as_table_mut()
+                .with_context(|| format!("{name} is not a table"))?;
+        }
+        key.insert(name.to_string(), value);
+        Ok(())
+    }
+}
+
+impl Storage for FileKey {
+    /// The top level of the settings file, which is empty when there's no file yet
+    fn open() -> anyhow::Result<Self> {
+        // Follows $XDG_CONFIG_HOME, falling back to ~/.config, on Linux
+        let path = sandbox::Native::config_dir()
+            .context("finding config directory")?
+            .join("headset-battery-indicator")
+            .join("settings.toml");
+
+        let table = match fs::read_to_string(&path) {
+            Ok(content) => content.parse::<Table>().unwrap_or_else(|e| {
+                // Kept for the user to fix, the next save writes the defaults in its place
+                let broken = path.with_extension("toml.broken");
+                log::error!(
+                    "Failed to parse {}, using the default settings and moving it to {}: {e}",
+                    path.display(),
+                    broken.display()
+                );
+                if let Err(e) = fs::rename(&path, &broken) {
+                    log::error!("Failed to move {}: {e}", path.display());
+                }
+                Table::new()
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Table::new(),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+
+        Ok(Self {
+            file: Rc::new(File {
+                path,
+                table: RefCell::new(table),
+            }),
+            path: vec![],
+        })
+    }
+
+    /// Like `Webhooks\Low` in the registry, nested tables can be named with backslashes
+    fn subkey(&self, name: &str) -> Option<Self> {
+        let mut path = self.path.clone();
+        path.extend(name.split('\\').map(str::to_string));
+
+        lookup(&self.file.table.borrow(), &path)?;
+        Some(Self {
+            file: self.file.clone(),
+            path,
+        })
+    }
+
+    fn get_u32(&self, name: &str) -> Option<u32> {
+        match self.get(name)? {
+            Value::Integer(i) => u32::try_from(i).ok(),
+            // `History = false` reads better than `History = 0`
+            Value::Boolean(b) => Some(u32::from(b)),
+            _ => None,
+        }
+    }
+
+    fn get_string(&self, name: &str) -> Option<String> {
+        match self.get(name)? {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn set_u32(&self, name: &str, value: u32) -> anyhow::Result<()> {
+        self.set(name, Value::Integer(i64::from(value)))
+    }
+
+    fn set_string(&self, name: &str, value: &str) -> anyhow::Result<()> {
+        self.set(name, Value::String(value.to_string()))
+    }
+
+    /// Without DPAPI the value stays as the user set it, in a file only they can read
+    fn get_secret(&self, name: &str) -> Option<String> {
+        self.get_string(name)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Writes the whole file to a temporary one next to it, which then replaces it, so a crash
+    /// while saving leaves the old file rather than half of the new one
+    fn flush(&self) -> anyhow::Result<()> {
+        let path = &self.file.path;
+        let content =
+            toml::to_string(&*self.file.table.borrow()).context("serializing settings")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        }
+        let temp = path.with_extension("toml.tmp");
+        write_private(&temp, content.as_bytes())
+            .with_context(|| format!("writing {}", temp.display()))?;
+        fs::rename(&temp, path).with_context(|| format!("replacing {}", path.display()))
+    }
+}
+
+fn lookup<'a>(table: &'a Table, path: &[String]) -> Option<&'a Table> {
+    path.iter()
+        .try_fold(table, |table, name| table.get(name)?.as_table())
+}
+
+/// Only readable by the user, since tokens are stored as-is
+fn write_private(path: &Path, content: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // The mode only applies to new files, not to one the user created
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(content)?;
+    // On the disk before it replaces the old file
+    file.sync_all()
+}
@@ -0,0 +1,461 @@
+//! The parts of the tray icon that differ between platforms.
+//!
+//! On Windows the icons are resources in the executable, and the tray icon gets its messages
+//! through the winit event loop. On Linux the icon is a StatusNotifierItem created through
+//! libappindicator, which needs GTK, so GTK's events are dispatched from the winit event loop.
+//! On macOS the icon is a template in the menu bar, which macOS colors to match the menu bar.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use tray_icon::menu::Menu;
+use tray_icon::{Icon, TrayIcon};
+use winit::window::Theme;
+
+use crate::headset_control::BatteryState;
+
+#[cfg(windows)]
+pub type Native = Windows;
+#[cfg(target_os = "linux")]
+pub type Native = Linux;
+#[cfg(target_os = "macos")]
+pub type Native = MacOs;
+
+pub trait Tray {
+    /// Whether the icons are templates, which only use the alpha channel
+    const TEMPLATE_ICONS: bool = false;
+
+    /// A battery level for each icon, for loading them all ahead of time. Empty when every
+    /// level has its own icon.
+    const ICON_LEVELS: &'static [isize] = &[];
+
+    /// Must be called before the tray icon is created
+    fn init() -> Result<()> {
+        Ok(())
+    }
+
+    /// Handles the events that don't wake up the winit event loop, the menu clicks among them
+    /// are then sent to it
+    fn dispatch_events() {}
+
+    fn load_icon(
+        theme: Theme,
+        battery_percent: isize,
+        state: BatteryState,
+        size: Option<(u32, u32)>,
+    ) -> Result<Icon>;
+
+    /// The part of the battery level that the icon shows, which is all of it by default.
+    /// The icon only has to be reloaded when this changes.
+    fn icon_level(battery_percent: isize) -> isize {
+        battery_percent
+    }
+
+    /// Size of the icons to load for the monitor showing the tray icon, when the OS
+    /// doesn't scale them itself
+    fn icon_size(_tray_icon: &TrayIcon) -> Option<(u32, u32)> {
+        None
+    }
+
+    /// Makes the tray menu pick up the current light/dark theme
+    fn refresh_menu_theme() -> Result<()> {
+        Ok(())
+    }
+
+    /// Opens the tray menu at the cursor, as if the tray icon had been clicked
+    fn show_menu(_tray_icon: &TrayIcon, _menu: &Menu) {}
+
+    /// Whether the app is running inside a Remote Desktop session
+    fn is_remote_session() -> bool {
+        false
+    }
+}
+
+/// Icons loaded before, so going back to one doesn't load its resource or draw it again
+#[derive(Default)]
+pub struct IconCache {
+    icons: HashMap<IconKey, Icon>,
+}
+
+/// What the loaded icon looks like, the theme is whether it's for a light panel
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct IconKey {
+    light: bool,
+    level: isize,
+    state: BatteryState,
+    size: Option<(u32, u32)>,
+}
+
+impl IconCache {
+    /// The icon from `T::load_icon`, which is only called the first time
+    pub fn get<T: Tray>(
+        &mut self,
+        theme: Theme,
+        battery_percent: isize,
+        state: BatteryState,
+        size: Option<(u32, u32)>,
+    ) -> Result<Icon> {
+        let key = IconKey {
+            light: theme == Theme::Light,
+            // No level is shown without a headset
+            level: if state == BatteryState::BatteryUnavailable {
+                0
+            } else {
+                T::icon_level(battery_percent)
+            },
+            state,
+            size,
+        };
+        if let Some(icon) = self.icons.get(&key) {
+            return Ok(icon.clone());
+        }
+
+        let icon = T::load_icon(theme, battery_percent, state, size)?;
+        self.icons.insert(key, icon.clone());
+        Ok(icon)
+    }
+
+    /// Loads every icon for `size` now, so updates hand out handles instead of loading icons.
+    /// Icons of other sizes aren't needed anymore.
+    pub fn preload<T: Tray>(&mut self, size: Option<(u32, u32)>) -> Result<()> {
+        self.icons.retain(|key, _| key.size == size);
+
+        for theme in [Theme::Dark, Theme::Light] {
+            self.get::<T>(theme, 0, BatteryState::BatteryUnavailable, size)?;
+            for &level in T::ICON_LEVELS {
+                for state in [
+                    BatteryState::BatteryAvailable,
+                    BatteryState::BatteryCharging,
+                ] {
+                    self.get::<T>(theme, level, state, size)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+pub struct Windows;
+
+#[cfg(windows)]
+impl Tray for Windows {
+    const ICON_LEVELS: &'static [isize] = &[0, 25, 50, 75, 100];
+
+    fn init() -> Result<()> {
+        if let Err(err) = enable_dark_mode_support() {
+            log::warn!("Failed to enable dark mode support: {:?}", err);
+        }
+        Ok(())
+    }
+
+    fn load_icon(
+        theme: Theme,
+        battery_percent: isize,
+        state: BatteryState,
+        size: Option<(u32, u32)>,
+    ) -> Result<Icon> {
+        use anyhow::Context;
+
+        let res_id = battery_res_id_for(theme, battery_percent, state);
+
+        Icon::from_resource(res_id, size)
+            .with_context(|| format!("loading icon from resource {res_id}"))
+    }
+
+    /// There's an icon for every quarter
+    fn icon_level(battery_percent: isize) -> isize {
+        level_bucket(battery_percent) as isize
+    }
+
+    /// Without an explicit size, icons are loaded for the DPI the app was started with
+    /// and look blurry after the scaling changes.
+    fn icon_size(tray_icon: &TrayIcon) -> Option<(u32, u32)> {
+        use windows::Win32::{
+            Foundation::POINT,
+            Graphics::Gdi::{MONITOR_DEFAULTTOPRIMARY, MonitorFromPoint},
+            UI::{
+                HiDpi::{GetDpiForMonitor, GetSystemMetricsForDpi, MDT_EFFECTIVE_DPI},
+                WindowsAndMessaging::{SM_CXSMICON, SM_CYSMICON},
+            },
+        };
+
+        let point = tray_icon
+            .rect()
+            .map(|rect| POINT {
+                x: rect.position.x as i32,
+                y: rect.position.y as i32,
+            })
+            .unwrap_or_default();
+
+        unsafe {
+            let monitor = MonitorFromPoint(point, MONITOR_DEFAULTTOPRIMARY);
+            let (mut dpi_x, mut dpi_y) = (0, 0);
+            GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y).ok()?;
+
+            let width = GetSystemMetricsForDpi(SM_CXSMICON, dpi_x);
+            let height = GetSystemMetricsForDpi(SM_CYSMICON, dpi_y);
+            (width > 0 && height > 0).then_some((width as u32, height as u32))
+        }
+    }
+
+    fn refresh_menu_theme() -> Result<()> {
+        unsafe {
+            // FlushMenuThemes is ordinal 136 in uxtheme.dll
+            let proc = uxtheme_proc(136)?;
+
+            let flush_menu_themes: FlushMenuThemesFn = std::mem::transmute(proc);
+            flush_menu_themes();
+
+            Ok(())
+        }
+    }
+
+    fn show_menu(tray_icon: &TrayIcon, menu: &Menu) {
+        use tray_icon::menu::ContextMenu as _;
+
+        let hwnd = tray_icon.window_handle() as isize;
+        unsafe {
+            menu.show_context_menu_for_hwnd(hwnd, None);
+        }
+    }
+
+    fn is_remote_session() -> bool {
+        use windows::Win32::UI::WindowsAndMessaging::{GetSystemMetrics, SM_REMOTESESSION};
+
+        unsafe { GetSystemMetrics(SM_REMOTESESSION) != 0 }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub struct Linux;
+
+#[cfg(target_os = "linux")]
+impl Tray for Linux {
+    fn init() -> Result<()> {
+        use anyhow::Context;
+
+        gtk::init().context("initializing GTK")?;
+        Ok(())
+    }
+
+    /// GTK can't wake up the winit event loop, so its events are handled when polled
+    fn dispatch_events() {
+        while gtk::events_pending() {
+            gtk::main_iteration_do(false);
+        }
+    }
+
+    /// The panel scales the drawn battery to its own size
+    fn load_icon(
+        theme: Theme,
+        battery_percent: isize,
+        state: BatteryState,
+        _size: Option<(u32, u32)>,
+    ) -> Result<Icon> {
+        drawn_icon(theme, battery_percent, state, Self::TEMPLATE_ICONS)
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub struct MacOs;
+
+#[cfg(target_os = "macos")]
+impl Tray for MacOs {
+    const TEMPLATE_ICONS: bool = true;
+    const ICON_LEVELS: &'static [isize] = &[0, 25, 50, 75, 100];
+
+    fn icon_level(battery_percent: isize) -> isize {
+        level_bucket(battery_percent) as isize
+    }
+
+    /// The menu bar scales the drawn battery to its own size
+    fn load_icon(
+        theme: Theme,
+        battery_percent: isize,
+        state: BatteryState,
+        _size: Option<(u32, u32)>,
+    ) -> Result<Icon> {
+        drawn_icon(theme, battery_percent, state, Self::TEMPLATE_ICONS)
+    }
+}
+
+#[cfg(not(windows))]
+fn drawn_icon(
+    theme: Theme,
+    battery_percent: isize,
+    state: BatteryState,
+    template: bool,
+) -> Result<Icon> {
+    use anyhow::Context;
+
+    let rgba = draw_battery(theme, battery_percent, state, template);
+    Icon::from_rgba(rgba, BATTERY_SIZE, BATTERY_SIZE).context("creating battery icon")
+}
+
+/// Width and height of the drawn battery
+#[cfg(not(windows))]
+pub const BATTERY_SIZE: u32 = 32;
+
+/// The battery as RGBA pixels, `BATTERY_SIZE` wide and high, since there are no icon
+/// resources outside of Windows.
+///
+/// Template icons can't be colored, so charging is shown with a bolt, and the level is
+/// rounded like on Windows since a sliver is hard to see in the menu bar.
+#[cfg(not(windows))]
+pub fn draw_battery(
+    theme: Theme,
+    battery_percent: isize,
+    state: BatteryState,
+    template: bool,
+) -> Vec<u8> {
+    const SIZE: u32 = BATTERY_SIZE;
+    let palette = crate::palette::current();
+    let [r, g, b] = palette.charging();
+    let charging = [r, g, b, 0xff];
+    let [r, g, b] = palette.low();
+    let low = [r, g, b, 0xff];
+
+    // Light icons on dark panels, like the Windows icons. macOS recolors templates.
+    let mut color = if template || theme == Theme::Light {
+        [0x20, 0x20, 0x20, 0xff]
+    } else {
+        [0xf0, 0xf0, 0xf0, 0xff]
+    };
+    if state == BatteryState::BatteryUnavailable {
+        color[3] = 0x80;
+    }
+
+    let mut rgba = vec![0u8; (SIZE * SIZE * 4) as usize];
+    let mut fill = |left: u32, top: u32, right: u32, bottom: u32, color: [u8; 4]| {
+        for y in top..bottom {
+            for x in left..right {
+                let i = ((y * SIZE + x) * 4) as usize;
+                rgba[i..i + 4].copy_from_slice(&color);
+            }
+        }
+    };
+
+    // Outline of the body, and the terminal on the right
+    fill(2, 9, 28, 11, color);
+    fill(2, 21, 28, 23, color);
+    fill(2, 11, 4, 21, color);
+    fill(26, 11, 28, 21, color);
+    fill(28, 13, 30, 19, color);
+
+    if state != BatteryState::BatteryUnavailable {
+        let level = if template {
+            u32::from(level_bucket(battery_percent)) * 25
+        } else {
+            battery_percent.clamp(0, 100) as u32
+        };
+        let level_color = if state == BatteryState::BatteryCharging {
+            charging
+        } else if level <= 10 {
+            low
+        } else {
+            color
+        };
+        // At least a sliver, so an empty battery doesn't look disconnected
+        fill(6, 13, 6 + (18 * level / 100).max(1), 19, level_color);
+    }
+
+    if template && state == BatteryState::BatteryCharging {
+        // Inverted over the level, so the bolt shows on a full battery too
+        let bolt = [(15, 18), (14, 17), (12, 19), (13, 18), (14, 17), (13, 16)];
+        for (y, (left, right)) in (13..19).zip(bolt) {
+            for x in left..right {
+                let i = ((y * SIZE + x) * 4) as usize;
+                rgba[i + 3] = if rgba[i + 3] == 0 { color[3] } else { 0 };
+                rgba[i..i + 3].copy_from_slice(&color[..3]);
+            }
+        }
+    }
+
+    rgba
+}
+
+#[cfg(windows)]
+fn battery_res_id_for(theme: Theme, battery_percent: isize, state: BatteryState) -> u16 {
+    let level = level_bucket(battery_percent) + 1;
+
+    // light mode icons are (10,20,...,50)
+    // dark mode icons are (15,25,...,55)
+    let theme_offset: u16 = if theme == Theme::Light { 5 } else { 0 };
+    // Charging icons are at icon id + 1, and the ones with the dot in another palette's
+    // color 100 apart after them
+    let charging_offset = if state == BatteryState::BatteryCharging {
+        1 + crate::palette::current().icon_offset()
+    } else {
+        0
+    };
+
+    if state == BatteryState::BatteryUnavailable {
+        10 + theme_offset
+    } else {
+        level * 10 + theme_offset + charging_offset
+    }
+}
+
+/// The level in quarters, from 0 for empty to 4 for full. -1 is an unknown level, shown as empty.
+fn level_bucket(battery_percent: isize) -> u16 {
+    match battery_percent {
+        ..=12 => 0,   // 0%
+        13..=37 => 1, // 25%
+        38..=62 => 2, // 50%
+        63..=87 => 3, // 75%
+        _ => 4,       // 100%
+    }
+}
+
+// Enable dark mode support on Windows 10/11
+
+#[cfg(windows)]
+#[repr(C)]
+#[allow(dead_code)]
+enum PreferredAppMode {
+    Default = 0,
+    AllowDark = 1,
+    ForceDark = 2,
+    ForceLight = 3,
+}
+
+#[cfg(windows)]
+type SetPreferredAppModeFn = unsafe extern "system" fn(PreferredAppMode) -> i32;
+
+#[cfg(windows)]
+type FlushMenuThemesFn = unsafe extern "system" fn();
+
+#[cfg(windows)]
+fn enable_dark_mode_support() -> Result<()> {
+    unsafe {
+        // SetPreferredAppMode is ordinal 135 in uxtheme.dll
+        let proc = uxtheme_proc(135)?;
+
+        let set_preferred_app_mode: SetPreferredAppModeFn = std::mem::transmute(proc);
+        set_preferred_app_mode(PreferredAppMode::AllowDark);
+
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+unsafe fn uxtheme_proc(ordinal: u16) -> Result<unsafe extern "system" fn() -> isize> {
+    use anyhow::Context;
+    use windows::{
+        Win32::{
+            Foundation::HMODULE,
+            System::LibraryLoader::{GetProcAddress, LoadLibraryA},
+        },
+        core::PCSTR,
+    };
+
+    unsafe {
+        // Load uxtheme.dll
+        let module: HMODULE =
+            LoadLibraryA(windows::core::s!("uxtheme.dll")).context("loading uxtheme.dll")?;
+
+        GetProcAddress(module, PCSTR::from_raw(ordinal as *const u8))
+            .with_context(|| format!("Failed to get proc address of ordinal {ordinal}"))
+    }
+}
@@ -0,0 +1,155 @@
+//! Polls the headsets on a separate thread, so the tray menu stays responsive while
+//! headsetcontrol takes seconds to wake up a dongle or waits for a HID timeout.
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use log::{debug, error};
+use winit::event_loop::EventLoopProxy;
+
+use crate::UserEvent;
+use crate::demo::Demo;
+use crate::headset_control::{Device, Registry};
+
+/// A poll that found no headset is reused for this long, so the refreshes after a theme or
+/// display change don't start headsetcontrol again while there's nothing to find
+const NO_DEVICE_CACHE: Duration = Duration::from_secs(3);
+
+enum Request {
+    Poll,
+    /// Polls even when the last poll found nothing a moment ago, after a device was plugged in
+    PollNow,
+    /// Polls with other backends from now on, after the HeadsetControl executable was changed
+    SetRegistry(Registry),
+}
+
+/// The result of one poll
+pub struct DeviceSnapshot {
+    /// Empty when no headset was found
+    pub devices: anyhow::Result<Vec<Device>>,
+    /// Whether no headset was found a moment ago, and headsetcontrol wasn't run again
+    pub cached: bool,
+}
+
+/// Runs a poll whenever one is requested, and wakes up the event loop with
+/// `UserEvent::DevicesPolled` when its snapshot is ready
+pub struct Poller {
+    requests: mpsc::Sender<Request>,
+    snapshots: mpsc::Receiver<DeviceSnapshot>,
+}
+
+impl Poller {
+    /// Polls the made-up headset of `demo` instead of the real ones when it's set
+    pub fn start(
+        proxy: EventLoopProxy<UserEvent>,
+        registry: Registry,
+        demo: Option<Demo>,
+    ) -> anyhow::Result<Self> {
+        let (requests, request_receiver) = mpsc::channel();
+        let (snapshot_sender, snapshots) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("poller".to_string())
+            .spawn(move || run(request_receiver, snapshot_sender, proxy, registry, demo))
+            .context("spawning poller thread")?;
+
+        Ok(Self {
+            requests,
+            snapshots,
+        })
+    }
+
+    /// Asks for a poll. Requests made while a poll is running are answered by one more poll.
+    pub fn request(&self) {
+        self.send(Request::Poll);
+    }
+
+    /// Asks for a poll that doesn't reuse the last one, when the devices have changed
+    pub fn request_now(&self) {
+        self.send(Request::PollNow);
+    }
+
+    /// Polls with `registry` from now on, starting right away
+    pub fn set_registry(&self, registry: Registry) {
+        self.send(Request::SetRegistry(registry));
+    }
+
+    fn send(&self, request: Request) {
+        if self.requests.send(request).is_err() {
+            error!("Poller thread has stopped");
+        }
+    }
+
+    /// The newest snapshot that hasn't been taken yet, older ones are dropped
+    pub fn latest(&self) -> Option<DeviceSnapshot> {
+        self.snapshots.try_iter().last()
+    }
+}
+
+fn run(
+    requests: mpsc::Receiver<Request>,
+    snapshots: mpsc::Sender<DeviceSnapshot>,
+    proxy: EventLoopProxy<UserEvent>,
+    mut registry: Registry,
+    demo: Option<Demo>,
+) {
+    // When the last poll found no headset
+    let mut found_none: Option<Instant> = None;
+    // What the last poll found, for the backends that haven't answered yet
+    let mut last_devices = Vec::new();
+
+    while let Ok(request) = requests.recv() {
+        // All waiting requests are answered by this poll
+        let mut now = false;
+        for request in std::iter::once(request).chain(requests.try_iter()) {
+            match request {
+                Request::Poll => {}
+                Request::PollNow => now = true,
+                Request::SetRegistry(new) => {
+                    registry = new;
+                    // Headsets of the old backends aren't kept around for the new ones
+                    last_devices.clear();
+                    now = true;
+                }
+            }
+        }
+
+        let snapshot = if let Some(demo) = &demo {
+            DeviceSnapshot {
+                devices: Ok(demo.devices()),
+                cached: false,
+            }
+        } else if !now && found_none.is_some_and(|at| at.elapsed() < NO_DEVICE_CACHE) {
+            DeviceSnapshot {
+                devices: Ok(Vec::new()),
+                cached: true,
+            }
+        } else {
+            let mut devices = last_devices.clone();
+            let result = registry.poll(&mut devices, |partial| {
+                let snapshot = DeviceSnapshot {
+                    devices: Ok(partial),
+                    cached: false,
+                };
+                if snapshots.send(snapshot).is_ok() {
+                    let _ = proxy.send_event(UserEvent::DevicesPolled);
+                }
+            });
+            found_none = (result.is_ok() && devices.is_empty()).then(Instant::now);
+            if result.is_ok() {
+                last_devices.clone_from(&devices);
+            }
+            DeviceSnapshot {
+                devices: result.map(|()| devices),
+                cached: false,
+            }
+        };
+        if snapshots.send(snapshot).is_err() || proxy.send_event(UserEvent::DevicesPolled).is_err()
+        {
+            debug!("Event loop closed, stopping poller");
+            return;
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Battery alerts on the phone through ntfy or Pushover, for when the PC is out of sight
+
+use std::thread;
+use std::time::Duration;
+
+use log::{error, info};
+use serde_json::json;
+
+use crate::actions::BatteryEvent;
+use crate::headset_control::Device;
+use crate::http;
+
+pub const DEFAULT_NTFY_SERVER: &str = "https://ntfy.sh";
+const PUSHOVER_URL: &str = "https://api.pushover.net/1/messages.json";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A push service read from the `Push` registry subkey
+#[derive(Debug, Clone)]
+pub enum PushService {
+    Ntfy { server: String, topic: String },
+    Pushover { token: String, user: String },
+}
+
+impl PushService {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PushService::Ntfy { .. } => "ntfy",
+            PushService::Pushover { .. } => "Pushover",
+        }
+    }
+}
+
+/// Sends a push notification for low and critical battery events, other events are ignored
+pub fn send(services: &[PushService], event: BatteryEvent, device: &Device) {
+    let (message, critical) = match event {
+        BatteryEvent::Low => (format!("Battery low ({}%)", device.battery.level), false),
+        BatteryEvent::Critical => (
+            format!("Battery critical ({}%)", device.battery.level),
+            true,
+        ),
+        _ => return,
+    };
+
+    for service in services {
+        let (url, body) = request(service, &device.product, &message, critical);
+        let name = service.name();
+
+        let res = thread::Builder::new()
+            .name("push".to_string())
+            .spawn(
+                move || match http::post(&url, "application/json", body.as_bytes(), TIMEOUT) {
+                    Ok(status) if (200..300).contains(&status) => {
+                        info!("Sent {} push notification via {name}", event.name())
+                    }
+                    Ok(status) => error!("{name} push notification failed with status {status}"),
+                    Err(e) => error!("{name} push notification failed: {e:?}"),
+                },
+            );
+        if let Err(e) = res {
+            error!("Failed to spawn push notification thread: {e:?}");
+        }
+    }
+}
+
+/// Both services accept JSON, so no form encoding is needed
+fn request(service: &PushService, title: &str, message: &str, critical: bool) -> (String, String) {
+    match service {
+        // Published to the server root, with the topic in the body
+        PushService::Ntfy { server, topic } => (
+            server.trim_end_matches('/').to_string(),
+            json!({
+                "topic": topic,
+                "title": title,
+                "message": message,
+                // "urgent" and "high"
+                "priority": if critical { 5 } else { 4 },
+                "tags": ["headphones"],
+            })
+            .to_string(),
+        ),
+        PushService::Pushover { token, user } => (
+            PUSHOVER_URL.to_string(),
+            json!({
+                "token": token,
+                "user": user,
+                "title": title,
+                "message": message,
+                // High priority bypasses the user's quiet hours
+                "priority": if critical { 1 } else { 0 },
+            })
+            .to_string(),
+        ),
+    }
+}
@@ -0,0 +1,279 @@
+//! Battery text in the RivaTuner Statistics Server on-screen display, next to the
+//! MSI Afterburner OSD that many players already have running.
+//!
+//! The layout of the shared memory follows RTSSSharedMemory.h from the RTSS SDK.
+
+use std::os::windows::io::{FromRawHandle, OwnedHandle};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use anyhow::Context;
+use log::{debug, error, info};
+use windows::{
+    Win32::{
+        Globalization::{CP_ACP, WideCharToMultiByte},
+        System::Memory::{
+            FILE_MAP_ALL_ACCESS, MEMORY_MAPPED_VIEW_ADDRESS, MapViewOfFile, OpenFileMappingW,
+            UnmapViewOfFile,
+        },
+    },
+    core::{HSTRING, PCSTR},
+};
+
+use crate::headset_control::Device;
+
+const SHARED_MEMORY_NAME: &str = "RTSSSharedMemoryV2";
+const SIGNATURE: u32 = u32::from_be_bytes(*b"RTSS");
+/// Identifies our slot, other clients leave it alone
+const OWNER: &[u8] = b"HeadsetBatteryIndicator";
+
+const VERSION_2_0: u32 = 0x0002_0000;
+/// The extended text slot was added in 2.7
+const VERSION_EXTENDED_TEXT: u32 = 0x0002_0007;
+/// The busy flag was added in 2.14
+const VERSION_BUSY_FLAG: u32 = 0x0002_000e;
+
+// Offsets and sizes of the fields in an OSD entry
+const TEXT: (usize, usize) = (0, 256);
+const OWNER_FIELD: (usize, usize) = (256, 256);
+const EXTENDED_TEXT: (usize, usize) = (512, 4096);
+
+/// Start of `RTSS_SHARED_MEMORY`
+#[repr(C)]
+struct Header {
+    signature: u32,
+    version: u32,
+    app_entry_size: u32,
+    app_arr_offset: u32,
+    app_arr_size: u32,
+    osd_entry_size: u32,
+    osd_arr_offset: u32,
+    osd_arr_size: u32,
+    osd_frame: u32,
+    busy: u32,
+}
+
+/// Shows the battery of the selected headset in the RTSS OSD while RTSS is running.
+///
+/// RTSS can be started and stopped at any time, so the shared memory is opened
+/// again on every update instead of once on startup.
+#[derive(Default)]
+pub struct RtssOsd {
+    /// Whether RTSS was running on the last update, to only log the changes
+    running: bool,
+}
+
+impl RtssOsd {
+    pub fn new() -> Self {
+        info!("Showing battery in the RTSS on-screen display");
+        Self::default()
+    }
+
+    pub fn publish(&mut self, device: Option<&Device>) {
+        let memory = match SharedMemory::open() {
+            Ok(memory) => memory,
+            Err(e) => {
+                if self.running {
+                    info!("RTSS is no longer running");
+                    self.running = false;
+                }
+                debug!("RTSS shared memory not available: {e:?}");
+                return;
+            }
+        };
+        if !self.running {
+            info!("RTSS found, version {:#x}", memory.header().version);
+            self.running = true;
+        }
+
+        let res = match device {
+            Some(device) => match device.chatmix_text() {
+                Some(chatmix) => memory.write(&format!("{device}\n{chatmix}")),
+                None => memory.write(&device.to_string()),
+            },
+            // Nothing to show, so don't leave an empty line in the OSD
+            None => memory.release(),
+        };
+        if let Err(e) = res {
+            error!("Failed to update RTSS OSD: {e:?}");
+        }
+    }
+}
+
+impl Drop for RtssOsd {
+    fn drop(&mut self) {
+        if let Ok(memory) = SharedMemory::open() {
+            let _ = memory.release();
+        }
+    }
+}
+
+struct SharedMemory {
+    _mapping: OwnedHandle,
+    view: MEMORY_MAPPED_VIEW_ADDRESS,
+}
+
+impl SharedMemory {
+    fn open() -> anyhow::Result<Self> {
+        let mapping = unsafe {
+            OpenFileMappingW(
+                FILE_MAP_ALL_ACCESS.0,
+                false,
+                &HSTRING::from(SHARED_MEMORY_NAME),
+            )
+        }
+        .context("OpenFileMappingW")?;
+        let mapping = unsafe { OwnedHandle::from_raw_handle(mapping.0) };
+
+        let view = unsafe { MapViewOfFile(&mapping, FILE_MAP_ALL_ACCESS, 0, 0, 0) };
+        if view.Value.is_null() {
+            return Err(windows::core::Error::from_win32()).context("MapViewOfFile");
+        }
+        let memory = Self {
+            _mapping: mapping,
+            view,
+        };
+
+        let header = memory.header();
+        if header.signature != SIGNATURE || header.version < VERSION_2_0 {
+            // RTSS is still starting up, or too old
+            anyhow::bail!(
+                "unsupported shared memory (signature {:#x}, version {:#x})",
+                header.signature,
+                header.version
+            );
+        }
+        let text_end = if header.version >= VERSION_EXTENDED_TEXT {
+            EXTENDED_TEXT.0 + EXTENDED_TEXT.1
+        } else {
+            OWNER_FIELD.0 + OWNER_FIELD.1
+        };
+        if (header.osd_entry_size as usize) < text_end {
+            anyhow::bail!("unexpected OSD entry size {}", header.osd_entry_size);
+        }
+
+        Ok(memory)
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.view.Value as *const Header) }
+    }
+
+    fn osd_entry(&self, index: u32) -> *mut u8 {
+        let header = self.header();
+        unsafe {
+            (self.view.Value as *mut u8)
+                .add(header.osd_arr_offset as usize)
+                .add((index * header.osd_entry_size) as usize)
+        }
+    }
+
+    /// The frame counter, bumped to make RTSS pick up the new text
+    fn osd_frame(&self) -> &AtomicU32 {
+        let header = self.view.Value as *mut Header;
+        unsafe { AtomicU32::from_ptr(&raw mut (*header).osd_frame) }
+    }
+
+    /// Set while a client is writing to the OSD slots
+    fn busy(&self) -> &AtomicU32 {
+        let header = self.view.Value as *mut Header;
+        unsafe { AtomicU32::from_ptr(&raw mut (*header).busy) }
+    }
+
+    /// Runs `f` while holding the busy flag, which newer RTSS versions use to
+    /// keep clients from writing to the same slot at the same time
+    fn locked<T>(&self, f: impl FnOnce() -> T) -> anyhow::Result<T> {
+        if self.header().version < VERSION_BUSY_FLAG {
+            return Ok(f());
+        }
+
+        let busy = self.busy();
+        if busy.fetch_or(1, Ordering::Acquire) & 1 != 0 {
+            // Someone else is writing, try again on the next update
+            anyhow::bail!("shared memory is busy");
+        }
+        let res = f();
+        busy.fetch_and(!1, Ordering::Release);
+        Ok(res)
+    }
+
+    /// Our slot, or the first free one which is then claimed.
+    ///
+    /// The first slot is reserved for the primary OSD client, e.g. MSI Afterburner.
+    fn slot(&self, claim: bool) -> Option<*mut u8> {
+        let header = self.header();
+        // Zero terminated, so the name is everything up to the first zero
+        let owned_by = |entry: *mut u8, name: &[u8]| {
+            let field =
+                unsafe { std::slice::from_raw_parts(entry.add(OWNER_FIELD.0), OWNER_FIELD.1) };
+            field.split(|&b| b == 0).next() == Some(name)
+        };
+        let mut entries = (1..header.osd_arr_size).map(|i| self.osd_entry(i));
+
+        if let Some(entry) = entries.clone().find(|&entry| owned_by(entry, OWNER)) {
+            return Some(entry);
+        }
+        if !claim {
+            return None;
+        }
+
+        let entry = entries.find(|&entry| owned_by(entry, b""))?;
+        unsafe { write_str(entry, OWNER_FIELD, OWNER) };
+        Some(entry)
+    }
+
+    fn write(&self, text: &str) -> anyhow::Result<()> {
+        let text = to_ansi(text);
+        let header = self.header();
+
+        self.locked(|| {
+            let entry = self.slot(true).context("no free OSD slot")?;
+            unsafe {
+                if header.version >= VERSION_EXTENDED_TEXT {
+                    write_str(entry, EXTENDED_TEXT, &text);
+                } else {
+                    write_str(entry, TEXT, &text);
+                }
+            }
+            self.osd_frame().fetch_add(1, Ordering::Release);
+            Ok(())
+        })?
+    }
+
+    fn release(&self) -> anyhow::Result<()> {
+        let header = self.header();
+
+        self.locked(|| {
+            if let Some(entry) = self.slot(false) {
+                unsafe { entry.write_bytes(0, header.osd_entry_size as usize) };
+                self.osd_frame().fetch_add(1, Ordering::Release);
+            }
+        })
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        let _ = unsafe { UnmapViewOfFile(self.view) };
+    }
+}
+
+/// Copies `value` into the field, cut off to leave room for the terminating zero
+unsafe fn write_str(entry: *mut u8, (offset, size): (usize, usize), value: &[u8]) {
+    let len = value.len().min(size - 1);
+    unsafe {
+        let field = entry.add(offset);
+        field.copy_from_nonoverlapping(value.as_ptr(), len);
+        field.add(len).write_bytes(0, size - len);
+    }
+}
+
+/// RTSS reads the text in the system code page
+fn to_ansi(text: &str) -> Vec<u8> {
+    let wide: Vec<u16> = text.encode_utf16().collect();
+    let len = unsafe { WideCharToMultiByte(CP_ACP, 0, &wide, None, PCSTR::null(), None) };
+    let mut ansi = vec![0; len.max(0) as usize];
+    let written =
+        unsafe { WideCharToMultiByte(CP_ACP, 0, &wide, Some(&mut ansi), PCSTR::null(), None) };
+    ansi.truncate(written.max(0) as usize);
+    ansi
+}
@@ -0,0 +1,164 @@
+use std::io::{Read, Write};
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+#[cfg(windows)]
+use std::path::Path;
+use std::process::{self, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use log::{debug, error, info, warn};
+use serde_json::json;
+
+use crate::actions::BatteryEvent;
+use crate::headset_control::{BatteryState, Device};
+
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+/// Scripts still running after this are killed
+const TIMEOUT: Duration = Duration::from_secs(60);
+/// Longest output logged per stream, the rest is cut off
+const MAX_LOGGED_OUTPUT: usize = 4096;
+
+/// Runs the script registered for the event in the background.
+///
+/// The event and device are written to the script's stdin as JSON, and its exit
+/// status and output end up in the log.
+pub fn run(script: &str, event: BatteryEvent, device: &Device) {
+    let script = script.to_string();
+    let payload = payload(event, device);
+
+    let res = thread::Builder::new()
+        .name("script".to_string())
+        .spawn(move || {
+            if let Err(e) = execute(&script, &payload) {
+                error!("{} script `{script}` failed: {e:?}", event.name());
+            }
+        });
+    if let Err(e) = res {
+        error!("Failed to spawn script thread: {e:?}");
+    }
+}
+
+fn payload(event: BatteryEvent, device: &Device) -> String {
+    let status = device.battery.status;
+    json!({
+        "event": event.name(),
+        "id": device.id(),
+        "name": device.product,
+        "vendor": device.vendor,
+        "level": device.battery.level,
+        "status": status.label(),
+        "charging": status == BatteryState::BatteryCharging,
+        "connected": matches!(
+            status,
+            BatteryState::BatteryAvailable | BatteryState::BatteryCharging
+        ),
+        "version": crate::VERSION,
+    })
+    .to_string()
+}
+
+#[cfg(windows)]
+fn command(script: &str) -> process::Command {
+    // PowerShell scripts can't be started directly, and the default execution policy blocks them
+    let is_powershell = Path::new(script.trim().trim_matches('"'))
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("ps1"));
+
+    let mut command = if is_powershell {
+        let mut command = process::Command::new("powershell");
+        command
+            .args([
+                "-NoProfile",
+                "-NonInteractive",
+                "-ExecutionPolicy",
+                "Bypass",
+            ])
+            .arg("-File")
+            .raw_arg(script);
+        command
+    } else {
+        let mut command = process::Command::new("cmd");
+        command.arg("/C").raw_arg(script);
+        command
+    };
+    command.creation_flags(CREATE_NO_WINDOW);
+    command
+}
+
+/// Scripts with a shebang line are started by `sh` like any other command
+#[cfg(not(windows))]
+fn command(script: &str) -> process::Command {
+    let mut command = process::Command::new("sh");
+    command.arg("-c").arg(script);
+    command
+}
+
+fn execute(script: &str, payload: &str) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let mut child = command(script)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("starting script")?;
+
+    // Read the output on separate threads, so a chatty script can't fill the pipe and block
+    let stdout = child.stdout.take().map(read_all);
+    let stderr = child.stderr.take().map(read_all);
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // Fails when the script exits without reading the payload, which is fine
+        if let Err(e) = stdin.write_all(payload.as_bytes()) {
+            debug!("Failed to write payload to script `{script}`: {e}");
+        }
+    }
+
+    let status = loop {
+        if let Some(status) = child.try_wait().context("waiting for script")? {
+            break Some(status);
+        }
+        if started.elapsed() > TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+
+    let output = |reader: Option<thread::JoinHandle<String>>| {
+        reader
+            .and_then(|reader| reader.join().ok())
+            .unwrap_or_default()
+    };
+    let (stdout, stderr) = (output(stdout), output(stderr));
+
+    for line in stdout.lines().filter(|line| !line.trim().is_empty()) {
+        info!("[{script}] {line}");
+    }
+    for line in stderr.lines().filter(|line| !line.trim().is_empty()) {
+        warn!("[{script}] {line}");
+    }
+
+    match status {
+        Some(status) if status.success() => {
+            info!("Script `{script}` finished in {:.1?}", started.elapsed());
+            Ok(())
+        }
+        Some(status) => anyhow::bail!("exited with {status}"),
+        None => anyhow::bail!("killed after running for {TIMEOUT:?}"),
+    }
+}
+
+fn read_all(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut buf = vec![];
+        // Read everything even if it's not logged, so the script doesn't block on a full pipe
+        let _ = pipe.read_to_end(&mut buf);
+        buf.truncate(MAX_LOGGED_OUTPUT);
+        String::from_utf8_lossy(&buf).into_owned()
+    })
+}
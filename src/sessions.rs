@@ -0,0 +1,210 @@
+//! Usage sessions, while a headset is on battery with something playing or recording on it,
+//! recorded to `sessions.csv` next to the battery history
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use log::{debug, error, info};
+
+use crate::audio::{self, Endpoint};
+use crate::headset_control::{BatteryState, Device};
+use crate::history;
+
+const HEADER: &str = "start,end,device";
+
+/// Enumerating the audio endpoints is too slow to do on every poll
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Pauses shorter than this, like between two songs, don't end a session
+const MAX_PAUSE: Duration = Duration::from_secs(5 * 60);
+/// Older sessions are only kept in the file
+const KEEP: Duration = Duration::from_secs(8 * 24 * 60 * 60);
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// Unix timestamps in seconds
+    pub start: u64,
+    pub end: u64,
+    pub device: String,
+}
+
+pub struct Sessions {
+    path: PathBuf,
+    /// When the running sessions started and audio was last seen, by device id
+    running: HashMap<String, (u64, u64)>,
+    /// The sessions of the last week, oldest first
+    ended: Vec<Session>,
+    last_check: Option<Instant>,
+}
+
+impl Sessions {
+    pub fn open() -> anyhow::Result<Self> {
+        let path = history::data_dir()?.join("sessions.csv");
+        info!("Recording usage sessions to {}", path.display());
+
+        let since = history::now().saturating_sub(KEEP.as_secs());
+        let ended = match fs::read_to_string(&path) {
+            Ok(content) => content
+                .lines()
+                .skip(1)
+                .filter_map(parse_line)
+                .filter(|session| session.end >= since)
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+
+        Ok(Self {
+            path,
+            running: HashMap::new(),
+            ended,
+            last_check: None,
+        })
+    }
+
+    /// Called on every poll, the audio endpoints are checked every 30 seconds
+    pub fn update(&mut self, devices: &[Device]) {
+        if self
+            .last_check
+            .is_some_and(|last_check| last_check.elapsed() < CHECK_INTERVAL)
+        {
+            return;
+        }
+        self.last_check = Some(Instant::now());
+
+        let endpoints = audio::endpoints().unwrap_or_else(|e| {
+            debug!("Failed to check audio endpoints: {e:?}");
+            vec![]
+        });
+        let now = history::now();
+
+        for device in devices {
+            let in_use = device.battery.status == BatteryState::BatteryAvailable
+                && is_playing(&endpoints, &device.product);
+            if !in_use {
+                continue;
+            }
+            self.running
+                .entry(device.id())
+                .and_modify(|(_, last_active)| *last_active = now)
+                .or_insert_with(|| {
+                    info!("Usage session of {} started", device.id());
+                    (now, now)
+                });
+        }
+
+        // Ends when the headset was quiet, charging or gone for a while
+        let quiet: Vec<String> = self
+            .running
+            .iter()
+            .filter(|&(_, &(_, last_active))| now - last_active >= MAX_PAUSE.as_secs())
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in quiet {
+            self.end(&id);
+        }
+    }
+
+    /// Ends the running sessions at the time audio was last seen, e.g. when the program exits
+    pub fn close(&mut self) {
+        let ids: Vec<String> = self.running.keys().cloned().collect();
+        for id in ids {
+            self.end(&id);
+        }
+    }
+
+    /// How long the device was used today, including the running session
+    pub fn today(&self, device: &Device) -> Duration {
+        let id = device.id();
+        let midnight = history::today_start();
+        let running = self.running.get(&id).copied();
+
+        let seconds = self
+            .ended
+            .iter()
+            .filter(|session| session.device == id)
+            .map(|session| (session.start, session.end))
+            .chain(running)
+            .map(|(start, end)| end.saturating_sub(start.max(midnight)))
+            .sum();
+        Duration::from_secs(seconds)
+    }
+
+    fn end(&mut self, id: &str) {
+        let Some((start, end)) = self.running.remove(id) else {
+            return;
+        };
+        info!(
+            "Usage session of {id} ended after {}",
+            history::format_minutes((end - start) / 60)
+        );
+        // Audio was only seen once
+        if end == start {
+            return;
+        }
+
+        let session = Session {
+            start,
+            end,
+            device: id.to_string(),
+        };
+        if let Err(e) = self.append(&session) {
+            error!("Failed to record usage session: {e:?}");
+        }
+
+        let since = history::now().saturating_sub(KEEP.as_secs());
+        self.ended.retain(|session| session.end >= since);
+        self.ended.push(session);
+    }
+
+    fn append(&self, session: &Session) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("opening {}", self.path.display()))?;
+        if file.metadata().map(|m| m.len() == 0).unwrap_or(true) {
+            writeln!(file, "{HEADER}")?;
+        }
+        writeln!(file, "{},{},{}", session.start, session.end, session.device)?;
+        Ok(())
+    }
+}
+
+/// Whether something plays on the headset's endpoint, found by its name, or on the default
+/// playback device when no endpoint is named after the headset
+fn is_playing(endpoints: &[Endpoint], product: &str) -> bool {
+    let product = product.to_lowercase();
+    // Endpoints are often named without the vendor, like "Headphones (Arctis 7)"
+    let model = product
+        .split_once(' ')
+        .map(|(_, model)| model)
+        .filter(|model| model.len() >= 3);
+
+    let named: Vec<&Endpoint> = endpoints
+        .iter()
+        .filter(|endpoint| {
+            let name = endpoint.name.to_lowercase();
+            name.contains(&product) || model.is_some_and(|model| name.contains(model))
+        })
+        .collect();
+    if named.is_empty() {
+        endpoints
+            .iter()
+            .any(|endpoint| endpoint.default && endpoint.active)
+    } else {
+        named.iter().any(|endpoint| endpoint.active)
+    }
+}
+
+fn parse_line(line: &str) -> Option<Session> {
+    let mut fields = line.split(',');
+    Some(Session {
+        start: fields.next()?.parse().ok()?,
+        end: fields.next()?.parse().ok()?,
+        device: fields.next()?.to_string(),
+    })
+}
@@ -1,37 +1,625 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
 use anyhow::{Context, Result};
-use winreg::enums::HKEY_CURRENT_USER;
+
+use crate::actions::BatteryEvent;
+use crate::discord::{self, DiscordConfig};
+use crate::headset_control::{self, BatteryState, Device, HeadsetControlConfig};
+use crate::history::Retention;
+#[cfg(windows)]
+use crate::hotkeys::{self, Hotkey};
+use crate::influx::{self, InfluxConfig};
+use crate::mqtt::{self, MqttConfig};
+#[cfg(target_os = "linux")]
+use crate::overlay::{self, Corner, OverlayConfig};
+use crate::palette::Palette;
+use crate::push::{self, PushService};
+use crate::platform::settings::{Native, Storage};
+#[cfg(windows)]
+use crate::sound::{self, SoundConfig};
+use crate::telegram::TelegramConfig;
+use crate::webhook::{self, Webhook};
 
 #[derive(Debug, Clone)]
 pub struct Settings {
     pub notifications_enabled: bool,
+    /// Commands to run on battery events, read from the `Actions` subkey
+    pub actions: HashMap<BatteryEvent, String>,
+    /// Scripts that get the event as JSON on stdin, read from the `Scripts` subkey
+    pub scripts: HashMap<BatteryEvent, String>,
+    /// Requests to send on battery events, read from the `Webhooks\<event>` subkeys
+    pub webhooks: HashMap<BatteryEvent, Webhook>,
+    /// Phone notifications for low battery, read from the `Push` subkey
+    pub push: Vec<PushService>,
+    /// Battery level and state last seen by the notifier, saved on exit
+    pub last_notification_state: Option<(isize, BatteryState)>,
+    /// The last reading of each headset, most recent first, saved on exit and shown
+    /// until the first poll
+    pub last_devices: Vec<Device>,
+    /// The id of the headset shown in the tray, saved on exit and selected again once it's found
+    pub selected_device: Option<String>,
+    pub api_enabled: bool,
+    pub api_port: u16,
+    /// Loopback only by default, other addresses require `api_token`
+    pub api_bind_address: IpAddr,
+    /// Required from API clients when set, stored encrypted
+    pub api_token: Option<String>,
+    /// Whether to advertise the local API with mDNS while it's enabled on a network address
+    pub api_mdns: bool,
+    /// Web pages allowed to read the API from a browser, from `ApiAllowedOrigins` split at spaces
+    pub api_allowed_origins: Vec<String>,
+    pub grpc_enabled: bool,
+    pub grpc_port: u16,
+    /// Whether to keep `status.json` in LocalAppData up to date
+    pub status_file_enabled: bool,
+    /// Whether to publish the headsets as HWiNFO custom sensors
+    #[cfg(windows)]
+    pub hwinfo_enabled: bool,
+    /// Whether to show the battery in the RivaTuner Statistics Server OSD
+    #[cfg(windows)]
+    pub rtss_enabled: bool,
+    /// Whether to serve the device list on the named pipe, enabled by default
+    #[cfg(windows)]
+    pub pipe_enabled: bool,
+    /// Whether to publish the performance counters, enabled by default
+    #[cfg(windows)]
+    pub perf_counters_enabled: bool,
+    /// Read from the `Hotkeys` subkey, in the order of `hotkeys::Action::ALL`
+    #[cfg(windows)]
+    pub hotkeys: Vec<(hotkeys::Action, Hotkey)>,
+    /// Read from the `Sounds` subkey, the sounds are played when an event has one
+    #[cfg(windows)]
+    pub sounds: Option<SoundConfig>,
+    /// Whether to hold back the notifications and sounds while the microphone is in use, off
+    /// by default
+    #[cfg(windows)]
+    pub quiet_while_recording: bool,
+    /// Whether to scan for the advertisements of AirPods, off by default
+    #[cfg(windows)]
+    pub airpods: bool,
+    /// Read from the `Backends` subkey, where a backend is disabled with a value of 0 under its
+    /// name, like `HeadsetControl` or `Logitech`
+    pub disabled_backends: Vec<String>,
+    /// From `HeadsetControlPath`, chosen in the tray menu, and `HeadsetControlArgs`, split at
+    /// spaces
+    pub headsetcontrol: HeadsetControlConfig,
+    /// Whether the lights of the headsets are on, `None` until they're set in the tray menu
+    pub lights: Option<bool>,
+    /// Whether to turn the lights off while the battery is below 20%, off by default
+    pub lights_off_when_low: bool,
+    /// Minutes until the headset turns itself off when idle, as last picked in the tray menu
+    /// since headsets don't report it, 0 for never
+    pub inactive_time: Option<u8>,
+    /// The colors for charging and low battery in the icons and the history chart
+    pub palette: Palette,
+    /// Whether to record battery samples to `history.db`, enabled by default
+    pub history_enabled: bool,
+    /// From `HistoryRetentionDays` and `HistoryFullResolutionDays`, 90 and 30 days by default
+    pub history_retention: Retention,
+    /// A notification is shown when the estimated battery health drops below this percent, 0 turns it off
+    pub health_warning: u8,
+    /// How long a headset charges at 100% before it's suggested to unplug it, off by default
+    pub unplug_reminder: Option<Duration>,
+    /// Whether to show a summary of the previous week on Monday mornings, off by default
+    pub weekly_summary: bool,
+    /// The Monday the weekly summary was last shown for, in days since 1970-01-01
+    pub last_weekly_summary: Option<i64>,
+    /// Whether to check the GitHub releases for a newer version once a day, off by default
+    pub update_check: bool,
+    /// When the releases were last checked, as a Unix timestamp in seconds
+    pub last_update_check: Option<u64>,
+    /// The tag of the newest release found by the last check, like `v3.4.0`
+    pub latest_release: Option<String>,
+    /// Read from the `Mqtt` subkey, publishing is enabled when `Broker` is set
+    pub mqtt: Option<MqttConfig>,
+    /// Read from the `Discord` subkey, the presence is shown when `ClientId` is set
+    pub discord: Option<DiscordConfig>,
+    /// Read from the `Telegram` subkey, the bot is started when `Token` and `ChatId` are set
+    pub telegram: Option<TelegramConfig>,
+    /// Read from the `InfluxDb` subkey, samples are written when `Url`, `Org`, `Bucket` and
+    /// `Token` are set
+    pub influx: Option<InfluxConfig>,
+    /// Read from the `Overlay` subkey, the overlay is shown when `Enabled` is set
+    #[cfg(target_os = "linux")]
+    pub overlay: Option<OverlayConfig>,
 }
 
 impl Settings {
     pub fn load() -> Result<Self> {
-        let hkcu = winreg::RegKey::predef(HKEY_CURRENT_USER);
-        let (key, _) = hkcu
-            .create_subkey("Software\\HeadsetBatteryIndicator")
-            .context("accessing registry key")?;
+        Self::load_from(&Native::open()?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&Native::open()?)
+    }
 
-        let notifications_enabled: u32 = key.get_value("NotificationsEnabled").unwrap_or_default();
+    fn load_from(key: &impl Storage) -> Result<Self> {
+        let notifications_enabled: u32 = key.get_u32("NotificationsEnabled").unwrap_or_default();
 
         log::debug!(
             "NotificationsEnabled={}",
             notifications_enabled
         );
 
+        let mut actions = HashMap::new();
+        if let Some(actions_key) = key.subkey("Actions") {
+            for event in BatteryEvent::ALL {
+                if let Some(command) = actions_key.get_string(event.name())
+                    && !command.trim().is_empty()
+                {
+                    log::debug!("Actions\\{}={}", event.name(), command);
+                    actions.insert(event, command);
+                }
+            }
+        }
+
+        let mut scripts = HashMap::new();
+        if let Some(scripts_key) = key.subkey("Scripts") {
+            for event in BatteryEvent::ALL {
+                if let Some(script) = scripts_key.get_string(event.name())
+                    && !script.trim().is_empty()
+                {
+                    log::debug!("Scripts\\{}={}", event.name(), script);
+                    scripts.insert(event, script);
+                }
+            }
+        }
+
+        let mut webhooks = HashMap::new();
+        if let Some(webhooks_key) = key.subkey("Webhooks") {
+            for event in BatteryEvent::ALL {
+                let Some(webhook_key) = webhooks_key.subkey(event.name()) else {
+                    continue;
+                };
+                let Some(url) = webhook_key.get_string("Url") else {
+                    continue;
+                };
+                let non_empty = |name: &str| {
+                    webhook_key
+                        .get_string(name)
+                        .filter(|value| !value.trim().is_empty())
+                };
+
+                log::debug!("Webhooks\\{}\\Url={}", event.name(), url);
+                webhooks.insert(
+                    event,
+                    Webhook {
+                        url,
+                        body: non_empty("Body").unwrap_or_else(|| webhook::DEFAULT_BODY.to_string()),
+                        content_type: non_empty("ContentType")
+                            .unwrap_or_else(|| webhook::DEFAULT_CONTENT_TYPE.to_string()),
+                    },
+                );
+            }
+        }
+
+        let mut push = vec![];
+        if let Some(push_key) = key.subkey("Push") {
+            let non_empty = |name: &str| {
+                push_key
+                    .get_string(name)
+                    .filter(|value| !value.trim().is_empty())
+            };
+
+            if let Some(topic) = non_empty("NtfyTopic") {
+                push.push(PushService::Ntfy {
+                    server: non_empty("NtfyServer")
+                        .unwrap_or_else(|| push::DEFAULT_NTFY_SERVER.to_string()),
+                    topic,
+                });
+            }
+            if let (Some(token), Some(user)) =
+                (push_key.get_secret("PushoverToken"), non_empty("PushoverUser"))
+            {
+                push.push(PushService::Pushover { token, user });
+            }
+            // The topic and tokens are secrets, so only log which services are set up
+            log::debug!("Push={:?}", push.iter().map(PushService::name).collect::<Vec<_>>());
+        }
+
+        let api_enabled: u32 = key.get_u32("ApiEnabled").unwrap_or_default();
+        let api_port: u32 = key
+            .get_u32("ApiPort")
+            .unwrap_or(crate::api::DEFAULT_PORT as u32);
+        let api_mdns: u32 = key.get_u32("ApiMdns").unwrap_or_default();
+        let api_bind_address = key
+            .get_string("ApiBindAddress")
+            .filter(|address| !address.trim().is_empty())
+            .and_then(|address| {
+                address
+                    .trim()
+                    .parse::<IpAddr>()
+                    .inspect_err(|e| log::error!("Invalid ApiBindAddress {address:?}: {e}"))
+                    .ok()
+            })
+            .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let api_token = key.get_secret("ApiToken");
+        let api_allowed_origins: Vec<String> = key
+            .get_string("ApiAllowedOrigins")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|origin| origin.trim_end_matches('/').to_string())
+            .collect();
+
+        log::debug!(
+            "ApiEnabled={} ApiPort={} ApiMdns={} ApiBindAddress={} ApiToken={}",
+            api_enabled,
+            api_port,
+            api_mdns,
+            api_bind_address,
+            api_token.is_some()
+        );
+        log::debug!("ApiAllowedOrigins={:?}", api_allowed_origins);
+
+        let grpc_enabled: u32 = key.get_u32("GrpcEnabled").unwrap_or_default();
+        let grpc_port: u32 = key
+            .get_u32("GrpcPort")
+            .unwrap_or(crate::grpc::DEFAULT_PORT as u32);
+
+        log::debug!("GrpcEnabled={} GrpcPort={}", grpc_enabled, grpc_port);
+
+        let status_file_enabled: u32 = key.get_u32("StatusFile").unwrap_or_default();
+        log::debug!("StatusFile={}", status_file_enabled);
+
+        #[cfg(windows)]
+        let (hwinfo_enabled, rtss_enabled, pipe_enabled, perf_counters_enabled) = {
+            let hwinfo_enabled: u32 = key.get_u32("HwInfo").unwrap_or_default();
+            log::debug!("HwInfo={}", hwinfo_enabled);
+
+            let rtss_enabled: u32 = key.get_u32("Rtss").unwrap_or_default();
+            log::debug!("Rtss={}", rtss_enabled);
+
+            let pipe_enabled: u32 = key.get_u32("Pipe").unwrap_or(1);
+            let perf_counters_enabled: u32 = key.get_u32("PerfCounters").unwrap_or(1);
+            log::debug!("Pipe={} PerfCounters={}", pipe_enabled, perf_counters_enabled);
+
+            (hwinfo_enabled, rtss_enabled, pipe_enabled, perf_counters_enabled)
+        };
+
+        #[cfg(windows)]
+        let mut hotkeys = Vec::new();
+        #[cfg(windows)]
+        if let Some(hotkeys_key) = key.subkey("Hotkeys") {
+            for action in hotkeys::Action::ALL {
+                let Some(text) = hotkeys_key.get_string(action.name()) else {
+                    continue;
+                };
+                if text.trim().is_empty() {
+                    continue;
+                }
+                log::debug!("Hotkeys\\{}={}", action.name(), text);
+                match Hotkey::parse(&text) {
+                    Ok(hotkey) => hotkeys.push((action, hotkey)),
+                    Err(e) => log::error!("Invalid hotkey for {}: {e:?}", action.name()),
+                }
+            }
+        }
+
+        #[cfg(windows)]
+        let sounds = key.subkey("Sounds").and_then(|sounds_key| {
+            let mut sounds = HashMap::new();
+            for event in BatteryEvent::ALL {
+                if let Some(value) = sounds_key.get_string(event.name())
+                    && !value.trim().is_empty()
+                {
+                    log::debug!("Sounds\\{}={}", event.name(), value);
+                    sounds.insert(event, sound::Source::parse(value.trim()));
+                }
+            }
+            if sounds.is_empty() {
+                return None;
+            }
+            let volume = sounds_key
+                .get_u32("Volume")
+                .map(|volume| volume.min(100) as u8)
+                .unwrap_or(sound::DEFAULT_VOLUME);
+            log::debug!("Sounds\\Volume={}", volume);
+
+            Some(SoundConfig { sounds, volume })
+        });
+
+        #[cfg(windows)]
+        let quiet_while_recording: u32 = key.get_u32("QuietWhileRecording").unwrap_or_default();
+        #[cfg(windows)]
+        log::debug!("QuietWhileRecording={}", quiet_while_recording);
+
+        #[cfg(windows)]
+        let airpods: u32 = key.get_u32("AirPods").unwrap_or_default();
+        #[cfg(windows)]
+        log::debug!("AirPods={}", airpods);
+
+        let disabled_backends: Vec<String> = key
+            .subkey("Backends")
+            .map(|backends_key| {
+                headset_control::backends(&HeadsetControlConfig::default(), false)
+                    .iter()
+                    .map(|backend| backend.name())
+                    .filter(|name| backends_key.get_u32(name) == Some(0))
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        log::debug!("DisabledBackends={:?}", disabled_backends);
+
+        let headsetcontrol = HeadsetControlConfig {
+            path: key
+                .get_string("HeadsetControlPath")
+                .filter(|path| !path.trim().is_empty())
+                .map(PathBuf::from),
+            args: key
+                .get_string("HeadsetControlArgs")
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+        };
+        log::debug!("HeadsetControl={:?}", headsetcontrol);
+
+        let lights: Option<u32> = key.get_u32("Lights");
+        log::debug!("Lights={:?}", lights);
+        let lights_off_when_low: u32 = key.get_u32("LightsOffWhenLow").unwrap_or_default();
+        log::debug!("LightsOffWhenLow={}", lights_off_when_low);
+        let inactive_time: Option<u32> = key.get_u32("InactiveTime");
+        log::debug!("InactiveTime={:?}", inactive_time);
+
+        let palette = key
+            .get_string("Palette")
+            .filter(|name| !name.trim().is_empty())
+            .and_then(|name| {
+                let palette = Palette::from_name(&name);
+                if palette.is_none() {
+                    log::error!("Unknown Palette {name:?}");
+                }
+                palette
+            })
+            .unwrap_or_default();
+        log::debug!("Palette={}", palette.name());
+
+        let history_enabled: u32 = key.get_u32("History").unwrap_or(1);
+        log::debug!("History={}", history_enabled);
+
+        const DAY: u64 = 24 * 60 * 60;
+        let retention_days: u32 = key.get_u32("HistoryRetentionDays").unwrap_or(90);
+        let full_resolution_days: u32 = key.get_u32("HistoryFullResolutionDays").unwrap_or(30);
+        log::debug!(
+            "HistoryRetentionDays={} HistoryFullResolutionDays={}",
+            retention_days,
+            full_resolution_days
+        );
+        let history_retention = Retention {
+            // 0 keeps everything
+            max_age: (retention_days != 0)
+                .then(|| Duration::from_secs(u64::from(retention_days) * DAY)),
+            full_resolution: Duration::from_secs(u64::from(full_resolution_days) * DAY),
+        };
+
+        let health_warning: u32 = key.get_u32("HealthWarning").unwrap_or(70);
+        log::debug!("HealthWarning={}", health_warning);
+
+        let unplug_reminder: u32 = key.get_u32("UnplugReminder").unwrap_or_default();
+        log::debug!("UnplugReminder={}", unplug_reminder);
+
+        let weekly_summary: u32 = key.get_u32("WeeklySummary").unwrap_or_default();
+        log::debug!("WeeklySummary={}", weekly_summary);
+        let last_weekly_summary: Option<u32> = key.get_u32("LastWeeklySummary");
+
+        let update_check: u32 = key.get_u32("UpdateCheck").unwrap_or_default();
+        log::debug!("UpdateCheck={}", update_check);
+        let last_update_check: Option<u32> = key.get_u32("LastUpdateCheck");
+        let latest_release: Option<String> = key
+            .get_string("LatestRelease")
+            .filter(|tag| !tag.trim().is_empty());
+
+        let mqtt = key.subkey("Mqtt").and_then(|mqtt_key| {
+            let broker: String = mqtt_key.get_string("Broker")?;
+            if broker.trim().is_empty() {
+                return None;
+            }
+            let non_empty = |name: &str| {
+                mqtt_key
+                    .get_string(name)
+                    .filter(|value| !value.is_empty())
+            };
+
+            let discovery: u32 = mqtt_key
+                .get_u32("HomeAssistantDiscovery")
+                .unwrap_or_default();
+
+            log::debug!(
+                "Mqtt\\Broker={} Mqtt\\HomeAssistantDiscovery={}",
+                broker,
+                discovery
+            );
+
+            Some(MqttConfig {
+                broker,
+                username: non_empty("Username"),
+                password: mqtt_key.get_secret("Password"),
+                topic_prefix: non_empty("TopicPrefix")
+                    .unwrap_or_else(|| mqtt::DEFAULT_TOPIC_PREFIX.to_string()),
+                discovery_prefix: (discovery != 0).then(|| {
+                    non_empty("DiscoveryPrefix")
+                        .unwrap_or_else(|| mqtt::discovery::DEFAULT_PREFIX.to_string())
+                }),
+            })
+        });
+
+        let discord = key.subkey("Discord").and_then(|discord_key| {
+            let client_id: String = discord_key.get_string("ClientId")?;
+            if client_id.trim().is_empty() {
+                return None;
+            }
+            let min_interval = discord_key
+                .get_u32("MinInterval")
+                .map(|secs| Duration::from_secs(secs.max(15) as u64))
+                .unwrap_or(discord::DEFAULT_MIN_INTERVAL);
+
+            log::debug!(
+                "Discord\\ClientId={} Discord\\MinInterval={:?}",
+                client_id,
+                min_interval
+            );
+
+            Some(DiscordConfig {
+                client_id: client_id.trim().to_string(),
+                min_interval,
+            })
+        });
+
+        let telegram = key
+            .subkey("Telegram")
+            .and_then(|telegram_key| {
+                let token = telegram_key.get_secret("Token")?;
+                let chat_id = telegram_key.get_secret("ChatId")?;
+                log::debug!("Telegram\\ChatId={}", chat_id);
+                Some(TelegramConfig { token, chat_id })
+            });
+
+        let influx = key
+            .subkey("InfluxDb")
+            .and_then(|influx_key| {
+                let non_empty = |name: &str| {
+                    influx_key
+                        .get_string(name)
+                        .map(|value| value.trim().to_string())
+                        .filter(|value| !value.is_empty())
+                };
+                let url = non_empty("Url")?;
+                let org = non_empty("Org")?;
+                let bucket = non_empty("Bucket")?;
+                let token = influx_key.get_secret("Token")?;
+                log::debug!(
+                    "InfluxDb\\Url={} InfluxDb\\Org={} InfluxDb\\Bucket={}",
+                    url,
+                    org,
+                    bucket
+                );
+                Some(InfluxConfig {
+                    url,
+                    org,
+                    bucket,
+                    token,
+                    measurement: non_empty("Measurement")
+                        .unwrap_or_else(|| influx::DEFAULT_MEASUREMENT.to_string()),
+                })
+            });
+
+        #[cfg(target_os = "linux")]
+        let overlay = key.subkey("Overlay").and_then(|overlay_key| {
+            if overlay_key.get_u32("Enabled").unwrap_or_default() == 0 {
+                return None;
+            }
+            let anchor = overlay_key
+                .get_string("Anchor")
+                .and_then(|name| Corner::from_name(&name))
+                .unwrap_or_default();
+            let margin_x = overlay_key
+                .get_u32("MarginX")
+                .unwrap_or(overlay::DEFAULT_MARGIN);
+            let margin_y = overlay_key
+                .get_u32("MarginY")
+                .unwrap_or(overlay::DEFAULT_MARGIN);
+            let opacity = overlay_key
+                .get_u32("Opacity")
+                .map(|opacity| opacity.min(100) as u8)
+                .unwrap_or(overlay::DEFAULT_OPACITY);
+
+            log::debug!(
+                "Overlay\\Anchor={:?} Overlay\\MarginX={} Overlay\\MarginY={} Overlay\\Opacity={}",
+                anchor,
+                margin_x,
+                margin_y,
+                opacity
+            );
+
+            Some(OverlayConfig {
+                anchor,
+                margin_x,
+                margin_y,
+                opacity,
+            })
+        });
+
+        let last_level: Option<u32> = key.get_u32("LastBatteryLevel");
+        let last_status: Option<String> = key.get_string("LastBatteryStatus");
+        let last_notification_state = match (last_level, last_status) {
+            (Some(level), Some(status)) => serde_json::from_value(status.into())
+                .ok()
+                .map(|status| (level as isize, status)),
+            _ => None,
+        };
+        let last_devices: Vec<Device> = key
+            .get_string("LastDevices")
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        let selected_device: Option<String> = key.get_string("SelectedDevice");
+
         Ok(Self {
             notifications_enabled: notifications_enabled != 0,
+            actions,
+            scripts,
+            webhooks,
+            push,
+            last_notification_state,
+            last_devices,
+            selected_device,
+            api_enabled: api_enabled != 0,
+            api_port: u16::try_from(api_port).unwrap_or(crate::api::DEFAULT_PORT),
+            api_mdns: api_mdns != 0,
+            api_bind_address,
+            api_token,
+            api_allowed_origins,
+            grpc_enabled: grpc_enabled != 0,
+            grpc_port: u16::try_from(grpc_port).unwrap_or(crate::grpc::DEFAULT_PORT),
+            status_file_enabled: status_file_enabled != 0,
+            #[cfg(windows)]
+            hwinfo_enabled: hwinfo_enabled != 0,
+            #[cfg(windows)]
+            rtss_enabled: rtss_enabled != 0,
+            #[cfg(windows)]
+            pipe_enabled: pipe_enabled != 0,
+            #[cfg(windows)]
+            perf_counters_enabled: perf_counters_enabled != 0,
+            #[cfg(windows)]
+            hotkeys,
+            #[cfg(windows)]
+            sounds,
+            #[cfg(windows)]
+            quiet_while_recording: quiet_while_recording != 0,
+            #[cfg(windows)]
+            airpods: airpods != 0,
+            disabled_backends,
+            headsetcontrol,
+            lights: lights.map(|lights| lights != 0),
+            lights_off_when_low: lights_off_when_low != 0,
+            // headsetcontrol takes up to 90 minutes
+            inactive_time: inactive_time.map(|minutes| minutes.min(90) as u8),
+            palette,
+            history_enabled: history_enabled != 0,
+            history_retention,
+            health_warning: u8::try_from(health_warning.min(100)).unwrap_or_default(),
+            // In minutes, 0 turns it off
+            unplug_reminder: (unplug_reminder != 0)
+                .then(|| Duration::from_secs(u64::from(unplug_reminder) * 60)),
+            weekly_summary: weekly_summary != 0,
+            last_weekly_summary: last_weekly_summary.map(i64::from),
+            update_check: update_check != 0,
+            last_update_check: last_update_check.map(u64::from),
+            latest_release,
+            mqtt,
+            discord,
+            telegram,
+            influx,
+            #[cfg(target_os = "linux")]
+            overlay,
         })
     }
 
-    pub fn save(&self) -> Result<()> {
-        let hkcu = winreg::RegKey::predef(HKEY_CURRENT_USER);
-        let (key, _) = hkcu
-            .create_subkey("Software\\HeadsetBatteryIndicator")
-            .context("accessing registry key")?;
-
-        key.set_value("NotificationsEnabled", &(self.notifications_enabled as u32))
+    fn save_to(&self, key: &impl Storage) -> Result<()> {
+        key.set_u32("NotificationsEnabled", self.notifications_enabled as u32)
             .context("setting NotificationsEnabled value")?;
 
         log::debug!(
@@ -39,6 +627,63 @@ impl Settings {
             self.notifications_enabled
         );
 
-        Ok(())
+        key.set_u32("ApiEnabled", self.api_enabled as u32)
+            .context("setting ApiEnabled value")?;
+        key.set_u32("ApiPort", self.api_port as u32)
+            .context("setting ApiPort value")?;
+
+        if let Some((level, status)) = self.last_notification_state
+            && let Ok(serde_json::Value::String(status)) = serde_json::to_value(status)
+        {
+            key.set_u32("LastBatteryLevel", level.max(0) as u32)
+                .context("setting LastBatteryLevel value")?;
+            key.set_string("LastBatteryStatus", &status)
+                .context("setting LastBatteryStatus value")?;
+        }
+
+        let last_devices = serde_json::to_string(&self.last_devices)?;
+        key.set_string("LastDevices", &last_devices)
+            .context("setting LastDevices value")?;
+        if let Some(id) = &self.selected_device {
+            key.set_string("SelectedDevice", id)
+                .context("setting SelectedDevice value")?;
+        }
+
+        if let Some(monday) = self.last_weekly_summary {
+            key.set_u32("LastWeeklySummary", monday.max(0) as u32)
+                .context("setting LastWeeklySummary value")?;
+        }
+
+        if let Some(checked) = self.last_update_check {
+            key.set_u32("LastUpdateCheck", checked.min(u64::from(u32::MAX)) as u32)
+                .context("setting LastUpdateCheck value")?;
+        }
+        if let Some(lights) = self.lights {
+            key.set_u32("Lights", lights as u32)
+                .context("setting Lights value")?;
+        }
+        key.set_u32("LightsOffWhenLow", self.lights_off_when_low as u32)
+            .context("setting LightsOffWhenLow value")?;
+        if let Some(minutes) = self.inactive_time {
+            key.set_u32("InactiveTime", u32::from(minutes))
+                .context("setting InactiveTime value")?;
+        }
+
+        // Empty when it was reset to the usual place
+        let headsetcontrol_path = self
+            .headsetcontrol
+            .path
+            .as_deref()
+            .map(Path::to_string_lossy)
+            .unwrap_or_default();
+        key.set_string("HeadsetControlPath", &headsetcontrol_path)
+            .context("setting HeadsetControlPath value")?;
+
+        if let Some(tag) = &self.latest_release {
+            key.set_string("LatestRelease", tag)
+                .context("setting LatestRelease value")?;
+        }
+
+        key.flush().context("saving settings")
     }
 }
@@ -0,0 +1,303 @@
+//! Plays a sound on battery events, read from the `Sounds` subkey, for when the notifications
+//! are turned off. Each event can have its own WAV file or the built-in chime, played on the
+//! default output device with waveOut.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{debug, error, info};
+use windows::{
+    Win32::Media::{
+        Audio::{
+            CALLBACK_NULL, HWAVEOUT, WAVE_FORMAT_PCM, WAVE_MAPPER, WAVEFORMATEX, WAVEHDR,
+            WHDR_DONE, waveOutClose, waveOutOpen, waveOutPrepareHeader, waveOutUnprepareHeader,
+            waveOutWrite,
+        },
+        MMSYSERR_NOERROR,
+    },
+    core::PSTR,
+};
+
+use crate::actions::BatteryEvent;
+
+pub const DEFAULT_VOLUME: u8 = 100;
+/// Longer than a few minutes of audio, which is surely not meant as an alert
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+/// How often the player checks if the sound has finished
+const DONE_INTERVAL: Duration = Duration::from_millis(20);
+
+const PCM: u16 = WAVE_FORMAT_PCM as u16;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+const CHIME_SAMPLE_RATE: u32 = 44100;
+
+#[derive(Debug, Clone)]
+pub enum Source {
+    /// The built-in chime of the event
+    Chime,
+    File(PathBuf),
+}
+
+impl Source {
+    /// `Chime`, or the path of a WAV file
+    pub fn parse(value: &str) -> Self {
+        if value.eq_ignore_ascii_case("Chime") {
+            Source::Chime
+        } else {
+            Source::File(PathBuf::from(value))
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SoundConfig {
+    pub sounds: HashMap<BatteryEvent, Source>,
+    /// In percent of the volume the sounds were recorded at
+    pub volume: u8,
+}
+
+/// 16-bit PCM samples, interleaved when there are two channels
+struct Clip {
+    channels: u16,
+    sample_rate: u32,
+    samples: Vec<i16>,
+}
+
+/// The decoded sounds, played one after another on the sound thread
+pub struct Sounds {
+    clips: HashMap<BatteryEvent, Arc<Clip>>,
+    sender: mpsc::Sender<Arc<Clip>>,
+}
+
+impl Sounds {
+    /// Loads the sounds and starts the thread that plays them. A file that can't be played is
+    /// logged and replaced by the chime, so the alert isn't lost.
+    pub fn start(config: SoundConfig) -> anyhow::Result<Self> {
+        let clips = config
+            .sounds
+            .into_iter()
+            .map(|(event, source)| {
+                let mut clip = match source {
+                    Source::Chime => chime(event),
+                    Source::File(path) => load(&path).unwrap_or_else(|e| {
+                        error!(
+                            "Failed to load the {} sound {}, playing the chime instead: {e:?}",
+                            event.name(),
+                            path.display()
+                        );
+                        chime(event)
+                    }),
+                };
+                clip.set_volume(config.volume);
+                (event, Arc::new(clip))
+            })
+            .collect();
+
+        let (sender, receiver) = mpsc::channel::<Arc<Clip>>();
+        thread::Builder::new()
+            .name("sound".to_string())
+            .spawn(move || {
+                // Ends when `Sounds` is dropped
+                for clip in receiver {
+                    if let Err(e) = play(&clip) {
+                        error!("Failed to play sound: {e:?}");
+                    }
+                }
+            })
+            .context("spawning sound thread")?;
+        info!("Playing sounds at {}% volume", config.volume);
+        Ok(Self { clips, sender })
+    }
+
+    /// Plays the sound of the event, if it has one, after the ones already playing
+    pub fn play(&self, event: BatteryEvent) {
+        if let Some(clip) = self.clips.get(&event) {
+            debug!("Playing the {} sound", event.name());
+            let _ = self.sender.send(Arc::clone(clip));
+        }
+    }
+}
+
+impl Clip {
+    fn set_volume(&mut self, volume: u8) {
+        let volume = i32::from(volume.min(100));
+        for sample in &mut self.samples {
+            *sample = (i32::from(*sample) * volume / 100) as i16;
+        }
+    }
+}
+
+fn load(path: &Path) -> anyhow::Result<Clip> {
+    let size = std::fs::metadata(path)
+        .with_context(|| format!("reading {}", path.display()))?
+        .len();
+    anyhow::ensure!(size <= MAX_FILE_SIZE, "{} is too large", path.display());
+    let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    decode_wav(&data).with_context(|| format!("decoding {}", path.display()))
+}
+
+/// Decodes 8, 16, 24 and 32-bit integer or 32-bit float WAV with one or two channels
+fn decode_wav(data: &[u8]) -> anyhow::Result<Clip> {
+    anyhow::ensure!(
+        data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WAVE",
+        "not a WAV file"
+    );
+    let u16_at = |bytes: &[u8], i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+
+    let mut format = None;
+    let mut pcm = None;
+    let mut rest = &data[12..];
+    while rest.len() >= 8 {
+        let size = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+        // Some encoders leave the size of the last chunk at 0 or too large
+        let body = rest.get(8..8 + size).unwrap_or(&rest[8..]);
+        match &rest[..4] {
+            b"fmt " => {
+                anyhow::ensure!(body.len() >= 16, "fmt chunk is too short");
+                let mut tag = u16_at(body, 0);
+                // The actual format is at the start of the sub format GUID
+                if tag == WAVE_FORMAT_EXTENSIBLE && body.len() >= 26 {
+                    tag = u16_at(body, 24);
+                }
+                let sample_rate = u32::from_le_bytes([body[4], body[5], body[6], body[7]]);
+                format = Some((tag, u16_at(body, 2), sample_rate, u16_at(body, 14)));
+            }
+            b"data" => pcm = Some(body),
+            _ => {}
+        }
+        // Chunks are padded to an even size
+        rest = rest.get(8 + size + size % 2..).unwrap_or_default();
+    }
+
+    let (tag, channels, sample_rate, bits) = format.context("no fmt chunk")?;
+    let pcm = pcm.context("no data chunk")?;
+    anyhow::ensure!(
+        matches!(channels, 1 | 2),
+        "{channels} channels, only mono and stereo are supported"
+    );
+    anyhow::ensure!(sample_rate > 0, "sample rate is 0");
+
+    let samples = match (tag, bits) {
+        (PCM, 8) => pcm
+            .iter()
+            .map(|&byte| (i16::from(byte) - 128) << 8)
+            .collect(),
+        (PCM, 16) => pcm.chunks_exact(2).map(|b| u16_at(b, 0) as i16).collect(),
+        // Only the most significant bytes matter at 16 bits
+        (PCM, 24) => pcm.chunks_exact(3).map(|b| u16_at(b, 1) as i16).collect(),
+        (PCM, 32) => pcm.chunks_exact(4).map(|b| u16_at(b, 2) as i16).collect(),
+        (WAVE_FORMAT_IEEE_FLOAT, 32) => pcm
+            .chunks_exact(4)
+            .map(|b| {
+                let sample = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16
+            })
+            .collect(),
+        _ => anyhow::bail!("unsupported format {tag} with {bits} bits per sample"),
+    };
+    Ok(Clip {
+        channels,
+        sample_rate,
+        samples,
+    })
+}
+
+/// A few short tones, falling for bad news and rising for good news
+fn chime(event: BatteryEvent) -> Clip {
+    // Frequency in Hz, 0 for a pause, and length in milliseconds
+    let notes: &[(f32, u32)] = match event {
+        BatteryEvent::Low => &[(880.0, 150), (659.3, 250)],
+        BatteryEvent::Critical => &[
+            (880.0, 120),
+            (0.0, 60),
+            (880.0, 120),
+            (0.0, 60),
+            (659.3, 300),
+        ],
+        BatteryEvent::Full => &[(523.3, 120), (659.3, 120), (784.0, 250)],
+        BatteryEvent::Connected => &[(659.3, 100), (987.8, 150)],
+        BatteryEvent::Disconnected => &[(987.8, 100), (659.3, 150)],
+    };
+    let rate = CHIME_SAMPLE_RATE as f32;
+    let mut samples = Vec::new();
+    for &(frequency, millis) in notes {
+        let len = (CHIME_SAMPLE_RATE * millis / 1000) as usize;
+        // Fades in and out over 10 ms, so the tones don't click
+        let fade = (rate * 0.01) as usize;
+        samples.extend((0..len).map(|i| {
+            let envelope = (i.min(len - i) as f32 / fade as f32).min(1.0);
+            let phase = std::f32::consts::TAU * frequency * i as f32 / rate;
+            (phase.sin() * envelope * 0.4 * f32::from(i16::MAX)) as i16
+        }));
+    }
+    Clip {
+        channels: 1,
+        sample_rate: CHIME_SAMPLE_RATE,
+        samples,
+    }
+}
+
+/// Plays the clip on the default output device and returns once it has finished
+fn play(clip: &Clip) -> anyhow::Result<()> {
+    let block_align = clip.channels * 2;
+    let format = WAVEFORMATEX {
+        wFormatTag: PCM,
+        nChannels: clip.channels,
+        nSamplesPerSec: clip.sample_rate,
+        nAvgBytesPerSec: clip.sample_rate * u32::from(block_align),
+        nBlockAlign: block_align,
+        wBitsPerSample: 16,
+        cbSize: 0,
+    };
+    let mut wave_out = HWAVEOUT::default();
+    unsafe {
+        check(
+            waveOutOpen(
+                Some(&mut wave_out as *mut HWAVEOUT),
+                WAVE_MAPPER,
+                &format,
+                0,
+                0,
+                CALLBACK_NULL,
+            ),
+            "waveOutOpen",
+        )?;
+        let res = write(wave_out, clip);
+        let _ = waveOutClose(wave_out);
+        res
+    }
+}
+
+unsafe fn write(wave_out: HWAVEOUT, clip: &Clip) -> anyhow::Result<()> {
+    let mut header = WAVEHDR {
+        lpData: PSTR(clip.samples.as_ptr() as *mut u8),
+        dwBufferLength: (clip.samples.len() * 2) as u32,
+        ..Default::default()
+    };
+    let size = size_of::<WAVEHDR>() as u32;
+    unsafe {
+        check(
+            waveOutPrepareHeader(wave_out, &mut header, size),
+            "waveOutPrepareHeader",
+        )?;
+        let res = check(waveOutWrite(wave_out, &mut header, size), "waveOutWrite");
+        if res.is_ok() {
+            // Set by the driver once the whole buffer has been played
+            while (&raw const header.dwFlags).read_unaligned() & WHDR_DONE == 0 {
+                thread::sleep(DONE_INTERVAL);
+            }
+        }
+        let _ = waveOutUnprepareHeader(wave_out, &mut header, size);
+        res
+    }
+}
+
+fn check(res: u32, function: &str) -> anyhow::Result<()> {
+    anyhow::ensure!(res == MMSYSERR_NOERROR, "{function} failed with {res}");
+    Ok(())
+}
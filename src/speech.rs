@@ -0,0 +1,34 @@
+//! Reads text aloud with the voice chosen in the Windows speech settings
+
+use anyhow::Context;
+use windows::{
+    Win32::{
+        Media::Speech::{ISpVoice, SPF_ASYNC, SPF_IS_NOT_XML, SPF_PURGEBEFORESPEAK, SpVoice},
+        System::Com::{CLSCTX_ALL, COINIT_APARTMENTTHREADED, CoCreateInstance, CoInitializeEx},
+    },
+    core::HSTRING,
+};
+
+/// Created on the first announcement, most users never hear one
+#[derive(Default)]
+pub struct Speech {
+    voice: Option<ISpVoice>,
+}
+
+impl Speech {
+    /// Starts reading `text` and returns right away, cutting off what was being read before
+    pub fn say(&mut self, text: &str) -> anyhow::Result<()> {
+        let voice = match &mut self.voice {
+            Some(voice) => voice,
+            slot @ None => slot.insert(unsafe {
+                // Fails when COM is already set up differently on this thread, which works too
+                let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+                CoCreateInstance(&SpVoice, None, CLSCTX_ALL).context("creating speech voice")?
+            }),
+        };
+        let flags = SPF_ASYNC.0 | SPF_PURGEBEFORESPEAK.0 | SPF_IS_NOT_XML.0;
+        unsafe { voice.Speak(&HSTRING::from(text), flags as u32, None) }
+            .context("speaking text")?;
+        Ok(())
+    }
+}
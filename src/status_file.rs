@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use log::{error, info};
+use serde_derive::Serialize;
+
+use crate::api::{self, DeviceResponse};
+use crate::headset_control::Device;
+use crate::platform::sandbox::{self, Sandbox as _};
+
+/// The device list as JSON in a file that desktop widgets like Rainmeter skins can read
+/// cheaply, since they usually can't talk to sockets or pipes.
+///
+/// The file is only rewritten when something changes, and removed on exit
+/// so a stale file doesn't look like a connected headset.
+pub struct StatusFile {
+    path: PathBuf,
+    last_written: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Status {
+    /// The device shown in the tray, so widgets don't have to search the list
+    selected: Option<DeviceResponse>,
+    devices: Vec<DeviceResponse>,
+}
+
+impl StatusFile {
+    pub fn new() -> anyhow::Result<Self> {
+        let dir = sandbox::Native::data_local_dir()
+            .context("getting LocalAppData directory")?
+            .join("HeadsetBatteryIndicator");
+        fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+
+        let path = dir.join("status.json");
+        info!("Writing status to {}", path.display());
+
+        Ok(Self {
+            path,
+            last_written: String::new(),
+        })
+    }
+
+    pub fn publish(&mut self, devices: &[Device], selected: usize) {
+        let devices = api::device_list(devices, selected);
+        let status = Status {
+            selected: devices.iter().find(|d| d.selected).cloned(),
+            devices,
+        };
+
+        let json = match serde_json::to_string_pretty(&status) {
+            Ok(json) => json,
+            Err(e) => {
+                error!("Failed to serialize status: {e:?}");
+                return;
+            }
+        };
+        if json == self.last_written {
+            return;
+        }
+
+        match self.write(&json) {
+            Ok(()) => self.last_written = json,
+            Err(e) => error!("Failed to write status file: {e:?}"),
+        }
+    }
+
+    /// Replaces the file in one step, so readers never see a half-written file
+    fn write(&self, json: &str) -> anyhow::Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, json).with_context(|| format!("writing {}", tmp.display()))?;
+        fs::rename(&tmp, &self.path).with_context(|| format!("replacing {}", self.path.display()))
+    }
+}
+
+impl Drop for StatusFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
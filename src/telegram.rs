@@ -0,0 +1,218 @@
+//! Battery alerts in a Telegram chat, and a `/battery` command that replies with the current levels
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{debug, error, info, warn};
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use serde_json::json;
+
+use crate::actions::BatteryEvent;
+use crate::headset_control::Device;
+use crate::http;
+use crate::lang::{self, Key::*};
+
+const API_URL: &str = "https://api.telegram.org/bot";
+
+/// How long `getUpdates` waits on the server for new messages
+const POLL_TIMEOUT: Duration = Duration::from_secs(25);
+const TIMEOUT: Duration = Duration::from_secs(10);
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Read from the `Telegram` registry subkey, where the values are stored encrypted
+#[derive(Debug, Clone)]
+pub struct TelegramConfig {
+    pub token: String,
+    /// Alerts are sent to this chat, and only commands from it are answered
+    pub chat_id: String,
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+    ok: bool,
+    description: Option<String>,
+    result: Option<T>,
+}
+
+#[derive(Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    chat: Chat,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+pub struct TelegramBot {
+    config: Arc<TelegramConfig>,
+    /// The reply to `/battery`, updated on every poll
+    levels: Arc<RwLock<String>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl TelegramBot {
+    pub fn start(config: TelegramConfig) -> anyhow::Result<Self> {
+        let config = Arc::new(config);
+        let levels = Arc::new(RwLock::new(lang::t(no_adapter_found).to_string()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        {
+            let config = config.clone();
+            let levels = levels.clone();
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("telegram".to_string())
+                .spawn(move || poll_commands(&config, &levels, &stop))
+                .context("spawning Telegram thread")?;
+        }
+
+        info!("Sending battery alerts to Telegram chat {}", config.chat_id);
+
+        Ok(Self {
+            config,
+            levels,
+            stop,
+        })
+    }
+
+    pub fn publish(&self, devices: &[Device]) {
+        let text = if devices.is_empty() {
+            lang::t(no_adapter_found).to_string()
+        } else {
+            devices
+                .iter()
+                .map(|device| device.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        *self.levels.write().unwrap_or_else(|e| e.into_inner()) = text;
+    }
+
+    /// Sends a message for low, critical and full battery events, other events are ignored
+    pub fn alert(&self, event: BatteryEvent, device: &Device) {
+        let key = match event {
+            BatteryEvent::Low => alert_low,
+            BatteryEvent::Critical => alert_critical,
+            BatteryEvent::Full => alert_full,
+            _ => return,
+        };
+        let text = format!(
+            "{}: {}",
+            device.product,
+            lang::t(key).replace("{level}", &device.battery.level.to_string())
+        );
+
+        let config = self.config.clone();
+        let res = thread::Builder::new()
+            .name("telegram-alert".to_string())
+            .spawn(move || match send_message(&config, &text) {
+                Ok(()) => info!("Sent {} alert to Telegram", event.name()),
+                Err(e) => error!("Failed to send {} alert to Telegram: {e:?}", event.name()),
+            });
+        if let Err(e) = res {
+            error!("Failed to spawn Telegram alert thread: {e:?}");
+        }
+    }
+}
+
+impl Drop for TelegramBot {
+    fn drop(&mut self) {
+        // The thread notices when its current long poll returns
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Long polls for messages to the bot and answers `/battery`
+fn poll_commands(config: &TelegramConfig, levels: &RwLock<String>, stop: &AtomicBool) {
+    let mut offset = 0;
+
+    while !stop.load(Ordering::Relaxed) {
+        let body = json!({
+            "offset": offset,
+            "timeout": POLL_TIMEOUT.as_secs(),
+            "allowed_updates": ["message"],
+        });
+        let updates: Vec<Update> = match call(config, "getUpdates", &body, POLL_TIMEOUT + TIMEOUT) {
+            Ok(updates) => updates,
+            Err(e) => {
+                warn!("Failed to get Telegram updates: {e:?}");
+                thread::sleep(RETRY_DELAY);
+                continue;
+            }
+        };
+
+        for update in updates {
+            // Confirms the update, so it isn't returned again
+            offset = update.update_id + 1;
+
+            let Some(message) = update.message else {
+                continue;
+            };
+            // Commands in groups can be addressed to a bot, like /battery@MyBot
+            let command = message
+                .text
+                .as_deref()
+                .and_then(|text| text.split_whitespace().next())
+                .and_then(|word| word.split('@').next());
+            if command != Some("/battery") {
+                continue;
+            }
+            if message.chat.id.to_string() != config.chat_id {
+                debug!("Ignoring Telegram command from chat {}", message.chat.id);
+                continue;
+            }
+
+            let text = levels.read().unwrap_or_else(|e| e.into_inner()).clone();
+            if let Err(e) = send_message(config, &text) {
+                error!("Failed to answer Telegram command: {e:?}");
+            }
+        }
+    }
+}
+
+fn send_message(config: &TelegramConfig, text: &str) -> anyhow::Result<()> {
+    let body = json!({ "chat_id": config.chat_id, "text": text });
+    call::<serde_json::Value>(config, "sendMessage", &body, TIMEOUT)?;
+    Ok(())
+}
+
+fn call<T: DeserializeOwned>(
+    config: &TelegramConfig,
+    method: &str,
+    body: &serde_json::Value,
+    timeout: Duration,
+) -> anyhow::Result<T> {
+    let url = format!("{API_URL}{}/{method}", config.token);
+    let (status, response) = http::post_with_response(
+        &url,
+        "application/json",
+        body.to_string().as_bytes(),
+        timeout,
+    )?;
+
+    let response: Response<T> = serde_json::from_slice(&response)
+        .with_context(|| format!("invalid {method} response (status {status})"))?;
+    match response {
+        Response {
+            ok: true,
+            result: Some(result),
+            ..
+        } => Ok(result),
+        Response { description, .. } => anyhow::bail!(
+            "{method} failed with status {status}: {}",
+            description.unwrap_or_default()
+        ),
+    }
+}
@@ -0,0 +1,108 @@
+//! Checks the GitHub releases for a newer version once a day when `UpdateCheck` is set
+
+#[cfg(windows)]
+pub mod install;
+
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{debug, info, warn};
+use serde_derive::Deserialize;
+use winit::event_loop::EventLoopProxy;
+
+use crate::UserEvent;
+use crate::history;
+use crate::http;
+
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/aarol/headset-battery-indicator/releases/latest";
+/// Opened by "View updates" while there's no newer release
+pub const RELEASES_URL: &str = "https://github.com/aarol/headset-battery-indicator/releases";
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How often the thread looks at the clock, so a sleeping PC doesn't delay the check by a day
+const WAKE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+}
+
+/// Checks the releases in the background, and sends `UserEvent::UpdateChecked` with the tag
+/// of the latest one after every check. `last_check` is a Unix timestamp in seconds.
+pub fn start(last_check: Option<u64>, proxy: EventLoopProxy<UserEvent>) -> anyhow::Result<()> {
+    thread::Builder::new()
+        .name("update-check".to_string())
+        .spawn(move || run(last_check, proxy))
+        .context("spawning update check thread")?;
+    Ok(())
+}
+
+fn run(mut last_check: Option<u64>, proxy: EventLoopProxy<UserEvent>) {
+    loop {
+        let now = history::now();
+        // A clock that went back counts as due too
+        let due =
+            last_check.is_none_or(|last| now < last || now - last >= CHECK_INTERVAL.as_secs());
+        if due {
+            match latest_release() {
+                Ok(tag) => {
+                    last_check = Some(now);
+                    if proxy.send_event(UserEvent::UpdateChecked(tag)).is_err() {
+                        debug!("Event loop closed, stopping update check");
+                        return;
+                    }
+                }
+                // Tried again on the next wake up
+                Err(e) => warn!("Failed to check for updates: {e:?}"),
+            }
+        }
+        thread::sleep(WAKE_INTERVAL);
+    }
+}
+
+fn latest_release() -> anyhow::Result<String> {
+    let (status, response) = http::get(
+        LATEST_RELEASE_URL,
+        &[("Accept", "application/vnd.github+json")],
+        TIMEOUT,
+    )?;
+    anyhow::ensure!(
+        status == 200,
+        "GitHub answered with status {status}: {}",
+        String::from_utf8_lossy(&response).trim()
+    );
+    let release: Release =
+        serde_json::from_slice(&response).context("parsing the latest release")?;
+    info!(
+        "Checked for updates, the latest release is {}",
+        release.tag_name
+    );
+    Ok(release.tag_name)
+}
+
+/// Whether the release `tag`, like `v3.4.0`, is newer than the running version
+pub fn is_newer(tag: &str) -> bool {
+    version_parts(tag) > version_parts(crate::VERSION)
+}
+
+/// The numbers of a version, `v3.4.0-beta` is `[3, 4]`
+fn version_parts(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+/// `v3.4.0` for both `3.4.0` and `v3.4.0` tags
+pub fn version_label(tag: &str) -> String {
+    format!("v{}", tag.trim_start_matches('v'))
+}
+
+/// The page of the release with `tag`, with its notes and downloads
+pub fn release_url(tag: &str) -> String {
+    format!("{RELEASES_URL}/tag/{tag}")
+}
@@ -0,0 +1,307 @@
+//! Replaces the running executable with the one from a newer release. The download has to
+//! match the digest GitHub computed when the asset was uploaded and carry a valid Authenticode
+//! signature made with the release certificate, pinned by its thumbprint when the program was
+//! built. Windows lets a running executable be renamed, so the new one takes its place and
+//! is started once the old one has saved its state.
+
+use std::os::windows::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{debug, error, info};
+use serde_derive::Deserialize;
+use windows::{
+    Win32::{
+        Foundation::{BOOL, HANDLE, HWND},
+        Security::Cryptography::{
+            BCRYPT_ALG_HANDLE, BCRYPT_OPEN_ALGORITHM_PROVIDER_FLAGS, BCRYPT_SHA256_ALGORITHM,
+            BCryptCloseAlgorithmProvider, BCryptHash, BCryptOpenAlgorithmProvider,
+        },
+        Security::WinTrust::{
+            WINTRUST_ACTION_GENERIC_VERIFY_V2, WINTRUST_DATA, WINTRUST_DATA_0, WINTRUST_FILE_INFO,
+            WTD_CHOICE_FILE, WTD_REVOKE_WHOLECHAIN, WTD_STATEACTION_CLOSE, WTD_STATEACTION_VERIFY,
+            WTD_UI_NONE, WTHelperGetProvSignerFromChain, WTHelperProvDataFromStateData,
+            WinVerifyTrust,
+        },
+    },
+    core::PCWSTR,
+};
+use winit::event_loop::EventLoopProxy;
+
+use crate::UserEvent;
+use crate::http;
+use crate::platform::features;
+
+const RELEASE_BY_TAG_URL: &str =
+    "https://api.github.com/repos/aarol/headset-battery-indicator/releases/tags/";
+/// The executable uploaded next to the installer
+const ASSET_NAME: &str = "headset-battery-indicator.exe";
+const MAX_SIZE: usize = 64 * 1024 * 1024;
+/// SHA-256 thumbprint of the certificate the releases are signed with, in hex, set by the release
+/// workflow. The digest comes from the same release as the download, so only a signature made
+/// with this certificate shows that the file was built by us. Builds without it don't update.
+const SIGNER_SHA256: Option<&str> = option_env!("RELEASE_CERT_SHA256");
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// How long the previous executable is waited for to exit before it's left for the next start
+const CLEAN_UP_RETRIES: usize = 20;
+const CLEAN_UP_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Deserialize)]
+struct Release {
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+    /// Like `sha256:<hex>`
+    digest: Option<String>,
+}
+
+/// Whether the running executable can replace itself. The debug build, builds that don't know
+/// the release certificate and packaged installs can't, the package is updated by the Store or
+/// App Installer.
+pub fn is_supported() -> bool {
+    !cfg!(feature = "msix")
+        && SIGNER_SHA256.is_some()
+        && std::env::current_exe()
+            .is_ok_and(|exe| exe.file_name().is_some_and(|name| name == ASSET_NAME))
+}
+
+/// Downloads and verifies the executable of the release with `tag` in the background, and sends
+/// `features::Event::UpdateDownloaded` with where it was saved
+pub fn download(tag: String, proxy: EventLoopProxy<UserEvent>) -> anyhow::Result<()> {
+    thread::Builder::new()
+        .name("update-download".to_string())
+        .spawn(move || {
+            let result = download_verified(&tag);
+            let _ = proxy.send_event(UserEvent::Platform(features::Event::UpdateDownloaded(
+                result,
+            )));
+        })
+        .context("spawning update download thread")?;
+    Ok(())
+}
+
+fn download_verified(tag: &str) -> anyhow::Result<PathBuf> {
+    let (status, response) = http::get(
+        &format!("{RELEASE_BY_TAG_URL}{tag}"),
+        &[("Accept", "application/vnd.github+json")],
+        TIMEOUT,
+    )?;
+    anyhow::ensure!(status == 200, "GitHub answered with status {status}");
+    let release: Release = serde_json::from_slice(&response).context("parsing the release")?;
+    let asset = release
+        .assets
+        .into_iter()
+        .find(|asset| asset.name == ASSET_NAME)
+        .with_context(|| format!("release {tag} has no {ASSET_NAME}"))?;
+    let digest = asset
+        .digest
+        .as_deref()
+        .and_then(|digest| digest.strip_prefix("sha256:"))
+        .with_context(|| format!("release {tag} has no SHA-256 digest for {ASSET_NAME}"))?
+        .to_ascii_lowercase();
+
+    info!("Downloading {}", asset.browser_download_url);
+    let (status, executable) = http::download(&asset.browser_download_url, MAX_SIZE, TIMEOUT)?;
+    anyhow::ensure!(
+        status == 200,
+        "downloading {ASSET_NAME} failed with status {status}"
+    );
+    let hash = hex(&sha256(&executable)?);
+    anyhow::ensure!(
+        hash == digest,
+        "{ASSET_NAME} has the SHA-256 hash {hash}, but the release says {digest}"
+    );
+
+    let path = sibling("new")?;
+    std::fs::write(&path, &executable)
+        .with_context(|| format!("saving the update to {}", path.display()))?;
+    if let Err(e) = verify_signature(&path) {
+        let _ = std::fs::remove_file(&path);
+        return Err(e);
+    }
+    info!("Downloaded and verified {tag} to {}", path.display());
+    Ok(path)
+}
+
+/// Moves the running executable out of the way and `new` in its place, and returns where the
+/// new one is to be started from. `new` is verified again, in case it changed since the download.
+pub fn replace(new: &Path) -> anyhow::Result<PathBuf> {
+    verify_signature(new)?;
+    let exe = std::env::current_exe().context("getting current executable path")?;
+    let old = sibling("old")?;
+    match std::fs::remove_file(&old) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(e).with_context(|| format!("removing {}", old.display()));
+        }
+        _ => {}
+    }
+
+    std::fs::rename(&exe, &old).with_context(|| format!("renaming {}", exe.display()))?;
+    if let Err(e) = std::fs::rename(new, &exe) {
+        // Put the running one back, so the next start doesn't find nothing
+        if let Err(e) = std::fs::rename(&old, &exe) {
+            error!("Failed to restore {}: {e:?}", exe.display());
+        }
+        return Err(e).with_context(|| format!("moving the update to {}", exe.display()));
+    }
+    info!("Replaced {} with the update", exe.display());
+    Ok(exe)
+}
+
+/// Starts the new executable, after the old one has saved its state and removed its tray icon
+pub fn relaunch(exe: &Path) {
+    info!("Starting {}", exe.display());
+    if let Err(e) = std::process::Command::new(exe).spawn() {
+        error!("Failed to start {}: {e:?}", exe.display());
+    }
+}
+
+/// Removes the executable left behind by an update, once the process running it has exited
+pub fn clean_up() {
+    let Ok(old) = sibling("old") else {
+        return;
+    };
+    if !old.exists() {
+        return;
+    }
+    let res = thread::Builder::new()
+        .name("update-clean-up".to_string())
+        .spawn(move || {
+            for _ in 0..CLEAN_UP_RETRIES {
+                match std::fs::remove_file(&old) {
+                    Ok(()) => {
+                        debug!("Removed {}", old.display());
+                        return;
+                    }
+                    Err(_) => thread::sleep(CLEAN_UP_INTERVAL),
+                }
+            }
+            error!("Failed to remove {}, it's still in use", old.display());
+        });
+    if let Err(e) = res {
+        error!("Failed to spawn update clean up thread: {e:?}");
+    }
+}
+
+/// `headset-battery-indicator.exe.<extension>` next to the running executable
+fn sibling(extension: &str) -> anyhow::Result<PathBuf> {
+    let exe = std::env::current_exe().context("getting current executable path")?;
+    Ok(exe.with_file_name(format!("{ASSET_NAME}.{extension}")))
+}
+
+fn sha256(data: &[u8]) -> anyhow::Result<[u8; 32]> {
+    let mut hash = [0; 32];
+    unsafe {
+        let mut algorithm = BCRYPT_ALG_HANDLE::default();
+        BCryptOpenAlgorithmProvider(
+            &mut algorithm,
+            BCRYPT_SHA256_ALGORITHM,
+            PCWSTR::null(),
+            BCRYPT_OPEN_ALGORITHM_PROVIDER_FLAGS(0),
+        )
+        .ok()
+        .context("BCryptOpenAlgorithmProvider")?;
+        let result = BCryptHash(algorithm, None, data, &mut hash).ok();
+        let _ = BCryptCloseAlgorithmProvider(algorithm, 0);
+        result.context("BCryptHash")?;
+    }
+    Ok(hash)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Checks that the file has a trusted Authenticode signature, whose certificates aren't revoked,
+/// made with the certificate of `SIGNER_SHA256`, without asking the user anything
+fn verify_signature(path: &Path) -> anyhow::Result<()> {
+    let expected = SIGNER_SHA256.context("the release certificate isn't known to this build")?;
+    let path_wide: Vec<u16> = path
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    let mut file = WINTRUST_FILE_INFO {
+        cbStruct: size_of::<WINTRUST_FILE_INFO>() as u32,
+        pcwszFilePath: PCWSTR(path_wide.as_ptr()),
+        hFile: HANDLE::default(),
+        pgKnownSubject: std::ptr::null_mut(),
+    };
+    let mut data = WINTRUST_DATA {
+        cbStruct: size_of::<WINTRUST_DATA>() as u32,
+        dwUIChoice: WTD_UI_NONE,
+        // A certificate that leaked and was revoked isn't trusted either
+        fdwRevocationChecks: WTD_REVOKE_WHOLECHAIN,
+        dwUnionChoice: WTD_CHOICE_FILE,
+        Anonymous: WINTRUST_DATA_0 { pFile: &mut file },
+        dwStateAction: WTD_STATEACTION_VERIFY,
+        ..Default::default()
+    };
+    let mut action = WINTRUST_ACTION_GENERIC_VERIFY_V2;
+
+    let status = unsafe {
+        WinVerifyTrust(
+            HWND::default(),
+            &mut action,
+            &mut data as *mut WINTRUST_DATA as *mut _,
+        )
+    };
+    // Read before the state is closed, which frees the certificates
+    let signer = (status == 0)
+        .then(|| unsafe { signer_certificate(data.hWVTStateData) })
+        .flatten();
+    // Frees what the verification allocated
+    data.dwStateAction = WTD_STATEACTION_CLOSE;
+    unsafe {
+        let _ = WinVerifyTrust(
+            HWND::default(),
+            &mut action,
+            &mut data as *mut WINTRUST_DATA as *mut _,
+        );
+    }
+
+    anyhow::ensure!(
+        status == 0,
+        "{} isn't signed, or its signature isn't trusted: {}",
+        path.display(),
+        windows::core::Error::from(windows::core::HRESULT(status))
+    );
+    let signer = signer.with_context(|| format!("reading the signer of {}", path.display()))?;
+    let thumbprint = hex(&sha256(&signer)?);
+    anyhow::ensure!(
+        thumbprint.eq_ignore_ascii_case(expected),
+        "{} is signed by the certificate {thumbprint} instead of {expected}",
+        path.display()
+    );
+    Ok(())
+}
+
+/// The encoded certificate that signed the file, from the state WinVerifyTrust kept
+unsafe fn signer_certificate(state: HANDLE) -> Option<Vec<u8>> {
+    let provider = unsafe { WTHelperProvDataFromStateData(state) };
+    if provider.is_null() {
+        return None;
+    }
+    let signer = unsafe { WTHelperGetProvSignerFromChain(provider, 0, BOOL::from(false), 0) };
+    if signer.is_null() || unsafe { (*signer).csCertChain } == 0 {
+        return None;
+    }
+    // The chain starts with the certificate that signed, followed by the ones that issued it
+    let certificate = unsafe { (*(*signer).pasCertChain).pCert };
+    if certificate.is_null() {
+        return None;
+    }
+    let encoded = unsafe {
+        std::slice::from_raw_parts(
+            (*certificate).pbCertEncoded,
+            (*certificate).cbCertEncoded as usize,
+        )
+    };
+    Some(encoded.to_vec())
+}
@@ -0,0 +1,104 @@
+use std::thread;
+use std::time::Duration;
+
+use log::{error, info, warn};
+
+use crate::actions::BatteryEvent;
+use crate::headset_control::{BatteryState, Device};
+use crate::http;
+
+pub const DEFAULT_CONTENT_TYPE: &str = "application/json";
+/// Used when no body template is configured
+pub const DEFAULT_BODY: &str = r#"{"event":"{event}","id":"{id}","name":"{name}","level":{level},"status":"{status}","charging":{charging}}"#;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// A request sent on a battery event, read from the `Webhooks\<event>` registry subkey
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    pub url: String,
+    /// Request body with placeholders, see [`render`]
+    pub body: String,
+    pub content_type: String,
+}
+
+/// Sends the webhook in the background, retrying failed deliveries a few times
+pub fn send(webhook: &Webhook, event: BatteryEvent, device: &Device) {
+    let json = webhook.content_type.contains("json");
+    let body = render(&webhook.body, event, device, json);
+    let webhook = webhook.clone();
+
+    let res = thread::Builder::new()
+        .name("webhook".to_string())
+        .spawn(move || deliver(&webhook, event, &body));
+    if let Err(e) = res {
+        error!("Failed to spawn webhook thread: {e:?}");
+    }
+}
+
+fn deliver(webhook: &Webhook, event: BatteryEvent, body: &str) {
+    for attempt in 1..=MAX_ATTEMPTS {
+        match http::post(
+            &webhook.url,
+            &webhook.content_type,
+            body.as_bytes(),
+            TIMEOUT,
+        ) {
+            Ok(status) if (200..300).contains(&status) => {
+                info!("Sent {} webhook ({status})", event.name());
+                return;
+            }
+            // Client errors won't go away by retrying
+            Ok(status) if (400..500).contains(&status) && status != 429 => {
+                error!("{} webhook was rejected with status {status}", event.name());
+                return;
+            }
+            Ok(status) => warn!(
+                "{} webhook failed with status {status} (attempt {attempt}/{MAX_ATTEMPTS})",
+                event.name()
+            ),
+            Err(e) => warn!(
+                "{} webhook failed (attempt {attempt}/{MAX_ATTEMPTS}): {e:?}",
+                event.name()
+            ),
+        }
+
+        if attempt < MAX_ATTEMPTS {
+            thread::sleep(RETRY_DELAY * attempt);
+        }
+    }
+
+    error!("Giving up on {} webhook", event.name());
+}
+
+/// Fills in `{event}`, `{name}`, `{id}`, `{level}`, `{status}` and `{charging}`.
+///
+/// Values are escaped for JSON string literals when the body is JSON,
+/// so device names with quotes don't break the template.
+fn render(template: &str, event: BatteryEvent, device: &Device, json: bool) -> String {
+    let escape = |value: &str| {
+        if json {
+            let quoted = serde_json::Value::from(value).to_string();
+            quoted[1..quoted.len() - 1].to_string()
+        } else {
+            value.to_string()
+        }
+    };
+
+    template
+        .replace("{event}", event.name())
+        .replace("{name}", &escape(&device.product))
+        .replace("{id}", &escape(&device.id()))
+        .replace("{level}", &device.battery.level.to_string())
+        .replace("{status}", device.battery.status.label())
+        .replace(
+            "{charging}",
+            if device.battery.status == BatteryState::BatteryCharging {
+                "true"
+            } else {
+                "false"
+            },
+        )
+}